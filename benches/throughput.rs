@@ -0,0 +1,144 @@
+//! Reader and writer throughput for each cipher and buffer size.
+//!
+//! Run with `cargo bench`, or e.g. `cargo bench -- read/ChaCha8` for one group.
+use std::io::{self, Cursor, Read};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use enard::tuning::ReaderOptions;
+use enard::{EnardReader, EnardWriter};
+
+const KEY: [u8; 32] = [0x42; 32];
+const IV: [u8; 12] = [0x24; 12];
+const CIPHERS: &[&str] = &["ChaCha8", "ChaCha12", "ChaCha20"];
+const SIZES: &[usize] = &[4 << 10, 256 << 10, 4 << 20];
+const BUFFER_SIZES: &[usize] = &[1 << 10, 8 << 10, 64 << 10];
+
+fn encrypt(cipher: &str, data: &[u8], buffer_size: usize) -> Vec<u8> {
+    let mut wr = EnardWriter::builder(Cursor::new(Vec::with_capacity(data.len() + 1024)))
+        .cipher(cipher.as_bytes())
+        .key(KEY)
+        .iv(IV)
+        .build()
+        .unwrap()
+        .with_buffer_size(buffer_size);
+    wr.write_complete(data).unwrap();
+    wr.into_inner().into_inner()
+}
+
+fn decrypt(file: &[u8], readahead: usize) -> u64 {
+    let mut rd = EnardReader::new_boxed(Cursor::new(file), KEY)
+        .unwrap()
+        .with_readahead(readahead);
+    io::copy(&mut rd, &mut io::sink()).unwrap()
+}
+
+fn ciphers(c: &mut Criterion) {
+    for &size in SIZES {
+        let data = vec![0x5au8; size];
+        let mut group = c.benchmark_group(format!("write/{}", size));
+        group.throughput(Throughput::Bytes(size as u64));
+        for &cipher in CIPHERS {
+            group.bench_with_input(BenchmarkId::from_parameter(cipher), &data, |b, data| {
+                b.iter(|| encrypt(cipher, data, enard::DEFAULT_BUFFER_SIZE))
+            });
+        }
+        group.finish();
+
+        let mut group = c.benchmark_group(format!("read/{}", size));
+        group.throughput(Throughput::Bytes(size as u64));
+        for &cipher in CIPHERS {
+            let file = encrypt(cipher, &data, enard::DEFAULT_BUFFER_SIZE);
+            group.bench_with_input(BenchmarkId::from_parameter(cipher), &file, |b, file| {
+                b.iter(|| decrypt(file, 0))
+            });
+        }
+        group.finish();
+    }
+}
+
+fn buffer_sizes(c: &mut Criterion) {
+    let size = 4 << 20;
+    let data = vec![0x5au8; size];
+    let file = encrypt("ChaCha12", &data, enard::DEFAULT_BUFFER_SIZE);
+    let mut group = c.benchmark_group("buffer_size");
+    group.throughput(Throughput::Bytes(size as u64));
+    for &buffer_size in BUFFER_SIZES {
+        group.bench_with_input(BenchmarkId::new("write", buffer_size), &data, |b, data| {
+            b.iter(|| encrypt("ChaCha12", data, buffer_size))
+        });
+        group.bench_with_input(
+            BenchmarkId::new("readahead", buffer_size),
+            &file,
+            |b, file| b.iter(|| decrypt(file, buffer_size)),
+        );
+    }
+    group.finish();
+}
+
+fn copy(c: &mut Criterion) {
+    let size = 64 << 20;
+    let data = vec![0x5au8; size];
+    let file = encrypt("ChaCha12", &data, enard::DEFAULT_BUFFER_SIZE);
+    let mut group = c.benchmark_group("copy");
+    group.throughput(Throughput::Bytes(size as u64));
+    group.sample_size(10);
+    group.bench_with_input("io::copy", &file, |b, file| b.iter(|| decrypt(file, 0)));
+    group.bench_with_input("copy_to", &file, |b, file| {
+        b.iter(|| {
+            let mut rd = EnardReader::new_boxed(Cursor::new(file), KEY).unwrap();
+            rd.copy_to(&mut io::sink()).unwrap()
+        })
+    });
+    group.bench_with_input("copy_from", &data, |b, data| {
+        b.iter(|| {
+            let mut wr = EnardWriter::builder(Cursor::new(Vec::with_capacity(size + 1024)))
+                .key(KEY)
+                .iv(IV)
+                .build()
+                .unwrap();
+            wr.write_header().unwrap();
+            wr.copy_from(&mut data.as_slice()).unwrap();
+            wr.finish().unwrap()
+        })
+    });
+    group.finish();
+}
+
+/// Read a file from disk with different inner buffer and read-ahead sizes, see
+/// `enard::tuning`.
+fn tuning(c: &mut Criterion) {
+    let size = 16 << 20;
+    let data = vec![0x5au8; size];
+    let path = std::env::temp_dir().join("enard-tuning-bench.enard");
+    std::fs::write(
+        &path,
+        encrypt("ChaCha12", &data, enard::DEFAULT_BUFFER_SIZE),
+    )
+    .unwrap();
+    let mut group = c.benchmark_group("tuning");
+    group.throughput(Throughput::Bytes(size as u64));
+    group.sample_size(10);
+    for &inner_buffer in &[8 << 10, 64 << 10, 1 << 20] {
+        for &readahead in &[0, enard::tuning::READAHEAD_SIZE] {
+            let opts = ReaderOptions {
+                inner_buffer,
+                readahead,
+                ..ReaderOptions::default()
+            };
+            let id = format!("inner={}/readahead={}", inner_buffer, readahead);
+            group.bench_function(id, |b| {
+                b.iter(|| {
+                    let mut rd = EnardReader::open_with_options(&path, KEY, &opts).unwrap();
+                    // Small reads, like a parser would make
+                    let mut buf = [0u8; 4096];
+                    while rd.read(&mut buf).unwrap() > 0 {}
+                })
+            });
+        }
+    }
+    group.finish();
+    let _ = std::fs::remove_file(&path);
+}
+
+criterion_group!(benches, ciphers, buffer_sizes, copy, tuning);
+criterion_main!(benches);
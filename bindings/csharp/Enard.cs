@@ -0,0 +1,205 @@
+// C# bindings for the enard C ABI (`include/enard.h`), see README.md.
+using System;
+using System.IO;
+using System.Runtime.InteropServices;
+using System.Text;
+
+namespace Enard
+{
+    /// <summary>Stable error codes, the same as <c>ErrorCode</c> in enard.h.</summary>
+    public enum ErrorCode : uint
+    {
+        None = 0,
+        IO = 1,
+        InvalidBufferSize = 2,
+        MacError = 3,
+        InvalidLength = 4,
+        UnsupportedEncryption = 5,
+        InvalidMagic = 6,
+        UnsupportedVersion = 7,
+        BlockTooLarge = 8,
+        NotFound = 9,
+        Truncated = 10,
+        MissingMeta = 11,
+        InvalidSignature = 12,
+        ChecksumMismatch = 13,
+        WrongKey = 14,
+        ReservedMeta = 15,
+        InvalidHex = 16,
+        KeyLengthMismatch = 17,
+        IvLengthMismatch = 18,
+        InvalidBase64 = 19,
+        HeaderCorrupt = 20,
+        InvalidArgument = 21,
+        Panic = 22,
+    }
+
+    /// <summary>An error from enard, with its <see cref="ErrorCode"/>.</summary>
+    public class EnardException : IOException
+    {
+        public ErrorCode Code { get; }
+
+        public EnardException(ErrorCode code) : base("enard error: " + code)
+        {
+            Code = code;
+        }
+
+        /// <summary>The error stored by the last failed call on this thread.</summary>
+        internal static EnardException Last()
+        {
+            return new EnardException((ErrorCode)Native.enard_last_error());
+        }
+    }
+
+    internal static class Native
+    {
+#if UNITY_IOS && !UNITY_EDITOR
+        // iOS links the static library into the app
+        private const string Lib = "__Internal";
+#else
+        private const string Lib = "enard";
+#endif
+
+        [DllImport(Lib)]
+        internal static extern uint enard_last_error();
+
+        // Paths are passed as nul-terminated UTF-8, which older Mono can't marshal itself
+        [DllImport(Lib)]
+        internal static extern ReaderHandle enard_reader_open(byte[] path, byte[] key, UIntPtr keyLen);
+
+        [DllImport(Lib)]
+        internal static extern ReaderHandle enard_reader_open_memory(
+            byte[] data, UIntPtr len, byte[] key, UIntPtr keyLen);
+
+        [DllImport(Lib)]
+        internal static extern long enard_reader_data_size(ReaderHandle reader);
+
+        [DllImport(Lib)]
+        internal static extern IntPtr enard_reader_read(ReaderHandle reader, ref byte buf, UIntPtr len);
+
+        [DllImport(Lib)]
+        internal static extern long enard_reader_seek(ReaderHandle reader, long offset, int whence);
+
+        [DllImport(Lib)]
+        internal static extern void enard_reader_close(IntPtr reader);
+    }
+
+    /// <summary>Closes the native reader when it's released.</summary>
+    internal sealed class ReaderHandle : SafeHandle
+    {
+        private ReaderHandle() : base(IntPtr.Zero, true)
+        {
+        }
+
+        public override bool IsInvalid => handle == IntPtr.Zero;
+
+        protected override bool ReleaseHandle()
+        {
+            Native.enard_reader_close(handle);
+            return true;
+        }
+    }
+
+    /// <summary>
+    /// Read-only, seekable stream over the decrypted data of an enard file. The MAC is
+    /// verified when the stream is opened.
+    /// </summary>
+    public sealed class EnardStream : Stream
+    {
+        private readonly ReaderHandle reader;
+        private readonly long length;
+
+        private EnardStream(ReaderHandle reader)
+        {
+            if (reader.IsInvalid)
+            {
+                throw EnardException.Last();
+            }
+            this.reader = reader;
+            length = Check(Native.enard_reader_data_size(reader));
+        }
+
+        /// <summary>Open the enard file at <paramref name="path"/>.</summary>
+        public static EnardStream Open(string path, byte[] key)
+        {
+            byte[] cPath = Encoding.UTF8.GetBytes(path + "\0");
+            return new EnardStream(Native.enard_reader_open(cPath, key, (UIntPtr)key.Length));
+        }
+
+        /// <summary>
+        /// Open an enard file held in memory, e.g. loaded from an Android APK. The data
+        /// is copied, so the array can be reused afterwards.
+        /// </summary>
+        public static EnardStream FromBytes(byte[] data, byte[] key)
+        {
+            return new EnardStream(
+                Native.enard_reader_open_memory(data, (UIntPtr)data.Length, key, (UIntPtr)key.Length));
+        }
+
+        public override bool CanRead => !reader.IsClosed;
+        public override bool CanSeek => !reader.IsClosed;
+        public override bool CanWrite => false;
+        public override long Length => length;
+
+        public override long Position
+        {
+            get => Seek(0, SeekOrigin.Current);
+            set => Seek(value, SeekOrigin.Begin);
+        }
+
+        public override int Read(byte[] buffer, int offset, int count)
+        {
+            if (buffer == null)
+            {
+                throw new ArgumentNullException(nameof(buffer));
+            }
+            if (offset < 0 || count < 0 || offset > buffer.Length - count)
+            {
+                throw new ArgumentOutOfRangeException(nameof(count));
+            }
+            if (count == 0)
+            {
+                return 0;
+            }
+            return (int)Check((long)Native.enard_reader_read(reader, ref buffer[offset], (UIntPtr)count));
+        }
+
+        public override long Seek(long offset, SeekOrigin origin)
+        {
+            // SeekOrigin has the same values as SEEK_SET, SEEK_CUR and SEEK_END
+            return Check(Native.enard_reader_seek(reader, offset, (int)origin));
+        }
+
+        public override void Flush()
+        {
+        }
+
+        public override void SetLength(long value)
+        {
+            throw new NotSupportedException();
+        }
+
+        public override void Write(byte[] buffer, int offset, int count)
+        {
+            throw new NotSupportedException();
+        }
+
+        protected override void Dispose(bool disposing)
+        {
+            if (disposing)
+            {
+                reader.Dispose();
+            }
+            base.Dispose(disposing);
+        }
+
+        private static long Check(long result)
+        {
+            if (result < 0)
+            {
+                throw EnardException.Last();
+            }
+            return result;
+        }
+    }
+}
@@ -1,43 +1,65 @@
 use std::fs::File;
-use std::io::{self, BufRead, Read, Seek, Write};
-use std::path::PathBuf;
+use std::io::{self, Read, Seek, Write};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::{Arc, Mutex};
 
 use anyhow::{anyhow, ensure, Error};
-use clap::Parser;
-use enard::cipher_factory::{CipherFactory, GetFactory};
-use enard::{BoxDynCipher, EnardReader, EnardWriter, MetaMap};
+use clap::{Parser, ValueEnum};
+use enard::ops::{self, ENV_VAR_KEY};
+use enard::patch::Patch;
+use enard::{BoxDynCipher, EnardError, EnardReader, MetaMap};
 use log::{log, trace, Level, LevelFilter};
-use rand::prelude::*;
 
-pub const ENV_VAR_KEY: &'static str = "ENARD_KEY";
+/// Size of the MAC tag at the end of an enard file
+const TAG_SIZE: u64 = enard::mac::TAG_SIZE as u64;
+/// Exit codes for `enard verify`, anything else that goes wrong exits with 1
+const EXIT_NOT_ENARD: i32 = 2;
+const EXIT_TRUNCATED: i32 = 3;
+const EXIT_BAD_MAC: i32 = 4;
+const EXIT_WRONG_VERSION: i32 = 5;
+/// Piped input larger than this is spilled to a temporary file when decrypting
+const STDIN_MEMORY_LIMIT: u64 = 64 << 20;
 
 /// CLI tool for for the enard encryption container format/library.
 /// (https://github.com/bindernews/enard)
 ///
 /// By default the encryption key is passed using the `ENARD_KEY` environment
-/// variable. This may be overriden with either --key or --keyfile. If a key
-/// starts with "0x" it will be decoded as a hex string, otherwise it will
-/// be interpreted as bytes and treated as the key directly.
+/// variable. This may be overriden with either --key or --keyfile. A key starting
+/// with "hex:" or "0x" is decoded as a hex string, and one starting with "base64:"
+/// as base64. Otherwise it's treated as the raw key bytes, so a key file may contain
+/// the binary key directly.
 #[derive(Debug, Parser)]
-#[clap(author, version, about, name = "enard")]
+#[clap(author, version, about, name = "enard", subcommand_negates_reqs = true)]
 struct CliArgs {
-    /// Input file or `-` to read from stdin
-    #[clap(value_parser)]
-    input: String,
+    #[clap(subcommand)]
+    command: Option<Command>,
 
-    /// Output file name or `-` to write to stdout
-    #[clap(value_parser)]
-    output: String,
+    /// Input file and output file, either may be `-` for stdin/stdout
+    ///
+    /// With --out-dir these are all inputs instead, which may be files, directories,
+    /// or glob patterns such as 'assets/**/*.pak' (quote them so the shell doesn't
+    /// expand them).
+    #[clap(value_parser, required = true, value_name = "FILES")]
+    files: Vec<String>,
+
+    /// Process every input and write the outputs to DIR, keeping their paths relative
+    /// to the input directory or the start of the glob pattern
+    #[clap(long, value_parser, value_name = "DIR")]
+    out_dir: Option<PathBuf>,
+
+    /// Number of files to process at once with --out-dir, defaults to the number of CPUs
+    #[clap(short, long, value_parser)]
+    jobs: Option<usize>,
 
     /// Provide the cipher key on the command line (not very safe)
     ///
     /// Normally the key is passed via the environment variable ENARD_KEY
-    #[clap(long, value_parser)]
+    #[clap(long, value_parser, global = true)]
     key: Option<String>,
 
     /// Read the cipher key from a file instead of the environment variable `ENARD_KEY`
-    #[clap(long, value_parser)]
+    #[clap(long, value_parser, global = true)]
     keyfile: Option<PathBuf>,
 
     // /// Don't delete the input file
@@ -52,18 +74,18 @@ struct CliArgs {
 
     /// Decrypt the input
     ///
-    /// When decrypting a file from stdin, the input will be buffered in memory
-    /// because it's necessary to be able to jump around in the file. When
-    /// decrypting large files please store them on disk.
+    /// Decrypting needs to jump around in the file, so when stdin is a pipe rather
+    /// than a redirected file the input is buffered in memory, or in a temporary
+    /// file once it's larger than 64 MiB.
     #[clap(short, long, action)]
     decrypt: bool,
 
     /// Encryption cipher to use
-    #[clap(long, value_enum, action, default_value_t)]
+    #[clap(long, value_enum, action, default_value_t, global = true)]
     cipher: SupportedCiphers,
 
     /// Set the logging level
-    #[clap(long, value_enum, action, default_value_t)]
+    #[clap(long, value_enum, action, default_value_t, global = true)]
     log: ArgLogLevel,
 
     /// Metadata to add when encrypting a file, may be specified multiple times
@@ -71,6 +93,69 @@ struct CliArgs {
     meta: Vec<MetaValue>,
 }
 
+#[derive(Debug, clap::Subcommand)]
+enum Command {
+    /// Create an encrypted patch which turns the enard file OLD into NEW
+    Diff {
+        #[clap(value_parser)]
+        old: PathBuf,
+        #[clap(value_parser)]
+        new: PathBuf,
+        /// Patch file to create
+        #[clap(value_parser)]
+        patch: PathBuf,
+    },
+    /// Apply a patch created with `diff` to the enard file OLD
+    Patch {
+        #[clap(value_parser)]
+        old: PathBuf,
+        #[clap(value_parser)]
+        patch: PathBuf,
+        /// Patched enard file to create, the metadata is copied from OLD
+        #[clap(value_parser)]
+        output: PathBuf,
+    },
+    /// Check that FILE is a valid enard file and print a summary of its header
+    ///
+    /// If a key is given the MAC is verified too, otherwise only the header is checked.
+    /// Exits with 2 if FILE isn't an enard file, 3 if it's truncated, 4 if the MAC
+    /// doesn't match (or the key is wrong), 5 if the format version isn't supported,
+    /// and 1 for any other error.
+    Verify {
+        #[clap(value_parser)]
+        file: PathBuf,
+    },
+    /// Print the metadata stored in the header of FILE, e.g. for shell scripts
+    ///
+    /// If a key is given the MAC is verified first, unless --no-verify is set. Values
+    /// which aren't printable UTF-8 are printed as "hex:" followed by the hex bytes.
+    Meta {
+        #[clap(value_parser)]
+        file: PathBuf,
+        #[clap(long, value_enum, action, default_value_t)]
+        format: MetaFormat,
+        /// Print the metadata without verifying the MAC, even if a key is given
+        #[clap(long, action)]
+        no_verify: bool,
+    },
+    /// Measure how fast each cipher encrypts and decrypts in memory
+    Bench {
+        /// Number of bytes to encrypt with each cipher
+        #[clap(long, value_parser, default_value_t = 64 << 20)]
+        size: usize,
+    },
+    /// Write the canonical test vectors to DIR, one `<name>.enard` file each
+    ///
+    /// With --verify, check the files in DIR (e.g. written by another implementation)
+    /// against the test vectors instead.
+    TestVectors {
+        #[clap(value_parser)]
+        dir: PathBuf,
+        #[clap(long, action)]
+        verify: bool,
+    },
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 struct MetaValue {
     key: String,
@@ -117,6 +202,23 @@ impl From<ArgLogLevel> for LevelFilter {
     }
 }
 
+/// Output format of `enard meta`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, clap::ValueEnum)]
+enum MetaFormat {
+    /// `NAME='value'` lines which can be `eval`ed by a shell, the names are upper-cased
+    /// and anything other than letters and digits replaced with `_`
+    Env,
+    /// A JSON object of names to values
+    Json,
+    /// `name=value` lines
+    Kv,
+}
+impl Default for MetaFormat {
+    fn default() -> Self {
+        Self::Kv
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, clap::ValueEnum)]
 enum SupportedCiphers {
     None,
@@ -144,6 +246,44 @@ fn main() -> Result<(), Error> {
     let args = CliArgs::parse();
     env_logger::builder().filter_level(args.log.into()).init();
 
+    if let Some(command) = &args.command {
+        return match command {
+            Command::Diff { old, new, patch } => {
+                let key = get_encryption_key(&args)?;
+                diff_files(old, new, patch, args.cipher, &key)
+            }
+            Command::Patch { old, patch, output } => {
+                let key = get_encryption_key(&args)?;
+                patch_file(old, patch, output, args.cipher, &key)
+            }
+            Command::Verify { file } => {
+                let key = if has_encryption_key(&args) {
+                    Some(get_encryption_key(&args)?)
+                } else {
+                    None
+                };
+                if let Err(e) = verify_file(file, key.as_deref()) {
+                    log!(Level::Error, "{}: {}", file.display(), e);
+                    std::process::exit(verify_exit_code(&e));
+                }
+                Ok(())
+            }
+            Command::Meta {
+                file,
+                format,
+                no_verify,
+            } => {
+                let key = if has_encryption_key(&args) && !no_verify {
+                    Some(get_encryption_key(&args)?)
+                } else {
+                    None
+                };
+                print_meta(file, key.as_deref(), *format)
+            }
+            Command::Bench { size } => bench_ciphers(*size),
+            Command::TestVectors { dir, verify } => test_vectors(dir, *verify),
+        };
+    }
     if args.decrypt == args.encrypt {
         log!(Level::Error, "must specify either --encrypt or --decrypt");
         return Err(Error::msg(""));
@@ -151,30 +291,42 @@ fn main() -> Result<(), Error> {
 
     let key = get_encryption_key(&args)?;
 
+    if let Some(out_dir) = &args.out_dir {
+        return batch_files(&args, out_dir, key);
+    }
+    let (input, output) = match args.files.as_slice() {
+        [input, output] => (input.clone(), output.clone()),
+        _ => {
+            return Err(anyhow!(
+                "expected an input and an output file, use --out-dir for more than one input"
+            ))
+        }
+    };
+
     if args.encrypt {
         trace!("beginning encrypt");
-        let input: Box<dyn Read> = if args.input == "-" {
+        let input: Box<dyn Read> = if input == "-" {
             trace!("locking stdin for reading");
             Box::new(io::stdin().lock())
         } else {
-            Box::new(File::open(&args.input)?)
+            Box::new(File::open(&input)?)
         };
 
-        let write_stdout = args.output == "-";
+        let write_stdout = output == "-";
         let mut output = if write_stdout {
             trace!("creating temporary output file");
             tempfile::tempfile()?
         } else {
-            File::create(&args.output)?
+            File::create(&output)?
         };
 
-        trace!("building metadata map");
-        let mut meta_map = MetaMap::new();
-        for m in args.meta {
-            meta_map.insert(m.key.into_bytes(), m.value.into_bytes());
-        }
-
-        encrypt_file(input, &mut output, args.cipher, &key, meta_map)?;
+        ops::encrypt(
+            input,
+            &mut output,
+            args.cipher.name_bytes(),
+            &key,
+            meta_map(&args),
+        )?;
         // If we wrote to a temp-file, write that file back out to stdout
         if write_stdout {
             trace!("writing temporary file to stdout");
@@ -184,105 +336,430 @@ fn main() -> Result<(), Error> {
     }
     if args.decrypt {
         trace!("beginning decrypt");
-        let output: Box<dyn Write> = if args.output == "-" {
+        let output: Box<dyn Write> = if output == "-" {
             trace!("locking stdout");
             Box::new(io::stdout().lock())
         } else {
-            Box::new(File::create(&args.output)?)
+            Box::new(File::create(&output)?)
         };
 
-        if args.input == "-" {
-            trace!("buffering stdin to memory");
-            // If we're reading from stdin we have to buffer
-            let mut buf = Vec::new();
-            io::stdin().lock().read_to_end(&mut buf)?;
-            let input = io::Cursor::new(buf);
-            decrypt_file(input, output, &key)?;
+        if input == "-" {
+            match seekable_stdin()? {
+                SeekableStdin::Memory(input) => ops::decrypt(input, output, &key)?,
+                SeekableStdin::File(file) => ops::decrypt(io::BufReader::new(file), output, &key)?,
+            };
         } else {
-            let input = io::BufReader::new(File::open(&args.input)?);
-            decrypt_file(input, output, &key)?;
+            let input = io::BufReader::new(File::open(&input)?);
+            ops::decrypt(input, output, &key)?;
         }
     }
 
     Ok(())
 }
 
+/// Stdin in a form that can be decrypted, see [`seekable_stdin`].
+enum SeekableStdin {
+    Memory(io::Cursor<Vec<u8>>),
+    File(File),
+}
+
+/// Make stdin seekable, using it directly if it's a redirected file and buffering it
+/// otherwise. Buffers larger than [`STDIN_MEMORY_LIMIT`] go to a temporary file.
+fn seekable_stdin() -> Result<SeekableStdin, Error> {
+    if let Some(file) = stdin_file()? {
+        trace!("stdin is a file, reading it directly");
+        return Ok(SeekableStdin::File(file));
+    }
+    trace!("buffering stdin to memory");
+    let mut stdin = io::stdin().lock();
+    let mut buf = Vec::new();
+    (&mut stdin)
+        .take(STDIN_MEMORY_LIMIT + 1)
+        .read_to_end(&mut buf)?;
+    if buf.len() as u64 <= STDIN_MEMORY_LIMIT {
+        return Ok(SeekableStdin::Memory(io::Cursor::new(buf)));
+    }
+    trace!("stdin is too large for memory, buffering to a temporary file");
+    let mut file = tempfile::tempfile()?;
+    file.write_all(&buf)?;
+    drop(buf);
+    io::copy(&mut stdin, &mut file)?;
+    file.rewind()?;
+    Ok(SeekableStdin::File(file))
+}
+
+/// Returns a duplicate handle to stdin if it's a regular file, which can be seeked.
+#[cfg(unix)]
+fn stdin_file() -> io::Result<Option<File>> {
+    use std::mem::ManuallyDrop;
+    use std::os::unix::io::{AsRawFd, FromRawFd};
+    // Borrow the descriptor without closing it when done
+    let stdin = ManuallyDrop::new(unsafe { File::from_raw_fd(io::stdin().as_raw_fd()) });
+    regular_file(&stdin)
+}
+
+/// Returns a duplicate handle to stdin if it's a regular file, which can be seeked.
+#[cfg(windows)]
+fn stdin_file() -> io::Result<Option<File>> {
+    use std::mem::ManuallyDrop;
+    use std::os::windows::io::{AsRawHandle, FromRawHandle};
+    // Borrow the handle without closing it when done
+    let stdin = ManuallyDrop::new(unsafe { File::from_raw_handle(io::stdin().as_raw_handle()) });
+    regular_file(&stdin)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn stdin_file() -> io::Result<Option<File>> {
+    Ok(None)
+}
+
+#[cfg(any(unix, windows))]
+fn regular_file(file: &File) -> io::Result<Option<File>> {
+    // A closed or otherwise unusable stdin is read the normal way, which reports the error
+    match file.metadata() {
+        Ok(meta) if meta.is_file() => file.try_clone().map(Some),
+        _ => Ok(None),
+    }
+}
+
+/// Returns `true` if a key was given in any of the ways [`get_encryption_key`] checks.
+fn has_encryption_key(args: &CliArgs) -> bool {
+    args.keyfile.is_some() || args.key.is_some() || std::env::var_os(ENV_VAR_KEY).is_some()
+}
+
 fn get_encryption_key(args: &CliArgs) -> Result<Vec<u8>, Error> {
-    if let Some(keyfile) = &args.keyfile {
-        trace!("encryption key from file");
-        let mut file = File::open(&keyfile).map_err(|e| {
-            Error::from(e).context(format!("file not found {}", keyfile.to_string_lossy()))
-        })?;
-        let mut key_buf = Vec::new();
-        file.read_to_end(&mut key_buf)?;
-        key_to_buf(&key_buf)
-    } else if let Some(cli_key) = &args.key {
-        trace!("encryption key from cli");
-        key_to_buf(cli_key.as_bytes())
-    } else if let Ok(env_key) = std::env::var(ENV_VAR_KEY) {
-        trace!("encryption key from environment variable");
-        key_to_buf(env_key.as_bytes())
+    let key = ops::load_key(args.keyfile.as_deref(), args.key.as_deref()).map_err(|e| {
+        Error::from(e).context("failed to load the key, use --key, --keyfile, or set ENARD_KEY")
+    })?;
+    Ok(key.as_bytes().to_vec())
+}
+
+fn meta_map(args: &CliArgs) -> MetaMap {
+    trace!("building metadata map");
+    let mut meta_map = MetaMap::new();
+    for m in args.meta.iter() {
+        meta_map.insert(m.key.clone().into_bytes(), m.value.clone().into_bytes());
+    }
+    meta_map
+}
+
+fn open_enard(
+    path: &Path,
+    key: &[u8],
+) -> Result<EnardReader<io::BufReader<File>, BoxDynCipher>, Error> {
+    let file = File::open(path)
+        .map_err(|e| Error::from(e).context(format!("failed to open {}", path.display())))?;
+    Ok(EnardReader::new_boxed(io::BufReader::new(file), key)?)
+}
+
+fn diff_files(
+    old: &Path,
+    new: &Path,
+    patch_path: &Path,
+    cipher_kind: SupportedCiphers,
+    key: &[u8],
+) -> Result<(), Error> {
+    trace!("diffing {} and {}", old.display(), new.display());
+    let patch = enard::patch::diff(open_enard(old, key)?, open_enard(new, key)?)?;
+    let mut encoded = Vec::new();
+    patch.encode(&mut encoded)?;
+    let output = File::create(patch_path)?;
+    ops::encrypt(
+        encoded.as_slice(),
+        output,
+        cipher_kind.name_bytes(),
+        key,
+        MetaMap::new(),
+    )?;
+    Ok(())
+}
+
+fn patch_file(
+    old: &Path,
+    patch_path: &Path,
+    output: &Path,
+    cipher_kind: SupportedCiphers,
+    key: &[u8],
+) -> Result<(), Error> {
+    trace!("applying {} to {}", patch_path.display(), old.display());
+    let old = open_enard(old, key)?;
+    let meta = old.meta().clone();
+    let patch = Patch::decode(io::BufReader::new(open_enard(patch_path, key)?))?;
+    // Decrypt to a temporary file first so a bad patch doesn't leave a broken output
+    let mut plain = tempfile::tempfile()?;
+    enard::patch::apply(old, &patch, &mut plain)?;
+    plain.rewind()?;
+    let output = File::create(output)?;
+    let plain = io::BufReader::new(plain);
+    ops::encrypt(plain, output, cipher_kind.name_bytes(), key, meta)?;
+    Ok(())
+}
+
+fn verify_file(path: &Path, key: Option<&[u8]>) -> Result<(), EnardError> {
+    trace!("verifying {}", path.display());
+    let file = io::BufReader::new(File::open(path)?);
+    let (version, header_size, data_size, cipher, meta_len) = if let Some(key) = key {
+        let report = enard::verify(file, key)?;
+        let meta_len = report.meta.len() as u64;
+        let cipher = String::from_utf8_lossy(&report.cipher).into_owned();
+        (
+            report.version,
+            report.header_size,
+            report.data_size,
+            cipher,
+            meta_len,
+        )
     } else {
-        Err(anyhow!(
-            "no secret key supplied, either use --key, --keyfile, or set environment variable {}",
-            ENV_VAR_KEY,
-        ))
+        let buf = read_header_bytes(file)?;
+        let header = enard::format::parse_header(&buf)?;
+        // Without the key the MAC can't be checked, but the file can be long enough
+        let expected = header.data_start + header.data_size + TAG_SIZE;
+        let actual = std::fs::metadata(path)?.len();
+        if actual < expected {
+            return Err(EnardError::Truncated { expected, actual });
+        }
+        let cipher = String::from_utf8_lossy(header.cipher).into_owned();
+        (
+            header.version,
+            header.header_size,
+            header.data_size,
+            cipher,
+            header.meta_len(),
+        )
+    };
+    println!("file:        {}", path.display());
+    println!("version:     {:?}", version);
+    println!("header size: {}", header_size);
+    println!("data size:   {}", data_size);
+    println!("cipher:      {}", cipher);
+    println!("metadata:    {} entries", meta_len);
+    println!(
+        "mac:         {}",
+        if key.is_some() {
+            "ok"
+        } else {
+            "not checked (no key)"
+        }
+    );
+    Ok(())
+}
+
+/// Read the start of an enard file, up to the end of the header.
+fn read_header_bytes<R: Read>(mut file: R) -> Result<Vec<u8>, EnardError> {
+    let mut buf = Vec::new();
+    (&mut file).take(4096).read_to_end(&mut buf)?;
+    if let Err(EnardError::Truncated { expected, .. }) = enard::format::parse_header(&buf) {
+        let more = expected - buf.len() as u64;
+        file.take(more).read_to_end(&mut buf)?;
+    }
+    Ok(buf)
+}
+
+fn verify_exit_code(e: &EnardError) -> i32 {
+    match e.root_cause() {
+        EnardError::InvalidMagic { .. } => EXIT_NOT_ENARD,
+        EnardError::Truncated { .. } => EXIT_TRUNCATED,
+        EnardError::IO(e) if e.kind() == io::ErrorKind::UnexpectedEof => EXIT_TRUNCATED,
+        EnardError::MacError(_) | EnardError::WrongKey => EXIT_BAD_MAC,
+        EnardError::UnsupportedVersion { .. } => EXIT_WRONG_VERSION,
+        _ => 1,
+    }
+}
+
+fn print_meta(path: &Path, key: Option<&[u8]>, format: MetaFormat) -> Result<(), Error> {
+    trace!("reading metadata of {}", path.display());
+    let file = io::BufReader::new(File::open(path)?);
+    let meta = if let Some(key) = key {
+        enard::verify(file, key)?.meta
+    } else {
+        let buf = read_header_bytes(file)?;
+        enard::format::parse_header(&buf)?.to_info().meta
+    };
+    let mut out = io::stdout().lock();
+    match format {
+        MetaFormat::Env => {
+            for (name, value) in meta.iter() {
+                let value = meta_text(value).replace('\'', "'\\''");
+                writeln!(out, "{}='{}'", env_name(name), value)?;
+            }
+        }
+        MetaFormat::Json => {
+            let json_str = |s: &[u8]| meta_text(s).replace('\\', "\\\\").replace('"', "\\\"");
+            write!(out, "{{")?;
+            for (i, (name, value)) in meta.iter().enumerate() {
+                let sep = if i == 0 { "" } else { "," };
+                write!(
+                    out,
+                    "{}\n  \"{}\": \"{}\"",
+                    sep,
+                    json_str(name),
+                    json_str(value)
+                )?;
+            }
+            writeln!(out, "{}}}", if meta.is_empty() { "" } else { "\n" })?;
+        }
+        MetaFormat::Kv => {
+            for (name, value) in meta.iter() {
+                writeln!(out, "{}={}", meta_text(name), meta_text(value))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Metadata as text for printing, as "hex:" and the hex bytes unless it's UTF-8
+/// without control characters.
+fn meta_text(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(s) if !s.chars().any(char::is_control) => s.to_string(),
+        _ => {
+            let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+            format!("hex:{}", hex)
+        }
     }
 }
 
-fn key_to_buf(key: &[u8]) -> Result<Vec<u8>, Error> {
-    if key.starts_with(b"0x") {
-        ensure!(
-            key.len() % 2 == 0,
-            "hex string length must be a multiple of 2"
+/// Turn a metadata name into an environment variable name, e.g. `enard.content-type`
+/// becomes `ENARD_CONTENT_TYPE`.
+fn env_name(name: &[u8]) -> String {
+    let mut env: String = name
+        .iter()
+        .map(|&b| {
+            if b.is_ascii_alphanumeric() {
+                b.to_ascii_uppercase() as char
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if !env.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        env.insert(0, '_');
+    }
+    env
+}
+
+fn bench_ciphers(size: usize) -> Result<(), Error> {
+    println!(
+        "{:<10} {:>14} {:>14}",
+        "cipher", "encrypt MB/s", "decrypt MB/s"
+    );
+    for cipher in SupportedCiphers::value_variants() {
+        let result = enard::bench::measure_throughput(cipher.name_bytes(), size)?;
+        let name = String::from_utf8_lossy(cipher.name_bytes());
+        println!(
+            "{:<10} {:>14.1} {:>14.1}",
+            if name.is_empty() { "none" } else { &name },
+            result.write_bytes_per_sec() / 1e6,
+            result.read_bytes_per_sec() / 1e6,
         );
-        let mut buf = Vec::new();
-        for i in (2..key.len()).step_by(2) {
-            let n0 = nibble(key[i])?;
-            let n1 = nibble(key[i + 1])?;
-            buf.push(n0 | (n1 << 4));
+    }
+    Ok(())
+}
+
+fn test_vectors(dir: &Path, verify: bool) -> Result<(), Error> {
+    let mut vectors = enard::testvec::generate()?;
+    if !verify {
+        std::fs::create_dir_all(dir)?;
+    }
+    for vector in vectors.iter_mut() {
+        let path = dir.join(format!("{}.enard", vector.name));
+        if verify {
+            vector.file = std::fs::read(&path)?;
+        } else {
+            std::fs::write(&path, &vector.file)?;
         }
-        Ok(buf)
-    } else {
-        Ok(Vec::from(key))
+        trace!("{}", path.display());
     }
+    if verify {
+        enard::testvec::verify_test_vectors(&vectors)?;
+        println!("{} test vectors match", vectors.len());
+    }
+    Ok(())
 }
 
-/// Returns a nibble from a hex character
-fn nibble(b: u8) -> Result<u8, Error> {
-    (b as char)
-        .to_digit(16)
-        .map(|v| v as u8)
-        .ok_or_else(|| Error::msg("character is not a hex digit"))
+/// Encrypt or decrypt every file matching the inputs into `out_dir`.
+fn batch_files(args: &CliArgs, out_dir: &Path, key: Vec<u8>) -> Result<(), Error> {
+    let mut jobs = Vec::new();
+    for (path, relative) in collect_inputs(&args.files)? {
+        jobs.push((path, out_dir.join(relative)));
+    }
+    let threads = args
+        .jobs
+        .or_else(|| std::thread::available_parallelism().ok().map(|n| n.get()))
+        .unwrap_or(1)
+        .clamp(1, jobs.len().max(1));
+    trace!("processing {} files with {} threads", jobs.len(), threads);
+
+    let queue = Arc::new(Mutex::new(jobs.into_iter()));
+    let key = Arc::new(key);
+    let meta = Arc::new(meta_map(args));
+    let (encrypt, cipher) = (args.encrypt, args.cipher);
+    let workers: Vec<_> = (0..threads)
+        .map(|_| {
+            let (queue, key, meta) = (queue.clone(), key.clone(), meta.clone());
+            std::thread::spawn(move || {
+                let mut failed = 0;
+                loop {
+                    let next = queue.lock().unwrap().next();
+                    let (input, output) = match next {
+                        Some(job) => job,
+                        None => return failed,
+                    };
+                    trace!("{} -> {}", input.display(), output.display());
+                    let result = if encrypt {
+                        let meta = (*meta).clone();
+                        ops::encrypt_file(&input, &output, cipher.name_bytes(), &*key, meta)
+                    } else {
+                        ops::decrypt_file(&input, &output, &*key)
+                    };
+                    if let Err(e) = result {
+                        log!(Level::Error, "{}: {}", input.display(), e);
+                        failed += 1;
+                    }
+                }
+            })
+        })
+        .collect();
+    let failed: usize = workers.into_iter().map(|w| w.join().unwrap_or(1)).sum();
+    ensure!(failed == 0, "{} files failed", failed);
+    Ok(())
 }
 
-fn encrypt_file<R: Read, W: Write + Seek>(
-    input: R,
-    output: W,
-    cipher_kind: SupportedCiphers,
-    key: &[u8],
-    meta: MetaMap,
-) -> Result<u64, Error> {
-    // Get the meta for the selected cipher type and generate an IV
-    let factory = BoxDynCipher::factory();
-    let c_meta = factory.get_meta(cipher_kind.name_bytes())?;
-    if key.len() != c_meta.key_size {
-        return Err(anyhow!(
-            "key length is {} bytes, should be {}",
-            key.len(),
-            c_meta.key_size
-        ));
+/// Expand the inputs into a list of files and their paths relative to the directory
+/// or glob pattern they were found through.
+fn collect_inputs(inputs: &[String]) -> Result<Vec<(PathBuf, PathBuf)>, Error> {
+    let mut files = Vec::new();
+    for input in inputs {
+        let path = Path::new(input);
+        let (pattern, base) = if path.is_file() {
+            let name = path
+                .file_name()
+                .ok_or_else(|| anyhow!("{} has no file name", input))?;
+            files.push((path.to_path_buf(), PathBuf::from(name)));
+            continue;
+        } else if path.is_dir() {
+            let pattern = format!("{}/**/*", glob::Pattern::escape(input));
+            (pattern, path.to_path_buf())
+        } else {
+            (input.clone(), glob_base(path))
+        };
+        let before = files.len();
+        for entry in glob::glob(&pattern)? {
+            let entry = entry?;
+            if entry.is_file() {
+                let relative = entry.strip_prefix(&base).unwrap_or(&entry).to_path_buf();
+                files.push((entry, relative));
+            }
+        }
+        ensure!(files.len() > before, "no files found for {}", input);
     }
-    let iv = c_meta.generate_iv(&mut StdRng::from_entropy());
-    let mut wr = EnardWriter::new(output, factory, cipher_kind.name_bytes(), key, &iv, meta)?;
-    Ok(wr.write_complete(input)?)
+    Ok(files)
 }
 
-fn decrypt_file<R: BufRead + Seek, W: Write>(
-    input: R,
-    mut output: W,
-    key: &[u8],
-) -> Result<u64, Error> {
-    let mut rd = EnardReader::new_boxed(input, key)?;
-    Ok(io::copy(&mut rd, &mut output)?)
+/// Returns the part of a glob pattern before the first component with a wildcard.
+fn glob_base(pattern: &Path) -> PathBuf {
+    pattern
+        .components()
+        .take_while(|c| !c.as_os_str().to_string_lossy().contains(['*', '?', '[']))
+        .collect()
 }
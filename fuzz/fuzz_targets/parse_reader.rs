@@ -0,0 +1,30 @@
+//! Feed arbitrary bytes to the header parser, run with
+//! `cargo fuzz run parse_reader fuzz/corpus/parse_reader fuzz/seeds/parse_reader`.
+//!
+//! `fuzz/seeds/parse_reader` holds valid files for every cipher and version, which use
+//! the same key as below. They're written by `enard test-vectors fuzz/seeds/parse_reader`.
+#![no_main]
+use std::io::{Cursor, Read};
+
+use enard::cipher_factory::GetFactory;
+use enard::limits::ParseLimits;
+use enard::{BoxDynCipher, EnardReader};
+use libfuzzer_sys::fuzz_target;
+
+const KEY: [u8; 32] = [0x42; 32];
+
+fuzz_target!(|data: &[u8]| {
+    let _ = enard::format::parse_header(data);
+    let limits = ParseLimits {
+        max_header_size: 1 << 16,
+        max_meta_entries: 256,
+        max_meta_value_size: 1 << 12,
+        max_data_size: 1 << 20,
+    };
+    let reader =
+        EnardReader::new_with_limits(Cursor::new(data), BoxDynCipher::factory(), KEY, limits);
+    if let Ok(mut reader) = reader {
+        let mut buf = Vec::new();
+        let _ = reader.read_to_end(&mut buf);
+    }
+});
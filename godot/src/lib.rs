@@ -0,0 +1,231 @@
+//! Godot 4 GDExtension for reading enard files.
+//!
+//! Registers the `EnardFile` class, which reads the decrypted data of an enard file with
+//! an API modelled on Godot's `FileAccess`. Files are read through `FileAccess`, so
+//! `res://` paths inside an exported pack work as well as `user://` and absolute paths.
+//!
+//! ```gdscript
+//! var file := EnardFile.new()
+//! if file.open("res://data/level1.enard", key) != OK:
+//!     push_error("can't open level1: enard error %d" % file.get_error_code())
+//!     return
+//! var text := file.get_as_text()
+//! ```
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom};
+
+use enard::stream::ReadSeek;
+use enard::{BoxDynCipher, EnardError, EnardReader, ErrorCode};
+use godot::classes::file_access::ModeFlags;
+use godot::classes::FileAccess;
+use godot::global::Error;
+use godot::prelude::*;
+
+struct EnardExtension;
+
+#[gdextension]
+unsafe impl ExtensionLibrary for EnardExtension {}
+
+/// Adapts a Godot `FileAccess` to [`Read`] and [`Seek`].
+struct GodotFile(Gd<FileAccess>);
+impl Read for GodotFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let data = self.0.get_buffer(buf.len() as i64);
+        let data = data.as_slice();
+        if data.is_empty() && !buf.is_empty() && !self.0.eof_reached() {
+            let msg = format!("FileAccess error {:?}", self.0.get_error());
+            return Err(io::Error::new(ErrorKind::Other, msg));
+        }
+        buf[..data.len()].copy_from_slice(data);
+        Ok(data.len())
+    }
+}
+impl Seek for GodotFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match pos {
+            SeekFrom::Start(pos) => self.0.seek(pos),
+            SeekFrom::End(offset) => self.0.seek_end_ex().position(offset).done(),
+            SeekFrom::Current(offset) => {
+                let pos = self.0.get_position() as i64 + offset;
+                if pos < 0 {
+                    let msg = "seek before the start of the file";
+                    return Err(io::Error::new(ErrorKind::InvalidInput, msg));
+                }
+                self.0.seek(pos as u64)
+            }
+        }
+        Ok(self.0.get_position())
+    }
+}
+
+/// Reads the decrypted data of an enard file, see the module docs.
+///
+/// The MAC is verified when the file is opened, so a file which opened successfully
+/// hasn't been modified.
+#[derive(GodotClass)]
+#[class(base = RefCounted, init)]
+pub struct EnardFile {
+    reader: Option<EnardReader<Box<dyn ReadSeek>, BoxDynCipher>>,
+    /// Enard error code of the last error, 0 if there hasn't been one
+    error_code: u32,
+    eof: bool,
+}
+
+#[godot_api]
+impl EnardFile {
+    /// Open the enard file at `path` with `key`, closing any file which is already open.
+    #[func]
+    fn open(&mut self, path: GString, key: PackedByteArray) -> Error {
+        let file = match FileAccess::open(&path, ModeFlags::READ) {
+            Some(file) => file,
+            None => return FileAccess::get_open_error(),
+        };
+        self.open_reader(Box::new(GodotFile(file)), key)
+    }
+
+    /// Open an enard file held in memory with `key`, closing any file which is already
+    /// open.
+    #[func]
+    fn open_buffer(&mut self, data: PackedByteArray, key: PackedByteArray) -> Error {
+        let data = io::Cursor::new(data.to_vec());
+        self.open_reader(Box::new(data), key)
+    }
+
+    /// Read up to `length` bytes. Fewer are returned at the end of the data.
+    #[func]
+    fn get_buffer(&mut self, length: i64) -> PackedByteArray {
+        let mut buf = vec![0u8; length.max(0) as usize];
+        let n = self.read_fully(&mut buf);
+        PackedByteArray::from(&buf[..n])
+    }
+
+    /// Read the rest of the data as UTF-8 text.
+    #[func]
+    fn get_as_text(&mut self) -> GString {
+        let mut data = Vec::new();
+        if let Some(reader) = self.reader.as_mut() {
+            if let Err(e) = reader.read_to_end(&mut data) {
+                self.error_code = EnardError::from(e).code();
+            }
+        }
+        self.eof = true;
+        GString::from(String::from_utf8_lossy(&data).as_ref())
+    }
+
+    /// Move to `position` bytes from the start of the data.
+    #[func]
+    fn seek(&mut self, position: u64) {
+        self.seek_to(SeekFrom::Start(position));
+    }
+
+    /// Move to `position` bytes from the end of the data, `position` should be negative
+    /// or 0.
+    #[func]
+    fn seek_end(&mut self, position: i64) {
+        self.seek_to(SeekFrom::End(position));
+    }
+
+    #[func]
+    fn get_position(&mut self) -> u64 {
+        match self.reader.as_mut() {
+            Some(reader) => reader.stream_position().unwrap_or(0),
+            None => 0,
+        }
+    }
+
+    /// Size of the decrypted data in bytes.
+    #[func]
+    fn get_length(&self) -> u64 {
+        self.reader.as_ref().map_or(0, |reader| reader.data_size())
+    }
+
+    /// Returns `true` once a read has reached the end of the data.
+    #[func]
+    fn eof_reached(&self) -> bool {
+        self.eof
+    }
+
+    #[func]
+    fn is_open(&self) -> bool {
+        self.reader.is_some()
+    }
+
+    #[func]
+    fn close(&mut self) {
+        self.reader = None;
+        self.eof = false;
+    }
+
+    /// Value of the metadata entry `name`, or an empty array if there isn't one.
+    #[func]
+    fn get_metadata(&self, name: GString) -> PackedByteArray {
+        let name = name.to_string();
+        self.reader
+            .as_ref()
+            .and_then(|reader| reader.meta().get(name.as_bytes()))
+            .map(|value| PackedByteArray::from(value.as_slice()))
+            .unwrap_or_default()
+    }
+
+    /// Enard error code of the last error (see `ErrorCode` in enard.h), or 0 if there
+    /// hasn't been one.
+    #[func]
+    fn get_error_code(&self) -> u32 {
+        self.error_code
+    }
+}
+
+impl EnardFile {
+    fn open_reader(&mut self, inner: Box<dyn ReadSeek>, key: PackedByteArray) -> Error {
+        self.close();
+        match EnardReader::new_dyn(inner, key.as_slice()) {
+            Ok(reader) => {
+                self.reader = Some(reader);
+                self.error_code = 0;
+                Error::OK
+            }
+            Err(e) => {
+                self.error_code = e.code();
+                match e.error_code() {
+                    ErrorCode::IO => Error::ERR_FILE_CANT_READ,
+                    ErrorCode::WrongKey | ErrorCode::KeyLengthMismatch | ErrorCode::MacError => {
+                        Error::ERR_UNAUTHORIZED
+                    }
+                    _ => Error::ERR_FILE_CORRUPT,
+                }
+            }
+        }
+    }
+
+    /// Fill as much of `buf` as possible, returning the number of bytes read.
+    fn read_fully(&mut self, buf: &mut [u8]) -> usize {
+        let reader = match self.reader.as_mut() {
+            Some(reader) => reader,
+            None => return 0,
+        };
+        let mut total = 0;
+        while total < buf.len() {
+            match reader.read(&mut buf[total..]) {
+                Ok(0) => {
+                    self.eof = true;
+                    break;
+                }
+                Ok(n) => total += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => {
+                    self.error_code = EnardError::from(e).code();
+                    break;
+                }
+            }
+        }
+        total
+    }
+
+    fn seek_to(&mut self, pos: SeekFrom) {
+        if let Some(reader) = self.reader.as_mut() {
+            match reader.seek(pos) {
+                Ok(_) => self.eof = false,
+                Err(e) => self.error_code = EnardError::from(e).code(),
+            }
+        }
+    }
+}
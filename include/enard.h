@@ -0,0 +1,100 @@
+#ifndef ENARD_H
+#define ENARD_H
+
+/* Generated with cbindgen, do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Stable numeric codes for each [`EnardError`] variant, primarily for crossing
+// an FFI boundary, plus a few only used by [`crate::ffi`]. `0` is never used so it can
+// represent success.
+//
+// Codes are never re-used, new variants will always get new codes.
+enum ErrorCode {
+  ERROR_CODE_IO = 1,
+  ERROR_CODE_INVALID_BUFFER_SIZE = 2,
+  ERROR_CODE_MAC_ERROR = 3,
+  ERROR_CODE_INVALID_LENGTH = 4,
+  ERROR_CODE_UNSUPPORTED_ENCRYPTION = 5,
+  ERROR_CODE_INVALID_MAGIC = 6,
+  ERROR_CODE_UNSUPPORTED_VERSION = 7,
+  ERROR_CODE_BLOCK_TOO_LARGE = 8,
+  ERROR_CODE_NOT_FOUND = 9,
+  ERROR_CODE_TRUNCATED = 10,
+  ERROR_CODE_MISSING_META = 11,
+  ERROR_CODE_INVALID_SIGNATURE = 12,
+  ERROR_CODE_CHECKSUM_MISMATCH = 13,
+  ERROR_CODE_WRONG_KEY = 14,
+  ERROR_CODE_RESERVED_META = 15,
+  ERROR_CODE_INVALID_HEX = 16,
+  ERROR_CODE_KEY_LENGTH_MISMATCH = 17,
+  ERROR_CODE_IV_LENGTH_MISMATCH = 18,
+  ERROR_CODE_INVALID_BASE64 = 19,
+  ERROR_CODE_HEADER_CORRUPT = 20,
+  // An argument to a C API function was invalid, such as a `NULL` handle
+  ERROR_CODE_INVALID_ARGUMENT = 21,
+  // A C API function panicked, the handle it was given shouldn't be used again
+  ERROR_CODE_PANIC = 22,
+};
+typedef uint32_t ErrorCode;
+
+// Opaque reader handle
+typedef struct EnardReaderHandle EnardReaderHandle;
+
+// Opaque writer handle
+typedef struct EnardWriterHandle EnardWriterHandle;
+
+// Returns the [`ErrorCode`] of the last error on this thread, or 0 if there hasn't
+// been one.
+uint32_t enard_last_error(void);
+
+// Open the enard file at `path` for reading. Returns `NULL` on error.
+EnardReaderHandle *enard_reader_open(const char *path, const uint8_t *key, size_t key_len);
+
+// Open an enard file held in memory, e.g. an asset which isn't a separate file on
+// the platform. The data is copied, so `data` can be freed afterwards. Returns `NULL`
+// on error.
+EnardReaderHandle *enard_reader_open_memory(const uint8_t *data,
+                                            size_t len,
+                                            const uint8_t *key,
+                                            size_t key_len);
+
+// Returns the size of the decrypted data in bytes, or -1 on error.
+int64_t enard_reader_data_size(const EnardReaderHandle *reader);
+
+// Read up to `len` decrypted bytes into `buf`. Returns the number of bytes read,
+// 0 at the end of the data, or -1 on error.
+ptrdiff_t enard_reader_read(EnardReaderHandle *reader, uint8_t *buf, size_t len);
+
+// Seek within the decrypted data. `whence` is one of `SEEK_SET` (0), `SEEK_CUR` (1) or
+// `SEEK_END` (2). Returns the new position, or -1 on error.
+int64_t enard_reader_seek(EnardReaderHandle *reader, int64_t offset, int whence);
+
+// Close a reader. Passing `NULL` does nothing.
+void enard_reader_close(EnardReaderHandle *reader);
+
+// Create a new enard file at `path` encrypted with the named cipher,
+// and write the header. Returns `NULL` on error.
+EnardWriterHandle *enard_writer_create(const char *path,
+                                       const char *cipher,
+                                       const uint8_t *key,
+                                       size_t key_len,
+                                       const uint8_t *iv,
+                                       size_t iv_len);
+
+// Encrypt and write `len` bytes from `buf`. Returns 0 on success or -1 on error.
+int enard_writer_write(EnardWriterHandle *writer, const uint8_t *buf, size_t len);
+
+// Finish writing the file and close the writer. Returns 0 on success or -1 on error.
+// The writer is closed even if an error occurs.
+int enard_writer_finish(EnardWriterHandle *writer);
+
+// Close the writer without finishing it, the resulting file will be invalid.
+// Passing `NULL` does nothing.
+void enard_writer_close(EnardWriterHandle *writer);
+
+#endif /* ENARD_H */
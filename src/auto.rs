@@ -0,0 +1,84 @@
+//! Read files which may or may not be encrypted.
+//!
+//! Development builds often use plain assets while shipping builds encrypt them.
+//! [`open_auto`] checks for the enard magic and returns an [`AutoReader`] which either
+//! decrypts the file or passes it through unchanged, so both builds share one code path.
+//!
+//! ```rust
+//! use enard::auto::{open_auto, AutoReader};
+//! use std::io::{Cursor, Read};
+//! # fn main() -> Result<(), enard::EnardError> {
+//! let mut asset = open_auto(Cursor::new(b"plain text".to_vec()), [0x42u8; 32])?;
+//! assert!(!asset.is_encrypted());
+//! let mut text = String::new();
+//! asset.read_to_string(&mut text)?;
+//! assert_eq!(text, "plain text");
+//! # Ok(())
+//! # }
+//! ```
+use std::io::{self, IoSliceMut, Read, Seek, SeekFrom};
+
+use crate::{BoxDynCipher, EnardError, EnardReader, KeyProvider};
+
+/// Either a decrypting [`EnardReader`] or the plain reader it would have wrapped.
+#[derive(Debug)]
+pub enum AutoReader<R: Read + Seek> {
+    Enard(EnardReader<R, BoxDynCipher>),
+    Plain(R),
+}
+impl<R: Read + Seek> AutoReader<R> {
+    /// Returns `true` if the file is an enard file
+    pub fn is_encrypted(&self) -> bool {
+        matches!(self, Self::Enard(_))
+    }
+
+    /// Unwraps this [`AutoReader`], returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        match self {
+            Self::Enard(rd) => rd.into_inner(),
+            Self::Plain(rd) => rd,
+        }
+    }
+}
+
+impl<R: Read + Seek> Read for AutoReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Enard(rd) => rd.read(buf),
+            Self::Plain(rd) => rd.read(buf),
+        }
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
+        match self {
+            Self::Enard(rd) => rd.read_vectored(bufs),
+            Self::Plain(rd) => rd.read_vectored(bufs),
+        }
+    }
+}
+
+impl<R: Read + Seek> Seek for AutoReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            Self::Enard(rd) => rd.seek(pos),
+            Self::Plain(rd) => rd.seek(pos),
+        }
+    }
+}
+
+/// Open `reader` as an enard file if it starts with the enard magic, otherwise return
+/// it unchanged. See [`EnardReader::is_enard`].
+///
+/// Files which look like enard files but fail to open, e.g. because of the wrong key,
+/// are still errors, they are never passed through.
+pub fn open_auto<R, K>(mut reader: R, key: K) -> Result<AutoReader<R>, EnardError>
+where
+    R: Read + Seek,
+    K: KeyProvider,
+{
+    if EnardReader::is_enard(&mut reader)? {
+        Ok(AutoReader::Enard(EnardReader::new_boxed(reader, key)?))
+    } else {
+        Ok(AutoReader::Plain(reader))
+    }
+}
@@ -0,0 +1,142 @@
+//! Open many enard files at once.
+//!
+//! Games often ship thousands of small enard files, and opening each one means reading
+//! the header and checking the MAC. [`open_all`] shares one cipher factory and key
+//! provider between all of the files and opens them lazily as the iterator advances.
+//! With the `parallel` feature [`par_open_all`] checks the MACs on the rayon thread pool
+//! instead, which is usually much faster when there are many files to open up front.
+//!
+//! Results are returned in the same order as the paths, so they can be zipped together
+//! to find out which file failed.
+//!
+//! ```rust,no_run
+//! use enard::batch::{open_all, BatchOptions};
+//! # fn main() -> Result<(), enard::EnardError> {
+//! let paths = ["assets/a.enard", "assets/b.enard"];
+//! let opts = BatchOptions::new().readahead(4096);
+//! for (path, reader) in paths.iter().zip(open_all(&paths, [0x42u8; 32], &opts)) {
+//!     let reader = reader?;
+//!     println!("{} has {} bytes", path, reader.data_size());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use std::fs::File;
+use std::path::Path;
+
+use crate::cipher_factory::{CipherFactory, GetFactory};
+use crate::{BoxDynCipher, DynCipher, EnardError, EnardReader, KeyProvider, MetaMap};
+
+/// Options shared by every file opened with [`open_all`] or [`par_open_all`].
+#[derive(Debug, Clone, Default)]
+pub struct BatchOptions {
+    readahead: usize,
+}
+impl BatchOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Enable read-ahead on each reader, see [`EnardReader::with_readahead`].
+    pub fn readahead(mut self, bytes: usize) -> Self {
+        self.readahead = bytes;
+        self
+    }
+}
+
+/// Lazily open each file in `paths`, choosing the cipher from each file's header.
+///
+/// Each file is opened and its MAC checked when the iterator reaches it, so files which
+/// are never used cost nothing. See [`open_all_with`] to use a different factory.
+pub fn open_all<I, P, K>(
+    paths: I,
+    key: K,
+    opts: &BatchOptions,
+) -> impl Iterator<Item = Result<EnardReader<File, BoxDynCipher>, EnardError>>
+where
+    I: IntoIterator<Item = P>,
+    P: AsRef<Path>,
+    K: KeyProvider,
+{
+    open_all_with(paths, BoxDynCipher::factory(), key, opts)
+}
+
+/// Same as [`open_all`] but creating the ciphers with `factory`.
+pub fn open_all_with<I, P, C, Cf, K>(
+    paths: I,
+    factory: Cf,
+    key: K,
+    opts: &BatchOptions,
+) -> impl Iterator<Item = Result<EnardReader<File, C>, EnardError>>
+where
+    I: IntoIterator<Item = P>,
+    P: AsRef<Path>,
+    C: DynCipher,
+    Cf: CipherFactory<C>,
+    K: KeyProvider,
+{
+    let opts = opts.clone();
+    paths
+        .into_iter()
+        .map(move |path| open_one(path.as_ref(), &factory, &key, &opts))
+}
+
+/// Open every file in `paths` at once, checking the MACs in parallel.
+///
+/// Unlike [`open_all`] all of the files are opened before this returns, errors are
+/// still reported per file and in order.
+#[cfg(feature = "parallel")]
+pub fn par_open_all<I, P, K>(
+    paths: I,
+    key: K,
+    opts: &BatchOptions,
+) -> std::vec::IntoIter<Result<EnardReader<File, BoxDynCipher>, EnardError>>
+where
+    I: IntoIterator<Item = P>,
+    P: AsRef<Path> + Send,
+    K: KeyProvider + Sync,
+{
+    par_open_all_with(paths, BoxDynCipher::factory(), key, opts)
+}
+
+/// Same as [`par_open_all`] but creating the ciphers with `factory`.
+#[cfg(feature = "parallel")]
+pub fn par_open_all_with<I, P, C, Cf, K>(
+    paths: I,
+    factory: Cf,
+    key: K,
+    opts: &BatchOptions,
+) -> std::vec::IntoIter<Result<EnardReader<File, C>, EnardError>>
+where
+    I: IntoIterator<Item = P>,
+    P: AsRef<Path> + Send,
+    C: DynCipher,
+    Cf: CipherFactory<C> + Sync,
+    K: KeyProvider + Sync,
+{
+    use rayon::prelude::*;
+
+    let paths: Vec<P> = paths.into_iter().collect();
+    let readers: Vec<_> = paths
+        .into_par_iter()
+        .map(|path| open_one(path.as_ref(), &factory, &key, opts))
+        .collect();
+    readers.into_iter()
+}
+
+fn open_one<C, Cf, K>(
+    path: &Path,
+    factory: &Cf,
+    key: &K,
+    opts: &BatchOptions,
+) -> Result<EnardReader<File, C>, EnardError>
+where
+    C: DynCipher,
+    Cf: CipherFactory<C>,
+    K: KeyProvider,
+{
+    let file = File::open(path)?;
+    let key = |meta: &MetaMap| key.key_for(meta);
+    let reader = EnardReader::new(file, factory, key)?;
+    Ok(reader.with_readahead(opts.readahead))
+}
@@ -0,0 +1,65 @@
+//! Measure how fast each cipher encrypts and decrypts on this machine.
+//!
+//! ChaCha8 is much faster than ChaCha20, but how much depends on the platform.
+//! [`measure_throughput`] writes and reads back an in-memory enard file, which is
+//! what `enard bench` prints. The criterion benches in `benches/` go into more detail.
+//!
+//! ```rust
+//! # fn main() -> Result<(), enard::EnardError> {
+//! let result = enard::bench::measure_throughput(b"ChaCha8", 1 << 16)?;
+//! println!("{:.1} MB/s", result.read_bytes_per_sec() / 1e6);
+//! # Ok(())
+//! # }
+//! ```
+use std::io::{self, Cursor};
+use std::time::{Duration, Instant};
+
+use crate::cipher_factory::{CipherFactory, GetFactory};
+use crate::{BoxDynCipher, EnardError, EnardReader, EnardWriter, MetaMap};
+
+/// Timings from [`measure_throughput`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Throughput {
+    /// Number of bytes of data written and read
+    pub size: usize,
+    /// Time spent writing the file, including the MAC
+    pub write: Duration,
+    /// Time spent opening and reading the file, including checking the MAC
+    pub read: Duration,
+}
+impl Throughput {
+    /// Bytes encrypted per second
+    pub fn write_bytes_per_sec(&self) -> f64 {
+        self.size as f64 / self.write.as_secs_f64()
+    }
+
+    /// Bytes decrypted per second
+    pub fn read_bytes_per_sec(&self) -> f64 {
+        self.size as f64 / self.read.as_secs_f64()
+    }
+}
+
+/// Encrypt `size` bytes with the cipher `cipher_name` into memory, then open and
+/// decrypt them again, timing both.
+///
+/// Uses a fixed key and IV, the results aren't meant to be kept.
+pub fn measure_throughput(cipher_name: &[u8], size: usize) -> Result<Throughput, EnardError> {
+    let factory = BoxDynCipher::factory();
+    let meta = factory.get_meta(cipher_name)?;
+    let key = vec![0x42u8; meta.key_size];
+    let iv = vec![0x24u8; meta.iv_size];
+    let data = vec![0x5au8; size];
+
+    let start = Instant::now();
+    let out = Cursor::new(Vec::with_capacity(size + 1024));
+    let mut wr = EnardWriter::new(out, factory, cipher_name, &key, iv.into(), MetaMap::new())?;
+    wr.write_complete(data.as_slice())?;
+    let write = start.elapsed();
+    let file = wr.into_inner().into_inner();
+
+    let start = Instant::now();
+    let mut rd = EnardReader::new_boxed(Cursor::new(&file), &key)?;
+    io::copy(&mut rd, &mut io::sink())?;
+    let read = start.elapsed();
+    Ok(Throughput { size, write, read })
+}
@@ -0,0 +1,134 @@
+//! Load Bevy assets from a zip archive inside an enard file.
+//!
+//! Replace the default asset server with one backed by [`EnardAssetIo`] and every asset
+//! is read from the single encrypted pack instead of the `assets` directory.
+//!
+//! ```ignore
+//! let key = [0x42u8; 32];
+//! let io = EnardAssetIo::open("assets.zip.enard", &key).unwrap();
+//! App::new()
+//!     .insert_resource(AssetServer::new(io))
+//!     .add_plugins(DefaultPlugins.build().disable::<AssetPlugin>())
+//!     .run();
+//! ```
+use bevy_asset::{AssetIo, AssetIoError, BoxedFuture, FileType, Metadata};
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::zip::{EnardZip, EnardZipArchive};
+use crate::EnardError;
+
+/// An [`AssetIo`] which reads assets from a zip archive inside an enard file.
+pub struct EnardAssetIo<R: Read + Seek = BufReader<File>> {
+    archive: Mutex<EnardZipArchive<R>>,
+}
+
+impl EnardAssetIo {
+    /// Open the enard file at `path` and read the zip archive inside it.
+    pub fn open<P: AsRef<Path>>(path: P, key: &[u8]) -> Result<Self, EnardError> {
+        Ok(Self::new(EnardZip::open(path, key)?))
+    }
+}
+
+impl<R: Read + Seek> EnardAssetIo<R> {
+    /// Serve assets from an already opened archive.
+    pub fn new(archive: EnardZipArchive<R>) -> Self {
+        Self {
+            archive: Mutex::new(archive),
+        }
+    }
+
+    fn read_file(&self, path: &Path) -> Result<Vec<u8>, AssetIoError> {
+        let mut archive = self.archive.lock().unwrap();
+        let mut file = match archive.by_name(&zip_name(path)) {
+            Ok(file) => file,
+            Err(::zip::result::ZipError::FileNotFound) => {
+                return Err(AssetIoError::NotFound(path.to_owned()))
+            }
+            Err(e) => return Err(io::Error::from(e).into()),
+        };
+        let mut buf = Vec::with_capacity(file.size() as usize);
+        file.read_to_end(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// Returns the names of the direct children of the directory `name`,
+    /// or `None` if there is no such directory.
+    fn children(&self, name: &str) -> Option<BTreeSet<String>> {
+        let archive = self.archive.lock().unwrap();
+        let prefix = if name.is_empty() {
+            String::new()
+        } else {
+            format!("{}/", name)
+        };
+        let mut found = name.is_empty();
+        let mut children = BTreeSet::new();
+        for entry in archive.file_names() {
+            if let Some(rest) = entry.strip_prefix(&prefix) {
+                found = true;
+                if let Some(child) = rest.split('/').next().filter(|c| !c.is_empty()) {
+                    children.insert(child.to_owned());
+                }
+            }
+        }
+        if found {
+            Some(children)
+        } else {
+            None
+        }
+    }
+}
+
+impl<R: Read + Seek + Send + 'static> AssetIo for EnardAssetIo<R> {
+    fn load_path<'a>(&'a self, path: &'a Path) -> BoxedFuture<'a, Result<Vec<u8>, AssetIoError>> {
+        Box::pin(async move { self.read_file(path) })
+    }
+
+    fn read_directory(
+        &self,
+        path: &Path,
+    ) -> Result<Box<dyn Iterator<Item = PathBuf>>, AssetIoError> {
+        let children = self
+            .children(&zip_name(path))
+            .ok_or_else(|| AssetIoError::NotFound(path.to_owned()))?;
+        let path = path.to_owned();
+        Ok(Box::new(
+            children.into_iter().map(move |child| path.join(child)),
+        ))
+    }
+
+    fn get_metadata(&self, path: &Path) -> Result<Metadata, AssetIoError> {
+        let name = zip_name(path);
+        if self.archive.lock().unwrap().by_name(&name).is_ok() {
+            Ok(Metadata::new(FileType::File))
+        } else if self.children(&name).is_some() {
+            Ok(Metadata::new(FileType::Directory))
+        } else {
+            Err(AssetIoError::NotFound(path.to_owned()))
+        }
+    }
+
+    fn watch_path_for_changes(&self, _path: &Path) -> Result<(), AssetIoError> {
+        // The archive can't change while it's open
+        Ok(())
+    }
+
+    fn watch_for_changes(&self) -> Result<(), AssetIoError> {
+        Ok(())
+    }
+}
+
+/// Convert an asset path to the name used in the zip archive.
+fn zip_name(path: &Path) -> String {
+    let parts: Vec<_> = path
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(s) => Some(s.to_string_lossy()),
+            _ => None,
+        })
+        .collect();
+    parts.join("/")
+}
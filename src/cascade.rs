@@ -0,0 +1,210 @@
+//! Encrypt with several ciphers in a row, so the data stays safe if one of them is broken.
+//!
+//! A cascade is stored as the cipher [`CascadeCipher::NAME`], with the ciphers and their
+//! IVs encoded in the IV field by [`encode_layers`] (see `format.md`). Each layer gets its
+//! own key, derived from the file key, so the file key is the same size as usual.
+//! [`crate::BoxDynCipher`] creates cascades of any ciphers it supports, so readers don't
+//! need to do anything special.
+//!
+//! ```rust
+//! # use std::io::Cursor;
+//! # use enard::{EnardReader, EnardWriter};
+//! use enard::cascade::{encode_layers, CascadeCipher};
+//! # fn main() -> Result<(), enard::EnardError> {
+//! let iv = encode_layers(&[(b"ChaCha20", &[0x24; 12]), (b"ChaCha12", &[0x25; 12])])?;
+//! let mut wr = EnardWriter::builder(Cursor::new(Vec::new()))
+//!     .cipher(CascadeCipher::NAME)
+//!     .key([0x42; 32])
+//!     .iv(iv)
+//!     .build()?;
+//! wr.write_complete(&b"hello"[..])?;
+//! let file = wr.into_inner().into_inner();
+//! let rd = EnardReader::new_boxed(Cursor::new(file), [0x42; 32])?;
+//! # Ok(())
+//! # }
+//! ```
+use cipher::{StreamCipher, StreamCipherError};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use std::io::{self, ErrorKind};
+use zeroize::Zeroizing;
+
+use crate::cipher_factory::{CipherFactory, CipherMeta};
+use crate::core::read_varint_block;
+use crate::dyn_cipher::BoxDynCipherFactory;
+use crate::{varint, BoxDynCipher, DynCipherCore, EnardError};
+
+/// Size in bytes of the key of a cascade, each layer's key is derived from it.
+pub const KEY_SIZE: usize = 32;
+/// Most layers a cascade may have.
+pub const MAX_LAYERS: usize = 8;
+/// HKDF info for the key of each layer, followed by the index of the layer as a `u8`.
+const LAYER_KEY_INFO: &[u8] = b"enard cascade key";
+
+/// Cipher name and IV of each layer of a cascade.
+pub type Layers = Vec<(Vec<u8>, Vec<u8>)>;
+
+/// Encode the cipher name and IV of each layer, in the order they're applied, as the
+/// IV of a cascade.
+///
+/// The encoding is a *varint* count followed by a *varint-block* name and IV for each
+/// layer, the same in both format versions.
+pub fn encode_layers(layers: &[(&[u8], &[u8])]) -> Result<Vec<u8>, EnardError> {
+    check_layer_count(layers.len())?;
+    let mut buf = Vec::new();
+    varint::write_u(&mut buf, layers.len() as u64)?;
+    for (name, iv) in layers {
+        check_layer_name(name)?;
+        varint::write_u(&mut buf, name.len() as u64)?;
+        buf.extend_from_slice(name);
+        varint::write_u(&mut buf, iv.len() as u64)?;
+        buf.extend_from_slice(iv);
+    }
+    Ok(buf)
+}
+
+/// Decode the cipher names and IVs written by [`encode_layers`].
+pub fn decode_layers(mut iv: &[u8]) -> Result<Layers, EnardError> {
+    let limit = iv.len() as u64;
+    let count = varint::read_u(&mut iv)?;
+    check_layer_count(count as usize)?;
+    let mut layers = Vec::new();
+    for _ in 0..count {
+        let name = read_varint_block(&mut iv, limit)?;
+        check_layer_name(&name)?;
+        layers.push((name, read_varint_block(&mut iv, limit)?));
+    }
+    if !iv.is_empty() {
+        let msg = format!("{} unexpected bytes after the cascade layers", iv.len());
+        return Err(io::Error::new(ErrorKind::InvalidData, msg).into());
+    }
+    Ok(layers)
+}
+
+fn check_layer_count(count: usize) -> Result<(), EnardError> {
+    if count == 0 || count > MAX_LAYERS {
+        let msg = format!("a cascade needs 1-{} layers, not {}", MAX_LAYERS, count);
+        return Err(io::Error::new(ErrorKind::InvalidInput, msg).into());
+    }
+    Ok(())
+}
+
+/// Layers must be real ciphers, and cascades can't be nested.
+fn check_layer_name(name: &[u8]) -> Result<(), EnardError> {
+    if name.is_empty() || name == CascadeCipher::NAME {
+        return Err(EnardError::new_unsupported_encryption(name));
+    }
+    Ok(())
+}
+
+/// Applies several ciphers in a row, see [`crate::cascade`].
+pub struct CascadeCipher {
+    layers: Vec<BoxDynCipher>,
+}
+impl CascadeCipher {
+    /// Name of the cipher in the header
+    pub const NAME: &'static [u8] = b"Cascade";
+
+    /// Create the ciphers of a cascade with the IV `iv` from [`encode_layers`], using
+    /// `factory` to create each layer.
+    pub fn new<Cf>(factory: &Cf, key: &[u8], iv: &[u8]) -> Result<Self, EnardError>
+    where
+        Cf: CipherFactory<BoxDynCipher> + ?Sized,
+    {
+        if key.len() != KEY_SIZE {
+            return Err(EnardError::KeyLengthMismatch {
+                expected: KEY_SIZE,
+                got: key.len(),
+            });
+        }
+        let hkdf = Hkdf::<Sha256>::new(None, key);
+        let mut layers = Vec::new();
+        for (i, (name, layer_iv)) in decode_layers(iv)?.into_iter().enumerate() {
+            let meta = factory.get_meta(&name)?;
+            let mut layer_key = Zeroizing::new(vec![0u8; meta.key_size]);
+            hkdf.expand_multi_info(&[LAYER_KEY_INFO, &[i as u8]], &mut layer_key)
+                .map_err(|_| EnardError::InvalidLength)?;
+            layers.push(factory.create(&name, &layer_key, &layer_iv)?);
+        }
+        Ok(Self { layers })
+    }
+
+    /// Name and sizes of a cascade. The IV size is `0` because it depends on the
+    /// layers.
+    pub fn meta() -> CipherMeta {
+        CipherMeta {
+            name: Self::NAME,
+            key_size: KEY_SIZE,
+            iv_size: 0,
+        }
+    }
+
+    /// Names of the ciphers, in the order they're applied.
+    pub fn layer_names(&self) -> Vec<&'static [u8]> {
+        self.layers.iter().map(|layer| layer.get_name()).collect()
+    }
+}
+impl StreamCipher for CascadeCipher {
+    fn try_apply_keystream_inout(
+        &mut self,
+        mut buf: cipher::inout::InOutBuf<'_, '_, u8>,
+    ) -> Result<(), StreamCipherError> {
+        let (first, rest) = self
+            .layers
+            .split_first_mut()
+            .expect("cascades aren't empty");
+        first.try_apply_keystream_inout(buf.reborrow())?;
+        let out = buf.into_out();
+        for layer in rest {
+            layer.try_apply_keystream(out)?;
+        }
+        Ok(())
+    }
+}
+impl DynCipherCore for CascadeCipher {
+    fn try_seek(&mut self, new_pos: u64) -> Result<(), StreamCipherError> {
+        self.layers
+            .iter_mut()
+            .try_for_each(|layer| layer.try_seek(new_pos))
+    }
+
+    fn current_pos(&self) -> u64 {
+        self.layers[0].current_pos()
+    }
+
+    fn get_name(&self) -> &'static [u8] {
+        Self::NAME
+    }
+
+    fn iv_size(&self) -> usize {
+        0
+    }
+
+    fn key_size(&self) -> usize {
+        KEY_SIZE
+    }
+
+    /// The layers must be the same ciphers, in the same order.
+    fn reset_iv(&mut self, iv: &[u8]) -> Result<(), EnardError> {
+        let layers = decode_layers(iv)?;
+        if layers.len() != self.layers.len()
+            || layers
+                .iter()
+                .zip(&self.layers)
+                .any(|((name, _), layer)| name.as_slice() != layer.get_name())
+        {
+            let msg = "the new IV must have the same cascade layers";
+            return Err(io::Error::new(ErrorKind::InvalidInput, msg).into());
+        }
+        for ((_, layer_iv), layer) in layers.iter().zip(self.layers.iter_mut()) {
+            layer.reset_iv(layer_iv)?;
+        }
+        Ok(())
+    }
+}
+
+/// Same as [`CascadeCipher::new`] with the ciphers [`BoxDynCipher`] supports.
+pub(crate) fn create(key: &[u8], iv: &[u8]) -> Result<BoxDynCipher, EnardError> {
+    let cascade = CascadeCipher::new(&BoxDynCipherFactory, key, iv)?;
+    Ok(BoxDynCipher(Box::new(cascade)))
+}
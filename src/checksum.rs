@@ -0,0 +1,52 @@
+//! Checksum of the plaintext, stored in the metadata.
+//!
+//! The MAC only proves the *encrypted* data wasn't modified. If the data is decrypted
+//! incorrectly, e.g. because a custom [`crate::cipher_factory::CipherFactory`] picked the
+//! wrong cipher, the result is garbage with no error. Storing a CRC-32 of the plaintext
+//! lets [`EnardReader::verify_plaintext_checksum`] catch that cheaply.
+//!
+//! The checksum has to be in the header, which is written before the data, so it's
+//! computed up front with [`insert_checksum`].
+//!
+//! [`EnardReader::verify_plaintext_checksum`]: crate::EnardReader::verify_plaintext_checksum
+use crc32fast::Hasher;
+use std::io::{self, ErrorKind, Read};
+
+use crate::{EnardError, MetaMap};
+
+/// Metadata key the checksum is stored under, as a little-endian `u32`.
+pub const CHECKSUM_META_KEY: &[u8] = b"enard.crc32";
+
+/// Compute the CRC-32 of everything in `reader`.
+pub fn checksum<R: Read>(mut reader: R) -> io::Result<u32> {
+    let mut hasher = Hasher::new();
+    let mut buf = [0u8; 8 * 1024];
+    loop {
+        match reader.read(&mut buf) {
+            Ok(0) => return Ok(hasher.finalize()),
+            Ok(n) => hasher.update(&buf[..n]),
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Compute the checksum of the plaintext in `reader` and store it in `meta`,
+/// returning the checksum.
+pub fn insert_checksum<R: Read>(meta: &mut MetaMap, reader: R) -> io::Result<u32> {
+    let crc = checksum(reader)?;
+    meta.insert(CHECKSUM_META_KEY.to_vec(), crc.to_le_bytes().to_vec());
+    Ok(crc)
+}
+
+/// Returns the checksum stored in `meta`.
+pub(crate) fn stored_checksum(meta: &MetaMap) -> Result<u32, EnardError> {
+    let value = meta
+        .get(CHECKSUM_META_KEY)
+        .ok_or_else(|| EnardError::new_missing_meta(CHECKSUM_META_KEY))?;
+    let bytes: [u8; 4] = value
+        .as_slice()
+        .try_into()
+        .map_err(|_| EnardError::InvalidLength)?;
+    Ok(u32::from_le_bytes(bytes))
+}
@@ -12,12 +12,54 @@ pub trait CipherName {
     fn name() -> &'static [u8];
 }
 
+/// Name, key size and IV size of a cipher.
+///
+/// With the `serde` feature this is serialized with the name as a string. Only ciphers
+/// known to [`crate::BoxDynCipher`] can be deserialized, because the name is `'static`.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct CipherMeta {
     pub name: &'static [u8],
     pub key_size: usize,
     pub iv_size: usize,
 }
+#[cfg(feature = "serde")]
+impl serde::Serialize for CipherMeta {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        CipherMetaRepr {
+            name: self.name.escape_ascii().to_string(),
+            key_size: self.key_size,
+            iv_size: self.iv_size,
+        }
+        .serialize(serializer)
+    }
+}
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for CipherMeta {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+        let repr = CipherMetaRepr::deserialize(deserializer)?;
+        let meta = crate::BoxDynCipher::factory()
+            .get_meta(repr.name.as_bytes())
+            .map_err(D::Error::custom)?;
+        if meta.key_size != repr.key_size || meta.iv_size != repr.iv_size {
+            return Err(D::Error::custom(format!(
+                "{} has a {} byte key and a {} byte IV",
+                repr.name, meta.key_size, meta.iv_size
+            )));
+        }
+        Ok(meta)
+    }
+}
+
+/// How [`CipherMeta`] is serialized.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename = "CipherMeta")]
+struct CipherMetaRepr {
+    name: String,
+    key_size: usize,
+    iv_size: usize,
+}
 impl CipherMeta {
     /// Helper function to generate an IV for this cipher using a cryptographic RNG
     #[cfg(feature = "random")]
@@ -54,6 +96,47 @@ pub trait CipherFactory<C> {
     /// If the name is an empty slice AND this factory only creates one type of
     /// cipher, it should ignore the name. See [`check_supported_name`].
     fn create(&self, name: &[u8], key: &[u8], iv: &[u8]) -> TResult<C>;
+
+    /// Check that the cipher `name` can be created with a key of `key_len` bytes and an
+    /// IV of `iv_len` bytes, without creating it.
+    ///
+    /// Returns [`EnardError::KeyLengthMismatch`] or [`EnardError::IvLengthMismatch`],
+    /// which say what the sizes should be, unlike the [`EnardError::InvalidLength`]
+    /// that [`CipherFactory::create`] usually returns.
+    fn validate(&self, name: &[u8], key_len: usize, iv_len: usize) -> TResult<()> {
+        let meta = self.get_meta(name)?;
+        if key_len != meta.key_size {
+            return Err(EnardError::KeyLengthMismatch {
+                expected: meta.key_size,
+                got: key_len,
+            });
+        }
+        if iv_len != meta.iv_size {
+            return Err(EnardError::IvLengthMismatch {
+                expected: meta.iv_size,
+                got: iv_len,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Allows one factory to be shared, such as between the files in [`crate::batch`].
+impl<C, T> CipherFactory<C> for &T
+where
+    T: CipherFactory<C> + ?Sized,
+{
+    fn get_meta(&self, name: &[u8]) -> TResult<CipherMeta> {
+        (**self).get_meta(name)
+    }
+
+    fn create(&self, name: &[u8], key: &[u8], iv: &[u8]) -> TResult<C> {
+        (**self).create(name, key, iv)
+    }
+
+    fn validate(&self, name: &[u8], key_len: usize, iv_len: usize) -> TResult<()> {
+        (**self).validate(name, key_len, iv_len)
+    }
 }
 
 /// Default implementation of [`CipherFactory`] for concrete ciphers (e.g. ChaCha12).
@@ -1,64 +1,702 @@
-use byteorder::{ReadBytesExt, WriteBytesExt, LE};
+use byteorder::{ByteOrder, ReadBytesExt, WriteBytesExt, LE};
 use cipher::StreamCipherError;
-use hmac::{Hmac, Mac};
-use sha2::Sha256;
+use indexmap::IndexMap;
+use sha2::{Digest, Sha256};
 use std::fmt::Debug;
-use std::io::{self, ErrorKind, Read, Seek, SeekFrom, Write};
-use std::{collections::HashMap, marker::PhantomData};
+use std::io::{self, BufRead, ErrorKind, IoSlice, IoSliceMut, Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::time::SystemTime;
+use zeroize::Zeroizing;
 
-use crate::{cipher_factory::*, dyn_cipher::*, error::*};
+use crate::limits::ParseLimits;
+use crate::mac::{BoxDynMac, DefaultMacFactory, MacFactory};
+use crate::sealed::SealedKey;
+use crate::stream::{ReadSeek, SubSeek};
+use crate::tuning::ReaderOptions;
+use crate::{cipher_factory::*, dyn_cipher::*, error::*, varint, EnardNonce, KeyProvider};
 
+/// Magic at the start of enard files, unless replaced with [`EnardWriter::with_magic`].
 pub const MAGIC: &[u8; 6] = b"\x03ENARD";
+/// Default alignment of the data section relative to the start of the file, see
+/// [`EnardWriter::with_data_alignment`].
 pub const DATA_ALIGNMENT: usize = 8;
+/// Default size of the buffer [`EnardWriter`] uses for encryption.
+pub const DEFAULT_BUFFER_SIZE: usize = 8 * 1024;
+/// Size of the buffer used by [`EnardReader::copy_to`] and [`EnardWriter::copy_from`].
+pub const COPY_BUFFER_SIZE: usize = 256 * 1024;
+/// [`EnardWriter::copy_from`] encrypts and MACs this much at a time, so each chunk is
+/// still in the CPU cache for the MAC.
+const CRYPT_CHUNK_SIZE: usize = 16 * 1024;
+/// Alignment in bytes of the buffers data is encrypted and decrypted in, so the SIMD
+/// cipher backends work on aligned memory.
+pub const CRYPT_ALIGN: usize = 64;
+/// Buffer sizes are rounded up to a multiple of this, four 64-byte ChaCha blocks, which
+/// is how many the SIMD backends process at once. Data is then encrypted and decrypted
+/// in whole blocks, except at the end and after seeking.
+pub const CRYPT_BLOCK_SIZE: usize = 256;
 /// Start of the header data relative to the file start.
 /// MAGIC + u16 + u32 + u64
-const HEADER_START: usize = 6 + 2 + 4 + 8;
+pub(crate) const HEADER_START: usize = 6 + 2 + 4 + 8;
+/// Start of the header data relative to the file start for format v2.
+/// MAGIC + u16 + u64 + u64
+pub(crate) const HEADER_START_V2: usize = 6 + 2 + 8 + 8;
+/// Flag in the version field of files whose header is obfuscated, see
+/// [`EnardWriter::with_obfuscated_header`].
+pub(crate) const OBFUSCATED_HEADER_FLAG: u16 = 0x8000;
+/// Flag in the version field of files whose header section ends with a checksum, see
+/// [`EnardWriter::with_header_checksum`].
+pub(crate) const HEADER_CHECKSUM_FLAG: u16 = 0x4000;
+/// Size in bytes of the header checksum, a CRC-32.
+pub(crate) const HEADER_CHECKSUM_SIZE: usize = 4;
+/// Size in bytes of the MAC tag at the end of the file.
+pub(crate) const TAG_SIZE: usize = 32;
+/// Forward seeks up to this many bytes read and discard data instead of seeking the inner
+/// reader, which would throw away the buffer of a [`std::io::BufReader`].
+const SKIP_READ_LIMIT: u64 = 8 * 1024;
+/// Magic at the end of the footer, see [`EnardWriter::finish_streaming`].
+pub(crate) const FOOTER_MAGIC: &[u8; 8] = b"\x03ENARDFT";
+/// Size in bytes of the footer: header size, data size, and magic.
+pub(crate) const FOOTER_SIZE: usize = 8 + 8 + FOOTER_MAGIC.len();
+/// Metadata key holding the size of the extended metadata, see
+/// [`EnardWriter::with_extended_meta`].
+pub const EXTENDED_META_KEY: &[u8] = b"enard.extmeta";
 
-/// Map of metadata keys to values
-pub type MetaMap = HashMap<Vec<u8>, Vec<u8>>;
-/// Hmac type for format v1
-type HmacV1 = Hmac<Sha256>;
+/// Map of metadata keys to values.
+///
+/// Entries are written in insertion order and read back in the order they're stored,
+/// so writing the same map twice gives the same bytes. Use `shift_remove` rather than
+/// `remove` to delete an entry without reordering the rest.
+pub type MetaMap = IndexMap<Vec<u8>, Vec<u8>>;
+
+/// Versions of the enard file format.
+///
+/// See `format.md` for the differences between versions. With the `serde` feature this
+/// is serialized as its number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(into = "u16", try_from = "u16")
+)]
+pub enum FormatVersion {
+    /// Original format with `u8` and `u16` length prefixes and a `u32` header size
+    V1 = 1,
+    /// Varint length prefixes and a `u64` header size
+    V2 = 2,
+}
+impl FormatVersion {
+    /// Parse the version field, returning the version and whether the header is
+    /// obfuscated, see [`EnardWriter::with_obfuscated_header`]. Use
+    /// [`check_header_checksum`] to check the header if [`HEADER_CHECKSUM_FLAG`] is set.
+    pub(crate) fn from_field(field: u16) -> Result<(Self, bool), EnardError> {
+        let version = Self::try_from(field & !(OBFUSCATED_HEADER_FLAG | HEADER_CHECKSUM_FLAG))?;
+        Ok((version, field & OBFUSCATED_HEADER_FLAG != 0))
+    }
+
+    /// Offset of the header data relative to the start of the file.
+    pub(crate) fn header_start(self) -> usize {
+        match self {
+            Self::V1 => HEADER_START,
+            Self::V2 => HEADER_START_V2,
+        }
+    }
+}
+impl Default for FormatVersion {
+    fn default() -> Self {
+        Self::V1
+    }
+}
+impl From<FormatVersion> for u16 {
+    fn from(version: FormatVersion) -> Self {
+        version as u16
+    }
+}
+impl TryFrom<u16> for FormatVersion {
+    type Error = EnardError;
+
+    fn try_from(version: u16) -> Result<Self, Self::Error> {
+        match version {
+            1 => Ok(Self::V1),
+            2 => Ok(Self::V2),
+            _ => Err(EnardError::UnsupportedVersion { version }),
+        }
+    }
+}
 
 /// Wraps an internal reader (usually [`std::fs::File`]) and implements
 /// [`Read`] and [`Seek`], decrypting the contents of the internal reader on the fly
 /// and acting as a reader for only the "wrapped" contents of the enard file.
 ///
 /// It's *highly* recommended to ensure the inner reader is buffered (e.g. using
-/// [`std::io::BufReader`]) as it will usually improve performance significantly.
+/// [`std::io::BufReader`], or [`EnardReader::new_buffered`] which does it for you) as it
+/// will usually improve performance significantly. See [`crate::tuning`] for choosing
+/// buffer sizes.
 ///
 pub struct EnardReader<R: Read + Seek, C: DynCipher> {
     inner: R,
     cipher: C,
+    /// Cipher IV, kept for [`EnardReader::save_position`]
+    iv: Box<[u8]>,
+    /// Format version of the file
+    version: FormatVersion,
+    /// Size in bytes of the header, including padding
+    header_size: u64,
     /// Offset in the inner reader where the data section starts
     data_start: u64,
-    /// Size in bytes of the data section
+    /// Size in bytes of the data, not including the extended metadata
     data_size: u64,
+    /// Size in bytes of the extended metadata stored after the data
+    ext_meta_size: u64,
     /// Current offset in the data for seek purposes
     current: u64,
     meta: MetaMap,
+    /// Decrypted read-ahead data, see [`EnardReader::with_readahead`]
+    readahead: CryptBuf,
+    /// Data position of the first byte in `readahead`
+    readahead_start: u64,
+    /// Maximum number of bytes to read ahead, 0 disables read-ahead
+    readahead_size: usize,
+    /// See [`EnardReader::with_crypt_chunk`]
+    crypt_chunk: usize,
+    /// Allow seeking beyond the end of the data, see [`EnardReader::with_seek_past_end`]
+    seek_past_end: bool,
 }
 impl<R, C> EnardReader<R, C>
 where
     R: Read + Seek,
     C: DynCipher,
 {
-    pub fn new<Cf: CipherFactory<C>>(
+    /// Create a new [`EnardReader`], verifying the MAC and parsing the header.
+    ///
+    /// `key` is usually a byte slice, but can be any [`KeyProvider`].
+    pub fn new<Cf: CipherFactory<C>, K: KeyProvider>(
         reader: R,
         factory: Cf,
-        key: &[u8],
+        key: K,
     ) -> Result<Self, EnardError> {
         EnardBuilder::new(reader, factory, key).build()
     }
 
+    /// Same as [`EnardReader::new`] but rejecting files which exceed `limits`, instead of
+    /// the defaults, see [`crate::limits`].
+    pub fn new_with_limits<Cf: CipherFactory<C>, K: KeyProvider>(
+        reader: R,
+        factory: Cf,
+        key: K,
+        limits: ParseLimits,
+    ) -> Result<Self, EnardError> {
+        EnardBuilder::new(reader, factory, key)
+            .limits(limits)
+            .build()
+    }
+
+    /// Same as [`EnardReader::new`] but creating the MAC with `mac_factory`, for MAC
+    /// algorithms which aren't built in, see [`crate::mac`].
+    pub fn new_with_mac_factory<Cf, K, M>(
+        reader: R,
+        factory: Cf,
+        key: K,
+        mac_factory: M,
+    ) -> Result<Self, EnardError>
+    where
+        Cf: CipherFactory<C>,
+        K: KeyProvider,
+        M: MacFactory + 'static,
+    {
+        EnardBuilder::new(reader, factory, key)
+            .mac_factory(Box::new(mac_factory))
+            .build()
+    }
+
+    /// Create a new [`EnardReader`] whose cipher and MAC are created by `sealed`, so the
+    /// key never has to be in memory, see [`crate::sealed`].
+    pub fn new_sealed<S: SealedKey<C> + ?Sized>(reader: R, sealed: &S) -> Result<Self, EnardError> {
+        EnardBuilder::with_keys(reader, Sealed(sealed)).build()
+    }
+
+    /// Create a new [`EnardReader`] for an enard file which starts at `offset` in `reader`,
+    /// such as when the file is embedded inside a larger file.
+    ///
+    /// All offsets reported by the reader (e.g. [`EnardReader::data_start`]) are relative
+    /// to the start of `reader`, not the start of the enard file.
+    pub fn new_at<Cf: CipherFactory<C>, K: KeyProvider>(
+        reader: R,
+        factory: Cf,
+        key: K,
+        offset: u64,
+    ) -> Result<Self, EnardError> {
+        EnardBuilder::new(reader, factory, key)
+            .start_at(offset)
+            .build()
+    }
+
+    /// Create a reader for a file opened before, at the position saved by
+    /// [`EnardReader::save_position`], e.g. to resume an audio stream after the reader was
+    /// dropped.
+    ///
+    /// **The MAC is not verified again**, only the key check (see
+    /// [`EnardWriter::with_key_check`]) if the file has one. The saved position vouches
+    /// for the file as it was when it was opened, so only use this if the file can't have
+    /// been modified since then.
+    pub fn restore_position<Cf: CipherFactory<C>, K: KeyProvider>(
+        reader: R,
+        factory: Cf,
+        key: K,
+        saved: &SavedPosition,
+    ) -> Result<Self, EnardError> {
+        Self::reopen(reader, factory, key, saved, true)
+    }
+
+    /// Open the same file again with a different `key`, e.g. to decrypt a per-user layer
+    /// over a per-title layer, reusing the header parsed and the MAC verified by this
+    /// reader. `reader` must be another handle to the same file, such as one from
+    /// [`std::fs::File::try_clone`].
+    ///
+    /// Only the cipher changes: `key` is given the metadata and split (see
+    /// [`crate::kdf`]) as usual, but the key check is skipped since it's for the key this
+    /// reader was opened with. The new reader starts at the beginning of the data. Like
+    /// [`EnardReader::save_position`], this must not be called after
+    /// [`EnardReader::take_meta`].
+    pub fn clone_with_new_key<Cf: CipherFactory<C>, K: KeyProvider>(
+        &self,
+        reader: R,
+        factory: Cf,
+        key: K,
+    ) -> Result<Self, EnardError> {
+        let mut saved = self.save_position();
+        saved.pos = 0;
+        Self::reopen(reader, factory, key, &saved, false)
+    }
+
+    fn reopen<Cf: CipherFactory<C>, K: KeyProvider>(
+        mut reader: R,
+        factory: Cf,
+        key: K,
+        saved: &SavedPosition,
+        check_key: bool,
+    ) -> Result<Self, EnardError> {
+        let key = key.key_for(&saved.header.meta)?;
+        if check_key {
+            crate::kdf::check_key(&saved.header.meta, &key)?;
+        }
+        let (cipher_key, _) = crate::kdf::split_keys(&saved.header.meta, &key)?;
+        let cipher = factory.create(&saved.header.cipher, &cipher_key, &saved.header.iv)?;
+        let data_start = saved.header_start + saved.header_size;
+        let expected = data_start + saved.data_size + TAG_SIZE as u64;
+        let actual = reader.seek(SeekFrom::End(0))?;
+        if actual < expected {
+            return Err(EnardError::Truncated { expected, actual });
+        }
+        reader.seek(SeekFrom::Start(data_start))?;
+        let parsed = Parsed {
+            version: saved.version,
+            header_start: saved.header_start,
+            header_size: saved.header_size,
+            data_size: saved.data_size,
+            header: saved.header.clone(),
+            mac_key: Zeroizing::default(),
+            cipher,
+        };
+        let mut rd = Self::from_parsed(reader, parsed, saved.ext_meta_size)
+            .with_readahead(saved.readahead_size)
+            .with_crypt_chunk(saved.crypt_chunk)
+            .with_seek_past_end(saved.seek_past_end);
+        rd.seek(SeekFrom::Start(saved.pos))?;
+        Ok(rd)
+    }
+
+    /// Save the position in the data, and what's needed to open the file again without
+    /// verifying the MAC, see [`EnardReader::restore_position`]. The key isn't saved.
+    ///
+    /// The metadata is saved too, because the key may depend on it, so this must not be
+    /// called after [`EnardReader::take_meta`].
+    pub fn save_position(&self) -> SavedPosition {
+        SavedPosition {
+            version: self.version,
+            header_start: self.data_start - self.header_size,
+            header_size: self.header_size,
+            data_size: self.data_size + self.ext_meta_size,
+            ext_meta_size: self.ext_meta_size,
+            header: Header {
+                cipher: self.cipher.get_name().to_vec(),
+                iv: self.iv.to_vec(),
+                meta: self.meta.clone(),
+            },
+            pos: self.current,
+            readahead_size: self.readahead_size,
+            crypt_chunk: self.crypt_chunk,
+            seek_past_end: self.seek_past_end,
+        }
+    }
+
+    /// Same as [`EnardReader::new`] for files which start with `magic` instead of
+    /// [`MAGIC`], see [`EnardWriter::with_magic`]. `magic` may be empty.
+    pub fn new_with_magic<Cf: CipherFactory<C>, K: KeyProvider>(
+        reader: R,
+        factory: Cf,
+        key: K,
+        magic: &[u8],
+    ) -> Result<Self, EnardError> {
+        EnardBuilder::new(reader, factory, key).magic(magic).build()
+    }
+
+    /// Same as [`EnardReader::new`] for files written with [`EnardWriter::with_aad`].
+    /// The MAC doesn't match unless `aad` is the same as when the file was written.
+    pub fn new_with_aad<Cf: CipherFactory<C>, K: KeyProvider>(
+        reader: R,
+        factory: Cf,
+        key: K,
+        aad: &[u8],
+    ) -> Result<Self, EnardError> {
+        EnardBuilder::new(reader, factory, key).aad(aad).build()
+    }
+
     /// Access the metadata from the enard file
-    pub fn meta(&self) -> &HashMap<Vec<u8>, Vec<u8>> {
+    pub fn meta(&self) -> &MetaMap {
         &self.meta
     }
 
+    /// Move the metadata out of the reader without cloning it, leaving it empty.
+    ///
+    /// Afterwards methods which read the metadata, such as [`EnardReader::content_type`],
+    /// behave as if it was never set. Reading the data is unaffected.
+    pub fn take_meta(&mut self) -> MetaMap {
+        std::mem::take(&mut self.meta)
+    }
+
+    /// MIME type of the plaintext, if set, see [`crate::meta::CONTENT_TYPE`].
+    pub fn content_type(&self) -> Option<&str> {
+        crate::meta::get_str(&self.meta, crate::meta::CONTENT_TYPE)
+    }
+
+    /// Returns `true` if the file is marked as unencrypted, see
+    /// [`EnardWriter::new_unencrypted`].
+    pub fn is_unencrypted(&self) -> bool {
+        self.meta.contains_key(crate::meta::UNENCRYPTED)
+    }
+
+    /// Compression applied to the plaintext, if set, see [`crate::meta::COMPRESSION`].
+    pub fn compression(&self) -> Option<&str> {
+        crate::meta::get_str(&self.meta, crate::meta::COMPRESSION)
+    }
+
+    /// Identifier of the key the file was written with, if set, see
+    /// [`crate::meta::KEY_ID`].
+    pub fn key_id(&self) -> Option<&[u8]> {
+        self.meta.get(crate::meta::KEY_ID).map(Vec::as_slice)
+    }
+
+    /// Creation time of the file, if set, see [`crate::meta::CREATED`].
+    pub fn created(&self) -> Option<SystemTime> {
+        self.meta
+            .get(crate::meta::CREATED)
+            .and_then(|v| crate::meta::decode_time(v))
+    }
+
+    /// Read and decrypt the extended metadata, see [`EnardWriter::with_extended_meta`].
+    ///
+    /// Returns an empty map if the file doesn't have any. The position in the data is
+    /// unchanged afterwards.
+    pub fn extended_meta(&mut self) -> Result<MetaMap, EnardError> {
+        if self.ext_meta_size == 0 {
+            return Ok(MetaMap::new());
+        }
+        let pos = self.current.min(self.data_size);
+        self.readahead.clear();
+        let mut buf = Zeroizing::new(vec![0u8; self.ext_meta_size as usize]);
+        let result = self
+            .inner
+            .seek(SeekFrom::Start(self.data_start + self.data_size))
+            .and_then(|_| self.inner.read_exact(&mut buf))
+            .and_then(|_| {
+                self.cipher
+                    .try_seek(self.data_size)
+                    .and_then(|_| self.cipher.try_apply_keystream(&mut buf))
+                    .map_err(cipher_to_io_error)
+            });
+        // Put the inner reader and cipher back where `seek` expects them
+        self.inner.seek(SeekFrom::Start(self.data_start + pos))?;
+        self.cipher.try_seek(pos).map_err(cipher_to_io_error)?;
+        result?;
+        // This has been authenticated, so it only has to fit in the data
+        read_varint_meta(buf.as_slice(), self.ext_meta_size, &ParseLimits::UNLIMITED)
+    }
+
+    /// SHA-256 of the plaintext, if the writer stored it, see
+    /// [`EnardWriter::with_plaintext_hash`].
+    ///
+    /// The hash is read from the extended metadata, so it's covered by the MAC.
+    pub fn plaintext_hash(&mut self) -> Result<Option<[u8; 32]>, EnardError> {
+        match self.extended_meta()?.get(crate::meta::PLAINTEXT_SHA256) {
+            Some(value) => value
+                .as_slice()
+                .try_into()
+                .map(Some)
+                .map_err(|_| EnardError::InvalidLength),
+            None => Ok(None),
+        }
+    }
+
+    /// Read and decrypt the index of assets in the payload, if the writer stored one,
+    /// see [`crate::index`]. Use [`EnardReader::sub_reader`] to read an asset.
+    ///
+    /// The index is read from the extended metadata, so it's covered by the MAC.
+    pub fn open_index(&mut self) -> Result<Option<crate::index::Index>, EnardError> {
+        match self.extended_meta()?.get(crate::meta::INDEX) {
+            Some(value) => crate::index::Index::decode(value, self.data_size).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Format version of the enard file
+    pub fn version(&self) -> FormatVersion {
+        self.version
+    }
+
+    /// Size in bytes of the decrypted data
+    pub fn data_size(&self) -> u64 {
+        self.data_size
+    }
+
+    /// Size in bytes of the header, including padding
+    pub fn header_size(&self) -> u64 {
+        self.header_size
+    }
+
+    /// Offset in the inner reader where the data section starts
+    pub fn data_start(&self) -> u64 {
+        self.data_start
+    }
+
+    /// Allow seeking beyond the end of the data like [`std::fs::File`] does, reads
+    /// there return 0 bytes. By default such seeks fail with [`ErrorKind::InvalidInput`].
+    ///
+    /// Some format probers seek past the end of a stream and expect to see EOF, rather
+    /// than an error.
+    pub fn with_seek_past_end(mut self, allow: bool) -> Self {
+        self.seek_past_end = allow;
+        self
+    }
+
+    /// Returns `true` if the file has no data, see [`EnardWriter::write_empty`].
+    pub fn is_empty(&self) -> bool {
+        self.data_size == 0
+    }
+
+    /// Returns `true` if the current position is at the end of the data
+    pub fn is_eof(&self) -> bool {
+        self.current >= self.data_size
+    }
+
+    /// Returns a [`SubSeek`] which reads `len` bytes of the decrypted data starting at
+    /// `offset`, e.g. to hand out one asset from a pack.
+    ///
+    /// Returns an error if the range isn't inside the data.
+    pub fn sub_reader(&mut self, offset: u64, len: u64) -> io::Result<SubSeek<&mut Self>> {
+        let end = offset.checked_add(len);
+        if !matches!(end, Some(end) if end <= self.data_size) {
+            let msg = format!(
+                "range of {} bytes at {} is outside the data (size {})",
+                len, offset, self.data_size
+            );
+            return Err(io::Error::new(ErrorKind::InvalidInput, msg));
+        }
+        SubSeek::new(self, offset, len)
+    }
+
+    /// Skip forward `n` bytes, stopping at the end of the data. Returns the number of
+    /// bytes skipped.
+    ///
+    /// Small skips read past the data instead of seeking the inner reader.
+    pub fn skip(&mut self, n: u64) -> io::Result<u64> {
+        let n = n.min(self.data_size.saturating_sub(self.current));
+        self.seek(SeekFrom::Start(self.current + n))?;
+        Ok(n)
+    }
+
+    /// Decrypt the rest of the data into `wr`, returning the number of bytes copied.
+    ///
+    /// This is faster than [`io::copy`], which reads 8 KiB at a time, because the data is
+    /// read, decrypted and written [`COPY_BUFFER_SIZE`] bytes at a time (see
+    /// [`EnardReader::with_crypt_chunk`]), bypassing the read-ahead buffer.
+    pub fn copy_to<W: Write + ?Sized>(&mut self, wr: &mut W) -> io::Result<u64> {
+        let mut total = 0;
+        if !self.readahead.is_empty() && self.current < self.readahead_end() {
+            // Write out what's left of the read-ahead buffer first
+            let offset = (self.current - self.readahead_start) as usize;
+            wr.write_all(&self.readahead[offset..])?;
+            total += (self.readahead.len() - offset) as u64;
+            self.current = self.readahead_end();
+        }
+        self.readahead.clear();
+        let remaining = self.data_size.saturating_sub(self.current);
+        let mut buf = CryptBuf::new((self.crypt_chunk as u64).min(remaining) as usize);
+        while self.current < self.data_size {
+            let limit = buf.len().min((self.data_size - self.current) as usize);
+            let n = read_up_to(&mut self.inner, &mut buf[..limit])?;
+            if n == 0 {
+                break;
+            }
+            self.current += n as u64;
+            self.cipher
+                .try_apply_keystream(&mut buf[..n])
+                .map_err(cipher_to_io_error)?;
+            wr.write_all(&buf[..n])?;
+            total += n as u64;
+        }
+        Ok(total)
+    }
+
+    /// Enable reading ahead up to `bytes` bytes of data at a time.
+    ///
+    /// Reads smaller than `bytes` will fill an internal buffer which is decrypted
+    /// all at once, and further small reads are served from that buffer. This greatly
+    /// reduces overhead when the caller issues many small reads. Seeking within the
+    /// buffer doesn't touch the inner reader. Set to 0 (the default) to disable.
+    ///
+    /// `bytes` is rounded up to a multiple of [`CRYPT_BLOCK_SIZE`].
+    pub fn with_readahead(mut self, bytes: usize) -> Self {
+        self.readahead_size = round_to_crypt_block(bytes);
+        self
+    }
+
+    /// Maximum number of bytes read ahead, after rounding, 0 if read-ahead is disabled
+    pub fn readahead_size(&self) -> usize {
+        self.readahead_size
+    }
+
+    /// Set how many bytes [`EnardReader::copy_to`] reads and decrypts at a time, the
+    /// default is [`COPY_BUFFER_SIZE`]. `bytes` is rounded up to a multiple of
+    /// [`CRYPT_BLOCK_SIZE`].
+    pub fn with_crypt_chunk(mut self, bytes: usize) -> Self {
+        self.crypt_chunk = round_to_crypt_block(bytes.max(1));
+        self
+    }
+
+    /// Apply the read-ahead and crypt chunk sizes from `opts`. The inner buffer can only
+    /// be chosen when opening the file, see [`EnardReader::new_with_options`].
+    pub fn with_options(self, opts: &ReaderOptions) -> Self {
+        self.with_readahead(opts.readahead)
+            .with_crypt_chunk(opts.crypt_chunk)
+    }
+
+    /// Decrypt all of the data and compare it with the checksum in the metadata,
+    /// see [`crate::checksum`].
+    ///
+    /// The position in the data is unchanged afterwards.
+    pub fn verify_plaintext_checksum(&mut self) -> Result<(), EnardError> {
+        let expected = crate::checksum::stored_checksum(&self.meta)?;
+        let pos = self.current;
+        self.seek(SeekFrom::Start(0))?;
+        let actual = crate::checksum::checksum(&mut *self);
+        self.seek(SeekFrom::Start(pos))?;
+        let actual = actual?;
+        if actual != expected {
+            return Err(EnardError::ChecksumMismatch { expected, actual });
+        }
+        Ok(())
+    }
+
+    /// Verify the signature of the file, see [`crate::signature`].
+    ///
+    /// The position in the data is unchanged afterwards.
+    #[cfg(feature = "signature")]
+    pub fn verify_signature(
+        &mut self,
+        public_key: &crate::signature::VerifyingKey,
+    ) -> Result<(), EnardError> {
+        let pos = self.inner.stream_position()?;
+        let result = crate::signature::verify_at(
+            &mut self.inner,
+            self.data_start - self.header_size,
+            self.version,
+            self.header_size,
+            self.data_size + self.ext_meta_size,
+            public_key,
+        );
+        self.inner.seek(SeekFrom::Start(pos))?;
+        result
+    }
+
     /// Unwraps this [`EnardReader`], returning the underlying writer.
     pub fn into_inner(self) -> R {
         self.inner
     }
+
+    /// Unwraps this [`EnardReader`], returning the underlying reader and the metadata.
+    ///
+    /// The underlying reader is left somewhere in the data, so seek it back to the start
+    /// of the enard file before opening it again.
+    pub fn into_parts(self) -> (R, MetaMap) {
+        (self.inner, self.meta)
+    }
+}
+impl EnardReader<io::BufReader<std::fs::File>, BoxDynCipher> {
+    /// Open the enard file at `path` read-only, using [`BoxDynCipher`]. Same as
+    /// [`EnardReader::open_with_hint`] with [`crate::fs::AccessHint::Normal`].
+    pub fn open<P: AsRef<std::path::Path>, K: KeyProvider>(
+        path: P,
+        key: K,
+    ) -> Result<Self, EnardError> {
+        Self::open_with_hint(path, key, crate::fs::AccessHint::Normal)
+    }
+
+    /// Open the enard file at `path` with [`crate::fs::open`], telling the OS how it's
+    /// going to be read.
+    pub fn open_with_hint<P: AsRef<std::path::Path>, K: KeyProvider>(
+        path: P,
+        key: K,
+        hint: crate::fs::AccessHint,
+    ) -> Result<Self, EnardError> {
+        Self::new_boxed(crate::fs::open(path, hint)?, key)
+    }
+
+    /// Open the enard file at `path` like [`EnardReader::open`], with the buffer sizes in
+    /// `opts`, see [`crate::tuning`].
+    pub fn open_with_options<P: AsRef<std::path::Path>, K: KeyProvider>(
+        path: P,
+        key: K,
+        opts: &ReaderOptions,
+    ) -> Result<Self, EnardError> {
+        let file = crate::fs::open_unbuffered(path.as_ref(), crate::fs::AccessHint::Normal)?;
+        Self::new_with_options(file, key, opts)
+    }
+}
+impl<R> EnardReader<io::BufReader<R>, BoxDynCipher>
+where
+    R: Read + Seek,
+{
+    /// Same as [`EnardReader::new_boxed`] but wrapping `reader` in a
+    /// [`std::io::BufReader`] with a buffer of `capacity` bytes, or
+    /// [`crate::tuning::INNER_BUFFER_SIZE`] if `capacity` is `0`.
+    ///
+    /// Short forward seeks are served from the buffer. Use
+    /// [`std::io::BufReader::into_inner`] on the result of [`EnardReader::into_inner`]
+    /// to get `reader` back.
+    pub fn new_buffered<K: KeyProvider>(
+        reader: R,
+        key: K,
+        capacity: usize,
+    ) -> Result<Self, EnardError> {
+        let capacity = if capacity == 0 {
+            crate::tuning::INNER_BUFFER_SIZE
+        } else {
+            capacity
+        };
+        Self::new_boxed(io::BufReader::with_capacity(capacity, reader), key)
+    }
+
+    /// Same as [`EnardReader::new_boxed`] with the buffer sizes in `opts`, see
+    /// [`crate::tuning`]. `reader` is wrapped in a [`std::io::BufReader`] with a buffer
+    /// of [`ReaderOptions::inner_buffer`] bytes.
+    pub fn new_with_options<K: KeyProvider>(
+        reader: R,
+        key: K,
+        opts: &ReaderOptions,
+    ) -> Result<Self, EnardError> {
+        let reader = io::BufReader::with_capacity(opts.inner_buffer, reader);
+        Ok(Self::new_boxed(reader, key)?.with_options(opts))
+    }
 }
 impl<R> EnardReader<R, BoxDynCipher>
 where
@@ -66,9 +704,154 @@ where
 {
     /// Create a new [`EnardReader`] that will determine the cipher based on the metadata in
     /// the enard file.
-    pub fn new_boxed(reader: R, key: &[u8]) -> Result<Self, EnardError> {
+    pub fn new_boxed<K: KeyProvider>(reader: R, key: K) -> Result<Self, EnardError> {
         Self::new(reader, BoxDynCipher::factory(), key)
     }
+
+    /// Same as [`EnardReader::new_boxed`] but for an enard file starting at `offset`.
+    /// See [`EnardReader::new_at`].
+    pub fn new_boxed_at<K: KeyProvider>(
+        reader: R,
+        key: K,
+        offset: u64,
+    ) -> Result<Self, EnardError> {
+        Self::new_at(reader, BoxDynCipher::factory(), key, offset)
+    }
+
+    /// Same as [`EnardReader::restore_position`] using [`BoxDynCipher`].
+    pub fn restore_position_boxed<K: KeyProvider>(
+        reader: R,
+        key: K,
+        saved: &SavedPosition,
+    ) -> Result<Self, EnardError> {
+        Self::restore_position(reader, BoxDynCipher::factory(), key, saved)
+    }
+
+    /// Open a file written by [`EnardWriter::new_unencrypted`], no key is needed.
+    ///
+    /// Fails with [`EnardError::MissingMeta`] if the file isn't marked as unencrypted.
+    pub fn new_unencrypted(reader: R) -> Result<Self, EnardError> {
+        let key = |meta: &MetaMap| {
+            if meta.contains_key(crate::meta::UNENCRYPTED) {
+                Ok(Zeroizing::new(Vec::new()))
+            } else {
+                Err(EnardError::new_missing_meta(crate::meta::UNENCRYPTED))
+            }
+        };
+        Self::new_boxed(reader, key)
+    }
+
+    /// Open a file with the private key of one of its recipients, see
+    /// [`EnardWriter::add_recipient`].
+    ///
+    /// Fails with [`EnardError::WrongKey`] if the file wasn't written for this key.
+    #[cfg(feature = "recipients")]
+    pub fn new_with_private_key(
+        reader: R,
+        key: &crate::recipients::StaticSecret,
+    ) -> Result<Self, EnardError> {
+        Self::new_boxed(reader, key.clone())
+    }
+
+    /// Returns `true` if `reader` starts with the enard magic at its current position,
+    /// which is unchanged afterwards. Nothing else is checked, see [`crate::auto`] to
+    /// open files which may not be encrypted.
+    pub fn is_enard(reader: &mut R) -> io::Result<bool> {
+        let pos = reader.stream_position()?;
+        let result = crate::locate::check_magic(&mut *reader, pos);
+        reader.seek(SeekFrom::Start(pos))?;
+        result
+    }
+
+    /// Create a new [`EnardReader`] for an enard file embedded somewhere in `reader`,
+    /// usually at the end of an executable. See [`crate::locate`] for details.
+    pub fn from_embedded<K: KeyProvider>(mut reader: R, key: K) -> Result<Self, EnardError> {
+        let offset = crate::locate::find(&mut reader)?.ok_or(EnardError::NotFound)?;
+        Self::new_boxed_at(reader, key, offset)
+    }
+
+    /// Create a new [`EnardReader`] with whichever of `keys` opens the file, returning
+    /// the reader and the index of the key in `keys`, e.g. for a client holding the
+    /// current and previous keys of a game which rotates them.
+    ///
+    /// Keys whose fingerprint matches the [`crate::meta::KEY_ID`] are tried first, and
+    /// keys which don't match the key check value ([`EnardWriter::with_key_check`]) are
+    /// skipped. Otherwise each key which is tried costs a pass over the file to check
+    /// the MAC. Returns [`EnardError::WrongKey`] if none of the keys open the file.
+    pub fn new_with_keys<T: AsRef<[u8]>>(
+        mut reader: R,
+        keys: &[T],
+    ) -> Result<(Self, usize), EnardError> {
+        let start = reader.stream_position()?;
+        // Order to try the keys in, decided once the metadata is known
+        let order = std::cell::RefCell::new(None);
+        for attempt in 0.. {
+            reader.seek(SeekFrom::Start(start))?;
+            let chosen = std::cell::Cell::new(None);
+            let key = |meta: &MetaMap| -> Result<Zeroizing<Vec<u8>>, EnardError> {
+                let mut order = order.borrow_mut();
+                let order = order.get_or_insert_with(|| key_order(meta, keys));
+                let i = *order.get(attempt).ok_or(EnardError::WrongKey)?;
+                chosen.set(Some(i));
+                Ok(Zeroizing::new(keys[i].as_ref().to_vec()))
+            };
+            let result = EnardReader::new_boxed(&mut reader, key);
+            let i = match chosen.get() {
+                Some(i) => i,
+                None => return Err(result.err().unwrap_or(EnardError::WrongKey)),
+            };
+            match result {
+                Ok(rd) => {
+                    // Open it again owning `reader`, without checking the MAC again
+                    let saved = rd.save_position();
+                    let rd = Self::restore_position_boxed(reader, keys[i].as_ref(), &saved)?;
+                    return Ok((rd, i));
+                }
+                Err(e) => match e.root_cause() {
+                    EnardError::MacError(_)
+                    | EnardError::WrongKey
+                    | EnardError::InvalidLength
+                    | EnardError::KeyLengthMismatch { .. } => {}
+                    _ => return Err(e),
+                },
+            }
+        }
+        unreachable!("the loop only ends by returning")
+    }
+}
+
+/// Indices of the keys [`EnardReader::new_with_keys`] tries for a file with `meta`, in
+/// order.
+fn key_order<T: AsRef<[u8]>>(meta: &MetaMap, keys: &[T]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..keys.len())
+        .filter(|&i| crate::kdf::check_key(meta, keys[i].as_ref()).is_ok())
+        .collect();
+    if let Some(id) = meta.get(crate::meta::KEY_ID) {
+        // The sort is stable, so the other keys keep their order
+        order.sort_by_key(|&i| crate::kdf::key_fingerprint(keys[i].as_ref())[..] != id[..]);
+    }
+    order
+}
+impl EnardReader<Box<dyn ReadSeek>, BoxDynCipher> {
+    /// Same as [`EnardReader::new_boxed`] but for a boxed reader, so readers from
+    /// different sources (files, memory, a network cache) all have the same type.
+    ///
+    /// ```rust
+    /// # use enard::{stream::ReadSeek, EnardReader};
+    /// # fn main() -> Result<(), enard::EnardError> {
+    /// # let mut wr = enard::EnardWriter::builder(std::io::Cursor::new(Vec::new()))
+    /// #     .key([0x42; 32]).iv([0x24; 12]).build()?;
+    /// # wr.write_complete(&b"hi"[..])?;
+    /// # let bytes = wr.into_inner().into_inner();
+    /// let source: Box<dyn ReadSeek> = Box::new(std::io::Cursor::new(bytes));
+    /// let reader = EnardReader::new_dyn(source, [0x42u8; 32])?;
+    /// assert_eq!(reader.data_size(), 2);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn new_dyn<K: KeyProvider>(reader: Box<dyn ReadSeek>, key: K) -> Result<Self, EnardError> {
+        Self::new_boxed(reader, key)
+    }
 }
 
 // Manually implement debug for user convenience and to ensure we don't leak sensitive information
@@ -82,20 +865,47 @@ where
         f.debug_struct("EnardReader")
             .field("inner", &self.inner)
             .field("cipher", &self.cipher.get_name())
+            .field("version", &self.version)
+            .field("header_size", &self.header_size)
             .field("data_start", &self.data_start)
             .field("data_size", &self.data_size)
             .field("current", &self.current)
             .field("meta", &self.meta)
+            .field("readahead_size", &self.readahead_size)
+            .field("seek_past_end", &self.seek_past_end)
             .finish()
     }
 }
 
-impl<R, C> Read for EnardReader<R, C>
+impl<R, C> EnardReader<R, C>
 where
     R: Read + Seek,
     C: DynCipher,
 {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    /// Create a reader for a parsed file, `inner` and the cipher must be at the start of
+    /// the data.
+    pub(crate) fn from_parsed(inner: R, parsed: Parsed<C>, ext_meta_size: u64) -> Self {
+        EnardReader {
+            inner,
+            cipher: parsed.cipher,
+            iv: parsed.header.iv.into_boxed_slice(),
+            version: parsed.version,
+            header_size: parsed.header_size,
+            data_start: parsed.header_start + parsed.header_size,
+            data_size: parsed.data_size - ext_meta_size,
+            ext_meta_size,
+            current: 0,
+            meta: parsed.header.meta,
+            readahead: CryptBuf::default(),
+            readahead_start: 0,
+            readahead_size: 0,
+            crypt_chunk: COPY_BUFFER_SIZE,
+            seek_past_end: false,
+        }
+    }
+
+    /// Read and decrypt directly into `buf`, bypassing the read-ahead buffer.
+    fn read_direct(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         // Determine the maximum number of bytes we're allowed to read
         let limit = buf.len().min((self.data_size - self.current) as usize);
         // Read the data into the destination buffer
@@ -106,238 +916,1500 @@ where
         self.cipher
             .try_apply_keystream(&mut buf[0..n])
             .map_err(cipher_to_io_error)?;
-        // println!("current = {}, n = {}, cipher_pos = {} -> {}", self.current, n, cipher_pos_before, cipher_pos);
         Ok(n)
     }
-}
 
-impl<R, C> Seek for EnardReader<R, C>
-where
-    R: Read + Seek,
-    C: DynCipher,
-{
-    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
-        let new_pos_raw = match pos {
-            SeekFrom::Current(rel) => self.current as i64 + rel,
-            SeekFrom::Start(pos) => pos as i64,
-            SeekFrom::End(rel) => self.data_size as i64 + rel,
-        };
-        if new_pos_raw < 0 || new_pos_raw > self.data_size as i64 {
-            let msg = format!(
-                "invalid seek to a negative or overflowing position: {:?}",
-                pos
-            );
-            return Err(io::Error::new(ErrorKind::InvalidInput, msg));
-        }
-        // Note: if the cipher seek fails, the stream will be in an invalid state.
-        // However seek failing is considered an error, so this shouldn't be used after a failure.
-        let new_pos = new_pos_raw as u64;
-        self.inner
-            .seek(SeekFrom::Start(self.data_start + new_pos))?;
-        self.cipher.try_seek(new_pos).map_err(cipher_to_io_error)?;
-        self.current = new_pos;
-        Ok(new_pos)
+    /// Data position just after the end of the read-ahead buffer.
+    /// When the buffer isn't empty, this is where the inner reader and cipher are.
+    fn readahead_end(&self) -> u64 {
+        self.readahead_start + self.readahead.len() as u64
     }
 
-    fn stream_position(&mut self) -> io::Result<u64> {
-        Ok(self.current)
+    fn fill_readahead(&mut self) -> io::Result<()> {
+        let limit = self
+            .readahead_size
+            .min((self.data_size - self.current) as usize);
+        self.readahead.resize(limit);
+        // Fill the whole buffer, so the next fill starts on a cipher block
+        let n = read_up_to(&mut self.inner, &mut self.readahead)?;
+        self.readahead.truncate(n);
+        self.cipher
+            .try_apply_keystream(&mut self.readahead)
+            .map_err(cipher_to_io_error)?;
+        self.readahead_start = self.current;
+        Ok(())
     }
 }
 
-fn cipher_to_io_error(e: StreamCipherError) -> io::Error {
-    io::Error::new(ErrorKind::Other, format!("{:?}", e))
-}
-
-/// Reader-builder that parses the enard format and returns a new [`EnardReader`].
-pub(crate) struct EnardBuilder<R, C, Cf> {
-    reader: R,
-    factory: Cf,
-    key: Vec<u8>,
-    phantom: PhantomData<C>,
-}
-impl<R, C, Cf> EnardBuilder<R, C, Cf>
+impl<R, C> Read for EnardReader<R, C>
 where
     R: Read + Seek,
     C: DynCipher,
-    Cf: CipherFactory<C>,
 {
-    pub fn new(reader: R, factory: Cf, key: &[u8]) -> Self {
-        let key = Vec::from(key);
-        let phantom = PhantomData;
-        Self {
-            reader,
-            factory,
-            key,
-            phantom,
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.current >= self.data_size {
+            // Possibly past the end, see `with_seek_past_end`
+            return Ok(0);
         }
-    }
-
-    pub fn build(mut self) -> Result<EnardReader<R, C>, EnardError> {
-        let mut magic_buf = [0u8; MAGIC.len()];
-        self.reader.read_exact(&mut magic_buf)?;
-        if &magic_buf != MAGIC {
-            return Err(EnardError::new_invalid_magic(MAGIC, &magic_buf));
+        if self.readahead.is_empty() || self.current >= self.readahead_end() {
+            // Either the read-ahead buffer is used up or it's disabled, and
+            // in both cases the inner reader is at `current`.
+            self.readahead.clear();
+            if buf.len() >= self.readahead_size {
+                return self.read_direct(buf);
+            }
+            self.fill_readahead()?;
         }
+        let offset = (self.current - self.readahead_start) as usize;
+        let n = buf.len().min(self.readahead.len() - offset);
+        buf[..n].copy_from_slice(&self.readahead[offset..offset + n]);
+        self.current += n as u64;
+        Ok(n)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
+        if self.current >= self.data_size {
+            return Ok(0);
+        }
+        let total: usize = bufs.iter().map(|b| b.len()).sum();
+        let buffered = !self.readahead.is_empty() && self.current < self.readahead_end();
+        if buffered || total < self.readahead_size {
+            // Same as the default implementation, small reads go through the read-ahead buffer
+            let buf = bufs.iter_mut().find(|b| !b.is_empty());
+            return self.read(buf.map_or(&mut [][..], |b| &mut **b));
+        }
+        self.readahead.clear();
+        // Don't read beyond the end of the data
+        let mut remaining = self.data_size - self.current;
+        let mut limited = Vec::with_capacity(bufs.len());
+        for b in bufs.iter_mut() {
+            let n = (b.len() as u64).min(remaining) as usize;
+            limited.push(IoSliceMut::new(&mut b[..n]));
+            remaining -= n as u64;
+        }
+        let n = self.inner.read_vectored(&mut limited)?;
+        self.current += n as u64;
+        // Decrypt the buffers in order so the keystream lines up
+        let mut left = n;
+        for b in limited.iter_mut() {
+            if left == 0 {
+                break;
+            }
+            let k = b.len().min(left);
+            self.cipher
+                .try_apply_keystream(&mut b[..k])
+                .map_err(cipher_to_io_error)?;
+            left -= k;
+        }
+        Ok(n)
+    }
+}
+
+impl<R, C> Seek for EnardReader<R, C>
+where
+    R: Read + Seek,
+    C: DynCipher,
+{
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Current(rel) => crate::stream::checked_add_signed(self.current, rel),
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::End(rel) => crate::stream::checked_add_signed(self.data_size, rel),
+        };
+        let new_pos = match new_pos {
+            Some(new_pos) if new_pos <= self.data_size || self.seek_past_end => new_pos,
+            _ => {
+                let msg = format!(
+                    "invalid seek to a negative or overflowing position: {:?}",
+                    pos
+                );
+                return Err(io::Error::new(ErrorKind::InvalidInput, msg));
+            }
+        };
+        let past_end = new_pos > self.data_size;
+        // Note: if the cipher seek fails, the stream will be in an invalid state.
+        // However seek failing is considered an error, so this shouldn't be used after a failure.
+        if past_end {
+            // Leave the inner reader and cipher at the end, nothing past it can be read
+            self.seek(SeekFrom::Start(self.data_size))?;
+            self.current = new_pos;
+            return Ok(new_pos);
+        }
+        if !self.readahead.is_empty()
+            && new_pos >= self.readahead_start
+            && new_pos <= self.readahead_end()
+        {
+            // Still inside the read-ahead buffer, no need to touch the inner reader
+            self.current = new_pos;
+            return Ok(new_pos);
+        }
+        // Where the inner reader and cipher actually are
+        let inner_pos = if self.readahead.is_empty() {
+            self.current.min(self.data_size)
+        } else {
+            self.readahead_end()
+        };
+        self.readahead.clear();
+        if new_pos == inner_pos {
+            self.current = new_pos;
+            return Ok(new_pos);
+        }
+        if new_pos > inner_pos && new_pos - inner_pos <= SKIP_READ_LIMIT {
+            let skip = new_pos - inner_pos;
+            let n = io::copy(&mut (&mut self.inner).take(skip), &mut io::sink())?;
+            if n != skip {
+                return Err(io::Error::from(ErrorKind::UnexpectedEof));
+            }
+        } else {
+            self.inner
+                .seek(SeekFrom::Start(self.data_start + new_pos))?;
+        }
+        self.cipher.try_seek(new_pos).map_err(cipher_to_io_error)?;
+        self.current = new_pos;
+        Ok(new_pos)
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        Ok(self.current)
+    }
+}
+
+/// Error for parsers which can't read obfuscated headers, see
+/// [`EnardWriter::with_obfuscated_header`].
+pub(crate) fn obfuscated_header() -> io::Error {
+    let msg = "the header is obfuscated, which requires opening the file with the key";
+    io::Error::new(ErrorKind::Unsupported, msg)
+}
+
+/// Error for readers which can't seek to the footer, see [`EnardWriter::finish_streaming`].
+pub(crate) fn sizes_in_footer() -> io::Error {
+    let msg = "header and data sizes are in the footer, which requires seeking";
+    io::Error::new(ErrorKind::Unsupported, msg)
+}
+
+/// Adds the phase and offset of a parse error, for use with `map_err`.
+fn at<E: Into<EnardError>>(phase: ParsePhase, offset: u64) -> impl FnOnce(E) -> EnardError {
+    move |e| e.into().at(phase, offset)
+}
+
+pub(crate) fn cipher_to_io_error(e: StreamCipherError) -> io::Error {
+    io::Error::new(ErrorKind::Other, format!("{:?}", e))
+}
+
+/// Creates the MAC and cipher for a file once [`EnardBuilder`] has read its header.
+pub(crate) trait OpenKeys<C> {
+    /// Create the MAC, returning the MAC key as well if it's known.
+    fn open_mac(
+        &mut self,
+        header: &Header,
+        mac_factory: &dyn MacFactory,
+    ) -> Result<(BoxDynMac, Zeroizing<Vec<u8>>), EnardError>;
+
+    /// Create the cipher, this is only called once the MAC has been verified.
+    fn open_cipher(&mut self, header: &Header) -> Result<C, EnardError>;
+
+    /// Derive the key for an obfuscated header, which has to be read before the
+    /// metadata is known.
+    fn header_key(&mut self) -> Result<Zeroizing<[u8; 32]>, EnardError>;
+}
+impl<C, O: OpenKeys<C> + ?Sized> OpenKeys<C> for &mut O {
+    fn open_mac(
+        &mut self,
+        header: &Header,
+        mac_factory: &dyn MacFactory,
+    ) -> Result<(BoxDynMac, Zeroizing<Vec<u8>>), EnardError> {
+        (**self).open_mac(header, mac_factory)
+    }
+
+    fn open_cipher(&mut self, header: &Header) -> Result<C, EnardError> {
+        (**self).open_cipher(header)
+    }
+
+    fn header_key(&mut self) -> Result<Zeroizing<[u8; 32]>, EnardError> {
+        (**self).header_key()
+    }
+}
+
+/// Opens files with a [`KeyProvider`] and a [`CipherFactory`].
+pub(crate) struct WithKey<Cf, K> {
+    factory: Cf,
+    key: K,
+    cipher_key: Zeroizing<Vec<u8>>,
+}
+impl<Cf, K> WithKey<Cf, K> {
+    pub fn new(factory: Cf, key: K) -> Self {
+        Self {
+            factory,
+            key,
+            cipher_key: Zeroizing::default(),
+        }
+    }
+
+    /// Returns the factory and the cipher key, which is only known once the MAC has
+    /// been created.
+    pub fn into_cipher_parts(self) -> (Cf, Zeroizing<Vec<u8>>) {
+        (self.factory, self.cipher_key)
+    }
+}
+impl<C, Cf, K> OpenKeys<C> for WithKey<Cf, K>
+where
+    Cf: CipherFactory<C>,
+    K: KeyProvider,
+{
+    fn open_mac(
+        &mut self,
+        header: &Header,
+        mac_factory: &dyn MacFactory,
+    ) -> Result<(BoxDynMac, Zeroizing<Vec<u8>>), EnardError> {
+        let key = self.key.key_for(&header.meta)?;
+        crate::kdf::check_key(&header.meta, &key)?;
+        let (cipher_key, mac_key) = crate::kdf::split_keys(&header.meta, &key)?;
+        // A key of the wrong size can't be right, so say so rather than failing the MAC
+        self.factory
+            .validate(&header.cipher, cipher_key.len(), header.iv.len())?;
+        let name = crate::mac::mac_name(&header.meta);
+        let mac = crate::mac::check_output_size(mac_factory.create(name, &mac_key)?, name)?;
+        self.cipher_key = cipher_key;
+        Ok((mac, mac_key))
+    }
+
+    fn open_cipher(&mut self, header: &Header) -> Result<C, EnardError> {
+        self.factory
+            .create(&header.cipher, &self.cipher_key, &header.iv)
+    }
+
+    fn header_key(&mut self) -> Result<Zeroizing<[u8; 32]>, EnardError> {
+        let key = self.key.key_for(&MetaMap::new())?;
+        Ok(crate::kdf::header_key(&key))
+    }
+}
+
+/// Opens files with a [`SealedKey`], the MAC key is never known.
+pub(crate) struct Sealed<'a, S: ?Sized>(&'a S);
+impl<'a, C, S> OpenKeys<C> for Sealed<'a, S>
+where
+    S: SealedKey<C> + ?Sized,
+{
+    fn open_mac(
+        &mut self,
+        header: &Header,
+        _mac_factory: &dyn MacFactory,
+    ) -> Result<(BoxDynMac, Zeroizing<Vec<u8>>), EnardError> {
+        let name = crate::mac::mac_name(&header.meta);
+        let mac = crate::mac::check_output_size(self.0.create_mac(name, &header.meta)?, name)?;
+        Ok((mac, Zeroizing::default()))
+    }
+
+    fn open_cipher(&mut self, header: &Header) -> Result<C, EnardError> {
+        self.0
+            .create_cipher(&header.cipher, &header.iv, &header.meta)
+    }
+
+    fn header_key(&mut self) -> Result<Zeroizing<[u8; 32]>, EnardError> {
+        let msg = "sealed keys can't read obfuscated headers";
+        Err(io::Error::new(ErrorKind::Unsupported, msg).into())
+    }
+}
+
+/// Reader-builder that parses the enard format and returns a new [`EnardReader`].
+pub(crate) struct EnardBuilder<R, C, O> {
+    reader: R,
+    keys: O,
+    /// Offset of the enard file in `reader`, or `None` to use the current position
+    start: Option<u64>,
+    /// Expected magic, see [`EnardWriter::with_magic`]
+    magic: Vec<u8>,
+    /// See [`EnardWriter::with_aad`]
+    aad: Zeroizing<Vec<u8>>,
+    limits: ParseLimits,
+    mac_factory: Box<dyn MacFactory>,
+    phantom: PhantomData<C>,
+}
+impl<R, C, Cf, K> EnardBuilder<R, C, WithKey<Cf, K>>
+where
+    R: Read + Seek,
+    C: DynCipher,
+    Cf: CipherFactory<C>,
+    K: KeyProvider,
+{
+    pub fn new(reader: R, factory: Cf, key: K) -> Self {
+        EnardBuilder::with_keys(reader, WithKey::new(factory, key))
+    }
+}
+impl<R, C, O> EnardBuilder<R, C, O>
+where
+    R: Read + Seek,
+    C: DynCipher,
+    O: OpenKeys<C>,
+{
+    pub fn with_keys(reader: R, keys: O) -> Self {
+        let phantom = PhantomData;
+        Self {
+            reader,
+            keys,
+            start: None,
+            magic: MAGIC.to_vec(),
+            aad: Zeroizing::default(),
+            limits: ParseLimits::default(),
+            mac_factory: Box::new(DefaultMacFactory),
+            phantom,
+        }
+    }
+
+    /// Reject files larger than `limits`.
+    pub fn limits(mut self, limits: ParseLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Create MACs with `factory` instead of [`DefaultMacFactory`].
+    pub fn mac_factory(mut self, factory: Box<dyn MacFactory>) -> Self {
+        self.mac_factory = factory;
+        self
+    }
+
+    /// Parse the enard file starting at `offset` instead of the current position.
+    pub fn start_at(mut self, offset: u64) -> Self {
+        self.start = Some(offset);
+        self
+    }
+
+    /// Expect the file to start with `magic` instead of [`MAGIC`].
+    pub fn magic(mut self, magic: &[u8]) -> Self {
+        self.magic = magic.to_vec();
+        self
+    }
+
+    /// Verify the MAC with the additional authenticated data `aad`, see
+    /// [`EnardWriter::with_aad`].
+    pub fn aad(mut self, aad: &[u8]) -> Self {
+        self.aad = Zeroizing::new(aad.to_vec());
+        self
+    }
+
+    pub fn build(self) -> Result<EnardReader<R, C>, EnardError> {
+        let (mut inner, mut parsed) = self.parse()?;
+        let ext_meta_size = extended_meta_size(&mut inner, &mut parsed)?;
+        Ok(EnardReader::from_parsed(inner, parsed, ext_meta_size))
+    }
+
+    /// Parse and verify the enard file, leaving the reader at the start of the data.
+    pub fn parse(mut self) -> Result<(R, Parsed<C>), EnardError> {
+        let start = match self.start {
+            Some(offset) => self.reader.seek(SeekFrom::Start(offset))?,
+            None => self.reader.stream_position()?,
+        };
+        let mut magic_buf = vec![0u8; self.magic.len()];
+        self.reader
+            .read_exact(&mut magic_buf)
+            .map_err(at(ParsePhase::Magic, start))?;
+        if magic_buf != self.magic {
+            return Err(
+                EnardError::new_invalid_magic(&self.magic, &magic_buf).at(ParsePhase::Magic, start)
+            );
+        }
+
+        let offset = start + self.magic.len() as u64;
+        let field = self
+            .reader
+            .read_u16::<LE>()
+            .map_err(at(ParsePhase::Header, offset))?;
+        let (version, obfuscated) =
+            FormatVersion::from_field(field).map_err(at(ParsePhase::Header, offset))?;
+        // Header size is a u32 in v1 and u64 in v2.
+        // This SHOULD be padded to make the data 8-byte aligned, but it's not required.
+        let header_size = match version {
+            FormatVersion::V1 => self.reader.read_u32::<LE>().map(u64::from),
+            FormatVersion::V2 => self.reader.read_u64::<LE>(),
+        }
+        .map_err(at(ParsePhase::Header, offset + 2))?;
+        // Next comes the data size. This is useful both to make sure we don't
+        // read outside the data, but also to easily jump to the MAC which is at the file end.
+        let data_size = self.reader.read_u64::<LE>().map_err(at(
+            ParsePhase::Header,
+            offset + (version.header_start() - MAGIC.len()) as u64 - 8,
+        ))?;
+        // Streaming writers can't go back to fill in the sizes, so they're in the footer
+        let (header_size, data_size) = if header_size == 0 && data_size == 0 {
+            self.read_footer()?
+        } else {
+            (header_size, data_size)
+        };
+        self.limits.check_sizes(header_size, data_size)?;
+        let flags = (obfuscated, field & HEADER_CHECKSUM_FLAG != 0);
+        self.read_header(version, flags, header_size, data_size)
+    }
+
+    /// Read the sizes from the footer at the end of the reader, see
+    /// [`EnardWriter::finish_streaming`]. The reader is left where it was.
+    fn read_footer(&mut self) -> Result<(u64, u64), EnardError> {
+        let header_start = self.reader.stream_position()?;
+        let end = self.reader.seek(SeekFrom::End(0))?;
+        let expected = header_start + FOOTER_SIZE as u64;
+        if end < expected {
+            return Err(EnardError::Truncated {
+                expected,
+                actual: end,
+            });
+        }
+        let footer_start = end - FOOTER_SIZE as u64;
+        self.reader.seek(SeekFrom::Start(footer_start))?;
+        let mut footer = [0u8; FOOTER_SIZE];
+        self.reader
+            .read_exact(&mut footer)
+            .map_err(at(ParsePhase::Footer, footer_start))?;
+        let header_size = LE::read_u64(&footer[..8]);
+        let data_size = LE::read_u64(&footer[8..16]);
+        let magic_buf = &footer[16..];
+        if magic_buf != FOOTER_MAGIC {
+            return Err(EnardError::new_invalid_magic(FOOTER_MAGIC, magic_buf)
+                .at(ParsePhase::Footer, footer_start + 16));
+        }
+        self.reader.seek(SeekFrom::Start(header_start))?;
+        Ok((header_size, data_size))
+    }
+
+    fn read_header(
+        mut self,
+        version: FormatVersion,
+        (obfuscated, checksummed): (bool, bool),
+        header_size: u64,
+        data_size: u64,
+    ) -> Result<(R, Parsed<C>), EnardError> {
+        // We'll need to know the header position for later
+        let header_start = self.reader.stream_position()?;
+        // Calculate the start of the data given
+        let data_start = header_start + header_size;
+        // Make sure the file is long enough before going through the MAC,
+        // so truncated files get a useful error.
+        let expected = data_start
+            .checked_add(data_size)
+            .and_then(|v| v.checked_add(TAG_SIZE as u64))
+            .unwrap_or(u64::MAX);
+        let actual = self.reader.seek(SeekFrom::End(0))?;
+        if actual < expected {
+            return Err(EnardError::Truncated { expected, actual });
+        }
+        self.reader.seek(SeekFrom::Start(header_start))?;
+        if checksummed {
+            // The sizes have been checked against the limits, so the header fits in memory
+            let mut buf = vec![0u8; header_size as usize];
+            self.reader
+                .read_exact(&mut buf)
+                .map_err(at(ParsePhase::Header, header_start))?;
+            check_header_checksum(&buf).map_err(at(ParsePhase::Header, header_start))?;
+            self.reader.seek(SeekFrom::Start(header_start))?;
+        }
+        // The key provider gets to look at the metadata, so the header has to be read
+        // before the MAC can be verified.
+        let header = if obfuscated {
+            self.read_obfuscated_header(version, header_start, header_size)?
+        } else {
+            let mut rd = io::BufReader::new((&mut self.reader).take(header_size));
+            // Position of `rd` in the reader, for errors
+            let offset = |rd: &io::BufReader<io::Take<&mut R>>| {
+                data_start - rd.get_ref().limit() - rd.buffer().len() as u64
+            };
+            let (cipher, iv) = Header::read_cipher(&mut rd, version, header_size)
+                .map_err(|e| e.at(ParsePhase::Header, offset(&rd)))?;
+            let meta = Header::read_meta(&mut rd, version, header_size, &self.limits)
+                .map_err(|e| e.at(ParsePhase::Meta, offset(&rd)))?;
+            Header { cipher, iv, meta }
+        };
+        let (mac, mac_key) = self.keys.open_mac(&header, self.mac_factory.as_ref())?;
+        // Now jump back and verify the MAC
+        self.reader.seek(SeekFrom::Start(header_start))?;
+        let sizes = (header_start, header_size, data_size);
+        Self::verify_mac(&mut self.reader, mac, &self.aad, sizes)?;
+        // Try to create the cipher
+        let cipher = self.keys.open_cipher(&header)?;
+        // Seek back to the start of the data (avoid padding)
+        self.reader.seek(SeekFrom::Start(data_start))?;
+
+        let parsed = Parsed {
+            version,
+            header_start,
+            header_size,
+            data_size,
+            header,
+            mac_key,
+            cipher,
+        };
+        Ok((self.reader, parsed))
+    }
+
+    /// Read and parse a header obfuscated by [`EnardWriter::with_obfuscated_header`],
+    /// the reader must be at `header_start`.
+    fn read_obfuscated_header(
+        &mut self,
+        version: FormatVersion,
+        header_start: u64,
+        header_size: u64,
+    ) -> Result<Header, EnardError> {
+        use crate::kdf::HEADER_SALT_SIZE;
+        if header_size < HEADER_SALT_SIZE as u64 {
+            let e = EnardError::new_block_size(HEADER_SALT_SIZE as u64, header_size);
+            return Err(e.at(ParsePhase::Header, header_start));
+        }
+        // The sizes have been checked against the limits, so the header fits in memory
+        let mut buf = Zeroizing::new(vec![0u8; header_size as usize]);
+        self.reader
+            .read_exact(&mut buf)
+            .map_err(at(ParsePhase::Header, header_start))?;
+        let header_key = self.keys.header_key()?;
+        let (salt, rest) = buf.split_at_mut(HEADER_SALT_SIZE);
+        crate::kdf::obfuscate_header(&header_key, salt, rest);
+        let size = rest.len() as u64;
+        let mut rd = &rest[..];
+        // Position of `rd` in the reader, for errors
+        let offset = |rd: &[u8]| header_start + header_size - rd.len() as u64;
+        let (cipher, iv) = Header::read_cipher(&mut rd, version, size)
+            .map_err(|e| e.at(ParsePhase::Header, offset(rd)))?;
+        let meta = Header::read_meta(&mut rd, version, size, &self.limits)
+            .map_err(|e| e.at(ParsePhase::Meta, offset(rd)))?;
+        Ok(Header { cipher, iv, meta })
+    }
+
+    /// Verify the MAC of the header, data and `aad`, `reader` must be at `header_start`.
+    fn verify_mac<R2: Read>(
+        mut reader: R2,
+        mut mac: BoxDynMac,
+        aad: &[u8],
+        (header_start, header_size, data_size): (u64, u64, u64),
+    ) -> Result<(), EnardError> {
+        let mut rd = (&mut reader).take(header_size + data_size);
+        if let Err(e) = io::copy(&mut rd, &mut mac) {
+            let offset = header_start + header_size + data_size - rd.limit();
+            let phase = if offset < header_start + header_size {
+                ParsePhase::Header
+            } else {
+                ParsePhase::Data
+            };
+            return Err(EnardError::from(e).at(phase, offset));
+        }
+        // Assume the mac tag is right after the data
+        let mut tag_buf = [0u8; TAG_SIZE];
+        reader
+            .read_exact(&mut tag_buf)
+            .map_err(at(ParsePhase::Tag, header_start + header_size + data_size))?;
+        crate::mac::update_aad(&mut mac, aad);
+        crate::mac::verify(mac, &tag_buf)
+    }
+}
+
+/// Position of an [`EnardReader`] saved by [`EnardReader::save_position`], along with the
+/// parsed header, so the file can be opened again without verifying the MAC.
+#[derive(Clone)]
+pub struct SavedPosition {
+    version: FormatVersion,
+    header_start: u64,
+    header_size: u64,
+    /// Size in bytes of the data, including the extended metadata
+    data_size: u64,
+    ext_meta_size: u64,
+    header: Header,
+    pos: u64,
+    readahead_size: usize,
+    crypt_chunk: usize,
+    seek_past_end: bool,
+}
+impl SavedPosition {
+    /// Saved position in the data
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+}
+impl Debug for SavedPosition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SavedPosition")
+            .field("version", &self.version)
+            .field("header_start", &self.header_start)
+            .field("data_size", &self.data_size)
+            .field("pos", &self.pos)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Everything [`EnardBuilder::parse`] learns about an enard file.
+pub(crate) struct Parsed<C> {
+    pub version: FormatVersion,
+    /// Offset of the header section in the reader
+    pub header_start: u64,
+    pub header_size: u64,
+    pub data_size: u64,
+    pub header: Header,
+    /// Empty if the file was opened with a [`SealedKey`]
+    pub mac_key: Zeroizing<Vec<u8>>,
+    pub cipher: C,
+}
+
+/// Parsed contents of the header section, minus the padding.
+#[derive(Clone)]
+pub(crate) struct Header {
+    /// Ascii name of the cipher
+    pub cipher: Vec<u8>,
+    /// Cipher IV (aka nonce), may be empty
+    pub iv: Vec<u8>,
+    pub meta: MetaMap,
+}
+impl Header {
+    /// Parse the header from `reader`, which should not read beyond the header section.
+    pub fn read<R: BufRead>(
+        mut reader: R,
+        version: FormatVersion,
+        header_size: u64,
+        limits: &ParseLimits,
+    ) -> Result<Self, EnardError> {
+        let (cipher, iv) = Self::read_cipher(&mut reader, version, header_size)?;
+        let meta = Self::read_meta(&mut reader, version, header_size, limits)?;
+        Ok(Self { cipher, iv, meta })
+    }
+
+    /// Read the cipher name and IV, the first part of [`Header::read`].
+    pub fn read_cipher<R: BufRead>(
+        mut reader: R,
+        version: FormatVersion,
+        header_size: u64,
+    ) -> Result<(Vec<u8>, Vec<u8>), EnardError> {
+        match version {
+            FormatVersion::V1 => Ok((read_u8_block(&mut reader)?, read_u8_block(&mut reader)?)),
+            FormatVersion::V2 => Ok((
+                read_varint_block(&mut reader, header_size)?,
+                read_varint_block(&mut reader, header_size)?,
+            )),
+        }
+    }
+
+    /// Read the metadata following the IV, the second part of [`Header::read`].
+    pub fn read_meta<R: BufRead>(
+        reader: R,
+        version: FormatVersion,
+        header_size: u64,
+        limits: &ParseLimits,
+    ) -> Result<MetaMap, EnardError> {
+        let value_limit = header_size.min(limits.max_meta_value_size);
+        match version {
+            FormatVersion::V1 => read_meta_blocks(reader, value_limit, limits),
+            FormatVersion::V2 => read_varint_meta(reader, value_limit, limits),
+        }
+    }
+}
+
+/// Round `size` up to a multiple of [`CRYPT_BLOCK_SIZE`].
+pub(crate) fn round_to_crypt_block(size: usize) -> usize {
+    (size + CRYPT_BLOCK_SIZE - 1) & !(CRYPT_BLOCK_SIZE - 1)
+}
+
+/// A buffer for plaintext whose contents start at a multiple of [`CRYPT_ALIGN`], and
+/// which is zeroed when it's dropped or reallocated.
+///
+/// The length can be changed without reallocating, up to the largest length so far.
+#[derive(Default)]
+pub(crate) struct CryptBuf {
+    /// Has `CRYPT_ALIGN - 1` extra bytes, so there's always an aligned `len` bytes in it
+    buf: Zeroizing<Box<[u8]>>,
+    len: usize,
+}
+impl CryptBuf {
+    pub fn new(len: usize) -> Self {
+        let mut buf = Self::default();
+        buf.resize(len);
+        buf
+    }
+
+    /// Set the length to `len`. The contents are unspecified afterwards.
+    pub fn resize(&mut self, len: usize) {
+        if len > self.buf.len().saturating_sub(CRYPT_ALIGN - 1) {
+            self.buf = Zeroizing::new(vec![0u8; len + CRYPT_ALIGN - 1].into_boxed_slice());
+        }
+        self.len = len;
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        self.len = self.len.min(len);
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    fn offset(&self) -> usize {
+        if self.buf.is_empty() {
+            0
+        } else {
+            self.buf.as_ptr().align_offset(CRYPT_ALIGN)
+        }
+    }
+}
+impl std::ops::Deref for CryptBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        let offset = self.offset();
+        &self.buf[offset..offset + self.len]
+    }
+}
+impl std::ops::DerefMut for CryptBuf {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        let offset = self.offset();
+        &mut self.buf[offset..offset + self.len]
+    }
+}
+
+/// Reads into `buf` until it's full or the reader reaches EOF, returning the
+/// number of bytes read.
+pub(crate) fn read_up_to<R: Read>(mut reader: R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match reader.read(&mut buf[n..]) {
+            Ok(0) => break,
+            Ok(m) => n += m,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(n)
+}
+
+/// Allocates a [`Vec<u8>`] with the given size, reads that many bytes
+/// into it, and returns the vec.
+pub(crate) fn read_vec<R: Read>(mut reader: R, size: usize) -> io::Result<Vec<u8>> {
+    let mut b_buf = vec![0u8; size];
+    reader.read_exact(&mut b_buf)?;
+    Ok(b_buf)
+}
+
+pub(crate) fn read_u8_block<R: Read>(mut reader: R) -> io::Result<Vec<u8>> {
+    let size = reader.read_u8()? as usize;
+    read_vec(reader, size)
+}
+
+pub(crate) fn read_u16_block<R: Read>(mut reader: R, limit: usize) -> Result<Vec<u8>, EnardError> {
+    let size = reader.read_u16::<LE>()? as usize;
+    if size > limit {
+        return Err(EnardError::new_block_size(size as u64, limit as u64));
+    }
+    Ok(read_vec(reader, size)?)
+}
+
+pub(crate) fn read_varint_block<R: BufRead>(
+    mut reader: R,
+    limit: u64,
+) -> Result<Vec<u8>, EnardError> {
+    let size = varint::read_u(&mut reader)?;
+    if size > limit {
+        return Err(EnardError::new_block_size(size, limit));
+    }
+    Ok(read_vec(reader, size as usize)?)
+}
+
+pub(crate) fn read_meta_blocks<R: Read>(
+    mut reader: R,
+    max_size: u64,
+    limits: &ParseLimits,
+) -> Result<MetaMap, EnardError> {
+    let mut result = MetaMap::new();
+    let count = reader.read_u8()?;
+    limits.check_meta_entries(count as u64)?;
+    for _ in 0..count {
+        // Read the key
+        let key = read_u8_block(&mut reader)?;
+        // Read the value, with a more specific error message to be helpful
+        let value = read_u16_block(&mut reader, max_size as usize)?;
+        // Put into hashmap
+        result.insert(key, value);
+    }
+    Ok(result)
+}
+
+/// Reads a varint count followed by that many varint-block key/value pairs, the
+/// metadata format used by v2 headers and the extended metadata.
+pub(crate) fn read_varint_meta<R: BufRead>(
+    mut reader: R,
+    limit: u64,
+    limits: &ParseLimits,
+) -> Result<MetaMap, EnardError> {
+    let count = varint::read_u(&mut reader)?;
+    limits.check_meta_entries(count)?;
+    let mut meta = MetaMap::new();
+    for _ in 0..count {
+        let key = read_varint_block(&mut reader, limit)?;
+        let value = read_varint_block(&mut reader, limit)?;
+        meta.insert(key, value);
+    }
+    Ok(meta)
+}
+
+/// Encodes `meta` in the format read by [`read_varint_meta`].
+fn encode_varint_meta(meta: &MetaMap) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    varint::write_u(&mut buf, meta.len() as u64)?;
+    for (key, val) in meta.iter() {
+        crate::format::write_small_block(&mut buf, FormatVersion::V2, key)?;
+        crate::format::write_large_block(&mut buf, FormatVersion::V2, val)?;
+    }
+    Ok(buf)
+}
+
+/// Returns the size of the extended metadata at the end of the data section, including
+/// the size at the very end if it's a trailer, see [`EnardWriter::with_meta_trailer`].
+///
+/// `reader` and the cipher must be at the start of the data, and are left there.
+pub(crate) fn extended_meta_size<R, C>(
+    reader: &mut R,
+    parsed: &mut Parsed<C>,
+) -> Result<u64, EnardError>
+where
+    R: Read + Seek,
+    C: DynCipher,
+{
+    let (meta, data_size) = (&parsed.header.meta, parsed.data_size);
+    if meta.contains_key(crate::meta::META_TRAILER) {
+        if data_size < 8 {
+            return Err(EnardError::new_block_size(8, data_size));
+        }
+        let data_start = parsed.header_start + parsed.header_size;
+        let mut buf = [0u8; 8];
+        reader.seek(SeekFrom::Start(data_start + data_size - 8))?;
+        reader.read_exact(&mut buf)?;
+        parsed
+            .cipher
+            .try_seek(data_size - 8)
+            .and_then(|_| parsed.cipher.try_apply_keystream(&mut buf))
+            .and_then(|_| parsed.cipher.try_seek(0))
+            .map_err(cipher_to_io_error)?;
+        reader.seek(SeekFrom::Start(data_start))?;
+        let size = u64::from_le_bytes(buf).saturating_add(8);
+        if size > data_size {
+            return Err(EnardError::new_block_size(size, data_size));
+        }
+        return Ok(size);
+    }
+    header_extended_meta_size(meta, data_size)
+}
+
+/// Returns the size of the extended metadata stored in the header metadata, which is 0
+/// if there isn't any or it's a trailer.
+pub(crate) fn header_extended_meta_size(meta: &MetaMap, data_size: u64) -> Result<u64, EnardError> {
+    let value = match meta.get(EXTENDED_META_KEY) {
+        Some(value) => value,
+        None => return Ok(0),
+    };
+    let size = <[u8; 8]>::try_from(value.as_slice())
+        .map(u64::from_le_bytes)
+        .map_err(|_| {
+            let msg = "extended metadata size must be a u64";
+            io::Error::new(ErrorKind::InvalidData, msg)
+        })?;
+    if size > data_size {
+        return Err(EnardError::new_block_size(size, data_size));
+    }
+    Ok(size)
+}
+
+/// Which part of the file an [`EnardWriter`] writes next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    Header,
+    Data,
+    Finished,
+}
+
+/// Tracks the [`Stage`] of an [`EnardWriter`], so calls in the wrong order are errors
+/// instead of corrupt files, and catches writers which are dropped part way through a
+/// file in debug builds.
+///
+/// Writers which hit an I/O error aren't checked on drop, the file is unusable anyway.
+#[derive(Debug)]
+struct WriterState {
+    stage: Stage,
+    /// An I/O error happened
+    failed: bool,
+}
+impl WriterState {
+    fn new() -> Self {
+        Self {
+            stage: Stage::Header,
+            failed: false,
+        }
+    }
+
+    /// Returns an error unless the writer is at `stage`.
+    fn expect(&self, stage: Stage) -> io::Result<()> {
+        let msg = match self.stage {
+            s if s == stage => return Ok(()),
+            Stage::Header => "the header must be written before the data",
+            Stage::Data => "the header has already been written",
+            Stage::Finished => "the file has already been finished",
+        };
+        Err(io::Error::new(ErrorKind::Other, msg))
+    }
+
+    /// Stop checking because of `e`, which is returned.
+    fn failed(&mut self, e: io::Error) -> io::Error {
+        self.failed = true;
+        e
+    }
+}
+impl Drop for WriterState {
+    fn drop(&mut self) {
+        debug_assert!(
+            self.stage != Stage::Data || self.failed || std::thread::panicking(),
+            "EnardWriter dropped without calling finish() or abort()"
+        );
+    }
+}
+
+/// What [`EnardWriter::finish`] wrote, e.g. to record in a manifest.
+///
+/// The MAC tag is stored right after the data (see `format.md`), so a file can be
+/// spot-checked against the recorded tag without computing the MAC again.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WriteSummary {
+    /// Size in bytes of the whole enard file, including the signature and footer
+    pub total_bytes: u64,
+    /// Size in bytes of the (encrypted) data section, including the extended metadata
+    pub data_bytes: u64,
+    /// The MAC tag written after the data
+    pub mac_tag: [u8; TAG_SIZE],
+    /// The cipher IV, may be empty
+    pub iv: Vec<u8>,
+}
+
+/// Wraps a [`Write`] + [`Seek`] to produce new encrypted enard files.
+///
+/// When creating a new file, first call [write_header](EnardWriter::write_header),
+/// then write the data and call [`EnardWriter::finish`]. Calls in the wrong order return
+/// an error rather than producing a corrupt file.
+///
+/// If the inner writer can't seek, finish with [`EnardWriter::finish_streaming`]
+/// instead of [`EnardWriter::finish`].
+pub struct EnardWriter<W, C> {
+    inner: W,
+    cipher: C,
+    iv: Zeroizing<Vec<u8>>,
+    mac: Option<BoxDynMac>,
+    mac_key: Zeroizing<Vec<u8>>,
+    meta: Option<MetaMap>,
+    version: FormatVersion,
+    header_size: u64,
+    /// Bytes of encrypted data written so far
+    data_len: u64,
+    /// See [`EnardWriter::with_data_alignment`]
+    data_alignment: usize,
+    /// Holds plaintext before it's encrypted, so it's zeroed when dropped
+    crypt_buf: CryptBuf,
+    /// Value for [`EnardWriter::with_key_check`]
+    key_check: [u8; 8],
+    /// Key for [`EnardWriter::with_obfuscated_header`]
+    header_key: Zeroizing<[u8; 32]>,
+    obfuscate_header: bool,
+    /// See [`EnardWriter::with_header_checksum`]
+    header_checksum: bool,
+    /// Written instead of [`MAGIC`], see [`EnardWriter::with_magic`]
+    magic: Vec<u8>,
+    /// See [`EnardWriter::with_aad`]
+    aad: Zeroizing<Vec<u8>>,
+    /// Encoded extended metadata, written after the data
+    ext_meta: Zeroizing<Vec<u8>>,
+    /// See [`EnardWriter::allow_reserved_meta`]
+    allow_reserved: bool,
+    /// Hash of the plaintext written so far, see [`EnardWriter::with_plaintext_hash`]
+    plaintext_hash: Option<Sha256>,
+    state: WriterState,
+    /// Metadata written after the data, see [`EnardWriter::with_meta_trailer`]
+    trailer: Option<MetaMap>,
+    /// The file key, kept to wrap it in [`EnardWriter::add_recipient`]
+    #[cfg(feature = "recipients")]
+    file_key: Zeroizing<Vec<u8>>,
+    #[cfg(feature = "signature")]
+    signer: Option<crate::signature::WriteSigner>,
+}
+impl<'a, W, C> EnardWriter<W, C>
+where
+    W: Write,
+    C: DynCipher,
+{
+    /// Create a new [`EnardWriter`] using the cipher `name`.
+    ///
+    /// `key` is usually a byte slice or [`crate::EnardKey`], but can be any
+    /// [`KeyProvider`], which is given `meta`.
+    pub fn new<Cf: CipherFactory<C>, K: KeyProvider>(
+        inner: W,
+        factory: Cf,
+        name: &[u8],
+        key: K,
+        iv: EnardNonce,
+        meta: MetaMap,
+    ) -> Result<Self, EnardError> {
+        let key = key.key_for(&meta)?;
+        let (cipher_key, mac_key) = crate::kdf::split_keys(&meta, &key)?;
+        factory.validate(name, cipher_key.len(), iv.as_bytes().len())?;
+        let cipher = factory.create(name, &cipher_key, iv.as_bytes())?;
+        Ok(Self {
+            inner,
+            cipher,
+            iv: Zeroizing::new(iv.as_bytes().to_vec()),
+            mac: None,
+            mac_key,
+            meta: Some(meta),
+            version: FormatVersion::default(),
+            header_size: 0,
+            data_len: 0,
+            data_alignment: DATA_ALIGNMENT,
+            crypt_buf: CryptBuf::new(DEFAULT_BUFFER_SIZE),
+            key_check: crate::kdf::key_check(&key),
+            header_key: crate::kdf::header_key(&key),
+            obfuscate_header: false,
+            header_checksum: false,
+            magic: MAGIC.to_vec(),
+            aad: Zeroizing::default(),
+            ext_meta: Zeroizing::default(),
+            allow_reserved: false,
+            plaintext_hash: None,
+            state: WriterState::new(),
+            trailer: None,
+            #[cfg(feature = "recipients")]
+            file_key: key.clone(),
+            #[cfg(feature = "signature")]
+            signer: None,
+        })
+    }
+
+    /// Same as [`EnardWriter::new`] but generating a random IV of the size `factory`
+    /// reports for the cipher `name`, so callers don't need to know how large it is.
+    ///
+    /// The IV is returned as well, e.g. for logging.
+    #[cfg(feature = "random")]
+    pub fn new_with_random_iv<Cf, K, Rng>(
+        inner: W,
+        factory: Cf,
+        name: &[u8],
+        key: K,
+        meta: MetaMap,
+        rng: &mut Rng,
+    ) -> Result<(Self, EnardNonce), EnardError>
+    where
+        Cf: CipherFactory<C>,
+        K: KeyProvider,
+        Rng: rand::CryptoRng + rand::Rng,
+    {
+        let iv = EnardNonce::generate(&factory.get_meta(name)?, rng);
+        let wr = Self::new(inner, factory, name, key, iv.clone(), meta)?;
+        Ok((wr, iv))
+    }
+
+    /// Set the size of the internal buffer used for encryption, the default is
+    /// [`DEFAULT_BUFFER_SIZE`]. Data is encrypted one buffer at a time, so larger
+    /// buffers mean fewer calls into the cipher and the inner writer.
+    ///
+    /// `size` is rounded up to a multiple of [`CRYPT_BLOCK_SIZE`]. Writes which are
+    /// multiples of it keep the cipher on whole blocks.
+    pub fn with_buffer_size(mut self, size: usize) -> Self {
+        self.crypt_buf = CryptBuf::new(round_to_crypt_block(size.max(1)));
+        self
+    }
+
+    /// Size of the internal buffer used for encryption, after rounding
+    pub fn buffer_size(&self) -> usize {
+        self.crypt_buf.len()
+    }
+
+    /// Pad the header so the data section starts at a multiple of `alignment` bytes from
+    /// the start of the file, the default is [`DATA_ALIGNMENT`].
+    ///
+    /// `alignment` must be a power of two from [`DATA_ALIGNMENT`] to
+    /// [`crate::stream::SECTOR_SIZE`], otherwise writing the header fails. Use
+    /// [`crate::stream::SECTOR_SIZE`] for readers which use unbuffered or direct I/O,
+    /// see [`crate::stream::AlignedRead`]. Readers find the data from the header size, so
+    /// they don't need to know the alignment, except for [`crate::EnardDecryptWriter`]
+    /// which only supports the default for files written with
+    /// [`EnardWriter::finish_streaming`].
+    pub fn with_data_alignment(mut self, alignment: usize) -> Self {
+        self.data_alignment = alignment;
+        self
+    }
+
+    /// Start the file with `magic` instead of [`MAGIC`], or with nothing if `magic` is
+    /// empty, so the file can't be found by scanning for the usual magic.
+    ///
+    /// Only [`EnardReader::new_with_magic`] can open such files. The other tools which
+    /// look for the magic, such as [`crate::Verifier`], [`crate::EnardEditor`],
+    /// [`crate::locate`] and [`crate::signature`], don't support them.
+    ///
+    /// This must be called before [`EnardWriter::write_header`].
+    pub fn with_magic(mut self, magic: &[u8]) -> Self {
+        self.magic = magic.to_vec();
+        self
+    }
+
+    /// Bind the file to `aad` (additional authenticated data), e.g. the game build ID or
+    /// the file name. It's added to the MAC but not stored in the file, so readers must
+    /// pass the same `aad` to [`EnardReader::new_with_aad`], and a file copied to the
+    /// wrong build or name fails with [`EnardError::MacError`]. Empty `aad` is the same as
+    /// none.
+    ///
+    /// [`crate::Verifier`] supports it with [`crate::Verifier::with_aad`], but
+    /// [`crate::EnardEditor`] and [`crate::EnardDecryptWriter`] don't.
+    ///
+    /// This may be called any time before the file is finished.
+    pub fn with_aad(mut self, aad: &[u8]) -> Self {
+        self.aad = Zeroizing::new(aad.to_vec());
+        self
+    }
+
+    /// Create the MAC with `factory` instead of [`DefaultMacFactory`], see [`crate::mac`].
+    ///
+    /// This must be called before [`EnardWriter::write_header`], and after the MAC
+    /// algorithm has been chosen.
+    pub fn with_mac_factory(mut self, factory: &dyn MacFactory) -> Result<Self, EnardError> {
+        let name = crate::mac::mac_name(self.meta.as_ref().unwrap());
+        let mac = factory.create(name, &self.mac_key)?;
+        self.mac = Some(crate::mac::check_output_size(mac, name)?);
+        Ok(self)
+    }
+
+    /// Set the format version to write, the default is [`FormatVersion::V1`].
+    ///
+    /// This must be called before [`EnardWriter::write_header`].
+    pub fn with_version(mut self, version: FormatVersion) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Store a short value derived from the key in the metadata, so readers given the
+    /// wrong key fail with [`EnardError::WrongKey`] straight away, instead of with a
+    /// [`EnardError::MacError`] after reading the whole file.
+    ///
+    /// This must be called before [`EnardWriter::write_header`].
+    pub fn with_key_check(mut self) -> Self {
+        let check = self.key_check.to_vec();
+        self.set_meta(crate::kdf::KEY_CHECK_META_KEY, check);
+        self
+    }
+
+    /// Record which key the file is written with, see [`crate::meta::KEY_ID`], so readers
+    /// managing several keys can tell which one to use from the header, with
+    /// [`EnardReader::key_id`], [`crate::format::Header::key_id`] or a
+    /// [`crate::key::KeyRing`].
+    ///
+    /// This must be called before [`EnardWriter::write_header`].
+    pub fn with_key_id(mut self, id: &[u8]) -> Self {
+        self.set_meta(crate::meta::KEY_ID, id.to_vec());
+        self
+    }
+
+    /// Obfuscate the header, so the cipher name and metadata can't be seen without the
+    /// key. Only the magic, version and sizes are left readable.
+    ///
+    /// The header is XORed with a keystream derived from the key and a salt stored at
+    /// the start of the header, see `format.md`. Readers need the key before they can
+    /// read the metadata, so they ask the [`KeyProvider`] for the key with empty
+    /// metadata. Key providers which need the metadata, such as
+    /// [`crate::kdf::DerivedKey`] and recipients, can't be used, and neither can
+    /// [`crate::sealed`] keys. [`crate::Verifier`] and [`crate::format::parse_header`]
+    /// can't read obfuscated headers.
+    ///
+    /// This must be called before [`EnardWriter::write_header`].
+    pub fn with_obfuscated_header(mut self) -> Self {
+        self.obfuscate_header = true;
+        self
+    }
+
+    /// End the header section with a CRC-32 of the rest of it, so readers can tell a
+    /// corrupt header apart from modified data, with [`EnardError::HeaderCorrupt`]
+    /// before the MAC is checked. The sizes aren't covered, because they're usually only
+    /// known once the header is part of the MAC, so a corrupt size may be reported as
+    /// [`EnardError::Truncated`] or [`EnardError::MacError`] instead, see `format.md`.
+    ///
+    /// This is marked by a flag in the version field, which older readers don't accept.
+    ///
+    /// This must be called before [`EnardWriter::write_header`].
+    pub fn with_header_checksum(mut self) -> Self {
+        self.header_checksum = true;
+        self
+    }
+
+    /// Set the MIME type of the plaintext, see [`crate::meta::CONTENT_TYPE`].
+    ///
+    /// This must be called before [`EnardWriter::write_header`].
+    pub fn with_content_type(mut self, content_type: &str) -> Self {
+        self.set_meta(crate::meta::CONTENT_TYPE, content_type.as_bytes().to_vec());
+        self
+    }
+
+    /// Record that the plaintext is compressed, see [`crate::meta::COMPRESSION`].
+    ///
+    /// This must be called before [`EnardWriter::write_header`].
+    pub fn with_compression(mut self, compression: &str) -> Self {
+        self.set_meta(crate::meta::COMPRESSION, compression.as_bytes().to_vec());
+        self
+    }
+
+    /// Set the creation time, see [`crate::meta::CREATED`].
+    ///
+    /// This must be called before [`EnardWriter::write_header`].
+    pub fn with_created(mut self, time: SystemTime) -> Self {
+        self.set_meta(crate::meta::CREATED, crate::meta::encode_time(time));
+        self
+    }
+
+    /// Allow metadata keys in the reserved `enard.` namespace which enard doesn't
+    /// know about, see [`crate::meta`]. Without this [`EnardWriter::write_header`]
+    /// fails with [`EnardError::ReservedMeta`].
+    pub fn allow_reserved_meta(mut self) -> Self {
+        self.allow_reserved = true;
+        self
+    }
+
+    /// Store `ext` as extended metadata.
+    ///
+    /// Unlike the normal metadata, extended metadata isn't limited by the size of the
+    /// header, and it's encrypted. It's stored at the end of the data section, so readers
+    /// only have to load it when [`EnardReader::extended_meta`] is called. The size is
+    /// stored in the metadata under [`EXTENDED_META_KEY`].
+    ///
+    /// This must be called before [`EnardWriter::write_header`].
+    pub fn with_extended_meta(mut self, ext: &MetaMap) -> Self {
+        let encoded = encode_varint_meta(ext).expect("encoding to a Vec can't fail");
+        let size = (encoded.len() as u64).to_le_bytes();
+        self.set_meta(EXTENDED_META_KEY, size.to_vec());
+        self.ext_meta = Zeroizing::new(encoded);
+        self
+    }
 
-        let version = self.reader.read_u16::<LE>()?;
-        match version {
-            1 => self.read_v1(),
-            _ => Err(EnardError::UnsupportedVersion { version }),
+    /// Store `index` in the extended metadata under [`crate::meta::INDEX`], see
+    /// [`crate::index`]. Use this if the asset sizes are known before writing them.
+    ///
+    /// This must be called before [`EnardWriter::write_header`], and after
+    /// [`EnardWriter::with_extended_meta`] which would replace it.
+    pub fn with_index(mut self, index: &crate::index::Index) -> Self {
+        let value = index.encode();
+        match self.trailer.as_mut() {
+            Some(trailer) => {
+                trailer.insert(crate::meta::INDEX.to_vec(), value);
+            }
+            None => self
+                .insert_ext_meta(crate::meta::INDEX, value)
+                .expect("the extended metadata was encoded by the writer"),
         }
+        self
     }
 
-    fn read_v1(mut self) -> Result<EnardReader<R, C>, EnardError> {
-        // First is the header size, which includes metadata about the encryption scheme.
-        // This SHOULD be padded to make the data 8-byte aligned, but it's not required.
-        let header_size = self.reader.read_u32::<LE>()?;
-        // Next comes the data size. This is useful both to make sure we don't
-        // read outside the data, but also to easily jump to the MAC which is at the file end.
-        // Sure this COULD be a varint, but this is easier and helps keep alignment.
-        let data_size = self.reader.read_u64::<LE>()?;
-        // We'll need to know the header position for later
-        let header_start = self.reader.stream_position()?;
-        // Calculate the start of the data given
-        let data_start = header_start + header_size as u64;
-        // Verify the MAC
-        Self::verify_mac(&mut self.reader, &self.key, header_size as u64 + data_size)?;
-        // Now jump back and read the header
-        self.reader.seek(SeekFrom::Start(header_start))?;
-
-        // Read cipher type
-        let cipher_kind = Self::read_u8_block(&mut self.reader)?;
-        // Read cipher iv (aka nonce), may be empty
-        let cipher_iv = Self::read_u8_block(&mut self.reader)?;
-        // Try to create the cipher
-        let cipher = self.factory.create(&cipher_kind, &self.key, &cipher_iv)?;
-
-        // Now we can read additional metadata
-        let meta = Self::read_meta_blocks(&mut self.reader, header_size as u64)?;
-        // Seek back to the start of the data (avoid padding)
-        self.reader.seek(SeekFrom::Start(data_start))?;
+    /// Store extended metadata after the data, with its size at the very end, instead of
+    /// storing the size in the header like [`EnardWriter::with_extended_meta`]. This
+    /// allows values which aren't known until all the data has been written, pass them to
+    /// [`EnardWriter::finish_with_meta_trailer`]. Readers get them from
+    /// [`EnardReader::extended_meta`].
+    ///
+    /// This must be called before [`EnardWriter::write_header`], and can't be combined
+    /// with [`EnardWriter::with_extended_meta`].
+    pub fn with_meta_trailer(mut self) -> Self {
+        self.set_meta(crate::meta::META_TRAILER, Vec::new());
+        self.trailer = Some(MetaMap::new());
+        self
+    }
 
-        // And FINALLY we can construct the actual object
-        Ok(EnardReader {
-            inner: self.reader,
-            cipher,
-            data_start,
-            data_size,
-            current: 0,
-            meta,
-        })
+    /// Modify the metadata. Changes made after [`EnardWriter::write_header`] aren't
+    /// written.
+    pub fn meta_mut(&mut self) -> &mut MetaMap {
+        self.meta.as_mut().unwrap()
     }
 
-    fn verify_mac<R2: Read>(mut reader: R2, key: &[u8], data_size: u64) -> Result<(), EnardError> {
-        let mut rd = (&mut reader).take(data_size);
-        let mut mac = HmacV1::new_from_slice(key)?;
-        io::copy(&mut rd, &mut mac)?;
-        // Assume the mac tag is right after the data
-        let mut tag_buf = [0u8; 32];
-        reader.read_exact(&mut tag_buf)?;
-        mac.verify_slice(&tag_buf)?;
-        Ok(())
+    /// Compute the SHA-256 of the plaintext while encrypting it, and store it in the
+    /// extended metadata under [`crate::meta::PLAINTEXT_SHA256`], so it can be used as a
+    /// content hash without reading the file twice. See [`EnardReader::plaintext_hash`].
+    ///
+    /// This must be called before [`EnardWriter::write_header`].
+    pub fn with_plaintext_hash(mut self) -> Self {
+        self.plaintext_hash = Some(Sha256::new());
+        self
     }
 
-    /// Allocates a [`Vec<u8>`] with the given size, reads that many bytes
-    /// into it, and returns the vec.
-    pub fn read_vec<R2: Read>(mut reader: R2, size: usize) -> io::Result<Vec<u8>> {
-        let mut b_buf = vec![0u8; size];
-        reader.read_exact(&mut b_buf)?;
-        Ok(b_buf)
+    /// Wrap the file key for `recipient`, so they can open the file with their private
+    /// key, see [`crate::recipients`].
+    ///
+    /// This must be called before [`EnardWriter::write_header`].
+    #[cfg(feature = "recipients")]
+    pub fn add_recipient<Rng: rand::CryptoRng + rand::Rng>(
+        mut self,
+        recipient: &crate::recipients::PublicKey,
+        rng: &mut Rng,
+    ) -> Result<Self, EnardError> {
+        let entry = crate::recipients::wrap_key(&self.file_key, recipient, rng)?;
+        if let Some(meta) = self.meta.as_mut() {
+            meta.entry(crate::meta::RECIPIENTS.to_vec())
+                .or_default()
+                .extend_from_slice(&entry);
+        }
+        Ok(self)
     }
 
-    pub fn read_u8_block<R2: Read>(mut reader: R2) -> io::Result<Vec<u8>> {
-        let size = reader.read_u8()? as usize;
-        Self::read_vec(reader, size)
+    /// Sign the file with `key`, see [`crate::signature`].
+    ///
+    /// This must be called before [`EnardWriter::write_header`].
+    #[cfg(feature = "signature")]
+    pub fn sign_with(mut self, key: crate::signature::SigningKey) -> Self {
+        self.signer = Some(crate::signature::WriteSigner::new(key));
+        self
     }
 
-    pub fn read_u16_block<R2: Read>(mut reader: R2, limit: usize) -> Result<Vec<u8>, EnardError> {
-        let size = reader.read_u16::<LE>()? as usize;
-        if size > limit {
-            return Err(EnardError::new_block_size(size as u64, limit as u64));
-        }
-        Ok(Self::read_vec(reader, size)?)
+    /// Same as [`EnardWriter::write_complete`], but finishes with
+    /// [`EnardWriter::finish_streaming`] so the inner writer doesn't need to seek.
+    pub fn write_complete_streaming(&mut self, rd: impl Read) -> io::Result<u64> {
+        self.write_header_and_data(rd)?;
+        Ok(self.finish_streaming()?.total_bytes)
     }
 
-    pub fn read_meta_blocks<R2: Read>(
-        mut reader: R2,
-        max_size: u64,
-    ) -> Result<MetaMap, EnardError> {
-        let mut result = HashMap::new();
-        let count = reader.read_u8()? as usize;
-        for _ in 0..count {
-            // Read the key
-            let key = Self::read_u8_block(&mut reader)?;
-            // Read the value, with a more specific error message to be helpful
-            let value = Self::read_u16_block(&mut reader, max_size as usize)?;
-            // Put into hashmap
-            result.insert(key, value);
+    /// Same as [`EnardWriter::write_complete`] for exactly `len` bytes of data, returning
+    /// the total number of bytes written.
+    ///
+    /// The header and data sizes are written with the header instead of being filled in
+    /// by [`EnardWriter::finish`], so the inner writer doesn't need to seek and there's
+    /// no footer like [`EnardWriter::finish_streaming`] adds. Only the first `len` bytes
+    /// of `rd` are read, and it's an [`ErrorKind::UnexpectedEof`] error if there are
+    /// fewer. Files with a metadata trailer can't be written like this.
+    pub fn write_complete_sized(&mut self, rd: impl Read, len: u64) -> io::Result<u64> {
+        if self.trailer.is_some() {
+            let msg = "the size of a metadata trailer isn't known until it's written";
+            return Err(io::Error::new(ErrorKind::InvalidInput, msg));
         }
-        Ok(result)
+        self.write_header_impl(Some(len))?;
+        let copied = self.copy_data(rd.take(len))?;
+        if copied != len {
+            let msg = format!("expected {} bytes of data, got {}", len, copied);
+            return Err(self
+                .state
+                .failed(io::Error::new(ErrorKind::UnexpectedEof, msg)));
+        }
+        let (written, mac_tag) = self.write_tag()?;
+        self.flush()?;
+        Ok(self.summary(written, mac_tag).total_bytes)
     }
-}
 
-/// Wraps a [`Write`] + [`Seek`] to produce new encrypted enard files.
-/// 
-/// When creating a new file, first call [write_header](EnardWriter::write_header)
-pub struct EnardWriter<W, C> {
-    inner: W,
-    cipher: C,
-    iv: Vec<u8>,
-    mac: Option<HmacV1>,
-    start_pos: u64,
-    meta: Option<MetaMap>,
-    header_size: u32,
-    crypt_buf: Vec<u8>,
-}
-impl<'a, W, C> EnardWriter<W, C>
-where
-    W: Write + Seek,
-    C: DynCipher,
-{
-    pub fn new<Cf: CipherFactory<C>>(
-        inner: W,
-        factory: Cf,
-        name: &[u8],
-        key: &[u8],
-        iv: &[u8],
-        meta: MetaMap,
-    ) -> Result<Self, EnardError> {
-        let cipher = factory.create(name, key, iv)?;
-        Ok(Self {
-            inner,
-            cipher,
-            iv: Vec::from(iv),
-            mac: Some(HmacV1::new_from_slice(key)?),
-            start_pos: 0,
-            meta: Some(meta),
-            header_size: 0,
-            crypt_buf: vec![0u8; 256],
-        })
+    /// Encrypts `buf` in place and writes it out.
+    ///
+    /// This avoids copying the data into an internal buffer like [`Write::write`] does,
+    /// but the contents of `buf` will be the encrypted data afterwards.
+    pub fn write_in_place(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        self.encrypt_in_place(buf)?;
+        self.inner.write_all(buf).map_err(|e| self.state.failed(e))
     }
 
-    /// Writes the header, the contents of `rd`, and then calls `finish()`,
-    /// returning the total number of bytes written.
+    /// Encrypt all of `rd` and write it out, returning the number of bytes copied. The
+    /// header must already have been written.
     ///
-    pub fn write_complete(&mut self, mut rd: impl Read) -> io::Result<u64> {
-        let mut n = self.write_header()? as u64;
-        n += io::copy(&mut rd, self)?;
-        n += self.finish()? as u64;
-        Ok(n)
+    /// This is faster than [`io::copy`], which writes 8 KiB at a time, because the data is
+    /// read and written [`COPY_BUFFER_SIZE`] bytes at a time. Each part of the buffer
+    /// is encrypted and added to the MAC while it's still in the CPU cache.
+    pub fn copy_from<R: Read + ?Sized>(&mut self, rd: &mut R) -> io::Result<u64> {
+        self.state.expect(Stage::Data)?;
+        let mut buf = CryptBuf::new(COPY_BUFFER_SIZE);
+        let mut total = 0;
+        loop {
+            let n = read_up_to(&mut *rd, &mut buf).map_err(|e| self.state.failed(e))?;
+            for chunk in buf[..n].chunks_mut(CRYPT_CHUNK_SIZE) {
+                self.encrypt_in_place(chunk)?;
+            }
+            self.inner
+                .write_all(&buf[..n])
+                .map_err(|e| self.state.failed(e))?;
+            total += n as u64;
+            // `read_up_to` only stops early at the end of `rd`
+            if n < buf.len() {
+                return Ok(total);
+            }
+        }
+    }
+
+    /// Encrypt `buf` in place and add it to the MAC, without writing it out.
+    fn encrypt_in_place(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        self.state.expect(Stage::Data)?;
+        if let Some(hash) = self.plaintext_hash.as_mut() {
+            hash.update(&*buf);
+        }
+        self.cipher
+            .try_apply_keystream(buf)
+            .map_err(|e| self.state.failed(cipher_to_io_error(e)))?;
+        self.data_len += buf.len() as u64;
+        self.mac_update(buf)
     }
 
     /// Writes the header of an enard file, returns the number of bytes written.
     /// This should be called immediately after creating a new [`EnardWriter`].
     pub fn write_header(&mut self) -> io::Result<usize> {
-        self.write_header_v1()?;
-        Ok(HEADER_START + self.header_size as usize)
+        self.write_header_impl(None)?;
+        Ok(self.header_start() + self.header_size as usize)
     }
 
-    /// Finalize writing the file and clean up internal resources.
-    /// 
-    /// After calling this method, [`EnardWriter::write`] will panic.
-    /// [`EnardWriter::into_inner`] and some other methods will still work though.
-    pub fn finish(&mut self) -> io::Result<usize> {
-        self.finish_v1()
+    /// Size in bytes of the whole file if `payload_len` bytes of data are written, with
+    /// the metadata and options set so far, e.g. to preallocate the output or to build a
+    /// pack index before writing.
+    ///
+    /// This is exact for files finished with [`EnardWriter::finish`], including the
+    /// header padding, extended metadata, MAC tag and signature.
+    /// [`EnardWriter::finish_streaming`] adds a 24-byte footer. Returns an error for
+    /// [`EnardWriter::with_meta_trailer`], because the trailer isn't known in advance.
+    /// See [`estimated_file_size`] to get the size before creating a writer.
+    pub fn estimated_total_size(&self, payload_len: u64) -> io::Result<u64> {
+        if self.trailer.is_some() {
+            let msg = "the size of a metadata trailer isn't known until it's written";
+            return Err(io::Error::new(ErrorKind::InvalidInput, msg));
+        }
+        let (header_size, ext_meta_len) = match self.state.stage {
+            Stage::Header => {
+                let mut meta = self.meta.clone().unwrap_or_default();
+                let mut ext_meta_len = self.ext_meta.len();
+                if self.plaintext_hash.is_some() {
+                    let ext = self.ext_meta_with(crate::meta::PLAINTEXT_SHA256, vec![0; 32])?;
+                    ext_meta_len = ext.len();
+                    let size = (ext_meta_len as u64).to_le_bytes().to_vec();
+                    meta.insert(EXTENDED_META_KEY.to_vec(), size);
+                }
+                let salt_len = if self.obfuscate_header {
+                    crate::kdf::HEADER_SALT_SIZE
+                } else {
+                    0
+                };
+                let header = self.encode_header_section(&vec![0; salt_len], &meta)?;
+                (header.len() as u64, ext_meta_len)
+            }
+            _ => (self.header_size, self.ext_meta.len()),
+        };
+        #[allow(unused_mut)]
+        let mut size = self.header_start() as u64
+            + header_size
+            + payload_len
+            + ext_meta_len as u64
+            + TAG_SIZE as u64;
+        #[cfg(feature = "signature")]
+        if self.signer.is_some() {
+            size += crate::signature::SIGNATURE_BLOCK_SIZE as u64;
+        }
+        Ok(size)
+    }
+
+    /// Size of the magic, version and sizes before the header section.
+    fn header_start(&self) -> usize {
+        self.version.header_start() - MAGIC.len() + self.magic.len()
+    }
+
+    /// Finalize the file without seeking back to fill in the header and data sizes.
+    ///
+    /// The sizes in the header are left as zero, and a footer with the real sizes is
+    /// written at the very end instead (see `format.md`), which readers fall back to.
+    /// Use this when the inner writer can't seek, such as a socket or pipe. Returns a
+    /// [`WriteSummary`] like [`EnardWriter::finish`].
+    ///
+    /// The footer must stay at the end of the file, so files written like this can't
+    /// be embedded in a larger file with [`crate::locate`].
+    pub fn finish_streaming(&mut self) -> io::Result<WriteSummary> {
+        let (written, mac_tag) = self.write_tag()?;
+        self.inner.write_u64::<LE>(self.header_size)?;
+        self.inner.write_u64::<LE>(self.data_len)?;
+        self.inner.write_all(FOOTER_MAGIC)?;
+        self.flush()?;
+        Ok(self.summary(written + FOOTER_SIZE, mac_tag))
     }
 
     /// Extracts the inner writer
@@ -345,136 +2417,504 @@ where
         self.inner
     }
 
-    fn write_header_v1(&mut self) -> io::Result<()> {
-        // See `EnardBuilder::read_v1` for format details
+    /// Summary of the finished file, given the number of bytes written after the data.
+    fn summary(&self, after_data: usize, mac_tag: [u8; TAG_SIZE]) -> WriteSummary {
+        WriteSummary {
+            total_bytes: self.header_start() as u64
+                + self.header_size
+                + self.data_len
+                + after_data as u64,
+            data_bytes: self.data_len,
+            mac_tag,
+            iv: self.iv.to_vec(),
+        }
+    }
+
+    fn set_meta(&mut self, key: &[u8], value: Vec<u8>) {
+        if let Some(meta) = self.meta.as_mut() {
+            meta.insert(key.to_vec(), value);
+        }
+    }
+
+    /// Write the header. The header and data sizes are written as placeholders unless
+    /// `payload_len`, the number of bytes of data which will be written, is known.
+    fn write_header_impl(&mut self, payload_len: Option<u64>) -> io::Result<()> {
+        self.state.expect(Stage::Header)?;
+        // See `EnardBuilder::build` and format.md for format details
+        let meta = self.meta.as_ref().unwrap();
+        if !self.allow_reserved {
+            crate::meta::check_reserved(meta)
+                .map_err(|e| io::Error::new(ErrorKind::InvalidInput, e))?;
+        }
+        if self.version == FormatVersion::V1 && meta.contains_key(crate::kdf::SPLIT_KEYS_META_KEY) {
+            let msg = "split keys require format v2";
+            return Err(io::Error::new(ErrorKind::InvalidInput, msg));
+        }
+        if self.version == FormatVersion::V1
+            && crate::mac::mac_name(meta) != crate::mac::HMAC_SHA256
+        {
+            let msg = "MACs other than HMAC-SHA256 require format v2";
+            return Err(io::Error::new(ErrorKind::InvalidInput, msg));
+        }
+        if self.mac.is_none() {
+            let mac = DefaultMacFactory
+                .create(crate::mac::mac_name(meta), &self.mac_key)
+                .map_err(|e| io::Error::new(ErrorKind::InvalidInput, e))?;
+            self.mac = Some(mac);
+        }
+        let alignment = self.data_alignment;
+        if !alignment.is_power_of_two()
+            || !(DATA_ALIGNMENT..=crate::stream::SECTOR_SIZE).contains(&alignment)
+        {
+            let msg = format!(
+                "data alignment must be a power of two from {} to {}, not {}",
+                DATA_ALIGNMENT,
+                crate::stream::SECTOR_SIZE,
+                alignment
+            );
+            return Err(io::Error::new(ErrorKind::InvalidInput, msg));
+        }
+        if self.trailer.is_some() && meta.contains_key(EXTENDED_META_KEY) {
+            let msg = "extended metadata can't be combined with a metadata trailer";
+            return Err(io::Error::new(ErrorKind::InvalidInput, msg));
+        }
+        if self.plaintext_hash.is_some() && self.trailer.is_none() {
+            // Reserve space for the hash, it's filled in by `write_tag`
+            self.insert_ext_meta(crate::meta::PLAINTEXT_SHA256, vec![0u8; 32])?;
+        }
 
-        // Store the start position for when we need to re-write the sizes
-        self.start_pos = self.inner.stream_position()?;
+        let salt = if self.obfuscate_header {
+            crate::kdf::header_salt(&self.iv).to_vec()
+        } else {
+            Vec::new()
+        };
+        let mut header = self.encode_header_section(&salt, self.meta.as_ref().unwrap())?;
+        if self.obfuscate_header {
+            let rest = &mut header[salt.len()..];
+            crate::kdf::obfuscate_header(&self.header_key, &salt, rest);
+        }
+        if self.header_checksum {
+            // Covers the header as stored, so it's checked before removing the obfuscation
+            set_header_checksum(&mut header);
+        }
+        self.header_size = header.len() as u64;
 
         // Write magic and version
-        self.inner.write_all(MAGIC)?;
-        self.inner.write_u16::<LE>(1)?;
-        // Write placeholders for header and data sizes
-        self.inner.write_all(&[0u8; 4 + 8])?;
-        // Track the header size
-        let mut hs = 0;
-        // Write required blocks
-        let name = self.cipher.get_name();
-        let iv = self.iv.clone();
-        hs += self.write_u8_block(name)?;
-        hs += self.write_u8_block(&iv)?;
-        // Write meta blocks
-        hs += self.write_meta_blocks()?;
-        // Pad to 8-byte alignment
-        let data_start = hs + HEADER_START;
-        let padding = (DATA_ALIGNMENT - (data_start % DATA_ALIGNMENT)) % DATA_ALIGNMENT;
-        let pad_buf = [0u8; DATA_ALIGNMENT];
-        self.mac_write(&pad_buf[0..padding])?;
-        hs += padding;
-        self.header_size = hs as u32;
+        self.inner.write_all(&self.magic)?;
+        let mut version = self.version as u16;
+        if self.obfuscate_header {
+            version |= OBFUSCATED_HEADER_FLAG;
+        }
+        if self.header_checksum {
+            version |= HEADER_CHECKSUM_FLAG;
+        }
+        self.inner.write_u16::<LE>(version)?;
+        match payload_len {
+            Some(len) => self.write_sizes(len + self.ext_meta.len() as u64)?,
+            None => {
+                // Write placeholders for header and data sizes
+                let sizes_len = self.version.header_start() - MAGIC.len() - 2;
+                self.inner.write_all(&[0u8; 8 + 8][..sizes_len])?;
+            }
+        }
+        self.mac_write(&header)?;
+        self.state.stage = Stage::Data;
+        Ok(())
+    }
+
+    /// Encode the header section, leaving room at the end for the checksum if there is
+    /// one, see [`encode_header`].
+    fn encode_header_section(&self, salt: &[u8], meta: &MetaMap) -> io::Result<Vec<u8>> {
+        let reserved = if self.header_checksum {
+            HEADER_CHECKSUM_SIZE
+        } else {
+            0
+        };
+        // Pad as if the header started later, so the data is still aligned
+        let mut header = encode_header(
+            self.version,
+            salt,
+            self.cipher.get_name(),
+            &self.iv,
+            meta,
+            self.header_start() + reserved,
+            self.data_alignment,
+        )?;
+        header.resize(header.len() + reserved, 0);
+        Ok(header)
+    }
+
+    /// Write the header size and `data_size`, which follow the magic and version.
+    fn write_sizes(&mut self, data_size: u64) -> io::Result<()> {
+        match self.version {
+            FormatVersion::V1 => self.inner.write_u32::<LE>(self.header_size as u32)?,
+            FormatVersion::V2 => self.inner.write_u64::<LE>(self.header_size)?,
+        }
+        self.inner.write_u64::<LE>(data_size)
+    }
 
+    /// Store `key` in the extended metadata, updating its size in the metadata.
+    fn insert_ext_meta(&mut self, key: &[u8], value: Vec<u8>) -> io::Result<()> {
+        let encoded = self.ext_meta_with(key, value)?;
+        let size = (encoded.len() as u64).to_le_bytes();
+        self.set_meta(EXTENDED_META_KEY, size.to_vec());
+        self.ext_meta = Zeroizing::new(encoded);
         Ok(())
     }
 
+    /// The encoded extended metadata with `key` added.
+    fn ext_meta_with(&self, key: &[u8], value: Vec<u8>) -> io::Result<Vec<u8>> {
+        let mut ext = if self.ext_meta.is_empty() {
+            MetaMap::new()
+        } else {
+            read_varint_meta(self.ext_meta.as_slice(), u64::MAX, &ParseLimits::UNLIMITED)
+                .map_err(|e| io::Error::new(ErrorKind::InvalidInput, e))?
+        };
+        ext.insert(key.to_vec(), value);
+        encode_varint_meta(&ext)
+    }
+
     /// Write a slice to the inner writer and also add the slice to the MAC.
     fn mac_write(&mut self, b: &[u8]) -> io::Result<()> {
-        self.inner.write_all(b)?;
-        self.mac.as_mut().unwrap().update(b);
+        self.inner.write_all(b).map_err(|e| self.state.failed(e))?;
+        self.mac_update(b)
+    }
+
+    /// Add a slice to the MAC, and the signature if there is one.
+    fn mac_update(&mut self, b: &[u8]) -> io::Result<()> {
+        let mac = self.mac.as_mut().ok_or_else(|| {
+            let msg = "the header must be written before the data";
+            io::Error::new(ErrorKind::Other, msg)
+        })?;
+        mac.update(b);
+        #[cfg(feature = "signature")]
+        if let Some(signer) = self.signer.as_mut() {
+            signer.update(b);
+        }
         Ok(())
     }
 
-    fn finish_v1(&mut self) -> io::Result<usize> {
-        let data_start = self.start_pos + (self.header_size + HEADER_START as u32) as u64;
-        let data_len = self.inner.stream_position()? - data_start;
+    /// Encrypt the first `len` bytes of `crypt_buf` and write them out.
+    fn write_crypt_buf(&mut self, len: usize) -> io::Result<()> {
+        let mut cbuf = std::mem::take(&mut self.crypt_buf);
+        let result = self.write_in_place(&mut cbuf[0..len]);
+        self.crypt_buf = cbuf;
+        result
+    }
+
+    /// Read all of `rd` using `buf`, writing each chunk with [`EnardWriter::write_in_place`].
+    fn copy_in_place<R: Read>(&mut self, mut rd: R, buf: &mut [u8]) -> io::Result<u64> {
+        let mut total = 0;
+        loop {
+            let n = match rd.read(buf) {
+                Ok(0) => return Ok(total),
+                Ok(n) => n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(self.state.failed(e)),
+            };
+            self.write_in_place(&mut buf[..n])?;
+            total += n as u64;
+        }
+    }
+
+    fn write_header_and_data(&mut self, mut rd: impl Read) -> io::Result<u64> {
+        let n = self.write_header()? as u64;
+        Ok(n + self.copy_data(&mut rd)?)
+    }
+
+    fn copy_data(&mut self, rd: impl Read) -> io::Result<u64> {
+        // Read straight into the crypt buffer, and encrypt it there, to avoid a copy
+        let mut buf = std::mem::take(&mut self.crypt_buf);
+        let copied = self.copy_in_place(rd, &mut buf);
+        self.crypt_buf = buf;
+        copied
+    }
+
+    /// Write the extended metadata, the MAC tag, and the signature if there is one,
+    /// returning the number of bytes written after the data and the tag.
+    fn write_tag(&mut self) -> io::Result<(usize, [u8; TAG_SIZE])> {
+        self.state.expect(Stage::Data)?;
+        // Take the hash first so the extended metadata isn't part of it
+        if let Some(hash) = self.plaintext_hash.take() {
+            let hash: [u8; 32] = hash.finalize().into();
+            match self.trailer.as_mut() {
+                Some(trailer) => {
+                    trailer.insert(crate::meta::PLAINTEXT_SHA256.to_vec(), hash.to_vec());
+                }
+                None => self.insert_ext_meta(crate::meta::PLAINTEXT_SHA256, hash.to_vec())?,
+            }
+        }
+        if let Some(trailer) = self.trailer.take() {
+            // The size goes last, so readers can find it from the end of the data
+            let mut encoded = encode_varint_meta(&trailer)?;
+            let size = encoded.len() as u64;
+            encoded.extend_from_slice(&size.to_le_bytes());
+            self.ext_meta = Zeroizing::new(encoded);
+        }
+        // The extended metadata is the last part of the data section
+        let mut ext_meta = std::mem::take(&mut self.ext_meta);
+        self.write_in_place(&mut ext_meta)?;
         // Write the MAC tag
-        let tag = self.mac.take().unwrap().finalize_reset().into_bytes();
-        self.inner.write_all(&tag)?;
+        let mut mac = self.mac.take().unwrap();
+        crate::mac::update_aad(&mut mac, &self.aad);
+        let tag = mac.finalize();
+        self.inner
+            .write_all(&tag)
+            .map_err(|e| self.state.failed(e))?;
+        self.state.stage = Stage::Finished;
+        #[allow(unused_mut)]
+        let mut written = tag.len();
+        #[cfg(feature = "signature")]
+        if let Some(signer) = self.signer.take() {
+            let block = signer.finish(self.version, self.header_size, self.data_len);
+            self.inner.write_all(&block)?;
+            written += block.len();
+        }
+        Ok((written, tag))
+    }
+}
+impl<C: DynCipher> EnardWriter<std::fs::File, C> {
+    /// Extend the output file to its final size for `payload_len` bytes of data, see
+    /// [`EnardWriter::estimated_total_size`], so the file system can allocate it in one
+    /// go. Files which are already long enough are left alone.
+    ///
+    /// This must be called before [`EnardWriter::write_header`], with the file
+    /// positioned where the enard file starts.
+    pub fn preallocate(&mut self, payload_len: u64) -> io::Result<()> {
+        self.state.expect(Stage::Header)?;
+        let end = self.inner.stream_position()? + self.estimated_total_size(payload_len)?;
+        if self.inner.metadata()?.len() < end {
+            self.inner.set_len(end)?;
+        }
+        Ok(())
+    }
+}
+impl<C: DynCipher> EnardWriter<crate::split::SplitFile, C> {
+    /// Same as [`EnardWriter::new`] but splitting the file into parts of at most
+    /// `max_part_size` bytes, named from `path_template`, see [`crate::split`].
+    pub fn new_split<Cf: CipherFactory<C>, K: KeyProvider>(
+        path_template: &str,
+        max_part_size: u64,
+        factory: Cf,
+        name: &[u8],
+        key: K,
+        iv: EnardNonce,
+        meta: MetaMap,
+    ) -> Result<Self, EnardError> {
+        let inner = crate::split::SplitFile::create(path_template, max_part_size)?;
+        Self::new(inner, factory, name, key, iv, meta)
+    }
+}
+impl<W, C> EnardWriter<W, C>
+where
+    W: Write + Seek,
+    C: DynCipher,
+{
+    /// Writes the header, the contents of `rd`, and then calls `finish()`,
+    /// returning the total number of bytes written.
+    ///
+    pub fn write_complete(&mut self, rd: impl Read) -> io::Result<u64> {
+        self.write_header_and_data(rd)?;
+        Ok(self.finish()?.total_bytes)
+    }
+
+    /// Write a file with no data, e.g. a tombstone marking a deleted asset, returning
+    /// the total number of bytes written. The MAC still covers the header, and readers
+    /// can check for it with [`EnardReader::is_empty`].
+    pub fn write_empty(&mut self) -> io::Result<u64> {
+        self.write_complete(io::empty())
+    }
+
+    /// Finalize writing the file and clean up internal resources, returning a
+    /// [`WriteSummary`] of the file.
+    ///
+    /// After calling this method, writing more data or finishing again returns an error.
+    /// [`EnardWriter::into_inner`] and some other methods will still work though.
+    pub fn finish(&mut self) -> io::Result<WriteSummary> {
+        let (written, mac_tag) = self.write_tag()?;
         // Save the end position
         let end_pos = self.inner.stream_position()?;
+        let start_pos = end_pos
+            - written as u64
+            - self.data_len
+            - self.header_size
+            - self.header_start() as u64;
         // Update original header and data sizes
-        self.inner.seek(SeekFrom::Start(self.start_pos + 6 + 2))?;
-        self.inner.write_u32::<LE>(self.header_size)?;
-        self.inner.write_u64::<LE>(data_len)?;
+        self.inner
+            .seek(SeekFrom::Start(start_pos + self.magic.len() as u64 + 2))?;
+        self.write_sizes(self.data_len)?;
         // Jump back to the end
         self.inner.seek(SeekFrom::Start(end_pos))?;
         self.flush()?;
-        Ok(tag.len())
+        Ok(self.summary(written, mac_tag))
     }
 
-    fn write_u8_block(&mut self, block: &[u8]) -> io::Result<usize> {
-        Self::block_size_check(block, u8::MAX as usize)?;
-        let blen = block.len() as u8;
-        self.mac_write(&blen.to_le_bytes())?;
-        self.mac_write(block)?;
-        Ok(1 + block.len())
+    /// Abandon a partly written file, such as when encryption is cancelled.
+    ///
+    /// The MAC state is dropped and the magic and version at the start of the file are
+    /// overwritten, so the partial file can't be mistaken for an enard file. The inner
+    /// writer is returned positioned at the start of the file, so it can be truncated
+    /// there, e.g. with [`std::fs::File::set_len`]. Nothing is written if the header
+    /// hasn't been written.
+    pub fn abort(mut self) -> io::Result<W> {
+        self.mac = None;
+        self.plaintext_hash = None;
+        #[cfg(feature = "signature")]
+        {
+            self.signer = None;
+        }
+        if self.state.stage == Stage::Data {
+            self.state.stage = Stage::Finished;
+            let start_pos = self.inner.stream_position()?
+                - self.data_len
+                - self.header_size
+                - self.header_start() as u64;
+            self.inner.seek(SeekFrom::Start(start_pos))?;
+            // Version 0 is invalid too, in case the magic is empty
+            self.inner.write_all(&vec![0u8; self.magic.len() + 2])?;
+            self.inner.seek(SeekFrom::Start(start_pos))?;
+            self.inner.flush()?;
+        }
+        Ok(self.inner)
     }
 
-    fn block_size_check(block: &[u8], size: usize) -> io::Result<()> {
-        if block.len() >= size {
-            let msg = format!("block size must be 0-{}, is {}", size - 1, block.len());
-            Err(io::Error::new(ErrorKind::Other, msg))
-        } else {
-            Ok(())
-        }
-    }
-
-    fn write_meta_blocks(&mut self) -> io::Result<usize> {
-        // Move meta out of self so we can safely mutate self.
-        // We'll put it back at the end.
-        let meta = self.meta.take().unwrap();
-        // Track how many bytes we're writing
-        let mut n = 0;
-        // Write meta count
-        let count = meta.len() as u8;
-        self.mac_write(&count.to_le_bytes())?;
-        n += 1;
-
-        for (key, val) in meta.iter() {
-            Self::block_size_check(key, u8::MAX as usize)?;
-            Self::block_size_check(val, u16::MAX as usize)?;
-            let k_len = key.len() as u8;
-            let v_len = val.len() as u16;
-            // Write data
-            self.mac_write(&k_len.to_le_bytes())?;
-            self.mac_write(key)?;
-            self.mac_write(&v_len.to_le_bytes())?;
-            self.mac_write(val)?;
-            // Update size
-            n += 1 + key.len() + 2 + val.len();
-        }
-        // Put the metadata back
-        self.meta = Some(meta);
-        Ok(n)
+    /// Same as [`EnardWriter::finish`], but adding `meta` to the metadata trailer first,
+    /// for values only known once all the data has been written, such as the compressed
+    /// size. Requires [`EnardWriter::with_meta_trailer`].
+    pub fn finish_with_meta_trailer(&mut self, meta: &MetaMap) -> io::Result<WriteSummary> {
+        let trailer = self.trailer.as_mut().ok_or_else(|| {
+            let msg = "with_meta_trailer must be called before writing the header";
+            io::Error::new(ErrorKind::InvalidInput, msg)
+        })?;
+        trailer.extend(meta.iter().map(|(k, v)| (k.clone(), v.clone())));
+        self.finish()
+    }
+}
+
+/// Store the checksum of `header` in its last bytes, see
+/// [`EnardWriter::with_header_checksum`].
+pub(crate) fn set_header_checksum(header: &mut [u8]) {
+    let (rest, checksum) = header.split_at_mut(header.len() - HEADER_CHECKSUM_SIZE);
+    checksum.copy_from_slice(&crc32fast::hash(rest).to_le_bytes());
+}
+
+/// Check the checksum at the end of the header section `header`, see
+/// [`EnardWriter::with_header_checksum`].
+pub(crate) fn check_header_checksum(header: &[u8]) -> Result<(), EnardError> {
+    if header.len() < HEADER_CHECKSUM_SIZE {
+        let size = HEADER_CHECKSUM_SIZE as u64;
+        return Err(EnardError::new_block_size(size, header.len() as u64));
+    }
+    let (rest, checksum) = header.split_at(header.len() - HEADER_CHECKSUM_SIZE);
+    let expected = LE::read_u32(checksum);
+    let actual = crc32fast::hash(rest);
+    if expected != actual {
+        return Err(EnardError::HeaderCorrupt { expected, actual });
+    }
+    Ok(())
+}
+
+/// Size in bytes of an enard file holding `payload_len` bytes of data, encrypted with
+/// the cipher `cipher_name` and with the metadata `meta`, without needing a writer, key
+/// or IV, e.g. to build a pack index before encrypting anything.
+///
+/// This is exact for files written by [`EnardWriter::new`] with the default options and
+/// finished with [`EnardWriter::finish`]. Use [`EnardWriter::estimated_total_size`]
+/// for other options.
+pub fn estimated_file_size(
+    payload_len: u64,
+    meta: &MetaMap,
+    cipher_name: &[u8],
+) -> Result<u64, EnardError> {
+    let cipher = BoxDynCipher::factory().get_meta(cipher_name)?;
+    let version = FormatVersion::default();
+    let header_start = version.header_start();
+    let iv = vec![0; cipher.iv_size];
+    let header = encode_header(
+        version,
+        &[],
+        cipher_name,
+        &iv,
+        meta,
+        header_start,
+        DATA_ALIGNMENT,
+    )?;
+    Ok(header_start as u64 + header.len() as u64 + payload_len + TAG_SIZE as u64)
+}
+
+/// Encodes the header section, including padding so the data starts at a multiple of
+/// `alignment` bytes from the start of the file, given the header section starts at
+/// `header_start`. `salt` is written first, it's only used by obfuscated headers.
+pub(crate) fn encode_header(
+    version: FormatVersion,
+    salt: &[u8],
+    cipher: &[u8],
+    iv: &[u8],
+    meta: &MetaMap,
+    header_start: usize,
+    alignment: usize,
+) -> io::Result<Vec<u8>> {
+    let mut buf = salt.to_vec();
+    crate::format::encode_fields(&mut buf, version, cipher, iv, meta)?;
+    // Pad to 8-byte alignment
+    let data_start = buf.len() + header_start;
+    let padding = (alignment - (data_start % alignment)) % alignment;
+    buf.resize(buf.len() + padding, 0);
+    if version == FormatVersion::V1 && buf.len() > u32::MAX as usize {
+        let msg = format!("header size must be 0-{}, is {}", u32::MAX, buf.len());
+        return Err(io::Error::new(ErrorKind::Other, msg));
     }
+    Ok(buf)
 }
 
 impl<W, C> Write for EnardWriter<W, C>
 where
-    W: Write + Seek,
+    W: Write,
     C: DynCipher,
 {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         let b_size = self.crypt_buf.len();
         // Encrypt each part of the input using the cipher and then write it out
         for chunk in buf.chunks(b_size) {
-            let cbuf = &mut self.crypt_buf[0..b_size.min(chunk.len())];
-            cbuf.clone_from_slice(chunk);
-            self.cipher
-                .try_apply_keystream(cbuf)
-                .map_err(cipher_to_io_error)?;
-            self.inner.write_all(cbuf)?;
-            self.mac.as_mut().unwrap().update(cbuf);
+            self.crypt_buf[0..chunk.len()].clone_from_slice(chunk);
+            self.write_crypt_buf(chunk.len())?;
         }
         Ok(buf.len())
     }
 
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        // Pack the buffers into `crypt_buf` so small buffers don't each cost a
+        // cipher call and a write.
+        let b_size = self.crypt_buf.len();
+        let mut used = 0;
+        let mut total = 0;
+        for buf in bufs {
+            let mut buf = &buf[..];
+            total += buf.len();
+            while !buf.is_empty() {
+                let n = buf.len().min(b_size - used);
+                self.crypt_buf[used..used + n].clone_from_slice(&buf[..n]);
+                used += n;
+                buf = &buf[n..];
+                if used == b_size {
+                    self.write_crypt_buf(used)?;
+                    used = 0;
+                }
+            }
+        }
+        if used > 0 {
+            self.write_crypt_buf(used)?;
+        }
+        Ok(total)
+    }
+
     fn flush(&mut self) -> io::Result<()> {
         self.inner.flush()
     }
 }
 impl<W, C> Debug for EnardWriter<W, C>
 where
-    W: Write + Seek + Debug,
+    W: Write + Debug,
     C: DynCipher,
 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -482,11 +2922,241 @@ where
             .field("inner", &self.inner)
             .field("cipher", &self.cipher.get_name())
             .field("iv", &self.iv)
-            .field("mac", &self.mac)
-            .field("start_pos", &self.start_pos)
             .field("meta", &self.meta)
+            .field("version", &self.version)
             .field("header_size", &self.header_size)
-            .field("crypt_buf", &self.crypt_buf)
+            .field("data_len", &self.data_len)
+            .field("buffer_size", &self.crypt_buf.len())
             .finish()
     }
 }
+
+/// Builder for [`EnardWriter`], see [`EnardWriter::builder`].
+///
+/// The key must be set with [`EnardWriterBuilder::key`] before calling
+/// [`EnardWriterBuilder::build`]. Everything else has a default.
+pub struct EnardWriterBuilder<W, C, Cf, K = ()> {
+    inner: W,
+    factory: Cf,
+    cipher: Vec<u8>,
+    key: K,
+    iv: Option<EnardNonce>,
+    meta: MetaMap,
+    version: FormatVersion,
+    data_alignment: usize,
+    /// Generate a salt for [`crate::kdf::DerivedKey`] if the metadata doesn't have one
+    #[cfg_attr(not(feature = "random"), allow(dead_code))]
+    random_salt: bool,
+    /// Deferred error from [`EnardWriterBuilder::random_iv`]
+    error: Option<EnardError>,
+    phantom: PhantomData<C>,
+}
+impl<W> EnardWriter<W, BoxDynCipher>
+where
+    W: Write,
+{
+    /// Start building a writer using [`BoxDynCipher`]. The cipher defaults to `ChaCha12`.
+    ///
+    /// ```rust
+    /// # use std::io::Cursor;
+    /// # use enard::EnardWriter;
+    /// # fn main() -> Result<(), enard::EnardError> {
+    /// let mut wr = EnardWriter::builder(Cursor::new(Vec::new()))
+    ///     .cipher(b"ChaCha20")
+    ///     .key([0x42; 32])
+    ///     .iv([0x24; 12])
+    ///     .meta("name", "hello.txt")
+    ///     .build()?;
+    /// wr.write_complete(&b"hello"[..])?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn builder(inner: W) -> EnardWriterBuilder<W, BoxDynCipher, BoxDynCipherFactory> {
+        EnardWriterBuilder::new(inner, BoxDynCipher::factory()).cipher(b"ChaCha12")
+    }
+
+    /// Create a writer which doesn't encrypt the data, using
+    /// [`crate::nothing_cipher::NothingCipher`] and an empty key.
+    ///
+    /// The file is marked with [`crate::meta::UNENCRYPTED`], open it with
+    /// [`EnardReader::new_unencrypted`]. The MAC still detects corruption, but anyone
+    /// can read or modify the file, so this is only meant for development builds.
+    pub fn new_unencrypted(inner: W, mut meta: MetaMap) -> Result<Self, EnardError> {
+        meta.insert(crate::meta::UNENCRYPTED.to_vec(), Vec::new());
+        let name = crate::nothing_cipher::NothingCipher::name();
+        let iv = EnardNonce::from(Vec::new());
+        Self::new(inner, BoxDynCipher::factory(), name, &[][..], iv, meta)
+    }
+}
+impl<W, C, Cf> EnardWriterBuilder<W, C, Cf>
+where
+    W: Write,
+    C: DynCipher,
+    Cf: CipherFactory<C>,
+{
+    /// Start building a writer which creates ciphers using `factory`.
+    ///
+    /// The cipher name defaults to empty, which factories for a single cipher type
+    /// accept as that cipher.
+    pub fn new(inner: W, factory: Cf) -> Self {
+        Self {
+            inner,
+            factory,
+            cipher: Vec::new(),
+            key: (),
+            iv: None,
+            meta: MetaMap::new(),
+            version: FormatVersion::default(),
+            data_alignment: DATA_ALIGNMENT,
+            random_salt: false,
+            error: None,
+            phantom: PhantomData,
+        }
+    }
+}
+impl<W, C, Cf, K> EnardWriterBuilder<W, C, Cf, K>
+where
+    W: Write,
+    C: DynCipher,
+    Cf: CipherFactory<C>,
+{
+    /// Set the name of the cipher to use.
+    pub fn cipher(mut self, name: &[u8]) -> Self {
+        self.cipher = name.to_vec();
+        self
+    }
+
+    /// Set the key, which can be any [`KeyProvider`].
+    pub fn key<K2: KeyProvider>(self, key: K2) -> EnardWriterBuilder<W, C, Cf, K2> {
+        EnardWriterBuilder {
+            inner: self.inner,
+            factory: self.factory,
+            cipher: self.cipher,
+            key,
+            iv: self.iv,
+            meta: self.meta,
+            version: self.version,
+            data_alignment: self.data_alignment,
+            random_salt: false,
+            error: self.error,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Derive the key for this file from `master`, see [`crate::kdf`].
+    ///
+    /// If the metadata doesn't have a salt when the writer is built, a random one is
+    /// generated when the `random` feature is enabled, otherwise
+    /// [`EnardWriterBuilder::build`] returns [`EnardError::MissingMeta`].
+    pub fn derived_key<K2: KeyProvider>(
+        self,
+        master: K2,
+    ) -> EnardWriterBuilder<W, C, Cf, crate::kdf::DerivedKey<K2>> {
+        let mut builder = self.key(crate::kdf::DerivedKey(master));
+        builder.random_salt = true;
+        builder
+    }
+
+    /// Set the IV. It must never be re-used with the same key.
+    pub fn iv(mut self, iv: impl Into<EnardNonce>) -> Self {
+        self.iv = Some(iv.into());
+        self
+    }
+
+    /// Generate a random IV of the right size for the cipher using `rng`.
+    ///
+    /// This must be called after [`EnardWriterBuilder::cipher`].
+    #[cfg(feature = "random")]
+    pub fn random_iv<Rng: rand::CryptoRng + rand::Rng>(mut self, rng: &mut Rng) -> Self {
+        match self.factory.get_meta(&self.cipher) {
+            Ok(meta) => self.iv = Some(EnardNonce::generate(&meta, rng)),
+            Err(e) => self.error = Some(e),
+        }
+        self
+    }
+
+    /// Add a metadata entry.
+    pub fn meta(mut self, key: impl Into<Vec<u8>>, value: impl Into<Vec<u8>>) -> Self {
+        self.meta.insert(key.into(), value.into());
+        self
+    }
+
+    /// Replace all metadata with `meta`.
+    pub fn meta_map(mut self, meta: MetaMap) -> Self {
+        self.meta = meta;
+        self
+    }
+
+    /// Set the format version, see [`EnardWriter::with_version`].
+    pub fn version(mut self, version: FormatVersion) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Align the data section to `alignment` bytes, see [`EnardWriter::with_data_alignment`].
+    pub fn data_alignment(mut self, alignment: usize) -> Self {
+        self.data_alignment = alignment;
+        self
+    }
+
+    /// Use the MAC algorithm `name`, see [`crate::mac`]. This also selects
+    /// [`FormatVersion::V2`], and must be called after [`EnardWriterBuilder::meta_map`].
+    pub fn mac(mut self, name: &[u8]) -> Self {
+        self.meta
+            .insert(crate::mac::MAC_META_KEY.to_vec(), name.to_vec());
+        self.version = FormatVersion::V2;
+        self
+    }
+
+    /// Use separate encryption and MAC keys derived from the key, see
+    /// [`crate::kdf::insert_split_keys`]. This also selects [`FormatVersion::V2`], and
+    /// must be called after [`EnardWriterBuilder::meta_map`].
+    pub fn split_keys(mut self) -> Self {
+        crate::kdf::insert_split_keys(&mut self.meta);
+        self.version = FormatVersion::V2;
+        self
+    }
+}
+impl<W, C, Cf, K> EnardWriterBuilder<W, C, Cf, K>
+where
+    W: Write,
+    C: DynCipher,
+    Cf: CipherFactory<C>,
+    K: KeyProvider,
+{
+    /// Create the [`EnardWriter`].
+    ///
+    /// If no IV was set, a random one is generated when the `random` feature is enabled,
+    /// otherwise this returns [`EnardError::InvalidLength`].
+    pub fn build(mut self) -> Result<EnardWriter<W, C>, EnardError> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        let iv = match self.iv.take() {
+            Some(iv) => iv,
+            #[cfg(feature = "random")]
+            None => {
+                let meta = self.factory.get_meta(&self.cipher)?;
+                EnardNonce::generate(&meta, &mut rand::rngs::OsRng)
+            }
+            #[cfg(not(feature = "random"))]
+            None => return Err(EnardError::InvalidLength),
+        };
+        #[cfg(feature = "random")]
+        if self.random_salt && !self.meta.contains_key(crate::kdf::SALT_META_KEY) {
+            let salt = crate::kdf::generate_salt(&mut rand::rngs::OsRng);
+            crate::kdf::insert_salt(&mut self.meta, &salt);
+        }
+        let wr = EnardWriter::new(
+            self.inner,
+            self.factory,
+            &self.cipher,
+            self.key,
+            iv,
+            self.meta,
+        )?;
+        Ok(wr
+            .with_version(self.version)
+            .with_data_alignment(self.data_alignment))
+    }
+}
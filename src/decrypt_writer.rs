@@ -0,0 +1,383 @@
+use byteorder::{ByteOrder, LE};
+use std::io::{self, ErrorKind, Write};
+use zeroize::Zeroizing;
+
+use crate::cipher_factory::{CipherFactory, GetFactory};
+use crate::core::{
+    check_header_checksum, cipher_to_io_error, header_extended_meta_size, CryptBuf, FormatVersion,
+    Header, OpenKeys, WithKey, DATA_ALIGNMENT, FOOTER_MAGIC, FOOTER_SIZE, HEADER_CHECKSUM_FLAG,
+    HEADER_CHECKSUM_SIZE, MAGIC, TAG_SIZE,
+};
+use crate::dyn_cipher::BoxDynCipherFactory;
+use crate::kdf::HEADER_SALT_SIZE;
+use crate::limits::ParseLimits;
+use crate::mac::{BoxDynMac, DefaultMacFactory};
+use crate::{BoxDynCipher, DynCipher, EnardError, KeyProvider, MetaMap, DEFAULT_BUFFER_SIZE};
+
+/// Size of a signature block, see [`crate::signature`]. Files written with
+/// [`crate::EnardWriter::finish_streaming`] may have one between the tag and the footer.
+pub(crate) const SIGNATURE_BLOCK_SIZE: usize = 64 + 8;
+
+/// Decrypts an enard file which is pushed into it, instead of read from a
+/// [`Read`](std::io::Read) + [`Seek`](std::io::Seek) like [`crate::EnardReader`].
+///
+/// Write the file to it as it arrives, e.g. from a download, and the plaintext is written
+/// to the inner writer. Files written with [`crate::EnardWriter::finish_streaming`] are
+/// supported too, the end of their data is held back until the footer arrives.
+///
+/// **The plaintext is not authenticated until [`EnardDecryptWriter::finish`] has
+/// verified the MAC.** Until then it may have been tampered with, so write it somewhere
+/// temporary and throw it away if `finish` fails. After any error the writer should be
+/// dropped.
+///
+/// Files with a metadata trailer (see [`crate::EnardWriter::with_meta_trailer`]) aren't
+/// supported, because the end of the plaintext can't be known until the whole file has
+/// arrived.
+///
+/// ```rust
+/// # use std::io::{Cursor, Write};
+/// # use enard::EnardDecryptWriter;
+/// # fn main() -> Result<(), enard::EnardError> {
+/// # let mut wr = enard::EnardWriter::builder(Cursor::new(Vec::new()))
+/// #     .key([0x42; 32])
+/// #     .iv([0x24; 12])
+/// #     .build()?;
+/// # wr.write_complete(&b"hello"[..])?;
+/// # let file = wr.into_inner().into_inner();
+/// let mut dec = EnardDecryptWriter::new_boxed(Vec::new(), [0x42; 32]);
+/// for chunk in file.chunks(4) {
+///     dec.write_all(chunk)?;
+/// }
+/// let plaintext = dec.finish()?;
+/// assert_eq!(plaintext, b"hello");
+/// # Ok(())
+/// # }
+/// ```
+pub struct EnardDecryptWriter<W, C, Cf, K> {
+    inner: W,
+    keys: WithKey<Cf, K>,
+    limits: ParseLimits,
+    /// Bytes received but not processed yet
+    pending: Vec<u8>,
+    /// Total number of bytes received
+    received: u64,
+    /// Set once the header has been parsed
+    body: Option<Body<C>>,
+    /// Key for an obfuscated header, set once the version field has arrived
+    header_key: Option<Zeroizing<[u8; 32]>>,
+    plain_buf: CryptBuf,
+}
+
+/// Returns the header fields from the start of the header section `section`, made
+/// readable if the header is obfuscated, or `None` if the salt hasn't arrived yet.
+fn header_fields(section: &[u8], header_key: Option<&[u8; 32]>) -> Option<Zeroizing<Vec<u8>>> {
+    match header_key {
+        Some(key) if section.len() >= HEADER_SALT_SIZE => {
+            let (salt, rest) = section.split_at(HEADER_SALT_SIZE);
+            let mut fields = Zeroizing::new(rest.to_vec());
+            crate::kdf::obfuscate_header(key, salt, &mut fields);
+            Some(fields)
+        }
+        Some(_) => None,
+        None => Some(Zeroizing::new(section.to_vec())),
+    }
+}
+
+/// State of an [`EnardDecryptWriter`] after the header.
+struct Body<C> {
+    cipher: C,
+    mac: BoxDynMac,
+    meta: MetaMap,
+    header_size: u64,
+    /// Offset of the data relative to the start of the file
+    data_start: u64,
+    /// `None` until the footer arrives if the sizes are in the footer
+    data_size: Option<u64>,
+    ext_meta_size: u64,
+    /// Number of bytes of the data section processed so far
+    data_pos: u64,
+}
+
+impl<W, K> EnardDecryptWriter<W, BoxDynCipher, BoxDynCipherFactory, K>
+where
+    W: Write,
+    K: KeyProvider,
+{
+    /// Create a new [`EnardDecryptWriter`] using [`BoxDynCipher`], which supports all
+    /// ciphers enard was built with.
+    pub fn new_boxed(inner: W, key: K) -> Self {
+        Self::new(inner, BoxDynCipher::factory(), key)
+    }
+}
+impl<W, C, Cf, K> EnardDecryptWriter<W, C, Cf, K>
+where
+    W: Write,
+    C: DynCipher,
+    Cf: CipherFactory<C>,
+    K: KeyProvider,
+{
+    /// Create a new [`EnardDecryptWriter`] which writes the plaintext to `inner`.
+    ///
+    /// `key` is only asked for the key once the header has arrived.
+    pub fn new(inner: W, factory: Cf, key: K) -> Self {
+        Self {
+            inner,
+            keys: WithKey::new(factory, key),
+            limits: ParseLimits::default(),
+            pending: Vec::new(),
+            received: 0,
+            body: None,
+            header_key: None,
+            plain_buf: CryptBuf::new(DEFAULT_BUFFER_SIZE),
+        }
+    }
+
+    /// Reject files larger than `limits`, see [`crate::EnardReader::new_with_limits`].
+    pub fn with_limits(mut self, limits: ParseLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Returns the metadata, or `None` if the header hasn't arrived yet.
+    ///
+    /// Like the plaintext, this isn't authenticated until [`EnardDecryptWriter::finish`]
+    /// returns.
+    pub fn meta(&self) -> Option<&MetaMap> {
+        self.body.as_ref().map(|body| &body.meta)
+    }
+
+    /// Access the inner writer, e.g. to take the plaintext written to a buffer so far.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Check that the whole file has arrived and verify the MAC, then flush and return
+    /// the inner writer.
+    ///
+    /// Returns [`EnardError::Truncated`] if the file is incomplete and
+    /// [`EnardError::MacError`] if it was modified.
+    pub fn finish(mut self) -> Result<W, EnardError> {
+        if self.body.is_none() {
+            return Err(io::Error::from(ErrorKind::UnexpectedEof).into());
+        }
+        if self.body.as_ref().unwrap().data_size.is_none() {
+            self.read_footer()?;
+        }
+        let body = self.body.take().unwrap();
+        let data_size = body.data_size.unwrap();
+        if body.data_pos < data_size || self.pending.len() < TAG_SIZE {
+            return Err(EnardError::Truncated {
+                expected: body.data_start + data_size + TAG_SIZE as u64,
+                actual: self.received,
+            });
+        }
+        crate::mac::verify(body.mac, &self.pending[..TAG_SIZE])?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    /// Process as much of `pending` as possible.
+    fn process(&mut self) -> Result<(), EnardError> {
+        if self.body.is_none() && !self.read_header()? {
+            return Ok(());
+        }
+        let body = self.body.as_ref().unwrap();
+        let len = match body.data_size {
+            Some(size) => (self.pending.len() as u64).min(size - body.data_pos) as usize,
+            None => {
+                self.limits.check_sizes(body.header_size, body.data_pos)?;
+                // The extended metadata, tag and footer must not be decrypted as data
+                let hold_back =
+                    body.ext_meta_size as usize + TAG_SIZE + SIGNATURE_BLOCK_SIZE + FOOTER_SIZE;
+                self.pending.len().saturating_sub(hold_back)
+            }
+        };
+        self.data(len)?;
+        let body = self.body.as_ref().unwrap();
+        if body.data_size == Some(body.data_pos) {
+            // Only the tag is left, anything after it is ignored
+            self.pending.truncate(TAG_SIZE);
+        }
+        Ok(())
+    }
+
+    /// Parse the header once enough of it has arrived, returns `false` if it hasn't.
+    fn read_header(&mut self) -> Result<bool, EnardError> {
+        if self.pending.len() < MAGIC.len() + 2 {
+            return Ok(false);
+        }
+        if &self.pending[..MAGIC.len()] != MAGIC {
+            return Err(EnardError::new_invalid_magic(
+                MAGIC,
+                &self.pending[..MAGIC.len()],
+            ));
+        }
+        let field = LE::read_u16(&self.pending[MAGIC.len()..]);
+        let (version, obfuscated) = FormatVersion::from_field(field)?;
+        let checksum_size = if field & HEADER_CHECKSUM_FLAG != 0 {
+            HEADER_CHECKSUM_SIZE
+        } else {
+            0
+        };
+        if obfuscated && self.header_key.is_none() {
+            self.header_key = Some(OpenKeys::<C>::header_key(&mut self.keys)?);
+        }
+        let start = version.header_start();
+        if self.pending.len() < start {
+            return Ok(false);
+        }
+        let sizes = &self.pending[MAGIC.len() + 2..start];
+        let header_size = match version {
+            FormatVersion::V1 => LE::read_u32(sizes) as u64,
+            FormatVersion::V2 => LE::read_u64(sizes),
+        };
+        let data_size = LE::read_u64(&sizes[sizes.len() - 8..]);
+
+        let (header, header_size, data_size) = if header_size == 0 && data_size == 0 {
+            // The sizes are in the footer, so parse until the header is complete
+            let fields = match header_fields(&self.pending[start..], self.header_key.as_deref()) {
+                Some(fields) => fields,
+                None => return Ok(false),
+            };
+            let mut rd = fields.as_slice();
+            let max_size = self.limits.max_header_size;
+            let header = match Header::read(&mut rd, version, max_size, &self.limits) {
+                Ok(header) => header,
+                Err(EnardError::IO(e)) if e.kind() == ErrorKind::UnexpectedEof => {
+                    let received = (self.pending.len() - start) as u64;
+                    self.limits.check_sizes(received, 0)?;
+                    return Ok(false);
+                }
+                Err(e) => return Err(e),
+            };
+            // Assume the padding `EnardWriter` adds, the footer is checked later
+            let len = self.pending.len() - start - rd.len() + checksum_size;
+            let padding = (DATA_ALIGNMENT - (start + len) % DATA_ALIGNMENT) % DATA_ALIGNMENT;
+            (header, (len + padding) as u64, None)
+        } else {
+            self.limits.check_sizes(header_size, data_size)?;
+            let end = (start as u64).saturating_add(header_size);
+            if (self.pending.len() as u64) < end {
+                return Ok(false);
+            }
+            let section = &self.pending[start..end as usize];
+            let fields = header_fields(section, self.header_key.as_deref())
+                .ok_or_else(|| EnardError::new_block_size(HEADER_SALT_SIZE as u64, header_size))?;
+            let size = fields.len() as u64;
+            let header = Header::read(fields.as_slice(), version, size, &self.limits)?;
+            (header, header_size, Some(data_size))
+        };
+        let data_start = start as u64 + header_size;
+        if (self.pending.len() as u64) < data_start {
+            return Ok(false);
+        }
+        if checksum_size > 0 {
+            check_header_checksum(&self.pending[start..data_start as usize])?;
+        }
+        if header.meta.contains_key(crate::meta::META_TRAILER) {
+            let msg = "metadata trailers require seeking";
+            return Err(io::Error::new(ErrorKind::Unsupported, msg).into());
+        }
+
+        let (mut mac, _) = OpenKeys::<C>::open_mac(&mut self.keys, &header, &DefaultMacFactory)?;
+        mac.update(&self.pending[start..data_start as usize]);
+        let cipher = OpenKeys::<C>::open_cipher(&mut self.keys, &header)?;
+        let ext_meta_size = header_extended_meta_size(&header.meta, data_size.unwrap_or(u64::MAX))?;
+        self.pending.drain(..data_start as usize);
+        self.body = Some(Body {
+            cipher,
+            mac,
+            meta: header.meta,
+            header_size,
+            data_start,
+            data_size,
+            ext_meta_size,
+            data_pos: 0,
+        });
+        Ok(true)
+    }
+
+    /// Read the sizes from the footer at the end of `pending`, and process the rest of
+    /// the data.
+    fn read_footer(&mut self) -> Result<(), EnardError> {
+        let body = self.body.as_mut().unwrap();
+        let truncated = EnardError::Truncated {
+            expected: body.data_start + body.data_pos + (TAG_SIZE + FOOTER_SIZE) as u64,
+            actual: self.received,
+        };
+        if self.pending.len() < FOOTER_SIZE {
+            return Err(truncated);
+        }
+        let footer = &self.pending[self.pending.len() - FOOTER_SIZE..];
+        let magic = &footer[16..];
+        if magic != FOOTER_MAGIC {
+            return Err(EnardError::new_invalid_magic(FOOTER_MAGIC, magic));
+        }
+        let header_size = LE::read_u64(&footer[..8]);
+        let data_size = LE::read_u64(&footer[8..16]);
+        if header_size != body.header_size {
+            let msg = "header size in the footer doesn't match the header";
+            return Err(io::Error::new(ErrorKind::InvalidData, msg).into());
+        }
+        self.limits.check_sizes(header_size, data_size)?;
+        if body.ext_meta_size > data_size {
+            return Err(EnardError::new_block_size(body.ext_meta_size, data_size));
+        }
+        let rest = data_size.checked_sub(body.data_pos).ok_or(truncated)?;
+        if (self.pending.len() as u64) < rest + (TAG_SIZE + FOOTER_SIZE) as u64 {
+            return Err(EnardError::Truncated {
+                expected: body.data_start + data_size + (TAG_SIZE + FOOTER_SIZE) as u64,
+                actual: self.received,
+            });
+        }
+        body.data_size = Some(data_size);
+        self.data(rest as usize)
+    }
+
+    /// Add the first `len` bytes of `pending` to the MAC, and decrypt and write out the
+    /// ones before the extended metadata.
+    fn data(&mut self, len: usize) -> Result<(), EnardError> {
+        let body = self.body.as_mut().unwrap();
+        let data = &self.pending[..len];
+        body.mac.update(data);
+        let payload_len = match body.data_size {
+            Some(size) => (size - body.ext_meta_size).saturating_sub(body.data_pos),
+            None => len as u64,
+        };
+        let payload = &data[..(len as u64).min(payload_len) as usize];
+        for chunk in payload.chunks(self.plain_buf.len()) {
+            let buf = &mut self.plain_buf[..chunk.len()];
+            buf.copy_from_slice(chunk);
+            body.cipher
+                .try_apply_keystream(buf)
+                .map_err(cipher_to_io_error)?;
+            self.inner.write_all(buf)?;
+        }
+        body.data_pos += len as u64;
+        self.pending.drain(..len);
+        Ok(())
+    }
+}
+
+/// Errors other than I/O errors are returned as [`ErrorKind::InvalidData`].
+impl<W, C, Cf, K> Write for EnardDecryptWriter<W, C, Cf, K>
+where
+    W: Write,
+    C: DynCipher,
+    Cf: CipherFactory<C>,
+    K: KeyProvider,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        self.received += buf.len() as u64;
+        self.process().map_err(|e| match e {
+            EnardError::IO(e) => e,
+            e => io::Error::new(ErrorKind::InvalidData, e),
+        })?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
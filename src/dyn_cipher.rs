@@ -1,7 +1,11 @@
 use crate::cipher_factory::*;
 use crate::error::EnardError;
 use crate::nothing_cipher::NothingCipher;
-use cipher::{IvSizeUser, KeySizeUser, StreamCipher, StreamCipherError, StreamCipherSeek};
+use cipher::{
+    IvSizeUser, KeyIvInit, KeySizeUser, StreamCipher, StreamCipherError, StreamCipherSeek,
+};
+use std::io::{self, ErrorKind};
+use zeroize::Zeroizing;
 
 type TResult<T> = Result<T, EnardError>;
 
@@ -21,6 +25,20 @@ pub trait DynCipherCore {
 
     /// Key size in bytes
     fn key_size(&self) -> usize;
+
+    /// Start over with the same key and a new IV, at position 0 of the key stream.
+    ///
+    /// Ciphers don't keep their key, so by default this returns an
+    /// [`ErrorKind::Unsupported`] error. Ciphers created by [`BoxDynCipherFactory`]
+    /// (including [`BoxDynCipher::new`]) support it.
+    fn reset_iv(&mut self, iv: &[u8]) -> Result<(), EnardError> {
+        let _ = iv;
+        let msg = format!(
+            "{} can't reset its IV, create it with BoxDynCipher::new",
+            self.get_name().escape_ascii()
+        );
+        Err(io::Error::new(ErrorKind::Unsupported, msg).into())
+    }
 }
 impl<T> DynCipherCore for T
 where
@@ -51,10 +69,65 @@ where
 pub trait DynCipher: StreamCipher + DynCipherCore + Send {}
 impl<T: StreamCipher + DynCipherCore + Send> DynCipher for T {}
 
+/// A cipher along with its key, so [`DynCipherCore::reset_iv`] can create it again.
+struct Rekeyable<C> {
+    cipher: C,
+    key: Zeroizing<Vec<u8>>,
+}
+impl<C: StreamCipher> StreamCipher for Rekeyable<C> {
+    #[inline]
+    fn try_apply_keystream_inout(
+        &mut self,
+        buf: cipher::inout::InOutBuf<'_, '_, u8>,
+    ) -> Result<(), StreamCipherError> {
+        self.cipher.try_apply_keystream_inout(buf)
+    }
+}
+impl<C> DynCipherCore for Rekeyable<C>
+where
+    C: DynCipherCore + KeyIvInit,
+{
+    delegate::delegate! {
+        to self.cipher {
+            fn try_seek(&mut self, new_pos: u64) -> Result<(), StreamCipherError>;
+            fn current_pos(&self) -> u64;
+            fn get_name(&self) -> &'static [u8];
+            fn iv_size(&self) -> usize;
+            fn key_size(&self) -> usize;
+        }
+    }
+
+    fn reset_iv(&mut self, iv: &[u8]) -> Result<(), EnardError> {
+        self.cipher = C::new_from_slices(&self.key, iv)?;
+        Ok(())
+    }
+}
+
 /// Wraps a [`Box<dyn DynCipher>`] and also provides a [`CipherFactory`] which dynamically
 /// selects the cipher implementation based on the provided name.
 ///
+/// Ciphers can also be created directly with [`BoxDynCipher::new`], e.g. to encrypt a
+/// custom file layout with [`crate::stream`].
+///
+/// ```rust
+/// use cipher::StreamCipher;
+/// use enard::{BoxDynCipher, DynCipherCore};
+/// # fn main() -> Result<(), enard::EnardError> {
+/// let mut cipher = BoxDynCipher::new(b"ChaCha12", &[0x42; 32], &[0x24; 12])?;
+/// let mut block = *b"first block";
+/// cipher.apply_keystream(&mut block);
+/// cipher.reset_iv(&[0x25; 12])?;
+/// # Ok(())
+/// # }
+/// ```
 pub struct BoxDynCipher(pub Box<dyn DynCipher>);
+impl BoxDynCipher {
+    /// Create the cipher `name` with `key` and `iv`, the same as
+    /// [`BoxDynCipherFactory::create`].
+    pub fn new(name: &[u8], key: &[u8], iv: &[u8]) -> TResult<Self> {
+        BoxDynCipherFactory.create(name, key, iv)
+    }
+}
 impl GetFactory<BoxDynCipherFactory> for BoxDynCipher {
     fn factory() -> BoxDynCipherFactory {
         BoxDynCipherFactory
@@ -77,6 +150,7 @@ impl DynCipherCore for BoxDynCipher {
             fn get_name(&self) -> &'static [u8];
             fn iv_size(&self) -> usize;
             fn key_size(&self) -> usize;
+            fn reset_iv(&mut self, iv: &[u8]) -> Result<(), EnardError>;
         }
     }
 }
@@ -92,6 +166,9 @@ impl CipherFactory<BoxDynCipher> for BoxDynCipherFactory {
             };
         }
 
+        if name == crate::cascade::CascadeCipher::NAME {
+            return Ok(crate::cascade::CascadeCipher::meta());
+        }
         name_check! { NothingCipher }
         #[cfg(feature = "chacha20")]
         {
@@ -100,6 +177,14 @@ impl CipherFactory<BoxDynCipher> for BoxDynCipherFactory {
             name_check! { ChaCha12 }
             name_check! { ChaCha20 }
         }
+        #[cfg(feature = "salsa20")]
+        {
+            use salsa20::*;
+            name_check! { Salsa8 }
+            name_check! { Salsa12 }
+            name_check! { Salsa20 }
+            name_check! { XSalsa20 }
+        }
         // If that all fails, error out
         Err(EnardError::new_unsupported_encryption(name))
     }
@@ -109,11 +194,15 @@ impl CipherFactory<BoxDynCipher> for BoxDynCipherFactory {
             ($type:ty) => {
                 if name == <$type>::name() {
                     let cipher = <$type>::factory().create(name, key, iv)?;
-                    return Ok(BoxDynCipher(Box::new(cipher)));
+                    let key = Zeroizing::new(key.to_vec());
+                    return Ok(BoxDynCipher(Box::new(Rekeyable { cipher, key })));
                 }
             };
         }
 
+        if name == crate::cascade::CascadeCipher::NAME {
+            return crate::cascade::create(key, iv);
+        }
         w_create! { NothingCipher }
         #[cfg(feature = "chacha20")]
         {
@@ -122,7 +211,33 @@ impl CipherFactory<BoxDynCipher> for BoxDynCipherFactory {
             w_create! { ChaCha12 }
             w_create! { ChaCha20 }
         }
+        #[cfg(feature = "salsa20")]
+        {
+            use salsa20::*;
+            w_create! { Salsa8 }
+            w_create! { Salsa12 }
+            w_create! { Salsa20 }
+            w_create! { XSalsa20 }
+        }
         // If that all fails, error out
         Err(EnardError::new_unsupported_encryption(name))
     }
+
+    fn validate(&self, name: &[u8], key_len: usize, iv_len: usize) -> TResult<()> {
+        let meta = self.get_meta(name)?;
+        if key_len != meta.key_size {
+            return Err(EnardError::KeyLengthMismatch {
+                expected: meta.key_size,
+                got: key_len,
+            });
+        }
+        // The IV of a cascade depends on its layers, it's checked when it's created
+        if iv_len != meta.iv_size && name != crate::cascade::CascadeCipher::NAME {
+            return Err(EnardError::IvLengthMismatch {
+                expected: meta.iv_size,
+                got: iv_len,
+            });
+        }
+        Ok(())
+    }
 }
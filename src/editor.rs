@@ -0,0 +1,233 @@
+use byteorder::{WriteBytesExt, LE};
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom, Write};
+use zeroize::Zeroizing;
+
+use crate::cipher_factory::CipherFactory;
+use crate::core::{
+    encode_header, extended_meta_size, obfuscated_header, set_header_checksum, EnardBuilder,
+    FormatVersion, DATA_ALIGNMENT, HEADER_CHECKSUM_FLAG, HEADER_CHECKSUM_SIZE, MAGIC,
+};
+use crate::mac::{DefaultMacFactory, MacFactory};
+use crate::stream::SECTOR_SIZE;
+use crate::{DynCipher, EnardError, KeyProvider, MetaMap};
+
+/// Size of the buffer used when moving the data section.
+const MOVE_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Modifies an existing enard file in place.
+///
+/// Writes go straight to the file, but the MAC is only recomputed when
+/// [`EnardEditor::finish`] is called, so the file is invalid until then. If the file is
+/// signed (see [`crate::signature`]) the signature will be invalid afterwards and
+/// should be removed or recreated.
+///
+/// ```rust
+/// # use std::io::Cursor;
+/// # use enard::{BoxDynCipher, EnardEditor};
+/// # use enard::cipher_factory::GetFactory;
+/// # fn patch(pack: Vec<u8>, key: &[u8]) -> Result<Vec<u8>, enard::EnardError> {
+/// let mut editor = EnardEditor::open(Cursor::new(pack), BoxDynCipher::factory(), key)?;
+/// editor.write_at(1024, b"new bytes")?;
+/// editor.meta_mut().insert(b"patch".to_vec(), b"2".to_vec());
+/// Ok(editor.finish()?.into_inner())
+/// # }
+/// ```
+pub struct EnardEditor<F, C> {
+    inner: F,
+    cipher: C,
+    mac_key: Zeroizing<Vec<u8>>,
+    version: FormatVersion,
+    /// Offset of the enard file in `inner`
+    start: u64,
+    header_size: u64,
+    /// Size of the whole data section, including the extended metadata
+    data_size: u64,
+    ext_meta_size: u64,
+    cipher_name: Vec<u8>,
+    iv: Vec<u8>,
+    meta: MetaMap,
+    meta_changed: bool,
+}
+impl<F, C> EnardEditor<F, C>
+where
+    F: Read + Write + Seek,
+    C: DynCipher,
+{
+    /// Open the enard file starting at the current position of `inner` for editing.
+    ///
+    /// The MAC is verified first, so a file that was already corrupt can't end up
+    /// with a valid MAC.
+    pub fn open<Cf: CipherFactory<C>, K: KeyProvider>(
+        mut inner: F,
+        factory: Cf,
+        key: K,
+    ) -> Result<Self, EnardError> {
+        let start = inner.stream_position()?;
+        let (mut inner, mut parsed) = EnardBuilder::new(inner, factory, key).parse()?;
+        let ext_meta_size = extended_meta_size(&mut inner, &mut parsed)?;
+        Ok(Self {
+            inner,
+            cipher: parsed.cipher,
+            mac_key: parsed.mac_key,
+            version: parsed.version,
+            start,
+            header_size: parsed.header_size,
+            data_size: parsed.data_size,
+            ext_meta_size,
+            cipher_name: parsed.header.cipher,
+            iv: parsed.header.iv,
+            meta: parsed.header.meta,
+            meta_changed: false,
+        })
+    }
+
+    /// Access the metadata
+    pub fn meta(&self) -> &MetaMap {
+        &self.meta
+    }
+
+    /// Modify the metadata, the header is rewritten by [`EnardEditor::finish`].
+    pub fn meta_mut(&mut self) -> &mut MetaMap {
+        self.meta_changed = true;
+        &mut self.meta
+    }
+
+    /// Size in bytes of the decrypted data
+    pub fn data_size(&self) -> u64 {
+        self.data_size - self.ext_meta_size
+    }
+
+    /// Encrypt `buf` and overwrite the data starting at `offset`.
+    ///
+    /// The data size can't be changed, so `offset + buf.len()` must not be
+    /// larger than [`EnardEditor::data_size`].
+    pub fn write_at(&mut self, offset: u64, buf: &[u8]) -> Result<(), EnardError> {
+        let end = offset.checked_add(buf.len() as u64);
+        if !matches!(end, Some(end) if end <= self.data_size()) {
+            let msg = format!(
+                "write of {} bytes at {} is outside the data (size {})",
+                buf.len(),
+                offset,
+                self.data_size()
+            );
+            return Err(io::Error::new(ErrorKind::InvalidInput, msg).into());
+        }
+        let mut crypt = Zeroizing::new(buf.to_vec());
+        self.cipher
+            .try_seek(offset)
+            .and_then(|_| self.cipher.try_apply_keystream(&mut crypt))
+            .map_err(|e| io::Error::new(ErrorKind::Other, format!("{:?}", e)))?;
+        self.inner
+            .seek(SeekFrom::Start(self.data_start() + offset))?;
+        self.inner.write_all(&crypt)?;
+        Ok(())
+    }
+
+    /// Rewrite the header if the metadata changed, recompute the MAC, and return
+    /// the inner writer.
+    ///
+    /// If the new header is larger than the old one, the data is moved to make room,
+    /// which means rewriting everything after the header.
+    pub fn finish(mut self) -> Result<F, EnardError> {
+        if self.meta_changed {
+            self.rewrite_header()?;
+        }
+        let header_start = self.header_start();
+        self.inner.seek(SeekFrom::Start(header_start))?;
+        let mac_name = crate::mac::mac_name(&self.meta);
+        let mut mac = DefaultMacFactory.create(mac_name, &self.mac_key)?;
+        let len = self.header_size + self.data_size;
+        let n = io::copy(&mut (&mut self.inner).take(len), &mut mac)?;
+        if n != len {
+            return Err(io::Error::from(ErrorKind::UnexpectedEof).into());
+        }
+        // The tag goes right after the data
+        self.inner.seek(SeekFrom::Start(header_start + len))?;
+        self.inner.write_all(&mac.finalize())?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    fn header_start(&self) -> u64 {
+        self.start + self.version.header_start() as u64
+    }
+
+    fn data_start(&self) -> u64 {
+        self.header_start() + self.header_size
+    }
+
+    /// Alignment of the data section relative to the start of the file, so growing the
+    /// header doesn't lose it, see [`crate::EnardWriter::with_data_alignment`].
+    fn data_alignment(&self) -> usize {
+        let data_start = self.version.header_start() as u64 + self.header_size;
+        let alignment = 1u64 << data_start.trailing_zeros().min(63);
+        (alignment.min(SECTOR_SIZE as u64) as usize).max(DATA_ALIGNMENT)
+    }
+
+    fn rewrite_header(&mut self) -> Result<(), EnardError> {
+        let mut field = [0u8; 2];
+        self.inner
+            .seek(SeekFrom::Start(self.start + MAGIC.len() as u64))?;
+        self.inner.read_exact(&mut field)?;
+        let field = u16::from_le_bytes(field);
+        if FormatVersion::from_field(field)?.1 {
+            return Err(obfuscated_header().into());
+        }
+        let reserved = if field & HEADER_CHECKSUM_FLAG != 0 {
+            HEADER_CHECKSUM_SIZE
+        } else {
+            0
+        };
+        let mut header = encode_header(
+            self.version,
+            &[],
+            &self.cipher_name,
+            &self.iv,
+            &self.meta,
+            self.version.header_start() + reserved,
+            self.data_alignment(),
+        )?;
+        header.resize(header.len() + reserved, 0);
+        let new_size = header.len() as u64;
+        if new_size <= self.header_size {
+            // Padding after the header fields is ignored, so keep the same size
+            // rather than moving the data.
+            header.resize(self.header_size as usize, 0);
+        } else {
+            let old_start = self.data_start();
+            let new_start = self.header_start() + new_size;
+            self.move_data(old_start, new_start)?;
+            self.header_size = new_size;
+            // Update the header size, and the data size in case it was only in the footer
+            self.inner
+                .seek(SeekFrom::Start(self.start + MAGIC.len() as u64 + 2))?;
+            match self.version {
+                FormatVersion::V1 => self.inner.write_u32::<LE>(new_size as u32)?,
+                FormatVersion::V2 => self.inner.write_u64::<LE>(new_size)?,
+            }
+            self.inner.write_u64::<LE>(self.data_size)?;
+        }
+        if reserved > 0 {
+            set_header_checksum(&mut header);
+        }
+        self.inner.seek(SeekFrom::Start(self.header_start()))?;
+        self.inner.write_all(&header)?;
+        Ok(())
+    }
+
+    /// Move the data section from `from` to `to`, where `to > from`.
+    fn move_data(&mut self, from: u64, to: u64) -> io::Result<()> {
+        let mut buf = vec![0u8; MOVE_BUFFER_SIZE];
+        // Copy from the end so nothing is overwritten before it's moved
+        let mut remaining = self.data_size;
+        while remaining > 0 {
+            let n = remaining.min(buf.len() as u64) as usize;
+            remaining -= n as u64;
+            self.inner.seek(SeekFrom::Start(from + remaining))?;
+            self.inner.read_exact(&mut buf[..n])?;
+            self.inner.seek(SeekFrom::Start(to + remaining))?;
+            self.inner.write_all(&buf[..n])?;
+        }
+        Ok(())
+    }
+}
@@ -1,3 +1,5 @@
+use std::fmt;
+
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -14,13 +16,196 @@ pub enum EnardError {
     UnsupportedEncryption { kind: Box<str> },
     #[error("expected magic header '{exp}' but found '{found}'")]
     InvalidMagic { exp: Box<str>, found: Box<str> },
-    #[error("unsupported format version {version}, supported versions: 1")]
+    #[error("unsupported format version {version}, supported versions: 1, 2")]
     UnsupportedVersion { version: u16 },
     #[error("block too large, size: {size}, limit: {limit}")]
     BlockTooLarge { size: u64, limit: u64 },
+    #[error("no embedded enard file found")]
+    NotFound,
+    #[error("file is truncated, expected {expected} bytes but found {actual}")]
+    Truncated { expected: u64, actual: u64 },
+    #[error("missing metadata '{key}'")]
+    MissingMeta { key: Box<str> },
+    #[error("missing or invalid signature")]
+    InvalidSignature,
+    #[error("plaintext checksum mismatch, expected {expected:08x} but found {actual:08x}")]
+    ChecksumMismatch { expected: u32, actual: u32 },
+    #[error("wrong key")]
+    WrongKey,
+    #[error("metadata key '{key}' is reserved")]
+    ReservedMeta { key: Box<str> },
+    #[error("invalid hex string")]
+    InvalidHex,
+    #[error("key is {got} bytes but the cipher needs {expected}")]
+    KeyLengthMismatch { expected: usize, got: usize },
+    #[error("IV is {got} bytes but the cipher needs {expected}")]
+    IvLengthMismatch { expected: usize, got: usize },
+    #[error("invalid base64 string")]
+    InvalidBase64,
+    /// The header section doesn't match its checksum, see
+    /// [`crate::EnardWriter::with_header_checksum`].
+    #[error("header is corrupt, checksum expected {expected:08x} but found {actual:08x}")]
+    HeaderCorrupt { expected: u32, actual: u32 },
+    /// `error` happened while parsing the `phase` of a file, at `offset` bytes into the
+    /// reader. Use [`EnardError::root_cause`] to match on the underlying error.
+    #[error("{error} (in the {phase} at offset {offset})")]
+    Parse {
+        phase: ParsePhase,
+        offset: u64,
+        error: Box<EnardError>,
+    },
+}
+
+/// Part of an enard file being parsed when an error happened, see [`EnardError::Parse`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ParsePhase {
+    Magic,
+    /// Version, sizes, cipher name and IV
+    Header,
+    Meta,
+    Data,
+    Tag,
+    /// Sizes at the end of a streamed file, see [`crate::EnardWriter::finish_streaming`]
+    Footer,
+}
+impl fmt::Display for ParsePhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Magic => "magic",
+            Self::Header => "header",
+            Self::Meta => "metadata",
+            Self::Data => "data",
+            Self::Tag => "tag",
+            Self::Footer => "footer",
+        })
+    }
+}
+
+/// Stable numeric codes for each [`EnardError`] variant, primarily for crossing
+/// an FFI boundary, plus a few only used by [`crate::ffi`]. `0` is never used so it can
+/// represent success.
+///
+/// Codes are never re-used, new variants will always get new codes.
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    IO = 1,
+    InvalidBufferSize = 2,
+    MacError = 3,
+    InvalidLength = 4,
+    UnsupportedEncryption = 5,
+    InvalidMagic = 6,
+    UnsupportedVersion = 7,
+    BlockTooLarge = 8,
+    NotFound = 9,
+    Truncated = 10,
+    MissingMeta = 11,
+    InvalidSignature = 12,
+    ChecksumMismatch = 13,
+    WrongKey = 14,
+    ReservedMeta = 15,
+    InvalidHex = 16,
+    KeyLengthMismatch = 17,
+    IvLengthMismatch = 18,
+    InvalidBase64 = 19,
+    HeaderCorrupt = 20,
+    /// An argument to a C API function was invalid, such as a `NULL` handle
+    InvalidArgument = 21,
+    /// A C API function panicked, the handle it was given shouldn't be used again
+    Panic = 22,
+}
+impl ErrorCode {
+    /// All error codes, in numeric order
+    pub const ALL: &'static [ErrorCode] = &[
+        Self::IO,
+        Self::InvalidBufferSize,
+        Self::MacError,
+        Self::InvalidLength,
+        Self::UnsupportedEncryption,
+        Self::InvalidMagic,
+        Self::UnsupportedVersion,
+        Self::BlockTooLarge,
+        Self::NotFound,
+        Self::Truncated,
+        Self::MissingMeta,
+        Self::InvalidSignature,
+        Self::ChecksumMismatch,
+        Self::WrongKey,
+        Self::ReservedMeta,
+        Self::InvalidHex,
+        Self::KeyLengthMismatch,
+        Self::IvLengthMismatch,
+        Self::InvalidBase64,
+        Self::HeaderCorrupt,
+        Self::InvalidArgument,
+        Self::Panic,
+    ];
+
+    /// Numeric value of this code
+    pub fn code(self) -> u32 {
+        self as u32
+    }
+
+    /// Returns the [`ErrorCode`] with the given numeric value, if there is one.
+    pub fn from_code(code: u32) -> Option<Self> {
+        Self::ALL.iter().copied().find(|c| c.code() == code)
+    }
 }
 
 impl EnardError {
+    /// Returns the [`ErrorCode`] for this error. [`EnardError::Parse`] has the code of
+    /// the error it wraps.
+    pub fn error_code(&self) -> ErrorCode {
+        match self {
+            Self::IO(_) => ErrorCode::IO,
+            Self::InvalidBufferSize(_) => ErrorCode::InvalidBufferSize,
+            Self::MacError(_) => ErrorCode::MacError,
+            Self::InvalidLength => ErrorCode::InvalidLength,
+            Self::UnsupportedEncryption { .. } => ErrorCode::UnsupportedEncryption,
+            Self::InvalidMagic { .. } => ErrorCode::InvalidMagic,
+            Self::UnsupportedVersion { .. } => ErrorCode::UnsupportedVersion,
+            Self::BlockTooLarge { .. } => ErrorCode::BlockTooLarge,
+            Self::NotFound => ErrorCode::NotFound,
+            Self::Truncated { .. } => ErrorCode::Truncated,
+            Self::MissingMeta { .. } => ErrorCode::MissingMeta,
+            Self::InvalidSignature => ErrorCode::InvalidSignature,
+            Self::ChecksumMismatch { .. } => ErrorCode::ChecksumMismatch,
+            Self::WrongKey => ErrorCode::WrongKey,
+            Self::ReservedMeta { .. } => ErrorCode::ReservedMeta,
+            Self::InvalidHex => ErrorCode::InvalidHex,
+            Self::KeyLengthMismatch { .. } => ErrorCode::KeyLengthMismatch,
+            Self::IvLengthMismatch { .. } => ErrorCode::IvLengthMismatch,
+            Self::InvalidBase64 => ErrorCode::InvalidBase64,
+            Self::HeaderCorrupt { .. } => ErrorCode::HeaderCorrupt,
+            Self::Parse { error, .. } => error.error_code(),
+        }
+    }
+
+    /// Returns the error without the context added by [`EnardError::Parse`].
+    pub fn root_cause(&self) -> &EnardError {
+        match self {
+            Self::Parse { error, .. } => error.root_cause(),
+            e => e,
+        }
+    }
+
+    /// Add the phase and offset where a parse error happened, if it doesn't have them yet.
+    pub(crate) fn at(self, phase: ParsePhase, offset: u64) -> Self {
+        match self {
+            e @ Self::Parse { .. } => e,
+            e => Self::Parse {
+                phase,
+                offset,
+                error: Box::new(e),
+            },
+        }
+    }
+
+    /// Returns the stable numeric code for this error, see [`ErrorCode`].
+    pub fn code(&self) -> u32 {
+        self.error_code().code()
+    }
+
     pub(crate) fn new_unsupported_encryption(kind_buf: &[u8]) -> Self {
         let kind = u8_to_box_str(kind_buf);
         Self::UnsupportedEncryption { kind }
@@ -32,6 +217,16 @@ impl EnardError {
         Self::InvalidMagic { exp, found }
     }
 
+    pub(crate) fn new_missing_meta(key: &[u8]) -> Self {
+        let key = u8_to_box_str(key);
+        Self::MissingMeta { key }
+    }
+
+    pub(crate) fn new_reserved_meta(key: &[u8]) -> Self {
+        let key = u8_to_box_str(key);
+        Self::ReservedMeta { key }
+    }
+
     pub(crate) fn new_block_size(size: u64, limit: u64) -> Self {
         Self::BlockTooLarge { size, limit }
     }
@@ -0,0 +1,284 @@
+//! C ABI for using enard from other languages.
+//!
+//! The matching C header is `include/enard.h`, which is generated with
+//! `cbindgen --config cbindgen.toml --output include/enard.h`. To get a library
+//! that C code can link against, build with e.g.
+//! `cargo rustc --release --features ffi --crate-type cdylib`.
+//!
+//! Functions that can fail return `NULL` or a negative number and store an
+//! [`ErrorCode`](crate::ErrorCode) which can be retrieved with [`enard_last_error`].
+//! `NULL` handles and other invalid arguments are reported as `InvalidArgument`, and
+//! panics are caught and reported as `Panic` instead of unwinding into the caller.
+//! Reader handles come from [`enard_reader_open`] or [`enard_reader_open_memory`].
+//!
+//! `bindings/csharp/Enard.cs` wraps the reader functions in a .NET `Stream` for C# and
+//! Unity, see `bindings/csharp/README.md`.
+use std::cell::Cell;
+use std::ffi::CStr;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write};
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+
+use crate::cipher_factory::GetFactory;
+use crate::error::{EnardError, ErrorCode};
+use crate::stream::ReadSeek;
+use crate::{BoxDynCipher, EnardReader, EnardWriter, MetaMap};
+
+/// Opaque reader handle
+pub struct EnardReaderHandle(EnardReader<Box<dyn ReadSeek>, BoxDynCipher>);
+
+/// Opaque writer handle
+pub struct EnardWriterHandle(EnardWriter<BufWriter<File>, BoxDynCipher>);
+
+thread_local! {
+    static LAST_ERROR: Cell<u32> = const { Cell::new(0) };
+}
+
+fn set_error(code: ErrorCode) {
+    LAST_ERROR.with(|e| e.set(code.code()));
+}
+
+/// Run `f`, storing the error code and returning `None` if it fails or panics.
+fn catch<T>(f: impl FnOnce() -> Result<T, EnardError>) -> Option<T> {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(v)) => Some(v),
+        Ok(Err(e)) => {
+            set_error(e.error_code());
+            None
+        }
+        Err(_) => {
+            set_error(ErrorCode::Panic);
+            None
+        }
+    }
+}
+
+unsafe fn str_from_c<'a>(path: *const c_char) -> Result<&'a str, EnardError> {
+    if path.is_null() {
+        return Err(io::Error::from(io::ErrorKind::InvalidInput).into());
+    }
+    CStr::from_ptr(path)
+        .to_str()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e).into())
+}
+
+unsafe fn slice_from_c<'a>(data: *const u8, len: usize) -> &'a [u8] {
+    if data.is_null() || len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(data, len)
+    }
+}
+
+/// Returns the [`ErrorCode`] of the last error on this thread, or 0 if there hasn't
+/// been one.
+#[no_mangle]
+pub extern "C" fn enard_last_error() -> u32 {
+    LAST_ERROR.with(|e| e.get())
+}
+
+/// Open the enard file at `path` for reading. Returns `NULL` on error.
+///
+/// # Safety
+/// `path` must be a valid nul-terminated UTF-8 string and `key` must point to
+/// `key_len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn enard_reader_open(
+    path: *const c_char,
+    key: *const u8,
+    key_len: usize,
+) -> *mut EnardReaderHandle {
+    catch(|| {
+        let file = File::open(str_from_c(path)?)?;
+        let reader =
+            EnardReader::new_dyn(Box::new(BufReader::new(file)), slice_from_c(key, key_len))?;
+        Ok(Box::into_raw(Box::new(EnardReaderHandle(reader))))
+    })
+    .unwrap_or(ptr::null_mut())
+}
+
+/// Open an enard file held in memory, e.g. an asset which isn't a separate file on
+/// the platform. The data is copied, so `data` can be freed afterwards. Returns `NULL`
+/// on error.
+///
+/// # Safety
+/// `data` must point to `len` bytes and `key` must point to `key_len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn enard_reader_open_memory(
+    data: *const u8,
+    len: usize,
+    key: *const u8,
+    key_len: usize,
+) -> *mut EnardReaderHandle {
+    catch(|| {
+        let data = Cursor::new(slice_from_c(data, len).to_vec());
+        let reader = EnardReader::new_dyn(Box::new(data), slice_from_c(key, key_len))?;
+        Ok(Box::into_raw(Box::new(EnardReaderHandle(reader))))
+    })
+    .unwrap_or(ptr::null_mut())
+}
+
+/// Returns the size of the decrypted data in bytes, or -1 on error.
+///
+/// # Safety
+/// `reader` must be a reader handle.
+#[no_mangle]
+pub unsafe extern "C" fn enard_reader_data_size(reader: *const EnardReaderHandle) -> i64 {
+    if reader.is_null() {
+        set_error(ErrorCode::InvalidArgument);
+        return -1;
+    }
+    (*reader).0.data_size() as i64
+}
+
+/// Read up to `len` decrypted bytes into `buf`. Returns the number of bytes read,
+/// 0 at the end of the data, or -1 on error.
+///
+/// # Safety
+/// `reader` must be a reader handle and `buf` must point to `len` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn enard_reader_read(
+    reader: *mut EnardReaderHandle,
+    buf: *mut u8,
+    len: usize,
+) -> isize {
+    if reader.is_null() || (buf.is_null() && len > 0) {
+        set_error(ErrorCode::InvalidArgument);
+        return -1;
+    }
+    let buf = if len == 0 {
+        &mut []
+    } else {
+        slice::from_raw_parts_mut(buf, len)
+    };
+    catch(|| Ok((*reader).0.read(buf)? as isize)).unwrap_or(-1)
+}
+
+/// Seek within the decrypted data. `whence` is one of `SEEK_SET` (0), `SEEK_CUR` (1) or
+/// `SEEK_END` (2). Returns the new position, or -1 on error.
+///
+/// # Safety
+/// `reader` must be a reader handle.
+#[no_mangle]
+pub unsafe extern "C" fn enard_reader_seek(
+    reader: *mut EnardReaderHandle,
+    offset: i64,
+    whence: c_int,
+) -> i64 {
+    if reader.is_null() {
+        set_error(ErrorCode::InvalidArgument);
+        return -1;
+    }
+    let pos = match whence {
+        0 if offset >= 0 => SeekFrom::Start(offset as u64),
+        1 => SeekFrom::Current(offset),
+        2 => SeekFrom::End(offset),
+        _ => {
+            set_error(ErrorCode::InvalidArgument);
+            return -1;
+        }
+    };
+    catch(|| Ok((*reader).0.seek(pos)? as i64)).unwrap_or(-1)
+}
+
+/// Close a reader. Passing `NULL` does nothing.
+///
+/// # Safety
+/// `reader` must be a reader handle which hasn't been closed yet.
+#[no_mangle]
+pub unsafe extern "C" fn enard_reader_close(reader: *mut EnardReaderHandle) {
+    if !reader.is_null() {
+        drop(Box::from_raw(reader));
+    }
+}
+
+/// Create a new enard file at `path` encrypted with the named cipher,
+/// and write the header. Returns `NULL` on error.
+///
+/// # Safety
+/// `path` and `cipher` must be valid nul-terminated UTF-8 strings, and `key` and `iv`
+/// must point to `key_len` and `iv_len` bytes respectively.
+#[no_mangle]
+pub unsafe extern "C" fn enard_writer_create(
+    path: *const c_char,
+    cipher: *const c_char,
+    key: *const u8,
+    key_len: usize,
+    iv: *const u8,
+    iv_len: usize,
+) -> *mut EnardWriterHandle {
+    catch(|| {
+        let cipher = str_from_c(cipher)?;
+        let file = File::create(str_from_c(path)?)?;
+        let mut writer = EnardWriter::new(
+            BufWriter::new(file),
+            BoxDynCipher::factory(),
+            cipher.as_bytes(),
+            slice_from_c(key, key_len),
+            slice_from_c(iv, iv_len).into(),
+            MetaMap::new(),
+        )?;
+        writer.write_header()?;
+        Ok(Box::into_raw(Box::new(EnardWriterHandle(writer))))
+    })
+    .unwrap_or(ptr::null_mut())
+}
+
+/// Encrypt and write `len` bytes from `buf`. Returns 0 on success or -1 on error.
+///
+/// # Safety
+/// `writer` must be a handle from [`enard_writer_create`] and `buf` must point to
+/// `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn enard_writer_write(
+    writer: *mut EnardWriterHandle,
+    buf: *const u8,
+    len: usize,
+) -> c_int {
+    if writer.is_null() {
+        set_error(ErrorCode::InvalidArgument);
+        return -1;
+    }
+    catch(|| Ok((*writer).0.write_all(slice_from_c(buf, len))?))
+        .map(|_| 0)
+        .unwrap_or(-1)
+}
+
+/// Finish writing the file and close the writer. Returns 0 on success or -1 on error.
+/// The writer is closed even if an error occurs.
+///
+/// # Safety
+/// `writer` must be a handle from [`enard_writer_create`] which hasn't been closed yet.
+#[no_mangle]
+pub unsafe extern "C" fn enard_writer_finish(writer: *mut EnardWriterHandle) -> c_int {
+    if writer.is_null() {
+        set_error(ErrorCode::InvalidArgument);
+        return -1;
+    }
+    let EnardWriterHandle(mut writer) = *Box::from_raw(writer);
+    catch(|| match writer.finish() {
+        Ok(_) => Ok(writer.into_inner().flush()?),
+        Err(e) => {
+            let _ = writer.abort();
+            Err(e.into())
+        }
+    })
+    .map(|_| 0)
+    .unwrap_or(-1)
+}
+
+/// Close the writer without finishing it, the resulting file will be invalid, see
+/// [`EnardWriter::abort`]. Passing `NULL` does nothing.
+///
+/// # Safety
+/// `writer` must be a handle from [`enard_writer_create`] which hasn't been closed yet.
+#[no_mangle]
+pub unsafe extern "C" fn enard_writer_close(writer: *mut EnardWriterHandle) {
+    if !writer.is_null() {
+        let EnardWriterHandle(writer) = *Box::from_raw(writer);
+        let _ = writer.abort();
+    }
+}
@@ -0,0 +1,393 @@
+//! The enard file format as code, see `format.md` in the repository.
+//!
+//! [`HeaderV1`] and [`HeaderV2`] encode and decode the fields of the header section
+//! exactly as [`crate::EnardWriter`] and [`crate::EnardReader`] do, so implementations
+//! in other languages can be checked against them.
+//!
+//! ```rust
+//! # fn main() -> Result<(), enard::EnardError> {
+//! use enard::format::HeaderV2;
+//! let mut header = HeaderV2::default();
+//! header.cipher = b"ChaCha12".to_vec();
+//! header.iv = vec![0x24; 12];
+//! header.meta.insert(b"name".to_vec(), b"a.png".to_vec());
+//! let mut buf = Vec::new();
+//! header.encode(&mut buf)?;
+//! assert_eq!(HeaderV2::decode(&buf[..])?, header);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! It can also parse the header of an enard file without allocating:
+//! [`parse_header`] returns a [`Header`] which borrows the cipher name, IV and metadata
+//! from the caller's buffer. This is useful when opening many small files, or where
+//! allocation isn't possible. Nothing is authenticated, [`crate::EnardReader`] or
+//! [`crate::verify`] still have to check the MAC before the header can be trusted.
+//!
+//! ```rust
+//! # fn main() -> Result<(), enard::EnardError> {
+//! # let file = enard::EnardWriter::builder(std::io::Cursor::new(Vec::new()))
+//! #     .key([0x42; 32]).iv([0x24; 12]).meta("name", "a.png").build()
+//! #     .and_then(|mut wr| { wr.write_complete(&b"..."[..])?; Ok(wr.into_inner().into_inner()) })?;
+//! let header = enard::format::parse_header(&file)?;
+//! assert_eq!(header.cipher, b"ChaCha12");
+//! assert_eq!(header.get_meta(b"name"), Some(&b"a.png"[..]));
+//! # Ok(())
+//! # }
+//! ```
+use byteorder::{WriteBytesExt, LE};
+use std::io::{self, BufRead, ErrorKind, Write};
+
+use crate::core::{HEADER_START, MAGIC};
+use crate::limits::ParseLimits;
+use crate::{varint, EnardError, FormatVersion, MetaMap};
+
+macro_rules! header_fields {
+    ($name:ident, $version:expr, $doc:literal) => {
+        #[doc = $doc]
+        ///
+        /// The header section of a file is these fields followed by padding, see
+        /// [`crate::EnardWriter::with_data_alignment`].
+        #[derive(Debug, Clone, Default, PartialEq, Eq)]
+        pub struct $name {
+            /// Ascii name of the cipher
+            pub cipher: Vec<u8>,
+            /// Cipher IV (aka nonce), may be empty
+            pub iv: Vec<u8>,
+            pub meta: MetaMap,
+        }
+        impl $name {
+            /// The version this header is encoded for
+            pub const VERSION: FormatVersion = $version;
+
+            /// Encode the header fields, without padding.
+            ///
+            /// Returns an error if a field is too large for the format.
+            pub fn encode<W: Write>(&self, mut writer: W) -> io::Result<()> {
+                let mut buf = Vec::new();
+                encode_fields(&mut buf, $version, &self.cipher, &self.iv, &self.meta)?;
+                writer.write_all(&buf)
+            }
+
+            /// Decode the header fields, leaving `reader` after them, which is where the
+            /// padding starts in a file.
+            pub fn decode<R: BufRead>(reader: R) -> Result<Self, EnardError> {
+                Self::decode_with_limits(reader, &ParseLimits::default())
+            }
+
+            /// Same as [`Self::decode`] but with custom [`ParseLimits`].
+            pub fn decode_with_limits<R: BufRead>(
+                reader: R,
+                limits: &ParseLimits,
+            ) -> Result<Self, EnardError> {
+                let header = crate::core::Header::read(reader, $version, u64::MAX, limits)?;
+                Ok(Self {
+                    cipher: header.cipher,
+                    iv: header.iv,
+                    meta: header.meta,
+                })
+            }
+        }
+    };
+}
+header_fields!(
+    HeaderV1,
+    FormatVersion::V1,
+    "Fields of a [`FormatVersion::V1`] header: the cipher and IV as u8-blocks, then a `u8` count of metadata entries, each a u8-block name and a u16-block value."
+);
+header_fields!(
+    HeaderV2,
+    FormatVersion::V2,
+    "Fields of a [`FormatVersion::V2`] header: the cipher and IV as varint-blocks, then a varint count of metadata entries, each a varint-block name and value."
+);
+
+/// Encode the header fields in the format of `version`, without padding.
+pub(crate) fn encode_fields(
+    buf: &mut Vec<u8>,
+    version: FormatVersion,
+    cipher: &[u8],
+    iv: &[u8],
+    meta: &MetaMap,
+) -> io::Result<()> {
+    write_small_block(buf, version, cipher)?;
+    write_small_block(buf, version, iv)?;
+    match version {
+        FormatVersion::V1 => {
+            if meta.len() > u8::MAX as usize {
+                let msg = format!("at most {} metadata entries allowed", u8::MAX);
+                return Err(io::Error::new(ErrorKind::Other, msg));
+            }
+            buf.push(meta.len() as u8);
+        }
+        FormatVersion::V2 => varint::write_u(&mut *buf, meta.len() as u64)?,
+    }
+    for (key, val) in meta.iter() {
+        write_small_block(buf, version, key)?;
+        write_large_block(buf, version, val)?;
+    }
+    Ok(())
+}
+
+/// Writes a u8-block in v1 or a varint-block in v2.
+pub(crate) fn write_small_block(
+    buf: &mut Vec<u8>,
+    version: FormatVersion,
+    block: &[u8],
+) -> io::Result<()> {
+    match version {
+        FormatVersion::V1 => {
+            block_size_check(block, u8::MAX as usize)?;
+            buf.push(block.len() as u8);
+        }
+        FormatVersion::V2 => varint::write_u(&mut *buf, block.len() as u64)?,
+    }
+    buf.extend_from_slice(block);
+    Ok(())
+}
+
+/// Writes a u16-block in v1 or a varint-block in v2.
+pub(crate) fn write_large_block(
+    buf: &mut Vec<u8>,
+    version: FormatVersion,
+    block: &[u8],
+) -> io::Result<()> {
+    match version {
+        FormatVersion::V1 => {
+            block_size_check(block, u16::MAX as usize)?;
+            buf.write_u16::<LE>(block.len() as u16)?;
+        }
+        FormatVersion::V2 => varint::write_u(&mut *buf, block.len() as u64)?,
+    }
+    buf.extend_from_slice(block);
+    Ok(())
+}
+
+fn block_size_check(block: &[u8], max: usize) -> io::Result<()> {
+    if block.len() > max {
+        let msg = format!("block size must be 0-{}, is {}", max, block.len());
+        Err(io::Error::new(ErrorKind::Other, msg))
+    } else {
+        Ok(())
+    }
+}
+
+/// Borrowed view of the header of an enard file, see [`parse_header`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header<'a> {
+    pub version: FormatVersion,
+    /// Size in bytes of the header section, including padding
+    pub header_size: u64,
+    /// Size in bytes of the (encrypted) data section
+    pub data_size: u64,
+    /// Offset of the data section from the start of the buffer
+    pub data_start: u64,
+    /// Ascii name of the cipher
+    pub cipher: &'a [u8],
+    pub iv: &'a [u8],
+    meta_count: u64,
+    /// The encoded metadata entries, and possibly padding
+    meta: &'a [u8],
+}
+impl<'a> Header<'a> {
+    /// Number of metadata entries
+    pub fn meta_len(&self) -> u64 {
+        self.meta_count
+    }
+
+    /// Iterate over the metadata entries in the order they're stored.
+    pub fn meta(&self) -> MetaIter<'a> {
+        MetaIter {
+            version: self.version,
+            remaining: self.meta_count,
+            buf: self.meta,
+        }
+    }
+
+    /// Returns the value of the first metadata entry named `key`.
+    pub fn get_meta(&self, key: &[u8]) -> Option<&'a [u8]> {
+        self.meta().find(|(k, _)| *k == key).map(|(_, v)| v)
+    }
+
+    /// Identifier of the key the file was written with, if set, see
+    /// [`crate::meta::KEY_ID`].
+    pub fn key_id(&self) -> Option<&'a [u8]> {
+        self.get_meta(crate::meta::KEY_ID)
+    }
+
+    /// Copy the header into a [`HeaderInfo`].
+    pub fn to_info(&self) -> HeaderInfo {
+        HeaderInfo {
+            version: self.version,
+            header_size: self.header_size,
+            data_size: self.data_size,
+            cipher: self.cipher.escape_ascii().to_string(),
+            iv: self.iv.to_vec(),
+            meta: self.meta().map(|(k, v)| (k.to_vec(), v.to_vec())).collect(),
+        }
+    }
+}
+
+/// Owned copy of a [`Header`], e.g. for build tools to keep in a manifest.
+///
+/// With the `serde` feature this can be serialized, with the IV as base64 and the
+/// metadata as described in [`crate::serde_meta`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HeaderInfo {
+    pub version: FormatVersion,
+    /// Size in bytes of the header section, including padding
+    pub header_size: u64,
+    /// Size in bytes of the (encrypted) data section
+    pub data_size: u64,
+    /// Name of the cipher, with any non-ascii bytes escaped
+    pub cipher: String,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_meta::bytes"))]
+    pub iv: Vec<u8>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_meta"))]
+    pub meta: MetaMap,
+}
+
+/// Iterator over the metadata entries of a [`Header`], as `(name, value)` pairs.
+#[derive(Debug, Clone)]
+pub struct MetaIter<'a> {
+    version: FormatVersion,
+    remaining: u64,
+    buf: &'a [u8],
+}
+impl<'a> Iterator for MetaIter<'a> {
+    type Item = (&'a [u8], &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        // Entries were checked by `parse_header`, so this doesn't fail
+        read_meta_entry(&mut self.buf, self.version).ok()
+    }
+}
+
+/// Parse the start of an enard file in `buf`.
+///
+/// `buf` must contain at least the header section, the data isn't needed. If it's too
+/// short this returns [`EnardError::Truncated`] with the number of bytes required, so
+/// callers can read a small prefix first and try again with more if needed. Headers with
+/// a checksum are checked, returning [`EnardError::HeaderCorrupt`] if they're corrupt.
+pub fn parse_header(buf: &[u8]) -> Result<Header<'_>, EnardError> {
+    // The v1 prefix is the shortest
+    check_len(buf, HEADER_START as u64)?;
+    let mut rd = buf;
+    let magic = take(&mut rd, MAGIC.len())?;
+    if magic != MAGIC {
+        return Err(EnardError::new_invalid_magic(MAGIC, magic));
+    }
+    let field = u16::from_le_bytes(take_array(&mut rd)?);
+    let (version, obfuscated) = FormatVersion::from_field(field)?;
+    if obfuscated {
+        return Err(crate::core::obfuscated_header().into());
+    }
+    let header_start = version.header_start() as u64;
+    check_len(buf, header_start)?;
+    let header_size = match version {
+        FormatVersion::V1 => u32::from_le_bytes(take_array(&mut rd)?) as u64,
+        FormatVersion::V2 => u64::from_le_bytes(take_array(&mut rd)?),
+    };
+    let data_size = u64::from_le_bytes(take_array(&mut rd)?);
+    if header_size == 0 && data_size == 0 {
+        return Err(crate::core::sizes_in_footer().into());
+    }
+    let data_start = header_start.saturating_add(header_size);
+    check_len(buf, data_start)?;
+    // Only look at the header section from here on
+    let mut rd = &buf[header_start as usize..data_start as usize];
+    if field & crate::core::HEADER_CHECKSUM_FLAG != 0 {
+        crate::core::check_header_checksum(rd)?;
+    }
+    let (cipher, iv, meta_count) = match version {
+        FormatVersion::V1 => {
+            let cipher = read_u8_block(&mut rd)?;
+            let iv = read_u8_block(&mut rd)?;
+            let count = read_u8_block_len(&mut rd)?;
+            (cipher, iv, count as u64)
+        }
+        FormatVersion::V2 => {
+            let cipher = read_varint_block(&mut rd)?;
+            let iv = read_varint_block(&mut rd)?;
+            let count = varint::read_u(&mut rd)?;
+            (cipher, iv, count)
+        }
+    };
+    // Check all of the entries now, so iterating over them can't fail
+    let meta = rd;
+    for _ in 0..meta_count {
+        read_meta_entry(&mut rd, version)?;
+    }
+    Ok(Header {
+        version,
+        header_size,
+        data_size,
+        data_start,
+        cipher,
+        iv,
+        meta_count,
+        meta,
+    })
+}
+
+fn read_meta_entry<'a>(
+    rd: &mut &'a [u8],
+    version: FormatVersion,
+) -> Result<(&'a [u8], &'a [u8]), EnardError> {
+    match version {
+        FormatVersion::V1 => {
+            let key = read_u8_block(rd)?;
+            let len = u16::from_le_bytes(take_array(rd)?);
+            Ok((key, take(rd, len as usize)?))
+        }
+        FormatVersion::V2 => Ok((read_varint_block(rd)?, read_varint_block(rd)?)),
+    }
+}
+
+fn read_u8_block_len(rd: &mut &[u8]) -> Result<u8, EnardError> {
+    Ok(take_array::<1>(rd)?[0])
+}
+
+fn read_u8_block<'a>(rd: &mut &'a [u8]) -> Result<&'a [u8], EnardError> {
+    let len = read_u8_block_len(rd)?;
+    take(rd, len as usize)
+}
+
+fn read_varint_block<'a>(rd: &mut &'a [u8]) -> Result<&'a [u8], EnardError> {
+    let len = varint::read_u(&mut *rd)?;
+    if len > rd.len() as u64 {
+        return Err(EnardError::new_block_size(len, rd.len() as u64));
+    }
+    take(rd, len as usize)
+}
+
+/// Returns [`EnardError::Truncated`] if `buf` is shorter than `len`.
+fn check_len(buf: &[u8], len: u64) -> Result<(), EnardError> {
+    if (buf.len() as u64) < len {
+        return Err(EnardError::Truncated {
+            expected: len,
+            actual: buf.len() as u64,
+        });
+    }
+    Ok(())
+}
+
+/// Split `n` bytes off the front of `rd`, running out means the header is corrupt.
+fn take<'a>(rd: &mut &'a [u8], n: usize) -> Result<&'a [u8], EnardError> {
+    if rd.len() < n {
+        return Err(io::Error::from(ErrorKind::UnexpectedEof).into());
+    }
+    let (head, tail) = rd.split_at(n);
+    *rd = tail;
+    Ok(head)
+}
+
+fn take_array<const N: usize>(rd: &mut &[u8]) -> Result<[u8; N], EnardError> {
+    let mut out = [0u8; N];
+    out.copy_from_slice(take(rd, N)?);
+    Ok(out)
+}
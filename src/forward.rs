@@ -0,0 +1,201 @@
+use std::io::{self, ErrorKind, Read, Write};
+use zeroize::Zeroize;
+
+use crate::cipher_factory::{CipherFactory, GetFactory};
+use crate::dyn_cipher::BoxDynCipherFactory;
+use crate::{
+    BoxDynCipher, DynCipher, EnardDecryptWriter, EnardError, KeyProvider, MetaMap,
+    DEFAULT_BUFFER_SIZE,
+};
+
+/// Decrypts an enard file front-to-back, only needing [`Read`] instead of
+/// [`Read`] + [`Seek`](std::io::Seek) like [`crate::EnardReader`].
+///
+/// This suits consumers which never seek, such as decompressors and hashers, without
+/// buffering a whole network stream first. The file is read in order and the MAC is
+/// verified when the end of the file is reached.
+///
+/// **The plaintext is not authenticated until the MAC has been verified.** A read
+/// returns an error instead of the end of the file if the MAC doesn't match, and
+/// [`EnardForwardReader::finish`] returns the result of the check, so don't use the
+/// plaintext unless one of them succeeded. Like [`EnardDecryptWriter`], files with a
+/// metadata trailer aren't supported.
+///
+/// ```rust
+/// # use std::io::{Cursor, Read};
+/// # use enard::EnardForwardReader;
+/// # fn main() -> Result<(), enard::EnardError> {
+/// # let mut wr = enard::EnardWriter::builder(Cursor::new(Vec::new()))
+/// #     .key([0x42; 32])
+/// #     .iv([0x24; 12])
+/// #     .build()?;
+/// # wr.write_complete(&b"hello"[..])?;
+/// # let file = wr.into_inner().into_inner();
+/// let mut rd = EnardForwardReader::new_boxed(&file[..], [0x42; 32]);
+/// let mut text = String::new();
+/// rd.read_to_string(&mut text)?;
+/// rd.finish()?;
+/// assert_eq!(text, "hello");
+/// # Ok(())
+/// # }
+/// ```
+pub struct EnardForwardReader<R, C, Cf, K> {
+    inner: R,
+    state: PullDecrypt<C, Cf, K>,
+    /// Why the MAC check failed, returned by [`EnardForwardReader::finish`]
+    error: Option<EnardError>,
+}
+
+impl<R, K> EnardForwardReader<R, BoxDynCipher, BoxDynCipherFactory, K>
+where
+    R: Read,
+    K: KeyProvider,
+{
+    /// Create a new [`EnardForwardReader`] using [`BoxDynCipher`], which supports all
+    /// ciphers enard was built with.
+    pub fn new_boxed(inner: R, key: K) -> Self {
+        Self::new(inner, BoxDynCipher::factory(), key)
+    }
+}
+impl<R, C, Cf, K> EnardForwardReader<R, C, Cf, K>
+where
+    R: Read,
+    C: DynCipher,
+    Cf: CipherFactory<C>,
+    K: KeyProvider,
+{
+    /// Create a new [`EnardForwardReader`] which decrypts the file read from `inner`.
+    ///
+    /// Nothing is read until the first read.
+    pub fn new(inner: R, factory: Cf, key: K) -> Self {
+        Self {
+            inner,
+            state: PullDecrypt::new(factory, key),
+            error: None,
+        }
+    }
+
+    /// Returns the metadata, or `None` if the header hasn't been read yet or the end
+    /// of the file has been reached.
+    ///
+    /// Like the plaintext, this isn't authenticated until the whole file has been read.
+    pub fn meta(&self) -> Option<&MetaMap> {
+        self.state.meta()
+    }
+
+    /// Returns `true` once the whole file has been read and the MAC verified.
+    pub fn is_verified(&self) -> bool {
+        self.state.is_verified()
+    }
+
+    /// Read the rest of the file, discarding the plaintext, and return the inner reader
+    /// if the MAC is correct.
+    ///
+    /// Returns [`EnardError::Truncated`] if the file is incomplete and
+    /// [`EnardError::MacError`] if it was modified.
+    pub fn finish(mut self) -> Result<R, EnardError> {
+        let copied = io::copy(&mut self, &mut io::sink());
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        copied?;
+        Ok(self.inner)
+    }
+}
+
+/// Errors other than I/O errors are returned as [`ErrorKind::InvalidData`], including
+/// a MAC mismatch at the end of the file.
+impl<R, C, Cf, K> Read for EnardForwardReader<R, C, Cf, K>
+where
+    R: Read,
+    C: DynCipher,
+    Cf: CipherFactory<C>,
+    K: KeyProvider,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if let Some(n) = self.state.take_plain(buf) {
+                return Ok(n);
+            }
+            let n = self.inner.read(&mut self.state.read_buf)?;
+            if let Err(e) = self.state.push(n) {
+                let err = match &e {
+                    EnardError::IO(io_err) => io::Error::new(io_err.kind(), e.to_string()),
+                    _ => io::Error::new(ErrorKind::InvalidData, e.to_string()),
+                };
+                self.error = Some(e);
+                return Err(err);
+            }
+        }
+    }
+}
+
+/// Pushes a file which is pulled from a reader into an [`EnardDecryptWriter`], and holds
+/// the plaintext until it's read. Shared by the readers which don't seek.
+pub(crate) struct PullDecrypt<C, Cf, K> {
+    /// `None` once the end of the file has been reached
+    decrypt: Option<EnardDecryptWriter<Vec<u8>, C, Cf, K>>,
+    verified: bool,
+    /// Plaintext which hasn't been read yet, starting at `plain_pos`
+    plain: Vec<u8>,
+    plain_pos: usize,
+    /// Buffer for the file, the first `n` bytes are given to [`PullDecrypt::push`]
+    pub read_buf: Vec<u8>,
+}
+impl<C, Cf, K> PullDecrypt<C, Cf, K>
+where
+    C: DynCipher,
+    Cf: CipherFactory<C>,
+    K: KeyProvider,
+{
+    pub fn new(factory: Cf, key: K) -> Self {
+        Self {
+            decrypt: Some(EnardDecryptWriter::new(Vec::new(), factory, key)),
+            verified: false,
+            plain: Vec::new(),
+            plain_pos: 0,
+            read_buf: vec![0u8; DEFAULT_BUFFER_SIZE],
+        }
+    }
+
+    pub fn meta(&self) -> Option<&MetaMap> {
+        self.decrypt.as_ref().and_then(|dec| dec.meta())
+    }
+
+    pub fn is_verified(&self) -> bool {
+        self.verified
+    }
+
+    /// Copy plaintext into `buf`, or return `None` if more of the file is needed first.
+    /// Returns `Some(0)` at the end of the file, and after the MAC check failed.
+    pub fn take_plain(&mut self, buf: &mut [u8]) -> Option<usize> {
+        let available = &self.plain[self.plain_pos..];
+        if available.is_empty() && self.decrypt.is_some() && !buf.is_empty() {
+            return None;
+        }
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.plain_pos += n;
+        Some(n)
+    }
+
+    /// Decrypt the first `n` bytes of `read_buf`, or verify the MAC if `n` is `0`
+    /// because the end of the file was reached.
+    pub fn push(&mut self, n: usize) -> Result<(), EnardError> {
+        let dec = match self.decrypt.as_mut() {
+            Some(dec) => dec,
+            None => return Ok(()),
+        };
+        self.plain.zeroize();
+        self.plain_pos = 0;
+        if n > 0 {
+            dec.write_all(&self.read_buf[..n])?;
+            std::mem::swap(&mut self.plain, dec.get_mut());
+        } else {
+            let dec = self.decrypt.take().unwrap();
+            self.plain = dec.finish()?;
+            self.verified = true;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,220 @@
+//! Crash-safe encrypted files, e.g. for save games, and opening files for reading.
+//!
+//! [`write_atomic`] never leaves a half-written file behind: the new file is written
+//! next to the old one, flushed to disk, and then renamed over it. If the game crashes
+//! part way through, the old file is still there. [`read`] reads the file back.
+//!
+//! [`open`] opens a file read-only with an [`AccessHint`], which is what
+//! [`EnardReader::open`] uses.
+//!
+//! ```rust,no_run
+//! # fn main() -> Result<(), enard::EnardError> {
+//! # let key = [0x42u8; 32];
+//! # #[cfg(feature = "random")]
+//! enard::fs::write_atomic("save1.enard", b"level 3", key, enard::MetaMap::new())?;
+//! let (data, _meta) = enard::fs::read("save1.enard", key)?;
+//! # Ok(())
+//! # }
+//! ```
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::stream::SECTOR_SIZE;
+use crate::{EnardError, EnardReader, KeyProvider, MetaMap};
+
+/// How a file is going to be read, so the OS can prefetch accordingly, see [`open`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AccessHint {
+    /// No particular pattern, the OS defaults are used
+    Normal,
+    /// Read from start to end, e.g. streaming audio or loading a whole pack
+    Sequential,
+    /// Seek around to read small parts, e.g. single assets from a large pack
+    Random,
+}
+impl AccessHint {
+    /// Size of the read buffer suited to the access pattern.
+    fn buffer_size(self) -> usize {
+        match self {
+            Self::Normal => crate::DEFAULT_BUFFER_SIZE,
+            Self::Sequential => 256 * 1024,
+            // Seeking throws the buffer away, so don't read much more than needed
+            Self::Random => SECTOR_SIZE,
+        }
+    }
+}
+impl Default for AccessHint {
+    fn default() -> Self {
+        Self::Normal
+    }
+}
+
+/// Open the file at `path` read-only, buffered according to `hint`.
+///
+/// Where the platform supports it, `hint` is passed on to the OS (`posix_fadvise` on
+/// Linux, Android and FreeBSD, `FILE_FLAG_SEQUENTIAL_SCAN` or `FILE_FLAG_RANDOM_ACCESS`
+/// on Windows). On Linux the access time isn't updated if the file belongs to the
+/// user, and on Windows other processes may read the file but not write it while it's
+/// open. The buffer is never larger than the file.
+pub fn open<P: AsRef<Path>>(path: P, hint: AccessHint) -> io::Result<io::BufReader<File>> {
+    let file = open_unbuffered(path.as_ref(), hint)?;
+    let len = file.metadata()?.len();
+    let capacity = (hint.buffer_size() as u64).min(len) as usize;
+    Ok(io::BufReader::with_capacity(capacity, file))
+}
+
+/// Same as [`open`] without the buffer, for callers which choose their own.
+pub(crate) fn open_unbuffered(path: &Path, hint: AccessHint) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.read(true);
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::OpenOptionsExt;
+        const FILE_SHARE_READ: u32 = 0x1;
+        const FILE_FLAG_SEQUENTIAL_SCAN: u32 = 0x0800_0000;
+        const FILE_FLAG_RANDOM_ACCESS: u32 = 0x1000_0000;
+        options.share_mode(FILE_SHARE_READ);
+        match hint {
+            AccessHint::Normal => {}
+            AccessHint::Sequential => {
+                options.custom_flags(FILE_FLAG_SEQUENTIAL_SCAN);
+            }
+            AccessHint::Random => {
+                options.custom_flags(FILE_FLAG_RANDOM_ACCESS);
+            }
+        }
+    }
+    let file = open_noatime(&options, path)?;
+    advise(&file, hint);
+    Ok(file)
+}
+
+/// Open with `O_NOATIME` where possible, which is only allowed for the file's owner.
+#[cfg(target_os = "linux")]
+fn open_noatime(options: &OpenOptions, path: &Path) -> io::Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    let mut noatime = options.clone();
+    match noatime.custom_flags(libc::O_NOATIME).open(path) {
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => options.open(path),
+        result => result,
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_noatime(options: &OpenOptions, path: &Path) -> io::Result<File> {
+    options.open(path)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+fn advise(file: &File, hint: AccessHint) {
+    use std::os::unix::io::AsRawFd;
+    let advice = match hint {
+        AccessHint::Normal => return,
+        AccessHint::Sequential => libc::POSIX_FADV_SEQUENTIAL,
+        AccessHint::Random => libc::POSIX_FADV_RANDOM,
+    };
+    // It's only advice, so errors don't matter
+    // SAFETY: the file descriptor is open for as long as `file` is borrowed
+    unsafe {
+        libc::posix_fadvise(file.as_raw_fd(), 0, 0, advice);
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "freebsd")))]
+fn advise(_file: &File, _hint: AccessHint) {}
+
+/// Read and decrypt the enard file at `path`, returning the data and the metadata.
+pub fn read<P: AsRef<Path>, K: KeyProvider>(
+    path: P,
+    key: K,
+) -> Result<(Vec<u8>, MetaMap), EnardError> {
+    let mut rd = EnardReader::open_with_hint(path, key, AccessHint::Sequential)?;
+    let mut data = Vec::with_capacity(rd.data_size() as usize);
+    rd.read_to_end(&mut data)?;
+    Ok((data, rd.meta().clone()))
+}
+
+/// Encrypt `data` with ChaCha12 and a random IV, and atomically replace the file at
+/// `path` with the result.
+///
+/// The temporary file is created in the same directory as `path`, because renaming
+/// only replaces a file atomically within one filesystem. It's removed if anything
+/// goes wrong.
+#[cfg(feature = "random")]
+pub fn write_atomic<P: AsRef<Path>, K: KeyProvider>(
+    path: P,
+    data: &[u8],
+    key: K,
+    meta: MetaMap,
+) -> Result<(), EnardError> {
+    let path = path.as_ref();
+    let (temp_path, file) = create_temp(path)?;
+    let result =
+        write_and_sync(file, data, key, meta).and_then(|_| Ok(std::fs::rename(&temp_path, path)?));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+        return result;
+    }
+    sync_dir(path)?;
+    Ok(())
+}
+
+#[cfg(feature = "random")]
+fn write_and_sync<K: KeyProvider>(
+    file: File,
+    data: &[u8],
+    key: K,
+    meta: MetaMap,
+) -> Result<(), EnardError> {
+    let mut wr = crate::EnardWriter::builder(io::BufWriter::new(file))
+        .key(key)
+        .meta_map(meta)
+        .build()?;
+    wr.write_complete(data)?;
+    let file = wr.into_inner().into_inner().map_err(|e| e.into_error())?;
+    file.sync_all()?;
+    Ok(())
+}
+
+/// Create a new file next to `path` with a unique name.
+#[cfg(feature = "random")]
+fn create_temp(path: &Path) -> io::Result<(std::path::PathBuf, File)> {
+    use rand::Rng;
+
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let mut rng = rand::rngs::OsRng;
+    loop {
+        let mut temp_name = std::ffi::OsString::from(".");
+        temp_name.push(name);
+        temp_name.push(format!(".{:08x}.tmp", rng.gen::<u32>()));
+        let temp_path = path.with_file_name(temp_name);
+        match std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)
+        {
+            Ok(file) => return Ok((temp_path, file)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Make the rename durable by syncing the directory, which is only possible on unix.
+#[cfg(feature = "random")]
+fn sync_dir(path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        File::open(dir)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
@@ -0,0 +1,134 @@
+//! Asynchronous decryption using the runtime-agnostic [`futures_io`] traits.
+//!
+//! [`AsyncEnardReader`] works with any executor (smol, async-std, or tokio through its
+//! `compat` layer), and with libraries which abstract over them. Like
+//! [`crate::EnardForwardReader`] it reads the file front-to-back, so the inner reader
+//! only needs [`AsyncRead`], and has the same caveat: **the plaintext is not
+//! authenticated until the MAC has been verified at the end of the file.** A read
+//! returns an error instead of the end of the file if the MAC doesn't match, so don't
+//! use the plaintext unless the whole file was read successfully.
+//!
+//! ```rust
+//! # use std::io::Cursor;
+//! # use std::pin::Pin;
+//! # use std::task::{Context, Poll};
+//! use futures_io::AsyncRead;
+//! use enard::futures::AsyncEnardReader;
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! # let mut wr = enard::EnardWriter::builder(Cursor::new(Vec::new()))
+//! #     .key([0x42; 32])
+//! #     .iv([0x24; 12])
+//! #     .build()?;
+//! # wr.write_complete(&b"hello"[..])?;
+//! # let file = wr.into_inner().into_inner();
+//! # struct Noop;
+//! # impl std::task::Wake for Noop { fn wake(self: std::sync::Arc<Self>) {} }
+//! # let waker: std::task::Waker = std::sync::Arc::new(Noop).into();
+//! # let mut cx = Context::from_waker(&waker);
+//! let mut rd = AsyncEnardReader::new_boxed(&file[..], [0x42; 32]);
+//! let mut buf = [0u8; 16];
+//! // Normally `rd.read(&mut buf).await` with `futures::AsyncReadExt`
+//! let n = match Pin::new(&mut rd).poll_read(&mut cx, &mut buf) {
+//!     Poll::Ready(n) => n?,
+//!     Poll::Pending => unreachable!(),
+//! };
+//! assert_eq!(&buf[..n], b"hello");
+//! # Ok(())
+//! # }
+//! ```
+use futures_io::AsyncRead;
+use std::io::{self, ErrorKind};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use crate::cipher_factory::{CipherFactory, GetFactory};
+use crate::dyn_cipher::BoxDynCipherFactory;
+use crate::forward::PullDecrypt;
+use crate::{BoxDynCipher, DynCipher, EnardError, KeyProvider, MetaMap};
+
+/// Decrypts an enard file read from an [`AsyncRead`], see [`crate::futures`].
+pub struct AsyncEnardReader<R, C, Cf, K> {
+    inner: R,
+    state: PullDecrypt<C, Cf, K>,
+}
+
+impl<R, K> AsyncEnardReader<R, BoxDynCipher, BoxDynCipherFactory, K>
+where
+    R: AsyncRead + Unpin,
+    K: KeyProvider + Unpin,
+{
+    /// Create a new [`AsyncEnardReader`] using [`BoxDynCipher`], which supports all
+    /// ciphers enard was built with.
+    pub fn new_boxed(inner: R, key: K) -> Self {
+        Self::new(inner, BoxDynCipher::factory(), key)
+    }
+}
+impl<R, C, Cf, K> AsyncEnardReader<R, C, Cf, K>
+where
+    R: AsyncRead + Unpin,
+    C: DynCipher + Unpin,
+    Cf: CipherFactory<C> + Unpin,
+    K: KeyProvider + Unpin,
+{
+    /// Create a new [`AsyncEnardReader`] which decrypts the file read from `inner`.
+    ///
+    /// Nothing is read until the first call to `poll_read`.
+    pub fn new(inner: R, factory: Cf, key: K) -> Self {
+        Self {
+            inner,
+            state: PullDecrypt::new(factory, key),
+        }
+    }
+
+    /// Returns the metadata, or `None` if the header hasn't been read yet or the end
+    /// of the file has been reached.
+    ///
+    /// Like the plaintext, this isn't authenticated until the whole file has been read.
+    pub fn meta(&self) -> Option<&MetaMap> {
+        self.state.meta()
+    }
+
+    /// Returns `true` once the whole file has been read and the MAC verified.
+    pub fn is_verified(&self) -> bool {
+        self.state.is_verified()
+    }
+
+    /// Unwraps this [`AsyncEnardReader`], returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+/// Errors other than I/O errors are returned as [`ErrorKind::InvalidData`], including
+/// a MAC mismatch at the end of the file. After an error at the end of the file,
+/// further reads return nothing.
+impl<R, C, Cf, K> AsyncRead for AsyncEnardReader<R, C, Cf, K>
+where
+    R: AsyncRead + Unpin,
+    C: DynCipher + Unpin,
+    Cf: CipherFactory<C> + Unpin,
+    K: KeyProvider + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(n) = this.state.take_plain(buf) {
+                return Poll::Ready(Ok(n));
+            }
+            let n = match Pin::new(&mut this.inner).poll_read(cx, &mut this.state.read_buf) {
+                Poll::Ready(Ok(n)) => n,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            };
+            this.state.push(n).map_err(|e| match e {
+                EnardError::IO(e) => e,
+                e => io::Error::new(ErrorKind::InvalidData, e),
+            })?;
+        }
+    }
+}
@@ -0,0 +1,202 @@
+//! An encrypted index of named assets in the payload, for engines which concatenate
+//! assets into one enard file themselves.
+//!
+//! An [`IndexBuilder`] records the name, offset and length of each asset as it's written.
+//! The finished [`Index`] is stored in the extended metadata under [`crate::meta::INDEX`],
+//! so it's encrypted and covered by the MAC, and isn't limited by the size of the header.
+//! If the asset sizes are known up front, pass the index to
+//! [`EnardWriter::with_index`]. Otherwise use [`EnardWriter::with_meta_trailer`] and pass
+//! [`Index::to_meta`] to [`EnardWriter::finish_with_meta_trailer`]. Readers load it with
+//! [`EnardReader::open_index`].
+//!
+//! ```rust
+//! # use std::io::{Cursor, Read};
+//! # use enard::{EnardReader, EnardWriter};
+//! use enard::index::IndexBuilder;
+//! # fn main() -> Result<(), enard::EnardError> {
+//! let mut wr = EnardWriter::builder(Cursor::new(Vec::new()))
+//!     .key([0x42; 32])
+//!     .iv([0x24; 12])
+//!     .build()?
+//!     .with_meta_trailer();
+//! wr.write_header()?;
+//! let mut index = IndexBuilder::new();
+//! index.write_asset(&mut wr, "a.txt", &b"first"[..])?;
+//! index.write_asset(&mut wr, "b.txt", &b"second"[..])?;
+//! wr.finish_with_meta_trailer(&index.build().to_meta())?;
+//! let file = wr.into_inner().into_inner();
+//!
+//! let mut rd = EnardReader::new_boxed(Cursor::new(file), [0x42; 32])?;
+//! let index = rd.open_index()?.expect("the file has an index");
+//! let entry = index.get("b.txt").unwrap();
+//! let mut text = String::new();
+//! rd.sub_reader(entry.offset, entry.len)?.read_to_string(&mut text)?;
+//! assert_eq!(text, "second");
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`EnardWriter::with_index`]: crate::EnardWriter::with_index
+//! [`EnardWriter::with_meta_trailer`]: crate::EnardWriter::with_meta_trailer
+//! [`EnardWriter::finish_with_meta_trailer`]: crate::EnardWriter::finish_with_meta_trailer
+//! [`EnardReader::open_index`]: crate::EnardReader::open_index
+use indexmap::IndexMap;
+use std::io::{self, BufRead, ErrorKind, Read, Write};
+
+use crate::{varint, EnardError, MetaMap};
+
+/// Position of one asset in the payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexEntry {
+    /// Offset of the asset from the start of the payload
+    pub offset: u64,
+    /// Length of the asset in bytes
+    pub len: u64,
+}
+impl IndexEntry {
+    /// Offset of the first byte after the asset
+    pub fn end(&self) -> u64 {
+        self.offset + self.len
+    }
+}
+
+/// Maps asset names to their position in the payload, see [`crate::index`].
+///
+/// Entries keep the order they were added in.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Index {
+    entries: IndexMap<String, IndexEntry>,
+}
+impl Index {
+    /// Position of the asset `name`
+    pub fn get(&self, name: &str) -> Option<IndexEntry> {
+        self.entries.get(name).copied()
+    }
+
+    /// Iterate over the names and positions of all assets
+    pub fn iter(&self) -> impl Iterator<Item = (&str, IndexEntry)> {
+        self.entries.iter().map(|(k, v)| (k.as_str(), *v))
+    }
+
+    /// Number of assets
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if there are no assets
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Encode the index as stored under [`crate::meta::INDEX`].
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        let write = |buf: &mut Vec<u8>| -> io::Result<()> {
+            varint::write_u(&mut *buf, self.entries.len() as u64)?;
+            for (name, entry) in self.entries.iter() {
+                varint::write_u(&mut *buf, name.len() as u64)?;
+                buf.extend_from_slice(name.as_bytes());
+                varint::write_u(&mut *buf, entry.offset)?;
+                varint::write_u(&mut *buf, entry.len)?;
+            }
+            Ok(())
+        };
+        write(&mut buf).expect("encoding to a Vec can't fail");
+        buf
+    }
+
+    /// Decode an index written by [`Index::encode`], checking that every asset ends
+    /// within `data_size` bytes.
+    pub fn decode(mut data: &[u8], data_size: u64) -> Result<Self, EnardError> {
+        let count = varint::read_u(&mut data)?;
+        let mut entries = IndexMap::new();
+        for _ in 0..count {
+            let name = read_name(&mut data)?;
+            let offset = varint::read_u(&mut data)?;
+            let len = varint::read_u(&mut data)?;
+            match offset.checked_add(len) {
+                Some(end) if end <= data_size => {}
+                _ => {
+                    let end = offset.saturating_add(len);
+                    return Err(EnardError::new_block_size(end, data_size));
+                }
+            }
+            entries.insert(name, IndexEntry { offset, len });
+        }
+        Ok(Self { entries })
+    }
+
+    /// A [`MetaMap`] holding just the encoded index, for
+    /// [`crate::EnardWriter::finish_with_meta_trailer`].
+    pub fn to_meta(&self) -> MetaMap {
+        let mut meta = MetaMap::new();
+        meta.insert(crate::meta::INDEX.to_vec(), self.encode());
+        meta
+    }
+}
+
+fn read_name<R: BufRead>(mut reader: R) -> io::Result<String> {
+    let len = varint::read_u(&mut reader)?;
+    let mut name = Vec::new();
+    (&mut reader).take(len).read_to_end(&mut name)?;
+    if name.len() as u64 != len {
+        return Err(ErrorKind::UnexpectedEof.into());
+    }
+    String::from_utf8(name).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+}
+
+/// Records the position of each asset as it's written, see [`crate::index`].
+#[derive(Debug, Clone, Default)]
+pub struct IndexBuilder {
+    index: Index,
+    /// Offset of the next asset added with [`IndexBuilder::push`]
+    offset: u64,
+}
+impl IndexBuilder {
+    /// Create an empty builder, the first asset starts at offset 0.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the asset `name` of `len` bytes, directly after the previous one.
+    pub fn push(&mut self, name: &str, len: u64) -> io::Result<IndexEntry> {
+        self.insert(name, self.offset, len)
+    }
+
+    /// Add the asset `name` at `offset`, e.g. if there's padding between assets. Later
+    /// calls to [`IndexBuilder::push`] add assets after this one.
+    pub fn insert(&mut self, name: &str, offset: u64, len: u64) -> io::Result<IndexEntry> {
+        self.check_new(name)?;
+        let entry = IndexEntry { offset, len };
+        let end = offset.checked_add(len).ok_or(ErrorKind::InvalidInput)?;
+        self.index.entries.insert(name.to_string(), entry);
+        self.offset = end;
+        Ok(entry)
+    }
+
+    /// Copy `asset` to `wr` and add it as `name`. The assets must all be written to `wr`
+    /// through this method, starting at the beginning of the payload.
+    pub fn write_asset<W: Write, R: Read>(
+        &mut self,
+        wr: &mut W,
+        name: &str,
+        mut asset: R,
+    ) -> io::Result<IndexEntry> {
+        self.check_new(name)?;
+        let len = io::copy(&mut asset, wr)?;
+        self.push(name, len)
+    }
+
+    /// Finish the index
+    pub fn build(self) -> Index {
+        self.index
+    }
+
+    fn check_new(&self, name: &str) -> io::Result<()> {
+        if self.index.entries.contains_key(name) {
+            let msg = format!("asset '{}' was already added to the index", name);
+            return Err(io::Error::new(ErrorKind::InvalidInput, msg));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,224 @@
+//! Derive a unique key for each file from a master key.
+//!
+//! The writer stores a random salt in the metadata, and the key used for the file is
+//! derived from the master key and the salt using HKDF-SHA256. Because every file gets a
+//! different key, leaking the keystream of one file doesn't reveal anything about the
+//! others. The reader just wraps the master key in a [`DerivedKey`].
+//!
+//! [`crate::EnardWriterBuilder::derived_key`] generates the salt with the `random`
+//! feature. Otherwise store a salt from a secure random source with [`insert_salt`]
+//! before creating the writer, never a constant, or every file gets the same key.
+//!
+//! ```rust
+//! # use std::io::Cursor;
+//! # use enard::{EnardReader, EnardWriter};
+//! use enard::kdf::DerivedKey;
+//! # fn main() -> Result<(), enard::EnardError> {
+//! let master = [0x42u8; 32];
+//! # #[cfg(feature = "random")]
+//! # {
+//! let mut wr = EnardWriter::builder(Cursor::new(Vec::new()))
+//!     .derived_key(master)
+//!     .build()?;
+//! wr.write_complete(&b"hello"[..])?;
+//! let file = wr.into_inner().into_inner();
+//! let rd = EnardReader::new_boxed(Cursor::new(file), DerivedKey(master))?;
+//! # }
+//! # Ok(())
+//! # }
+//! ```
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+#[cfg(feature = "random")]
+use rand::{CryptoRng, Rng};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+use zeroize::Zeroizing;
+
+use crate::{EnardError, KeyProvider, MetaMap};
+
+/// Metadata key the salt is stored under.
+pub const SALT_META_KEY: &[u8] = b"enard.salt";
+/// Metadata key the key check value is stored under, see
+/// [`crate::EnardWriter::with_key_check`].
+pub const KEY_CHECK_META_KEY: &[u8] = b"enard.keycheck";
+/// Metadata key recording that the encryption and MAC keys are derived separately, see
+/// [`insert_split_keys`].
+pub const SPLIT_KEYS_META_KEY: &[u8] = b"enard.keysplit";
+/// Size in bytes of salts created by [`generate_salt`].
+pub const SALT_SIZE: usize = 32;
+/// HKDF info, so keys derived for enard can't collide with other uses of the master key.
+const INFO: &[u8] = b"enard file key";
+/// HKDF info for the key check value.
+const KEY_CHECK_INFO: &[u8] = b"enard key check";
+/// HKDF info for key fingerprints.
+const FINGERPRINT_INFO: &[u8] = b"enard key fingerprint";
+/// HKDF info for the header obfuscation key.
+const HEADER_KEY_INFO: &[u8] = b"enard header key";
+/// Prefix hashed with the IV to make the header salt.
+const HEADER_SALT_PREFIX: &[u8] = b"enard header salt";
+/// Size in bytes of the salt at the start of an obfuscated header.
+pub(crate) const HEADER_SALT_SIZE: usize = 16;
+/// Value stored under [`SPLIT_KEYS_META_KEY`], the only supported key split.
+const SPLIT_HKDF: &[u8] = b"hkdf-sha256";
+/// HKDF info for the encryption key of split keys.
+const CIPHER_KEY_INFO: &[u8] = b"enard cipher key";
+/// HKDF info for the MAC key of split keys.
+const MAC_KEY_INFO: &[u8] = b"enard mac key";
+/// Size in bytes of derived MAC keys, the block size of SHA-256 is larger so this
+/// doesn't lose anything.
+const MAC_KEY_SIZE: usize = 32;
+
+/// Derive the key for a file from `master` and `salt`. The derived key is the same size
+/// as the master key.
+pub fn derive_key(master: &[u8], salt: &[u8]) -> Result<Zeroizing<Vec<u8>>, EnardError> {
+    let mut key = Zeroizing::new(vec![0u8; master.len()]);
+    Hkdf::<Sha256>::new(Some(salt), master)
+        .expand(INFO, &mut key)
+        .map_err(|_| EnardError::InvalidLength)?;
+    Ok(key)
+}
+
+/// Derive the value stored under [`KEY_CHECK_META_KEY`] for `key`.
+pub(crate) fn key_check(key: &[u8]) -> [u8; 8] {
+    let mut check = [0u8; 8];
+    Hkdf::<Sha256>::new(None, key)
+        .expand(KEY_CHECK_INFO, &mut check)
+        .expect("8 bytes is a valid HKDF output length");
+    check
+}
+
+/// Derive a short fingerprint of `key` to use as a [`crate::meta::KEY_ID`].
+///
+/// The fingerprint can't be used to recover the key, and is different from the key check
+/// value so the two can't be compared. Use the master key rather than a key derived for
+/// each file, so every file written with it has the same fingerprint.
+pub fn key_fingerprint(key: &[u8]) -> [u8; 8] {
+    let mut fingerprint = [0u8; 8];
+    Hkdf::<Sha256>::new(None, key)
+        .expand(FINGERPRINT_INFO, &mut fingerprint)
+        .expect("8 bytes is a valid HKDF output length");
+    fingerprint
+}
+
+/// Derive the key which obfuscates the header, see
+/// [`crate::EnardWriter::with_obfuscated_header`].
+pub(crate) fn header_key(key: &[u8]) -> Zeroizing<[u8; 32]> {
+    let mut header_key = Zeroizing::new([0u8; 32]);
+    Hkdf::<Sha256>::new(None, key)
+        .expand(HEADER_KEY_INFO, header_key.as_mut())
+        .expect("32 bytes is a valid HKDF output length");
+    header_key
+}
+
+/// Salt for an obfuscated header, derived from the IV so it's unique for each file
+/// without needing a random number generator.
+pub(crate) fn header_salt(iv: &[u8]) -> [u8; HEADER_SALT_SIZE] {
+    let hash = Sha256::new()
+        .chain_update(HEADER_SALT_PREFIX)
+        .chain_update(iv)
+        .finalize();
+    let mut salt = [0u8; HEADER_SALT_SIZE];
+    salt.copy_from_slice(&hash[..HEADER_SALT_SIZE]);
+    salt
+}
+
+/// XOR `buf` with the header keystream for `salt`. Block `i` of the keystream is
+/// HMAC-SHA256 of the salt followed by `i` as a `u64`, keyed with `header_key`.
+pub(crate) fn obfuscate_header(header_key: &[u8; 32], salt: &[u8], buf: &mut [u8]) {
+    let mac = Hmac::<Sha256>::new_from_slice(header_key).expect("HMAC accepts any key size");
+    for (i, chunk) in buf.chunks_mut(32).enumerate() {
+        let block = mac
+            .clone()
+            .chain_update(salt)
+            .chain_update((i as u64).to_le_bytes())
+            .finalize()
+            .into_bytes();
+        chunk.iter_mut().zip(block).for_each(|(b, k)| *b ^= k);
+    }
+}
+
+/// Returns [`EnardError::WrongKey`] if `meta` has a key check value which doesn't match
+/// `key`.
+pub(crate) fn check_key(meta: &MetaMap, key: &[u8]) -> Result<(), EnardError> {
+    match meta.get(KEY_CHECK_META_KEY) {
+        Some(expected) if expected.ct_eq(&key_check(key)).unwrap_u8() == 0 => {
+            Err(EnardError::WrongKey)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Record in `meta` that separate encryption and MAC keys should be derived from the key,
+/// instead of using the same key for both. Requires [`crate::FormatVersion::V2`].
+///
+/// Both keys are derived with HKDF-SHA256 (no salt). Whoever has the key can give
+/// [`mac_key`] to someone else, who can then check files with
+/// [`crate::Verifier::with_mac_key`] but can't decrypt them.
+pub fn insert_split_keys(meta: &mut MetaMap) {
+    meta.insert(SPLIT_KEYS_META_KEY.to_vec(), SPLIT_HKDF.to_vec());
+}
+
+/// Returns the MAC key of a file with the metadata `meta`, given its key.
+///
+/// Without [`insert_split_keys`] this is just `key`.
+pub fn mac_key(meta: &MetaMap, key: &[u8]) -> Result<Zeroizing<Vec<u8>>, EnardError> {
+    Ok(split_keys(meta, key)?.1)
+}
+
+/// Returns the encryption key of a file with the metadata `meta`, given its key.
+///
+/// Without [`insert_split_keys`] this is just `key`.
+pub fn cipher_key(meta: &MetaMap, key: &[u8]) -> Result<Zeroizing<Vec<u8>>, EnardError> {
+    Ok(split_keys(meta, key)?.0)
+}
+
+/// Encryption key and MAC key.
+type KeyPair = (Zeroizing<Vec<u8>>, Zeroizing<Vec<u8>>);
+
+/// Returns the encryption and MAC keys for a file with the metadata `meta`.
+pub(crate) fn split_keys(meta: &MetaMap, key: &[u8]) -> Result<KeyPair, EnardError> {
+    match meta.get(SPLIT_KEYS_META_KEY) {
+        None => Ok((Zeroizing::new(key.to_vec()), Zeroizing::new(key.to_vec()))),
+        Some(kind) if kind.as_slice() == SPLIT_HKDF => {
+            let hkdf = Hkdf::<Sha256>::new(None, key);
+            let mut cipher_key = Zeroizing::new(vec![0u8; key.len()]);
+            let mut mac_key = Zeroizing::new(vec![0u8; MAC_KEY_SIZE]);
+            hkdf.expand(CIPHER_KEY_INFO, &mut cipher_key)
+                .and_then(|_| hkdf.expand(MAC_KEY_INFO, &mut mac_key))
+                .map_err(|_| EnardError::InvalidLength)?;
+            Ok((cipher_key, mac_key))
+        }
+        Some(kind) => Err(EnardError::new_unsupported_encryption(kind)),
+    }
+}
+
+/// Store `salt` in `meta` so it can be used with [`DerivedKey`].
+pub fn insert_salt(meta: &mut MetaMap, salt: &[u8]) {
+    meta.insert(SALT_META_KEY.to_vec(), salt.to_vec());
+}
+
+/// Generate a random salt of [`SALT_SIZE`] bytes.
+#[cfg(feature = "random")]
+pub fn generate_salt<R: CryptoRng + Rng>(rng: &mut R) -> Vec<u8> {
+    let mut salt = vec![0u8; SALT_SIZE];
+    rng.fill_bytes(&mut salt);
+    salt
+}
+
+/// A [`KeyProvider`] which derives the key for each file from the master key it wraps
+/// and the salt stored in the file's metadata.
+///
+/// Returns [`EnardError::MissingMeta`] if the file doesn't have a salt.
+#[derive(Clone)]
+pub struct DerivedKey<K>(pub K);
+
+impl<K: KeyProvider> KeyProvider for DerivedKey<K> {
+    fn key_for(&self, meta: &MetaMap) -> Result<Zeroizing<Vec<u8>>, EnardError> {
+        let salt = meta
+            .get(SALT_META_KEY)
+            .ok_or_else(|| EnardError::new_missing_meta(SALT_META_KEY))?;
+        let master = self.0.key_for(meta)?;
+        derive_key(&master, salt)
+    }
+}
@@ -0,0 +1,356 @@
+//! Keys, IVs, and the ways of supplying them.
+//!
+//! [`parse`] reads a key in any of the text formats the CLI accepts, so tools built on
+//! the library can take keys the same way:
+//!
+//! | Format              | Example                 |
+//! |---------------------|-------------------------|
+//! | `hex:` or `0x` hex  | `0x000102...1e1f`       |
+//! | `base64:` base64    | `base64:AAECAwQF...`    |
+//! | anything else       | used directly as bytes  |
+//!
+//! ```rust
+//! let key = enard::key::parse(b"base64:AAECAw==").unwrap();
+//! assert_eq!(key.as_bytes(), [0, 1, 2, 3]);
+//! assert_eq!(enard::key::parse(b"hex:00010203").unwrap(), key);
+//! ```
+use base64::Engine;
+use std::fmt;
+use std::str::FromStr;
+use zeroize::Zeroizing;
+
+use crate::cipher_factory::CipherMeta;
+use crate::{EnardError, MetaMap};
+
+/// Supplies the key used to open an enard file.
+///
+/// Plain byte slices, arrays and vectors are key providers which always return themselves,
+/// so existing code which passes a `&[u8]` keeps working. With the `secrecy` feature
+/// [`secrecy::SecretVec`] is also a key provider. Implement this to fetch keys
+/// from an OS keystore, derive a per-file key from a master key and the file's metadata,
+/// or ask a license server. Closures taking the metadata also implement this trait.
+///
+/// ```rust
+/// # use enard::{EnardError, KeyProvider, MetaMap, Zeroizing};
+/// let master = [0x42u8; 32];
+/// let provider = |meta: &MetaMap| -> Result<Zeroizing<Vec<u8>>, EnardError> {
+///     let mut key = master.to_vec();
+///     if let Some(salt) = meta.get(b"salt".as_slice()) {
+///         key.iter_mut().zip(salt).for_each(|(k, s)| *k ^= s);
+///     }
+///     Ok(Zeroizing::new(key))
+/// };
+/// # let _ = provider.key_for(&MetaMap::new());
+/// ```
+///
+/// Note that the metadata passed to [`KeyProvider::key_for`] has **not** been verified,
+/// because the MAC can't be checked until the key is known.
+pub trait KeyProvider {
+    /// Returns the key for the enard file with the metadata `meta`.
+    fn key_for(&self, meta: &MetaMap) -> Result<Zeroizing<Vec<u8>>, EnardError>;
+}
+
+impl KeyProvider for [u8] {
+    fn key_for(&self, _meta: &MetaMap) -> Result<Zeroizing<Vec<u8>>, EnardError> {
+        Ok(Zeroizing::new(self.to_vec()))
+    }
+}
+
+impl<const N: usize> KeyProvider for [u8; N] {
+    fn key_for(&self, meta: &MetaMap) -> Result<Zeroizing<Vec<u8>>, EnardError> {
+        self[..].key_for(meta)
+    }
+}
+
+impl KeyProvider for Vec<u8> {
+    fn key_for(&self, meta: &MetaMap) -> Result<Zeroizing<Vec<u8>>, EnardError> {
+        self[..].key_for(meta)
+    }
+}
+
+impl KeyProvider for Zeroizing<Vec<u8>> {
+    fn key_for(&self, meta: &MetaMap) -> Result<Zeroizing<Vec<u8>>, EnardError> {
+        self[..].key_for(meta)
+    }
+}
+
+impl KeyProvider for &[u8] {
+    fn key_for(&self, meta: &MetaMap) -> Result<Zeroizing<Vec<u8>>, EnardError> {
+        (**self).key_for(meta)
+    }
+}
+
+impl<const N: usize> KeyProvider for &[u8; N] {
+    fn key_for(&self, meta: &MetaMap) -> Result<Zeroizing<Vec<u8>>, EnardError> {
+        (**self).key_for(meta)
+    }
+}
+
+impl KeyProvider for &Vec<u8> {
+    fn key_for(&self, meta: &MetaMap) -> Result<Zeroizing<Vec<u8>>, EnardError> {
+        (**self).key_for(meta)
+    }
+}
+
+impl<F> KeyProvider for F
+where
+    F: Fn(&MetaMap) -> Result<Zeroizing<Vec<u8>>, EnardError>,
+{
+    fn key_for(&self, meta: &MetaMap) -> Result<Zeroizing<Vec<u8>>, EnardError> {
+        self(meta)
+    }
+}
+
+#[cfg(feature = "secrecy")]
+impl KeyProvider for secrecy::SecretVec<u8> {
+    fn key_for(&self, meta: &MetaMap) -> Result<Zeroizing<Vec<u8>>, EnardError> {
+        use secrecy::ExposeSecret;
+        self.expose_secret().key_for(meta)
+    }
+}
+
+#[cfg(feature = "secrecy")]
+impl KeyProvider for &secrecy::SecretVec<u8> {
+    fn key_for(&self, meta: &MetaMap) -> Result<Zeroizing<Vec<u8>>, EnardError> {
+        (**self).key_for(meta)
+    }
+}
+
+impl KeyProvider for EnardKey {
+    fn key_for(&self, meta: &MetaMap) -> Result<Zeroizing<Vec<u8>>, EnardError> {
+        self.0.key_for(meta)
+    }
+}
+
+impl KeyProvider for &EnardKey {
+    fn key_for(&self, meta: &MetaMap) -> Result<Zeroizing<Vec<u8>>, EnardError> {
+        (**self).key_for(meta)
+    }
+}
+
+/// A symmetric key, zeroed when dropped.
+///
+/// Raw bytes also work as keys, but this type can't be passed where an IV is expected,
+/// see [`EnardNonce`]. Parse keys from hex with [`str::parse`], an optional `0x`
+/// prefix is allowed.
+///
+/// ```rust
+/// # use enard::EnardKey;
+/// let key: EnardKey = "0x000102030405060708090a0b0c0d0e0f".parse().unwrap();
+/// assert_eq!(key.as_bytes()[1], 1);
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+pub struct EnardKey(Zeroizing<Vec<u8>>);
+impl EnardKey {
+    /// The raw key bytes
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Short fingerprint of this key, see [`crate::kdf::key_fingerprint`].
+    pub fn fingerprint(&self) -> [u8; 8] {
+        crate::kdf::key_fingerprint(&self.0)
+    }
+
+    /// Returns [`EnardError::KeyLengthMismatch`] if this isn't the right size for `cipher`.
+    pub fn check_size(&self, cipher: &CipherMeta) -> Result<(), EnardError> {
+        match self.0.len() {
+            got if got == cipher.key_size => Ok(()),
+            got => Err(EnardError::KeyLengthMismatch {
+                expected: cipher.key_size,
+                got,
+            }),
+        }
+    }
+}
+impl<const N: usize> From<[u8; N]> for EnardKey {
+    fn from(key: [u8; N]) -> Self {
+        Self(Zeroizing::new(key.to_vec()))
+    }
+}
+impl From<Vec<u8>> for EnardKey {
+    fn from(key: Vec<u8>) -> Self {
+        Self(Zeroizing::new(key))
+    }
+}
+impl From<&[u8]> for EnardKey {
+    fn from(key: &[u8]) -> Self {
+        Self(Zeroizing::new(key.to_vec()))
+    }
+}
+impl FromStr for EnardKey {
+    type Err = EnardError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_hex(s).map(Self::from)
+    }
+}
+impl fmt::Debug for EnardKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Never print the key itself
+        write!(f, "EnardKey({} bytes)", self.0.len())
+    }
+}
+
+/// Several keys, picked by the [`crate::meta::KEY_ID`] in the metadata.
+///
+/// Returns [`EnardError::MissingMeta`] for files without a key ID and
+/// [`EnardError::WrongKey`] for files with a key ID which isn't in the ring.
+///
+/// ```rust
+/// # use std::io::Cursor;
+/// # use enard::{key::KeyRing, EnardKey, EnardReader, EnardWriter};
+/// # fn main() -> Result<(), enard::EnardError> {
+/// let old = EnardKey::from([0x42; 32]);
+/// let new = EnardKey::from([0x43; 32]);
+/// let mut wr = EnardWriter::builder(Cursor::new(Vec::new()))
+///     .key(new.clone())
+///     .iv([0x24; 12])
+///     .build()?
+///     .with_key_id(&new.fingerprint());
+/// wr.write_complete(&b"hello"[..])?;
+/// let file = wr.into_inner().into_inner();
+///
+/// let mut ring = KeyRing::new();
+/// ring.insert_fingerprinted(old);
+/// ring.insert_fingerprinted(new);
+/// let rd = EnardReader::new_boxed(Cursor::new(file), &ring)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct KeyRing {
+    keys: Vec<(Vec<u8>, EnardKey)>,
+}
+impl KeyRing {
+    /// Create an empty [`KeyRing`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `key` for files with the key ID `id`, replacing any key with the same ID.
+    pub fn insert(&mut self, id: &[u8], key: EnardKey) {
+        match self.keys.iter_mut().find(|(k, _)| k == id) {
+            Some(entry) => entry.1 = key,
+            None => self.keys.push((id.to_vec(), key)),
+        }
+    }
+
+    /// Add `key` with its [`EnardKey::fingerprint`] as the key ID.
+    pub fn insert_fingerprinted(&mut self, key: EnardKey) {
+        self.insert(&key.fingerprint(), key);
+    }
+
+    /// Returns the key for files with the key ID `id`.
+    pub fn get(&self, id: &[u8]) -> Option<&EnardKey> {
+        self.keys.iter().find(|(k, _)| k == id).map(|(_, key)| key)
+    }
+}
+impl KeyProvider for KeyRing {
+    fn key_for(&self, meta: &MetaMap) -> Result<Zeroizing<Vec<u8>>, EnardError> {
+        let id = meta
+            .get(crate::meta::KEY_ID)
+            .ok_or_else(|| EnardError::new_missing_meta(crate::meta::KEY_ID))?;
+        self.get(id).ok_or(EnardError::WrongKey)?.key_for(meta)
+    }
+}
+impl KeyProvider for &KeyRing {
+    fn key_for(&self, meta: &MetaMap) -> Result<Zeroizing<Vec<u8>>, EnardError> {
+        (**self).key_for(meta)
+    }
+}
+
+/// An IV (nonce) for a cipher.
+///
+/// [`crate::EnardWriter::new`] takes the IV as this type, so it can't be accidentally
+/// swapped with the key. Converts from byte arrays, slices and vectors, or parse it
+/// from hex with [`str::parse`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnardNonce(Vec<u8>);
+impl EnardNonce {
+    /// Generate a random IV of the right size for `cipher`.
+    #[cfg(feature = "random")]
+    pub fn generate<R: rand::CryptoRng + rand::Rng>(cipher: &CipherMeta, rng: &mut R) -> Self {
+        Self(cipher.generate_iv(rng))
+    }
+
+    /// The raw IV bytes
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Returns [`EnardError::IvLengthMismatch`] if this isn't the right size for `cipher`.
+    pub fn check_size(&self, cipher: &CipherMeta) -> Result<(), EnardError> {
+        match self.0.len() {
+            got if got == cipher.iv_size => Ok(()),
+            got => Err(EnardError::IvLengthMismatch {
+                expected: cipher.iv_size,
+                got,
+            }),
+        }
+    }
+}
+impl<const N: usize> From<[u8; N]> for EnardNonce {
+    fn from(iv: [u8; N]) -> Self {
+        Self(iv.to_vec())
+    }
+}
+impl From<Vec<u8>> for EnardNonce {
+    fn from(iv: Vec<u8>) -> Self {
+        Self(iv)
+    }
+}
+impl From<&[u8]> for EnardNonce {
+    fn from(iv: &[u8]) -> Self {
+        Self(iv.to_vec())
+    }
+}
+impl FromStr for EnardNonce {
+    type Err = EnardError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_hex(s).map(Self)
+    }
+}
+
+/// Parse a key from text or raw bytes, see the [module documentation](self).
+///
+/// Prefixed keys may have whitespace around them, e.g. a trailing newline in a key file.
+/// Anything without a prefix is used as the key exactly as given, so binary key files
+/// can be read and passed straight in.
+pub fn parse(key: &[u8]) -> Result<EnardKey, EnardError> {
+    if let Ok(text) = std::str::from_utf8(key).map(str::trim) {
+        if let Some(hex) = text
+            .strip_prefix("hex:")
+            .or_else(|| text.strip_prefix("0x"))
+        {
+            return parse_hex(hex).map(EnardKey::from);
+        }
+        if let Some(b64) = text.strip_prefix("base64:") {
+            return base64::engine::general_purpose::STANDARD
+                .decode(b64)
+                .map(EnardKey::from)
+                .map_err(|_| EnardError::InvalidBase64);
+        }
+    }
+    Ok(EnardKey::from(key))
+}
+
+/// Parse a hex string with an optional `0x` prefix.
+fn parse_hex(s: &str) -> Result<Vec<u8>, EnardError> {
+    let digits = s.strip_prefix("0x").unwrap_or(s).as_bytes();
+    let pairs = digits.chunks_exact(2);
+    if !pairs.remainder().is_empty() {
+        return Err(EnardError::InvalidHex);
+    }
+    pairs
+        .map(|pair| {
+            let hi = (pair[0] as char).to_digit(16);
+            let lo = (pair[1] as char).to_digit(16);
+            match (hi, lo) {
+                (Some(hi), Some(lo)) => Ok((hi << 4 | lo) as u8),
+                _ => Err(EnardError::InvalidHex),
+            }
+        })
+        .collect()
+}
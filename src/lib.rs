@@ -2,26 +2,96 @@
 //! of enabling on-the-fly game asset decryption.
 //!
 //!
+pub mod auto;
+pub mod batch;
+pub mod bench;
+pub mod cascade;
+pub mod checksum;
 pub mod cipher_factory;
 mod core;
+mod decrypt_writer;
 mod dyn_cipher;
+mod editor;
 mod error;
+pub mod format;
+mod forward;
+pub mod fs;
+pub mod index;
+pub mod kdf;
+pub mod key;
+pub mod limits;
+pub mod locate;
+pub mod mac;
+pub mod meta;
 pub mod nothing_cipher;
+mod oneshot;
+pub mod ops;
+pub mod patch;
+mod pool;
+pub mod sealed;
+#[cfg(feature = "serde")]
+pub mod serde_meta;
+pub mod split;
+pub mod stream;
+pub mod testvec;
+pub mod tuning;
+pub mod varint;
+mod verify;
 
-pub use crate::core::{EnardReader, EnardWriter, MetaMap};
+pub use crate::core::{
+    estimated_file_size, EnardReader, EnardWriter, EnardWriterBuilder, FormatVersion, MetaMap,
+    SavedPosition, WriteSummary, COPY_BUFFER_SIZE, CRYPT_ALIGN, CRYPT_BLOCK_SIZE, DATA_ALIGNMENT,
+    DEFAULT_BUFFER_SIZE, EXTENDED_META_KEY, MAGIC,
+};
 pub use crate::dyn_cipher::{BoxDynCipher, DynCipher, DynCipherCore};
-pub use error::EnardError;
+pub use decrypt_writer::EnardDecryptWriter;
+pub use editor::EnardEditor;
+pub use error::{EnardError, ErrorCode, ParsePhase};
+pub use forward::EnardForwardReader;
+pub use key::{EnardKey, EnardNonce, KeyProvider};
+pub use oneshot::decrypt_to_vec;
+#[cfg(feature = "random")]
+pub use oneshot::encrypt_to_vec;
+pub use pool::EnardReaderPool;
+pub use verify::{verify, verify_tag, Verifier, VerifyReport};
+pub use zeroize::Zeroizing;
 
+#[cfg(feature = "bevy")]
+pub mod bevy;
 #[cfg(feature = "chacha")]
 mod chacha;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "futures")]
+pub mod futures;
+#[cfg(feature = "recipients")]
+pub mod recipients;
+#[cfg(feature = "remote")]
+pub mod remote;
+#[cfg(feature = "salsa")]
+mod salsa;
+#[cfg(feature = "signature")]
+pub mod signature;
+#[cfg(feature = "vfs-integration")]
+pub mod vfs;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "zip-integration")]
+pub mod zip;
 
 #[cfg(test)]
 mod tests {
-    use crate::cipher_factory::{CipherName, GetFactory};
+    use crate::cipher_factory::{CipherFactory, CipherMeta, CipherName, GetFactory};
     use crate::dyn_cipher::BoxDynCipher;
     use chacha20::ChaCha12;
+    use proptest::collection::vec;
+    use proptest::option;
+    use proptest::prelude::{
+        any, prop_assert_eq, prop_oneof, proptest, Just, ProptestConfig, Strategy,
+    };
+    use proptest::sample::{select, Index};
     use std::fs;
-    use std::io::{Cursor, Read};
+    use std::io::{Cursor, Read, Seek, SeekFrom};
 
     use super::error::*;
     use super::*;
@@ -56,8 +126,8 @@ mod tests {
                 inner,
                 BoxDynCipher::factory(),
                 ChaCha12::name(),
-                &KEY1,
-                &NONCE,
+                KEY1,
+                NONCE.into(),
                 MetaMap::new(),
             )?
             .write_complete(data.as_slice())?;
@@ -65,12 +135,48 @@ mod tests {
         }()
         .unwrap();
         out.resize(n, 0u8);
-        let rd = EnardReader::new_boxed(Cursor::new(&mut out), &KEY1).unwrap();
+        let rd = EnardReader::new_boxed(Cursor::new(&mut out), KEY1).unwrap();
         // Check to make sure it came out right
         let dst_buf = read_all(rd);
         compare_bufs(&dst_buf, &data);
     }
 
+    #[test]
+    fn reader_size_accessors() {
+        let data = vec![0x42; 3 * KB + 7];
+        for version in [FormatVersion::V1, FormatVersion::V2] {
+            let mut wr = EnardWriter::new(
+                Cursor::new(Vec::new()),
+                BoxDynCipher::factory(),
+                ChaCha12::name(),
+                KEY1,
+                NONCE.into(),
+                MetaMap::new(),
+            )
+            .unwrap()
+            .with_version(version);
+            wr.write_complete(&data[..]).unwrap();
+            let file = wr.into_inner().into_inner();
+
+            let mut rd = EnardReader::new_boxed(Cursor::new(&file[..]), KEY1).unwrap();
+            assert_eq!(rd.version(), version);
+            assert_eq!(rd.data_size(), data.len() as u64);
+            assert_eq!(
+                rd.header_size() + version.header_start() as u64,
+                rd.data_start()
+            );
+            assert_eq!(rd.data_start() % DATA_ALIGNMENT as u64, 0);
+            assert!(!rd.is_eof());
+            let mut buf = vec![0u8; KB];
+            rd.read_exact(&mut buf).unwrap();
+            assert!(!rd.is_eof());
+            assert_eq!(read_all(&mut rd).len(), data.len() - KB);
+            assert!(rd.is_eof());
+            rd.seek(SeekFrom::Start(0)).unwrap();
+            assert!(!rd.is_eof());
+        }
+    }
+
     #[test]
     fn roundtrip_in_memory_zip() {
         let in_path = "./arrow_up.zip";
@@ -82,8 +188,8 @@ mod tests {
             Cursor::new(&mut tmp_buf),
             BoxDynCipher::factory(),
             ChaCha12::name(),
-            &KEY1,
-            &iv,
+            KEY1,
+            iv.into(),
             MetaMap::new(),
         )
         .unwrap();
@@ -94,10 +200,3397 @@ mod tests {
             // io::BufReader::new(Cursor::new(&tmp_buf)), BoxDynCipher::factory(), &KEY1
             Cursor::new(&tmp_buf),
             BoxDynCipher::factory(),
-            &KEY1,
+            KEY1,
         )
         .unwrap();
         let dst_buf = read_all(rd);
         compare_bufs(&dst_buf, &src_buf);
     }
+
+    #[test]
+    fn key_provider_from_meta() {
+        let key2 = [0x43u8; 32];
+        let mut meta = MetaMap::new();
+        meta.insert(b"key-id".to_vec(), b"2".to_vec());
+        let mut wr = EnardWriter::new(
+            Cursor::new(Vec::new()),
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            key2,
+            NONCE.into(),
+            meta,
+        )
+        .unwrap();
+        wr.write_complete(&[0x11u8; 100][..]).unwrap();
+        let buf = wr.into_inner().into_inner();
+
+        let provider = |meta: &MetaMap| -> Result<Zeroizing<Vec<u8>>, EnardError> {
+            match meta.get(b"key-id".as_slice()).map(Vec::as_slice) {
+                Some(b"2") => Ok(Zeroizing::new(key2.to_vec())),
+                _ => Ok(Zeroizing::new(KEY1.to_vec())),
+            }
+        };
+        let rd = EnardReader::new_boxed(Cursor::new(&buf), provider).unwrap();
+        compare_bufs(&read_all(rd), &[0x11u8; 100]);
+        assert!(matches!(
+            EnardReader::new_boxed(Cursor::new(&buf), KEY1),
+            Err(EnardError::MacError(_))
+        ));
+    }
+
+    #[test]
+    fn derived_key_per_file() {
+        use crate::kdf::{insert_salt, DerivedKey};
+        let write = |salt: &[u8]| {
+            let mut meta = MetaMap::new();
+            insert_salt(&mut meta, salt);
+            let mut wr = EnardWriter::new(
+                Cursor::new(Vec::new()),
+                BoxDynCipher::factory(),
+                ChaCha12::name(),
+                DerivedKey(KEY1),
+                NONCE.into(),
+                meta,
+            )
+            .unwrap();
+            wr.write_complete(&[0x11u8; 100][..]).unwrap();
+            wr.into_inner().into_inner()
+        };
+        let a = write(&[1; 32]);
+        let b = write(&[2; 32]);
+        // Same master key, IV and plaintext but different keystreams
+        assert_ne!(
+            a[a.len() - 132..a.len() - 32],
+            b[b.len() - 132..b.len() - 32]
+        );
+        let rd = EnardReader::new_boxed(Cursor::new(&b), DerivedKey(KEY1)).unwrap();
+        compare_bufs(&read_all(rd), &[0x11u8; 100]);
+        assert!(EnardReader::new_boxed(Cursor::new(&b), KEY1).is_err());
+
+        let mut wr = EnardWriter::new(
+            Cursor::new(Vec::new()),
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            MetaMap::new(),
+        )
+        .unwrap();
+        wr.write_complete(&[0x11u8; 100][..]).unwrap();
+        let buf = wr.into_inner().into_inner();
+        assert_eq!(
+            EnardReader::new_boxed(Cursor::new(&buf), DerivedKey(KEY1))
+                .unwrap_err()
+                .error_code(),
+            ErrorCode::MissingMeta
+        );
+    }
+
+    #[cfg(feature = "random")]
+    #[test]
+    fn derived_key_random_salt() {
+        use crate::kdf::{DerivedKey, SALT_META_KEY, SALT_SIZE};
+        let write = || {
+            let mut wr = EnardWriter::builder(Cursor::new(Vec::new()))
+                .derived_key(KEY1)
+                .iv(NONCE)
+                .build()
+                .unwrap();
+            wr.write_complete(&[0x11u8; 100][..]).unwrap();
+            wr.into_inner().into_inner()
+        };
+        let (a, b) = (write(), write());
+        let salt = |file: &[u8]| {
+            let mut rd = EnardReader::new_boxed(Cursor::new(file), DerivedKey(KEY1)).unwrap();
+            let salt = rd.meta().get(SALT_META_KEY).unwrap().clone();
+            compare_bufs(&read_all(&mut rd), &[0x11u8; 100]);
+            salt
+        };
+        assert_eq!(salt(&a).len(), SALT_SIZE);
+        assert_ne!(salt(&a), salt(&b));
+    }
+
+    #[test]
+    fn plaintext_checksum() {
+        let data = vec![0x5au8; 3000];
+        let mut meta = MetaMap::new();
+        crate::checksum::insert_checksum(&mut meta, data.as_slice()).unwrap();
+        let mut wr = EnardWriter::new(
+            Cursor::new(Vec::new()),
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            meta,
+        )
+        .unwrap();
+        wr.write_complete(data.as_slice()).unwrap();
+        let buf = wr.into_inner().into_inner();
+
+        let mut rd = EnardReader::new_boxed(Cursor::new(&buf), KEY1).unwrap();
+        rd.seek(SeekFrom::Start(100)).unwrap();
+        rd.verify_plaintext_checksum().unwrap();
+        assert_eq!(rd.stream_position().unwrap(), 100);
+        // Decrypting with the wrong cipher produces garbage, but the MAC is still fine
+        struct WrongFactory;
+        impl CipherFactory<BoxDynCipher> for WrongFactory {
+            fn get_meta(&self, name: &[u8]) -> Result<CipherMeta, EnardError> {
+                BoxDynCipher::factory().get_meta(name)
+            }
+            fn create(&self, _: &[u8], key: &[u8], iv: &[u8]) -> Result<BoxDynCipher, EnardError> {
+                BoxDynCipher::factory().create(b"ChaCha20", key, iv)
+            }
+        }
+        let mut rd = EnardReader::new(Cursor::new(&buf), WrongFactory, KEY1).unwrap();
+        assert!(matches!(
+            rd.verify_plaintext_checksum(),
+            Err(EnardError::ChecksumMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn wrong_key_detected() {
+        let mut wr = EnardWriter::new(
+            Cursor::new(Vec::new()),
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            MetaMap::new(),
+        )
+        .unwrap()
+        .with_key_check();
+        wr.write_complete(&[0x11u8; 100][..]).unwrap();
+        let buf = wr.into_inner().into_inner();
+
+        let rd = EnardReader::new_boxed(Cursor::new(&buf), KEY1).unwrap();
+        compare_bufs(&read_all(rd), &[0x11u8; 100]);
+        assert!(matches!(
+            EnardReader::new_boxed(Cursor::new(&buf), [0x43u8; 32]),
+            Err(EnardError::WrongKey)
+        ));
+        assert!(matches!(
+            verify(Cursor::new(&buf), [0x43u8; 32]),
+            Err(EnardError::WrongKey)
+        ));
+    }
+
+    #[test]
+    fn edit_in_place() {
+        let data: Vec<u8> = (0..5000u32).map(|i| i as u8).collect();
+        let mut wr = EnardWriter::new(
+            Cursor::new(Vec::new()),
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            MetaMap::new(),
+        )
+        .unwrap();
+        wr.write_complete(data.as_slice()).unwrap();
+        let buf = wr.into_inner().into_inner();
+
+        let mut expected = data.clone();
+        expected[1000..1004].copy_from_slice(b"edit");
+        let mut editor =
+            EnardEditor::open(Cursor::new(buf), BoxDynCipher::factory(), KEY1).unwrap();
+        editor.write_at(1000, b"edit").unwrap();
+        assert!(editor.write_at(4999, b"xy").is_err());
+        let buf = editor.finish().unwrap().into_inner();
+        let rd = EnardReader::new_boxed(Cursor::new(&buf), KEY1).unwrap();
+        compare_bufs(&read_all(rd), &expected);
+
+        // Growing the header moves the data
+        let mut editor =
+            EnardEditor::open(Cursor::new(buf), BoxDynCipher::factory(), KEY1).unwrap();
+        editor
+            .meta_mut()
+            .insert(b"long".to_vec(), vec![0x33u8; 300]);
+        let buf = editor.finish().unwrap().into_inner();
+        let rd = EnardReader::new_boxed(Cursor::new(&buf), KEY1).unwrap();
+        assert_eq!(rd.meta()[b"long".as_slice()], vec![0x33u8; 300]);
+        compare_bufs(&read_all(rd), &expected);
+    }
+
+    #[test]
+    fn diff_and_apply_patch() {
+        use crate::patch::{apply, diff, Patch, PatchOp};
+        let old: Vec<u8> = (0..20_000u32).map(|i| (i * 7 % 251) as u8).collect();
+        let mut new = old.clone();
+        new.splice(5000..5000, b"inserted".iter().copied());
+        new[15_000] ^= 0xff;
+        new.truncate(18_000);
+
+        let patch = diff(Cursor::new(&old), new.as_slice()).unwrap();
+        let inserted: usize = patch
+            .ops
+            .iter()
+            .map(|op| match op {
+                PatchOp::Insert(data) => data.len(),
+                PatchOp::Copy { .. } => 0,
+            })
+            .sum();
+        assert!(inserted < 3 * crate::patch::BLOCK_SIZE);
+
+        let mut encoded = Vec::new();
+        patch.encode(&mut encoded).unwrap();
+        let decoded = Patch::decode(encoded.as_slice()).unwrap();
+        assert_eq!(decoded, patch);
+        let mut out = Vec::new();
+        apply(Cursor::new(&old), &decoded, &mut out).unwrap();
+        compare_bufs(&out, &new);
+        assert!(matches!(
+            apply(Cursor::new(&new), &decoded, Vec::new()),
+            Err(EnardError::ChecksumMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn roundtrip_at_offset() {
+        const PREFIX: usize = 123;
+        let data = vec![0x11u8; 3000];
+        let mut buf = Cursor::new(vec![0xffu8; PREFIX]);
+        buf.set_position(PREFIX as u64);
+        let mut wr = EnardWriter::new(
+            buf,
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            MetaMap::new(),
+        )
+        .unwrap();
+        wr.write_complete(data.as_slice()).unwrap();
+        let buf = wr.into_inner().into_inner();
+
+        let mut rd = EnardReader::new_boxed_at(Cursor::new(&buf), KEY1, PREFIX as u64).unwrap();
+        assert!(rd.data_start() > PREFIX as u64);
+        compare_bufs(&read_all(&mut rd), &data);
+        // Seeking must be relative to the data, not the inner reader
+        rd.seek(SeekFrom::Start(10)).unwrap();
+        assert_eq!(read_all(rd).len(), data.len() - 10);
+    }
+
+    #[test]
+    fn readahead_small_reads_and_seeks() {
+        let data: Vec<u8> = (0..20000u32).map(|i| (i % 251) as u8).collect();
+        let mut wr = EnardWriter::new(
+            Cursor::new(Vec::new()),
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            MetaMap::new(),
+        )
+        .unwrap();
+        wr.write_complete(data.as_slice()).unwrap();
+        let buf = wr.into_inner().into_inner();
+
+        let mut rd = EnardReader::new_boxed(Cursor::new(&buf), KEY1)
+            .unwrap()
+            .with_readahead(1000);
+        let mut out = Vec::new();
+        let mut small = [0u8; 7];
+        loop {
+            let n = rd.read(&mut small).unwrap();
+            if n == 0 {
+                break;
+            }
+            out.extend_from_slice(&small[..n]);
+        }
+        compare_bufs(&out, &data);
+        // Seek inside and outside of the buffer, and do a read larger than the buffer
+        for &pos in &[19990u64, 19500, 100, 5000, 4999] {
+            rd.seek(SeekFrom::Start(pos)).unwrap();
+            rd.read_exact(&mut small).unwrap();
+            compare_bufs(&small, &data[pos as usize..pos as usize + small.len()]);
+        }
+        let mut big = vec![0u8; 3000];
+        rd.read_exact(&mut big).unwrap();
+        compare_bufs(&big, &data[5006..8006]);
+    }
+
+    #[test]
+    fn seek_past_end() {
+        let data: Vec<u8> = (0..3000u32).map(|i| (i % 251) as u8).collect();
+        let mut wr = EnardWriter::new(
+            Cursor::new(Vec::new()),
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            MetaMap::new(),
+        )
+        .unwrap();
+        wr.write_complete(data.as_slice()).unwrap();
+        let buf = wr.into_inner().into_inner();
+        let mut rd = EnardReader::new_boxed(Cursor::new(&buf), KEY1).unwrap();
+        assert!(rd.seek(SeekFrom::End(1)).is_err());
+
+        let mut rd = rd.with_seek_past_end(true).with_readahead(500);
+        rd.read_exact(&mut [0u8; 10]).unwrap();
+        assert_eq!(rd.seek(SeekFrom::End(100)).unwrap(), 3100);
+        assert_eq!(rd.read(&mut [0u8; 10]).unwrap(), 0);
+        assert_eq!(rd.stream_position().unwrap(), 3100);
+        assert_eq!(rd.skip(10).unwrap(), 0);
+        // Seeking back works from past the end
+        let mut small = [0u8; 10];
+        rd.seek(SeekFrom::Current(-200)).unwrap();
+        rd.read_exact(&mut small).unwrap();
+        compare_bufs(&small, &data[2900..2910]);
+        assert!(rd.seek(SeekFrom::Current(-5000)).is_err());
+        // Positions which don't fit in a u64 are errors, not overflows
+        rd.seek(SeekFrom::End(100)).unwrap();
+        let far = rd.seek(SeekFrom::Current(i64::MAX)).unwrap();
+        assert_eq!(far, 3100 + i64::MAX as u64);
+        let err = rd.seek(SeekFrom::Current(i64::MAX)).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        assert_eq!(rd.stream_position().unwrap(), far);
+    }
+
+    #[cfg(feature = "salsa")]
+    #[test]
+    fn salsa_roundtrip_and_seek() {
+        let data: Vec<u8> = (0..5000u32).map(|i| (i % 251) as u8).collect();
+        let ciphers: [(&[u8], usize); 4] = [
+            (b"Salsa8", 8),
+            (b"Salsa12", 8),
+            (b"Salsa20", 8),
+            (b"XSalsa20", 24),
+        ];
+        for (name, iv_size) in ciphers {
+            let mut wr = EnardWriter::new(
+                Cursor::new(Vec::new()),
+                BoxDynCipher::factory(),
+                name,
+                KEY1,
+                vec![0x24u8; iv_size].into(),
+                MetaMap::new(),
+            )
+            .unwrap();
+            wr.write_complete(data.as_slice()).unwrap();
+            let buf = wr.into_inner().into_inner();
+            assert!(!buf.windows(64).any(|w| w == &data[..64]));
+
+            let mut rd = EnardReader::new_boxed(Cursor::new(&buf), KEY1).unwrap();
+            compare_bufs(&read_all(&mut rd), &data);
+            // Seek into the middle of blocks, across block boundaries and backwards
+            for pos in [4000usize, 63, 64, 1, 2049, 0, 4990] {
+                let mut small = [0u8; 10];
+                rd.seek(SeekFrom::Start(pos as u64)).unwrap();
+                rd.read_exact(&mut small).unwrap();
+                compare_bufs(&small, &data[pos..pos + 10]);
+            }
+        }
+    }
+
+    #[test]
+    fn sub_reader_is_bounded() {
+        let data: Vec<u8> = (0..10000u32).map(|i| (i % 249) as u8).collect();
+        let mut wr = EnardWriter::new(
+            Cursor::new(Vec::new()),
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            MetaMap::new(),
+        )
+        .unwrap();
+        wr.write_complete(data.as_slice()).unwrap();
+        let buf = wr.into_inner().into_inner();
+
+        let mut rd = EnardReader::new_boxed(Cursor::new(&buf), KEY1).unwrap();
+        assert!(rd.sub_reader(9000, 1001).is_err());
+        let mut sub = rd.sub_reader(3000, 500).unwrap();
+        compare_bufs(&read_all(&mut sub), &data[3000..3500]);
+        assert_eq!(sub.seek(SeekFrom::End(-10)).unwrap(), 490);
+        compare_bufs(&read_all(&mut sub), &data[3490..3500]);
+        assert!(sub.seek(SeekFrom::Current(-1000)).is_err());
+        assert!(sub.seek(SeekFrom::Start(501)).is_err());
+    }
+
+    #[test]
+    fn cipher_stream_adapters() {
+        use crate::stream::{CipherRead, CipherWriteStream};
+        use std::io::Write;
+        let data: Vec<u8> = (0..5000u32).map(|i| (i % 247) as u8).collect();
+        let new_cipher = || ChaCha12::factory().create(b"", &KEY1, &NONCE).unwrap();
+        let mut wr = CipherWriteStream::with_buffer_size(Vec::new(), new_cipher(), 100);
+        wr.write_all(&data).unwrap();
+        let encrypted = wr.into_inner();
+        assert_eq!(encrypted.len(), data.len());
+        assert_ne!(encrypted, data);
+
+        let mut rd = CipherRead::new(Cursor::new(encrypted), new_cipher());
+        compare_bufs(&read_all(&mut rd), &data);
+        rd.seek(SeekFrom::Start(1234)).unwrap();
+        let mut buf = [0u8; 100];
+        rd.read_exact(&mut buf).unwrap();
+        compare_bufs(&buf, &data[1234..1334]);
+    }
+
+    #[test]
+    fn seek_skips_without_inner_seek() {
+        use std::{cell::Cell, io, rc::Rc};
+        /// Counts calls to `seek` on the inner reader
+        struct CountSeeks<R>(R, Rc<Cell<usize>>);
+        impl<R: Read> Read for CountSeeks<R> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                self.0.read(buf)
+            }
+        }
+        impl<R: Seek> Seek for CountSeeks<R> {
+            fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+                self.1.set(self.1.get() + 1);
+                self.0.seek(pos)
+            }
+        }
+
+        let data: Vec<u8> = (0..50000u32).map(|i| (i % 253) as u8).collect();
+        let mut wr = EnardWriter::new(
+            Cursor::new(Vec::new()),
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            MetaMap::new(),
+        )
+        .unwrap();
+        wr.write_complete(data.as_slice()).unwrap();
+        let buf = wr.into_inner().into_inner();
+
+        let count = Rc::new(Cell::new(0));
+        let inner = CountSeeks(Cursor::new(&buf), count.clone());
+        let mut rd = EnardReader::new_boxed(inner, KEY1).unwrap();
+        let mut small = [0u8; 16];
+        rd.read_exact(&mut small).unwrap();
+        let seeks = count.get();
+        assert_eq!(rd.seek(SeekFrom::Start(16)).unwrap(), 16);
+        assert_eq!(rd.skip(1000).unwrap(), 1000);
+        rd.seek(SeekFrom::Current(100)).unwrap();
+        rd.read_exact(&mut small).unwrap();
+        compare_bufs(&small, &data[1116..1132]);
+        assert_eq!(count.get(), seeks);
+        // Large and backwards seeks still seek the inner reader
+        rd.seek(SeekFrom::Start(40000)).unwrap();
+        rd.read_exact(&mut small).unwrap();
+        compare_bufs(&small, &data[40000..40016]);
+        assert_eq!(count.get(), seeks + 1);
+        assert_eq!(rd.skip(u64::MAX).unwrap(), 50000 - 40016);
+        assert!(rd.is_eof());
+    }
+
+    #[test]
+    fn vectored_roundtrip() {
+        use std::io::{IoSlice, IoSliceMut, Write};
+
+        let data: Vec<u8> = (0..2000u32).map(|i| (i % 251) as u8).collect();
+        let mut wr = EnardWriter::new(
+            Cursor::new(Vec::new()),
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            MetaMap::new(),
+        )
+        .unwrap()
+        .with_buffer_size(100);
+        wr.write_header().unwrap();
+        let slices: Vec<_> = data[..1000].chunks(37).map(IoSlice::new).collect();
+        assert_eq!(wr.write_vectored(&slices).unwrap(), 1000);
+        let mut rest = data[1000..].to_vec();
+        wr.write_in_place(&mut rest).unwrap();
+        wr.finish().unwrap();
+        let buf = wr.into_inner().into_inner();
+
+        let mut rd = EnardReader::new_boxed(Cursor::new(&buf), KEY1).unwrap();
+        let (mut a, mut b) = (vec![0u8; 500], vec![0u8; 2000]);
+        let n = rd
+            .read_vectored(&mut [IoSliceMut::new(&mut a), IoSliceMut::new(&mut b)])
+            .unwrap();
+        assert_eq!(n, data.len());
+        compare_bufs(&a, &data[..500]);
+        compare_bufs(&b[..1500], &data[500..]);
+    }
+
+    #[cfg(feature = "zip-integration")]
+    #[test]
+    fn open_encrypted_zip() {
+        let src_buf = fs::read("./arrow_up.zip").unwrap();
+        let mut wr = EnardWriter::new(
+            Cursor::new(Vec::new()),
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            MetaMap::new(),
+        )
+        .unwrap();
+        wr.write_complete(src_buf.as_slice()).unwrap();
+        let buf = wr.into_inner().into_inner();
+
+        let mut archive = crate::zip::EnardZip::from_reader(Cursor::new(buf), &KEY1).unwrap();
+        let plain = ::zip::ZipArchive::new(Cursor::new(&src_buf)).unwrap();
+        assert_eq!(archive.len(), plain.len());
+        let name = archive.file_names().next().unwrap().to_string();
+        let mut file = archive.by_name(&name).unwrap();
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents).unwrap();
+        assert_eq!(contents.len() as u64, file.size());
+    }
+
+    #[cfg(feature = "bevy")]
+    #[test]
+    fn bevy_asset_io() {
+        use ::bevy_asset::AssetIo;
+        use std::path::{Path, PathBuf};
+
+        let src_buf = fs::read("./arrow_up.zip").unwrap();
+        let mut wr = EnardWriter::new(
+            Cursor::new(Vec::new()),
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            MetaMap::new(),
+        )
+        .unwrap();
+        wr.write_complete(src_buf.as_slice()).unwrap();
+        let buf = wr.into_inner().into_inner();
+
+        let archive = crate::zip::EnardZip::from_reader(Cursor::new(buf), &KEY1).unwrap();
+        let io = crate::bevy::EnardAssetIo::new(archive);
+        assert!(io.is_file(Path::new("arrow_up.png")));
+        assert!(io.is_dir(Path::new("")));
+        assert!(!io.is_file(Path::new("missing.png")));
+        let entries: Vec<_> = io.read_directory(Path::new("")).unwrap().collect();
+        assert_eq!(entries, vec![PathBuf::from("arrow_up.png")]);
+    }
+
+    #[cfg(feature = "recipients")]
+    #[test]
+    fn multiple_recipients() {
+        use crate::recipients::{PublicKey, StaticSecret};
+        let mut rng = rand::rngs::OsRng;
+        let dev = StaticSecret::from([1u8; 32]);
+        let shipping = StaticSecret::from([2u8; 32]);
+        let other = StaticSecret::from([3u8; 32]);
+        let mut wr = EnardWriter::new(
+            Cursor::new(Vec::new()),
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            MetaMap::new(),
+        )
+        .unwrap()
+        .add_recipient(&PublicKey::from(&dev), &mut rng)
+        .unwrap()
+        .add_recipient(&PublicKey::from(&shipping), &mut rng)
+        .unwrap();
+        wr.write_complete(&[0x11u8; 1000][..]).unwrap();
+        let buf = wr.into_inner().into_inner();
+
+        for key in [&dev, &shipping] {
+            let rd = EnardReader::new_with_private_key(Cursor::new(&buf), key).unwrap();
+            compare_bufs(&read_all(rd), &[0x11u8; 1000]);
+        }
+        // The symmetric key still works
+        assert!(EnardReader::new_boxed(Cursor::new(&buf), KEY1).is_ok());
+        assert!(matches!(
+            EnardReader::new_with_private_key(Cursor::new(&buf), &other),
+            Err(EnardError::WrongKey)
+        ));
+    }
+
+    #[cfg(feature = "signature")]
+    #[test]
+    fn signed_roundtrip() {
+        use crate::signature::{verify_signature, SigningKey};
+        let signing = SigningKey::from_bytes(&[7u8; 32]);
+        let mut wr = EnardWriter::new(
+            Cursor::new(Vec::new()),
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            MetaMap::new(),
+        )
+        .unwrap()
+        .sign_with(signing.clone());
+        wr.write_complete(&[0x11u8; 1000][..]).unwrap();
+        let mut buf = wr.into_inner().into_inner();
+
+        let public = signing.verifying_key();
+        verify_signature(Cursor::new(&buf), &public).unwrap();
+        let mut rd = EnardReader::new_boxed(Cursor::new(&buf), KEY1).unwrap();
+        rd.seek(SeekFrom::Start(10)).unwrap();
+        rd.verify_signature(&public).unwrap();
+        let mut rest = Vec::new();
+        rd.read_to_end(&mut rest).unwrap();
+        compare_bufs(&rest, &[0x11u8; 990]);
+        assert_eq!(crate::locate::find(Cursor::new(&buf)).unwrap(), Some(0));
+
+        let other = SigningKey::from_bytes(&[8u8; 32]).verifying_key();
+        assert!(matches!(
+            verify_signature(Cursor::new(&buf), &other),
+            Err(EnardError::InvalidSignature)
+        ));
+        buf[100] ^= 1;
+        assert!(matches!(
+            verify_signature(Cursor::new(&buf), &public),
+            Err(EnardError::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn find_embedded() {
+        let exe = vec![0x90u8; 100 * KB];
+        let data = vec![0x11u8; 3000];
+        let mut buf = Cursor::new(exe.clone());
+        buf.seek(SeekFrom::End(0)).unwrap();
+        let mut wr = EnardWriter::new(
+            buf,
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            MetaMap::new(),
+        )
+        .unwrap();
+        wr.write_complete(data.as_slice()).unwrap();
+        let mut buf = wr.into_inner();
+
+        // Without a trailer
+        let rd = EnardReader::from_embedded(Cursor::new(buf.get_ref()), KEY1).unwrap();
+        compare_bufs(&read_all(rd), &data);
+        // With a trailer
+        locate::write_trailer(&mut buf, exe.len() as u64).unwrap();
+        let pos = locate::find(Cursor::new(buf.get_ref())).unwrap();
+        assert_eq!(pos, Some(exe.len() as u64));
+        // Not there at all
+        let pos = locate::find(Cursor::new(&exe)).unwrap();
+        assert_eq!(pos, None);
+    }
+
+    #[test]
+    fn open_plain_or_encrypted() {
+        let mut wr = EnardWriter::new(
+            Cursor::new(Vec::new()),
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            MetaMap::new(),
+        )
+        .unwrap();
+        wr.write_complete(&b"shipping"[..]).unwrap();
+        let mut encrypted = Cursor::new(wr.into_inner().into_inner());
+        assert!(EnardReader::is_enard(&mut encrypted).unwrap());
+        assert_eq!(encrypted.position(), 0);
+        let mut rd = auto::open_auto(encrypted, KEY1).unwrap();
+        assert!(rd.is_encrypted());
+        assert_eq!(read_all(&mut rd), b"shipping");
+
+        let mut plain = Cursor::new(b"dev".to_vec());
+        assert!(!EnardReader::is_enard(&mut plain).unwrap());
+        let rd = auto::open_auto(plain, KEY1).unwrap();
+        assert!(!rd.is_encrypted());
+        assert_eq!(read_all(rd), b"dev");
+        // Too short to hold the magic
+        let rd = auto::open_auto(Cursor::new(Vec::new()), KEY1).unwrap();
+        assert!(!rd.is_encrypted());
+    }
+
+    #[test]
+    fn unencrypted_roundtrip() {
+        let mut wr = EnardWriter::new_unencrypted(Cursor::new(Vec::new()), MetaMap::new()).unwrap();
+        wr.write_complete(&b"dev asset"[..]).unwrap();
+        let buf = wr.into_inner().into_inner();
+        // The data is stored as-is
+        assert!(buf.windows(9).any(|w| w == b"dev asset"));
+        let rd = EnardReader::new_unencrypted(Cursor::new(&buf)).unwrap();
+        assert!(rd.is_unencrypted());
+        assert_eq!(read_all(rd), b"dev asset");
+
+        let mut wr = EnardWriter::new(
+            Cursor::new(Vec::new()),
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            MetaMap::new(),
+        )
+        .unwrap();
+        wr.write_complete(&b"shipping"[..]).unwrap();
+        let buf = wr.into_inner().into_inner();
+        assert!(matches!(
+            EnardReader::new_unencrypted(Cursor::new(&buf)),
+            Err(EnardError::MissingMeta { .. })
+        ));
+    }
+
+    #[test]
+    fn pluggable_mac() {
+        let write = |mac: &[u8], factory: Option<&dyn mac::MacFactory>| {
+            let mut wr = EnardWriter::builder(Cursor::new(Vec::new()))
+                .key(KEY1)
+                .iv(NONCE)
+                .mac(mac)
+                .build()?;
+            if let Some(factory) = factory {
+                wr = wr.with_mac_factory(factory)?;
+            }
+            wr.write_complete(&b"tagged"[..])?;
+            Ok::<_, EnardError>(wr.into_inner().into_inner())
+        };
+        let buf = write(mac::HMAC_SHA512, None).unwrap();
+        let rd = EnardReader::new_boxed(Cursor::new(&buf), KEY1).unwrap();
+        assert_eq!(mac::mac_name(rd.meta()), mac::HMAC_SHA512);
+        assert_eq!(read_all(rd), b"tagged");
+        assert!(verify(Cursor::new(&buf), KEY1).is_ok());
+        let mut corrupt = buf.clone();
+        *corrupt.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            EnardReader::new_boxed(Cursor::new(&corrupt), KEY1),
+            Err(EnardError::MacError(_))
+        ));
+
+        // Unknown MACs need their own factory
+        struct Sha384Macs;
+        impl mac::MacFactory for Sha384Macs {
+            fn create(&self, name: &[u8], key: &[u8]) -> Result<mac::BoxDynMac, EnardError> {
+                match name {
+                    b"HMAC-SHA384" => Ok(Box::new(
+                        <hmac::Hmac<sha2::Sha384> as hmac::Mac>::new_from_slice(key)?,
+                    )),
+                    // Too short for a tag
+                    b"HMAC-SHA224" => Ok(Box::new(
+                        <hmac::Hmac<sha2::Sha224> as hmac::Mac>::new_from_slice(key)?,
+                    )),
+                    _ => Err(EnardError::new_unsupported_encryption(name)),
+                }
+            }
+        }
+        let err = write(b"HMAC-SHA384", None).unwrap_err();
+        assert!(matches!(err, EnardError::IO(e) if e.kind() == std::io::ErrorKind::InvalidInput));
+        let buf = write(b"HMAC-SHA384", Some(&Sha384Macs)).unwrap();
+        assert!(matches!(
+            EnardReader::new_boxed(Cursor::new(&buf), KEY1),
+            Err(EnardError::UnsupportedEncryption { .. })
+        ));
+        let rd = EnardReader::new_with_mac_factory(
+            Cursor::new(&buf),
+            BoxDynCipher::factory(),
+            KEY1,
+            Sha384Macs,
+        )
+        .unwrap();
+        assert_eq!(read_all(rd), b"tagged");
+        assert!(matches!(
+            write(b"HMAC-SHA224", Some(&Sha384Macs)),
+            Err(EnardError::UnsupportedEncryption { .. })
+        ));
+    }
+
+    #[test]
+    fn take_meta_and_into_parts() {
+        let mut meta = MetaMap::new();
+        meta.insert(b"manifest".to_vec(), vec![b'm'; 10 * KB]);
+        let mut wr = EnardWriter::new(
+            Cursor::new(Vec::new()),
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            meta.clone(),
+        )
+        .unwrap();
+        wr.write_complete(&b"data"[..]).unwrap();
+        let buf = wr.into_inner().into_inner();
+
+        let mut rd = EnardReader::new_boxed(Cursor::new(&buf), KEY1).unwrap();
+        assert_eq!(rd.take_meta(), meta);
+        assert!(rd.meta().is_empty());
+        assert_eq!(read_all(&mut rd), b"data");
+
+        let rd = EnardReader::new_boxed(Cursor::new(&buf), KEY1).unwrap();
+        let (mut inner, taken) = rd.into_parts();
+        assert_eq!(taken, meta);
+        inner.seek(SeekFrom::Start(0)).unwrap();
+        let rd = EnardReader::new_boxed(inner, KEY1).unwrap();
+        assert_eq!(read_all(rd), b"data");
+    }
+
+    #[test]
+    fn sealed_key() {
+        use crate::mac::MacFactory;
+        use crate::sealed::SealedKey;
+        use std::cell::Cell;
+        // Counts calls, to check the cipher is only created after the MAC is verified
+        struct Keystore(Cell<u32>);
+        impl SealedKey<BoxDynCipher> for Keystore {
+            fn create_cipher(
+                &self,
+                name: &[u8],
+                iv: &[u8],
+                meta: &MetaMap,
+            ) -> Result<BoxDynCipher, EnardError> {
+                self.0.set(self.0.get() + 1);
+                let key = kdf::cipher_key(meta, &KEY1)?;
+                BoxDynCipher::factory().create(name, &key, iv)
+            }
+
+            fn create_mac(
+                &self,
+                name: &[u8],
+                meta: &MetaMap,
+            ) -> Result<mac::BoxDynMac, EnardError> {
+                let key = kdf::mac_key(meta, &KEY1)?;
+                mac::DefaultMacFactory.create(name, &key)
+            }
+        }
+        let mut wr = EnardWriter::builder(Cursor::new(Vec::new()))
+            .key(KEY1)
+            .iv(NONCE)
+            .split_keys()
+            .build()
+            .unwrap();
+        wr.write_complete(&b"sealed"[..]).unwrap();
+        let mut buf = wr.into_inner().into_inner();
+
+        let keystore = Keystore(Cell::new(0));
+        let rd = EnardReader::new_sealed(Cursor::new(&buf), &keystore).unwrap();
+        assert_eq!(read_all(rd), b"sealed");
+        assert_eq!(keystore.0.get(), 1);
+        let last = buf.len() - 1;
+        buf[last] ^= 1;
+        assert!(matches!(
+            EnardReader::new_sealed(Cursor::new(&buf), &keystore),
+            Err(EnardError::MacError(_))
+        ));
+        assert_eq!(keystore.0.get(), 1);
+    }
+
+    #[test]
+    fn roundtrip_v2_large_meta() {
+        let data = vec![0x11u8; 3000];
+        let mut meta = MetaMap::new();
+        meta.insert(vec![b'k'; 300], vec![b'v'; 100 * KB]);
+        let mut wr = EnardWriter::new(
+            Cursor::new(Vec::new()),
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            meta.clone(),
+        )
+        .unwrap()
+        .with_version(FormatVersion::V2);
+        wr.write_complete(data.as_slice()).unwrap();
+        let buf = wr.into_inner().into_inner();
+
+        let rd = EnardReader::new_boxed(Cursor::new(&buf), KEY1).unwrap();
+        assert_eq!(rd.version(), FormatVersion::V2);
+        assert_eq!(rd.data_start() % 8, 0);
+        assert_eq!(rd.meta(), &meta);
+        compare_bufs(&read_all(rd), &data);
+        assert_eq!(verify(Cursor::new(&buf), KEY1).unwrap().meta, meta);
+    }
+
+    #[test]
+    fn meta_order_preserved() {
+        let keys: [&[u8]; 4] = [b"zeta", b"alpha", b"mid", b"beta"];
+        let mut meta = MetaMap::new();
+        for k in keys {
+            meta.insert(k.to_vec(), k.to_vec());
+        }
+        let write = || {
+            let mut wr = EnardWriter::new(
+                Cursor::new(Vec::new()),
+                BoxDynCipher::factory(),
+                ChaCha12::name(),
+                KEY1,
+                NONCE.into(),
+                meta.clone(),
+            )
+            .unwrap();
+            wr.write_complete(&b"data"[..]).unwrap();
+            wr.into_inner().into_inner()
+        };
+        let buf = write();
+        assert_eq!(buf, write());
+        let rd = EnardReader::new_boxed(Cursor::new(&buf), KEY1).unwrap();
+        let read_keys: Vec<&[u8]> = rd.meta().keys().map(|k| k.as_slice()).collect();
+        assert_eq!(read_keys, keys);
+    }
+
+    #[test]
+    fn extended_meta_roundtrip() {
+        let data = vec![0x22u8; 5000];
+        let mut ext = MetaMap::new();
+        ext.insert(b"thumbnail".to_vec(), vec![0x33; 100 * KB]);
+        ext.insert(b"manifest".to_vec(), b"a.txt\nb.txt".to_vec());
+        let mut wr = EnardWriter::new(
+            Cursor::new(Vec::new()),
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            MetaMap::new(),
+        )
+        .unwrap()
+        .with_extended_meta(&ext);
+        wr.write_complete(data.as_slice()).unwrap();
+        let buf = wr.into_inner().into_inner();
+
+        let mut rd = EnardReader::new_boxed(Cursor::new(&buf), KEY1)
+            .unwrap()
+            .with_readahead(64);
+        assert_eq!(rd.data_size(), data.len() as u64);
+        let mut head = [0u8; 10];
+        rd.read_exact(&mut head).unwrap();
+        assert_eq!(rd.extended_meta().unwrap(), ext);
+        assert_eq!(rd.stream_position().unwrap(), 10);
+        let mut rest = read_all(&mut rd);
+        rest.splice(0..0, head);
+        compare_bufs(&rest, &data);
+        assert!(verify(Cursor::new(&buf), KEY1).is_ok());
+    }
+
+    #[test]
+    fn plaintext_hash() {
+        use sha2::{Digest, Sha256};
+        let data = vec![0x22u8; 20 * KB];
+        let mut ext = MetaMap::new();
+        ext.insert(b"manifest".to_vec(), b"a.txt".to_vec());
+        let mut wr = EnardWriter::new(
+            Cursor::new(Vec::new()),
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            MetaMap::new(),
+        )
+        .unwrap()
+        .with_plaintext_hash()
+        .with_extended_meta(&ext);
+        wr.write_complete(data.as_slice()).unwrap();
+        let buf = wr.into_inner().into_inner();
+
+        let mut rd = EnardReader::new_boxed(Cursor::new(&buf), KEY1).unwrap();
+        let expected: [u8; 32] = Sha256::digest(&data).into();
+        assert_eq!(rd.plaintext_hash().unwrap(), Some(expected));
+        assert_eq!(rd.extended_meta().unwrap()[&b"manifest"[..]], b"a.txt");
+        compare_bufs(&read_all(rd), &data);
+        assert!(verify(Cursor::new(&buf), KEY1).is_ok());
+    }
+
+    #[test]
+    fn meta_trailer() {
+        use sha2::{Digest, Sha256};
+        use std::io::Write;
+        let data = vec![0x33u8; 10 * KB];
+        let mut wr = EnardWriter::new(
+            Cursor::new(Vec::new()),
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            MetaMap::new(),
+        )
+        .unwrap()
+        .with_meta_trailer()
+        .with_plaintext_hash();
+        wr.meta_mut().insert(b"late".to_vec(), b"header".to_vec());
+        wr.write_header().unwrap();
+        wr.write_all(&data).unwrap();
+        let mut trailer = MetaMap::new();
+        trailer.insert(b"compressed-size".to_vec(), b"1234".to_vec());
+        wr.finish_with_meta_trailer(&trailer).unwrap();
+        let buf = wr.into_inner().into_inner();
+
+        let mut rd = EnardReader::new_boxed(Cursor::new(&buf), KEY1).unwrap();
+        assert_eq!(rd.meta()[&b"late"[..]], b"header");
+        assert_eq!(rd.data_size(), data.len() as u64);
+        let ext = rd.extended_meta().unwrap();
+        assert_eq!(ext[&b"compressed-size"[..]], b"1234");
+        let expected: [u8; 32] = Sha256::digest(&data).into();
+        assert_eq!(rd.plaintext_hash().unwrap(), Some(expected));
+        compare_bufs(&read_all(rd), &data);
+        assert!(verify(Cursor::new(&buf), KEY1).is_ok());
+
+        // The trailer has to be asked for up front
+        let mut wr = EnardWriter::new(
+            Cursor::new(Vec::new()),
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            MetaMap::new(),
+        )
+        .unwrap();
+        wr.write_header().unwrap();
+        assert!(wr.finish_with_meta_trailer(&trailer).is_err());
+        wr.abort().unwrap();
+    }
+
+    #[test]
+    fn writer_call_order() {
+        let mut wr = EnardWriter::new(
+            Cursor::new(Vec::new()),
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            MetaMap::new(),
+        )
+        .unwrap();
+        assert!(wr.write_in_place(&mut [1u8; 10]).is_err());
+        assert!(wr.finish().is_err());
+        wr.write_header().unwrap();
+        assert!(wr.write_header().is_err());
+        wr.write_in_place(&mut b"hello".to_vec()).unwrap();
+        wr.finish().unwrap();
+        assert!(wr.write_in_place(&mut [1u8; 10]).is_err());
+        assert!(wr.finish().is_err());
+        // Nothing was written by the failed calls
+        let buf = wr.into_inner().into_inner();
+        let rd = EnardReader::new_boxed(Cursor::new(&buf), KEY1).unwrap();
+        assert_eq!(read_all(rd), b"hello");
+    }
+
+    #[test]
+    fn abort_writer() {
+        // Embedded after some other data, to check the right part is invalidated
+        let mut out = Cursor::new(b"prefix".to_vec());
+        out.set_position(6);
+        let mut wr = EnardWriter::new(
+            out,
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            MetaMap::new(),
+        )
+        .unwrap();
+        wr.write_header().unwrap();
+        wr.write_in_place(&mut [0x11u8; 100]).unwrap();
+        let mut inner = wr.abort().unwrap();
+        assert_eq!(inner.position(), 6);
+        assert!(!EnardReader::is_enard(&mut inner).unwrap());
+        let buf = inner.into_inner();
+        assert_eq!(&buf[..12], b"prefix\0\0\0\0\0\0");
+    }
+
+    #[test]
+    fn reserved_meta() {
+        let created = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_600_000_000);
+        let writer = |meta: MetaMap| {
+            EnardWriter::new(
+                Cursor::new(Vec::new()),
+                BoxDynCipher::factory(),
+                ChaCha12::name(),
+                KEY1,
+                NONCE.into(),
+                meta,
+            )
+            .unwrap()
+        };
+        let mut wr = writer(MetaMap::new())
+            .with_content_type("text/plain")
+            .with_created(created);
+        wr.write_complete(&b"hello"[..]).unwrap();
+        let buf = wr.into_inner().into_inner();
+        let rd = EnardReader::new_boxed(Cursor::new(&buf), KEY1).unwrap();
+        assert_eq!(rd.content_type(), Some("text/plain"));
+        assert_eq!(rd.compression(), None);
+        assert_eq!(rd.created(), Some(created));
+
+        let mut meta = MetaMap::new();
+        meta.insert(b"enard.unknown".to_vec(), b"x".to_vec());
+        let err = writer(meta.clone()).write_header().unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(matches!(
+            err.into_inner().unwrap().downcast_ref::<EnardError>(),
+            Some(EnardError::ReservedMeta { .. })
+        ));
+        let mut wr = writer(meta).allow_reserved_meta();
+        assert!(wr.write_header().is_ok());
+        wr.abort().unwrap();
+    }
+
+    #[test]
+    fn writer_builder() {
+        let build = || {
+            EnardWriter::builder(Cursor::new(Vec::new()))
+                .cipher(ChaCha12::name())
+                .key(KEY1)
+                .meta("name", "file.txt")
+                .version(FormatVersion::V2)
+        };
+        let mut wr = build().iv(NONCE).build().unwrap();
+        wr.write_complete(&b"hello"[..]).unwrap();
+        let buf = wr.into_inner().into_inner();
+        let rd = EnardReader::new_boxed(Cursor::new(&buf), KEY1).unwrap();
+        assert_eq!(rd.version(), FormatVersion::V2);
+        assert_eq!(rd.meta().get(&b"name"[..]).unwrap(), b"file.txt");
+        assert_eq!(read_all(rd), b"hello");
+
+        // Without an IV one is generated, or it's an error
+        let result = build().build();
+        #[cfg(feature = "random")]
+        assert!(result.is_ok());
+        #[cfg(not(feature = "random"))]
+        assert!(matches!(result, Err(EnardError::InvalidLength)));
+    }
+
+    #[test]
+    fn parse_key_formats() {
+        let expected = [0x12u8, 0x34, 0xab];
+        for text in ["hex:1234ab", "0x1234AB\n", "base64:EjSr", " base64:EjSr "] {
+            assert_eq!(key::parse(text.as_bytes()).unwrap().as_bytes(), expected);
+        }
+        // Binary keys are used as-is, even if they happen to look like text
+        assert_eq!(key::parse(&[0xff, 0, 1]).unwrap().as_bytes(), [0xff, 0, 1]);
+        assert_eq!(key::parse(b"1234ab").unwrap().as_bytes(), b"1234ab");
+        assert!(matches!(key::parse(b"0x123"), Err(EnardError::InvalidHex)));
+        assert!(matches!(
+            key::parse(b"base64:!!"),
+            Err(EnardError::InvalidBase64)
+        ));
+    }
+
+    #[test]
+    fn key_and_nonce_types() {
+        let key: EnardKey = "0x0a0B".parse().unwrap();
+        assert_eq!(key.as_bytes(), &[0x0a, 0x0b]);
+        assert!(matches!(
+            "abc".parse::<EnardKey>(),
+            Err(EnardError::InvalidHex)
+        ));
+        assert!(matches!(
+            "zz".parse::<EnardNonce>(),
+            Err(EnardError::InvalidHex)
+        ));
+        let meta = ChaCha12::factory().get_meta(b"").unwrap();
+        assert!(EnardKey::from(KEY1).check_size(&meta).is_ok());
+        assert!(matches!(
+            key.check_size(&meta),
+            Err(EnardError::KeyLengthMismatch {
+                expected: 32,
+                got: 2
+            })
+        ));
+        let nonce = EnardNonce::from(NONCE);
+        assert!(nonce.check_size(&meta).is_ok());
+
+        let mut wr = EnardWriter::new(
+            Cursor::new(Vec::new()),
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            EnardKey::from(KEY1),
+            nonce,
+            MetaMap::new(),
+        )
+        .unwrap();
+        wr.write_complete(&b"hello"[..]).unwrap();
+        let buf = wr.into_inner().into_inner();
+        let rd = EnardReader::new_boxed(Cursor::new(&buf), EnardKey::from(KEY1)).unwrap();
+        assert_eq!(read_all(rd), b"hello");
+    }
+
+    #[test]
+    fn parse_borrowed_header() {
+        for version in [FormatVersion::V1, FormatVersion::V2] {
+            let mut wr = EnardWriter::new(
+                Cursor::new(Vec::new()),
+                BoxDynCipher::factory(),
+                ChaCha12::name(),
+                KEY1,
+                NONCE.into(),
+                MetaMap::new(),
+            )
+            .unwrap()
+            .with_version(version)
+            .with_content_type("text/plain")
+            .with_compression("none");
+            wr.write_complete(&b"hello"[..]).unwrap();
+            let buf = wr.into_inner().into_inner();
+
+            let header = format::parse_header(&buf).unwrap();
+            let rd = EnardReader::new_boxed(Cursor::new(&buf), KEY1).unwrap();
+            assert_eq!(header.version, version);
+            assert_eq!(header.cipher, ChaCha12::name());
+            assert_eq!(header.iv, NONCE);
+            assert_eq!(header.data_start, rd.data_start());
+            assert_eq!(header.data_size, 5);
+            let meta: Vec<_> = header.meta().collect();
+            let expected: Vec<_> = rd
+                .meta()
+                .iter()
+                .map(|(k, v)| (k.as_slice(), v.as_slice()))
+                .collect();
+            assert_eq!(meta, expected);
+            assert_eq!(header.get_meta(meta::COMPRESSION), Some(&b"none"[..]));
+
+            let short = &buf[..header.data_start as usize - 1];
+            assert!(matches!(
+                format::parse_header(short),
+                Err(EnardError::Truncated { expected, .. }) if expected == header.data_start
+            ));
+            assert!(matches!(
+                format::parse_header(&buf[..4]),
+                Err(EnardError::Truncated { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn batch_open_all() {
+        let dir = std::env::temp_dir().join("enard_batch_open_all");
+        fs::create_dir_all(&dir).unwrap();
+        let mut paths = Vec::new();
+        for i in 0..4u8 {
+            let path = dir.join(format!("{}.enard", i));
+            let mut wr = EnardWriter::new(
+                fs::File::create(&path).unwrap(),
+                BoxDynCipher::factory(),
+                ChaCha12::name(),
+                KEY1,
+                NONCE.into(),
+                MetaMap::new(),
+            )
+            .unwrap();
+            wr.write_complete(&[i; 100][..]).unwrap();
+            paths.push(path);
+        }
+        // Corrupt one of them
+        let mut bad = fs::read(&paths[2]).unwrap();
+        *bad.last_mut().unwrap() ^= 1;
+        fs::write(&paths[2], bad).unwrap();
+        paths.push(dir.join("missing.enard"));
+
+        let check = |results: Vec<Result<EnardReader<fs::File, BoxDynCipher>, EnardError>>| {
+            assert_eq!(results.len(), 5);
+            for (i, rd) in results.into_iter().enumerate() {
+                match i {
+                    2 => assert!(matches!(rd, Err(EnardError::MacError(_)))),
+                    4 => assert!(matches!(rd, Err(EnardError::IO(_)))),
+                    _ => {
+                        let mut buf = Vec::new();
+                        rd.unwrap().read_to_end(&mut buf).unwrap();
+                        assert_eq!(buf, [i as u8; 100]);
+                    }
+                }
+            }
+        };
+        let opts = batch::BatchOptions::new().readahead(64);
+        check(batch::open_all(&paths, KEY1, &opts).collect());
+        #[cfg(feature = "parallel")]
+        check(batch::par_open_all(&paths, KEY1, &opts).collect());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn streaming_footer() {
+        let data = vec![0x5au8; 3 * KB];
+        for version in [FormatVersion::V1, FormatVersion::V2] {
+            // Vec<u8> can't seek, so the sizes have to go in the footer
+            let mut wr = EnardWriter::new(
+                Vec::new(),
+                BoxDynCipher::factory(),
+                ChaCha12::name(),
+                KEY1,
+                NONCE.into(),
+                MetaMap::new(),
+            )
+            .unwrap()
+            .with_version(version);
+            wr.write_complete_streaming(data.as_slice()).unwrap();
+            let buf = wr.into_inner();
+            let sizes = version.header_start() - 8;
+            assert!(buf[8..sizes + 8].iter().all(|&b| b == 0));
+
+            let mut rd = EnardReader::new_boxed(Cursor::new(&buf), KEY1).unwrap();
+            assert_eq!(rd.data_size(), data.len() as u64);
+            let mut out = Vec::new();
+            rd.read_to_end(&mut out).unwrap();
+            assert_eq!(out, data);
+            assert!(verify(Cursor::new(&buf), KEY1).is_err());
+            assert!(format::parse_header(&buf).is_err());
+
+            // Growing the header in the editor writes the real sizes
+            let mut editor =
+                EnardEditor::open(Cursor::new(buf), BoxDynCipher::factory(), KEY1).unwrap();
+            editor.meta_mut().insert(vec![b'x'; 64], vec![1; 64]);
+            let buf = editor.finish().unwrap().into_inner();
+            let report = verify(Cursor::new(&buf), KEY1).unwrap();
+            assert_eq!(report.data_size, data.len() as u64);
+        }
+    }
+
+    #[test]
+    fn split_keys() {
+        let data = vec![0x11u8; 2 * KB];
+        let mut wr = EnardWriter::builder(Cursor::new(Vec::new()))
+            .key(KEY1)
+            .iv(NONCE)
+            .split_keys()
+            .build()
+            .unwrap();
+        wr.write_complete(data.as_slice()).unwrap();
+        let buf = wr.into_inner().into_inner();
+
+        let mut rd = EnardReader::new_boxed(Cursor::new(&buf), KEY1).unwrap();
+        assert_eq!(rd.version(), FormatVersion::V2);
+        let mut out = Vec::new();
+        rd.read_to_end(&mut out).unwrap();
+        assert_eq!(out, data);
+        verify(Cursor::new(&buf), KEY1).unwrap();
+
+        // The MAC key alone is enough to verify, but it isn't the encryption key
+        let mac_key = kdf::mac_key(rd.meta(), &KEY1).unwrap();
+        assert_ne!(mac_key.as_slice(), KEY1);
+        Verifier::with_mac_key(Cursor::new(&buf), &mac_key)
+            .and_then(|v| v.verify())
+            .unwrap();
+        assert!(matches!(
+            EnardReader::new_boxed(Cursor::new(&buf), mac_key.as_slice()),
+            Err(EnardError::MacError(_))
+        ));
+
+        // v1 can't record it
+        let mut wr = EnardWriter::builder(Cursor::new(Vec::new()))
+            .key(KEY1)
+            .iv(NONCE)
+            .split_keys()
+            .version(FormatVersion::V1)
+            .build()
+            .unwrap();
+        assert!(wr.write_header().is_err());
+    }
+
+    #[cfg(feature = "random")]
+    #[test]
+    fn writer_random_iv() {
+        let (mut wr, iv) = EnardWriter::new_with_random_iv(
+            Cursor::new(Vec::new()),
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            MetaMap::new(),
+            &mut rand::rngs::OsRng,
+        )
+        .unwrap();
+        assert_eq!(iv.as_bytes().len(), 12);
+        wr.write_complete(&b"hello"[..]).unwrap();
+        let buf = wr.into_inner().into_inner();
+        let header = format::parse_header(&buf).unwrap();
+        assert_eq!(header.iv, iv.as_bytes());
+
+        let err = EnardWriter::new_with_random_iv(
+            Cursor::new(Vec::new()),
+            BoxDynCipher::factory(),
+            b"Rot13",
+            KEY1,
+            MetaMap::new(),
+            &mut rand::rngs::OsRng,
+        );
+        assert!(matches!(err, Err(EnardError::UnsupportedEncryption { .. })));
+    }
+
+    #[test]
+    fn length_mismatch_errors() {
+        let factory = BoxDynCipher::factory();
+        factory.validate(ChaCha12::name(), 32, 12).unwrap();
+        assert!(matches!(
+            factory.validate(ChaCha12::name(), 16, 12),
+            Err(EnardError::KeyLengthMismatch {
+                expected: 32,
+                got: 16
+            })
+        ));
+        let new_writer = |key: &[u8], iv: &[u8]| {
+            EnardWriter::new(
+                Cursor::new(Vec::new()),
+                BoxDynCipher::factory(),
+                ChaCha12::name(),
+                key,
+                iv.into(),
+                MetaMap::new(),
+            )
+        };
+        assert!(matches!(
+            new_writer(&KEY1, &[0u8; 8]),
+            Err(EnardError::IvLengthMismatch {
+                expected: 12,
+                got: 8
+            })
+        ));
+
+        let mut wr = new_writer(&KEY1, &NONCE).unwrap();
+        wr.write_complete(&b"hello"[..]).unwrap();
+        let buf = wr.into_inner().into_inner();
+        assert!(matches!(
+            EnardReader::new_boxed(Cursor::new(&buf), &KEY1[..16]),
+            Err(EnardError::KeyLengthMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn parse_limits() {
+        use crate::limits::ParseLimits;
+
+        let mut meta = MetaMap::new();
+        for i in 0..3u8 {
+            meta.insert(vec![b'k', i], vec![i; 100]);
+        }
+        let mut wr = EnardWriter::new(
+            Cursor::new(Vec::new()),
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            meta,
+        )
+        .unwrap();
+        wr.write_complete(&[0u8; KB][..]).unwrap();
+        let buf = wr.into_inner().into_inner();
+        let open = |limits: ParseLimits| {
+            EnardReader::new_with_limits(Cursor::new(&buf), BoxDynCipher::factory(), KEY1, limits)
+        };
+        open(ParseLimits::default()).unwrap();
+        for limits in [
+            ParseLimits {
+                max_meta_entries: 2,
+                ..ParseLimits::default()
+            },
+            ParseLimits {
+                max_meta_value_size: 99,
+                ..ParseLimits::default()
+            },
+            ParseLimits {
+                max_data_size: 1000,
+                ..ParseLimits::default()
+            },
+            ParseLimits {
+                max_header_size: 64,
+                ..ParseLimits::default()
+            },
+        ] {
+            let err = open(limits).err().unwrap();
+            assert!(matches!(err.root_cause(), EnardError::BlockTooLarge { .. }));
+        }
+
+        // A v2 header claiming 2^62 entries fails straight away
+        let mut hostile = crate::core::MAGIC.to_vec();
+        hostile.extend_from_slice(&2u16.to_le_bytes());
+        hostile.extend_from_slice(&16u64.to_le_bytes());
+        hostile.extend_from_slice(&0u64.to_le_bytes());
+        let mut header = vec![0u8, 0];
+        varint::write_u(&mut header, 1 << 62).unwrap();
+        header.resize(16, 0);
+        hostile.extend_from_slice(&header);
+        hostile.extend_from_slice(&[0u8; 32]);
+        let err = EnardReader::new_boxed(Cursor::new(&hostile), KEY1)
+            .err()
+            .unwrap();
+        assert!(matches!(
+            &err,
+            EnardError::Parse { phase: ParsePhase::Meta, error, .. }
+                if matches!(**error, EnardError::BlockTooLarge { .. })
+        ));
+        assert_eq!(err.error_code(), ErrorCode::BlockTooLarge);
+    }
+
+    #[cfg(feature = "random")]
+    #[test]
+    fn oneshot_roundtrip() {
+        let mut meta = MetaMap::new();
+        meta.insert(b"slot".to_vec(), b"2".to_vec());
+        let file = encrypt_to_vec(b"save data", KEY1, b"ChaCha20", meta.clone()).unwrap();
+        let (data, read_meta) = decrypt_to_vec(&file, KEY1).unwrap();
+        assert_eq!(data, b"save data");
+        assert_eq!(read_meta, meta);
+        assert!(decrypt_to_vec(&file, [0x13u8; 32]).is_err());
+        assert!(encrypt_to_vec(b"", KEY1, b"Rot13", MetaMap::new()).is_err());
+    }
+
+    #[cfg(feature = "random")]
+    #[test]
+    fn ops_file_roundtrip() {
+        let dir = std::env::temp_dir().join("enard_ops_file_roundtrip");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("plain.txt"), b"asset data").unwrap();
+        let key = ops::load_key(
+            None,
+            Some("base64:QkJCQkJCQkJCQkJCQkJCQkJCQkJCQkJCQkJCQkJCQkI="),
+        )
+        .unwrap();
+        assert_eq!(key.as_bytes(), KEY1);
+        let enc = dir.join("nested/plain.enard");
+        ops::encrypt_file(
+            dir.join("plain.txt"),
+            &enc,
+            b"ChaCha8",
+            &key,
+            MetaMap::new(),
+        )
+        .unwrap();
+        assert_eq!(
+            ops::decrypt_file(&enc, dir.join("out.txt"), &key).unwrap(),
+            10
+        );
+        assert_eq!(fs::read(dir.join("out.txt")).unwrap(), b"asset data");
+        let result = ops::encrypt_file(
+            dir.join("plain.txt"),
+            &enc,
+            b"ChaCha8",
+            [1u8; 4],
+            MetaMap::new(),
+        );
+        assert!(matches!(result, Err(EnardError::KeyLengthMismatch { .. })));
+    }
+
+    #[cfg(feature = "random")]
+    #[test]
+    fn atomic_save_file() {
+        let dir = std::env::temp_dir().join("enard_atomic_save_file");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("save.enard");
+        let mut meta = MetaMap::new();
+        meta.insert(b"slot".to_vec(), b"1".to_vec());
+        crate::fs::write_atomic(&path, b"first", KEY1, meta.clone()).unwrap();
+        crate::fs::write_atomic(&path, b"second", KEY1, meta.clone()).unwrap();
+        let (data, read_meta) = crate::fs::read(&path, KEY1).unwrap();
+        assert_eq!(data, b"second");
+        assert_eq!(read_meta, meta);
+        // A failed write leaves the old file alone and cleans up
+        let bad_meta = [(b"enard.bogus".to_vec(), vec![])].into_iter().collect();
+        assert!(crate::fs::write_atomic(&path, b"third", KEY1, bad_meta).is_err());
+        assert_eq!(crate::fs::read(&path, KEY1).unwrap().0, b"second");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn varint_roundtrip() {
+        for &v in &[0u64, 1, 63, 64, 127, 128, 300, 1 << 35, (1 << 63) - 1] {
+            let mut buf = Vec::new();
+            varint::write_u(&mut buf, v).unwrap();
+            assert_eq!(varint::read_u(buf.as_slice()).unwrap(), v);
+        }
+        assert!(varint::write_u(Vec::new(), 1 << 63).is_err());
+        let limit = 1i64 << 62;
+        for &v in &[
+            0i64,
+            1,
+            -1,
+            63,
+            64,
+            -64,
+            -65,
+            1000,
+            -1000,
+            limit - 1,
+            -limit,
+        ] {
+            let mut buf = Vec::new();
+            varint::write_i(&mut buf, v).unwrap();
+            assert_eq!(varint::read_i(buf.as_slice()).unwrap(), v, "{}", v);
+        }
+        assert!(varint::write_i(Vec::new(), limit).is_err());
+        assert!(varint::write_i(Vec::new(), i64::MIN).is_err());
+        // Known encodings
+        let mut buf = Vec::new();
+        varint::write_i(&mut buf, -123456).unwrap();
+        assert_eq!(buf, [0xc0, 0xbb, 0x78]);
+        // Too many continuation bytes
+        assert!(varint::read_u(&[0xffu8; 10][..]).is_err());
+    }
+
+    #[test]
+    fn error_code_roundtrip() {
+        for (i, code) in ErrorCode::ALL.iter().enumerate() {
+            assert_eq!(code.code(), i as u32 + 1);
+            assert_eq!(ErrorCode::from_code(code.code()), Some(*code));
+        }
+        assert_eq!(ErrorCode::from_code(0), None);
+        let e = EnardError::UnsupportedVersion { version: 99 };
+        assert_eq!(
+            ErrorCode::from_code(e.code()),
+            Some(ErrorCode::UnsupportedVersion)
+        );
+    }
+
+    #[cfg(feature = "ffi")]
+    #[test]
+    fn ffi_roundtrip() {
+        use crate::ffi::*;
+        use std::ffi::CString;
+        use std::ptr;
+
+        let path = std::env::temp_dir().join("enard_ffi_roundtrip.enard");
+        let c_path = CString::new(path.to_str().unwrap()).unwrap();
+        let c_cipher = CString::new("ChaCha12").unwrap();
+        let data = vec![0x42u8; 5000];
+        unsafe {
+            let wr = enard_writer_create(
+                c_path.as_ptr(),
+                c_cipher.as_ptr(),
+                KEY1.as_ptr(),
+                KEY1.len(),
+                NONCE.as_ptr(),
+                NONCE.len(),
+            );
+            assert!(!wr.is_null());
+            assert_eq!(enard_writer_write(wr, data.as_ptr(), data.len()), 0);
+            assert_eq!(enard_writer_finish(wr), 0);
+
+            let rd = enard_reader_open(c_path.as_ptr(), KEY1.as_ptr(), KEY1.len());
+            assert!(!rd.is_null());
+            assert_eq!(enard_reader_seek(rd, 0, 2), data.len() as i64);
+            assert_eq!(enard_reader_seek(rd, 10, 0), 10);
+            let mut buf = vec![0u8; data.len()];
+            let n = enard_reader_read(rd, buf.as_mut_ptr(), buf.len());
+            assert_eq!(n, data.len() as isize - 10);
+            enard_reader_close(rd);
+
+            let file = fs::read(&path).unwrap();
+            let rd = enard_reader_open_memory(file.as_ptr(), file.len(), KEY1.as_ptr(), KEY1.len());
+            drop(file);
+            assert!(!rd.is_null());
+            assert_eq!(enard_reader_data_size(rd), data.len() as i64);
+            let n = enard_reader_read(rd, buf.as_mut_ptr(), buf.len());
+            assert_eq!(n, data.len() as isize);
+            enard_reader_close(rd);
+
+            let rd = enard_reader_open(c_path.as_ptr(), NONCE.as_ptr(), NONCE.len());
+            assert!(rd.is_null());
+            assert_eq!(enard_last_error(), ErrorCode::KeyLengthMismatch.code());
+            let wrong_key = [0x13u8; 32];
+            let rd = enard_reader_open(c_path.as_ptr(), wrong_key.as_ptr(), wrong_key.len());
+            assert!(rd.is_null());
+            assert_eq!(enard_last_error(), ErrorCode::MacError.code());
+            assert_eq!(enard_reader_data_size(ptr::null()), -1);
+            assert_eq!(enard_last_error(), ErrorCode::InvalidArgument.code());
+        }
+        fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(feature = "remote")]
+    #[test]
+    fn read_over_range_requests() {
+        use crate::remote::*;
+        use std::io;
+
+        struct MemFetch(Vec<u8>, usize);
+        impl RangeFetch for MemFetch {
+            fn content_length(&mut self) -> io::Result<u64> {
+                Ok(self.0.len() as u64)
+            }
+            fn fetch(&mut self, start: u64, buf: &mut [u8]) -> io::Result<usize> {
+                self.1 += 1;
+                let start = start as usize;
+                let n = buf.len().min(self.0.len() - start);
+                buf[..n].copy_from_slice(&self.0[start..start + n]);
+                Ok(n)
+            }
+        }
+
+        let src_buf = fs::read("./arrow_up.zip").unwrap();
+        let mut wr = EnardWriter::new(
+            Cursor::new(Vec::new()),
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            MetaMap::new(),
+        )
+        .unwrap();
+        wr.write_complete(src_buf.as_slice()).unwrap();
+        let buf = wr.into_inner().into_inner();
+
+        let remote = HttpRangeReader::new(MemFetch(buf, 0))
+            .unwrap()
+            .with_block_size(16 * KB);
+        let mut rd = EnardReader::new_boxed(remote, KEY1).unwrap();
+        compare_bufs(&read_all(&mut rd), &src_buf);
+        let fetch = rd.into_inner().into_inner();
+        // Blocks are cached, so there shouldn't be a request per read
+        assert!(fetch.1 < 3 * (fetch.0.len() / (16 * KB) + 1));
+    }
+
+    #[test]
+    fn verify_without_decrypting() {
+        let mut meta = MetaMap::new();
+        meta.insert(b"name".to_vec(), b"value".to_vec());
+        let mut buf = Vec::new();
+        EnardWriter::new(
+            Cursor::new(&mut buf),
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            meta.clone(),
+        )
+        .unwrap()
+        .write_complete(&[0x42u8; 1000][..])
+        .unwrap();
+
+        let report = verify(Cursor::new(&buf), KEY1).unwrap();
+        assert_eq!(report.data_size, 1000);
+        assert_eq!(report.cipher, ChaCha12::name());
+        assert_eq!(report.meta, meta);
+
+        // Chop off the end of the tag
+        let n = buf.len();
+        let truncated = &buf[..n - 10];
+        assert!(matches!(
+            verify(Cursor::new(truncated), KEY1),
+            Err(EnardError::Truncated { expected, actual }) if expected == n as u64 && actual == n as u64 - 10
+        ));
+        assert!(matches!(
+            EnardReader::new_boxed(Cursor::new(truncated), KEY1),
+            Err(EnardError::Truncated { .. })
+        ));
+
+        // Flip a bit in the data and make sure verification fails
+        let n = buf.len();
+        buf[n - 100] ^= 1;
+        assert!(matches!(
+            verify(Cursor::new(&buf), KEY1),
+            Err(EnardError::MacError(_))
+        ));
+    }
+
+    #[test]
+    fn decrypt_writer() {
+        use std::io::Write;
+        let data: Vec<u8> = (0..10 * KB).map(|i| i as u8).collect();
+        let mut ext = MetaMap::new();
+        ext.insert(b"ext".to_vec(), vec![7u8; 100]);
+        let new_writer = || {
+            EnardWriter::new(
+                Cursor::new(Vec::new()),
+                BoxDynCipher::factory(),
+                ChaCha12::name(),
+                KEY1,
+                NONCE.into(),
+                MetaMap::new(),
+            )
+            .unwrap()
+            .with_extended_meta(&ext)
+        };
+        let mut wr = new_writer();
+        wr.write_complete(data.as_slice()).unwrap();
+        let file = wr.into_inner().into_inner();
+        let mut wr = new_writer();
+        wr.write_complete_streaming(data.as_slice()).unwrap();
+        let streamed = wr.into_inner().into_inner();
+
+        let decrypt = |file: &[u8]| {
+            let mut dec = EnardDecryptWriter::new_boxed(Vec::new(), KEY1);
+            for chunk in file.chunks(7) {
+                dec.write_all(chunk).unwrap();
+            }
+            dec.finish()
+        };
+        for file in [&file, &streamed] {
+            compare_bufs(&decrypt(file).unwrap(), &data);
+            let mut tampered = file.clone();
+            tampered[200] ^= 1;
+            assert!(matches!(decrypt(&tampered), Err(EnardError::MacError(_))));
+        }
+        // The footer of a streamed file is missing, so only the rest can tell
+        assert!(matches!(
+            decrypt(&file[..file.len() - 40]),
+            Err(EnardError::Truncated { expected, actual }) if expected == file.len() as u64 && actual + 40 == expected
+        ));
+        assert!(matches!(
+            decrypt(&streamed[..streamed.len() - 40]),
+            Err(EnardError::InvalidMagic { .. })
+        ));
+    }
+
+    #[test]
+    fn parse_error_offsets() {
+        // Embed the file 100 bytes in, so the offsets are relative to the reader
+        let mut out = Cursor::new(vec![0u8; 100]);
+        out.set_position(100);
+        let mut wr = EnardWriter::new(
+            out,
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            MetaMap::new(),
+        )
+        .unwrap();
+        wr.write_complete(&b"hello"[..]).unwrap();
+        let file = wr.into_inner().into_inner();
+        let open = |file: &[u8]| {
+            let mut rd = Cursor::new(file);
+            rd.set_position(100);
+            EnardReader::new_boxed(rd, KEY1).err().unwrap()
+        };
+
+        let mut bad = file.clone();
+        bad[101] ^= 1;
+        let err = open(&bad);
+        assert!(matches!(
+            err,
+            EnardError::Parse {
+                phase: ParsePhase::Magic,
+                offset: 100,
+                ..
+            }
+        ));
+        assert!(matches!(err.root_cause(), EnardError::InvalidMagic { .. }));
+        assert!(err.to_string().ends_with("(in the magic at offset 100)"));
+
+        let mut bad = file.clone();
+        bad[106] = 9;
+        assert!(matches!(
+            open(&bad),
+            EnardError::Parse {
+                phase: ParsePhase::Header,
+                offset: 106,
+                ..
+            }
+        ));
+        assert_eq!(open(&bad).error_code(), ErrorCode::UnsupportedVersion);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_header_info() {
+        let mut meta = MetaMap::new();
+        meta.insert(b"name".to_vec(), b"a.png".to_vec());
+        meta.insert(b"bin".to_vec(), vec![0, 0xff]);
+        let mut wr = EnardWriter::new(
+            Cursor::new(Vec::new()),
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            meta.clone(),
+        )
+        .unwrap();
+        wr.write_complete(&b"hello"[..]).unwrap();
+        let file = wr.into_inner().into_inner();
+
+        let info = format::parse_header(&file).unwrap().to_info();
+        assert_eq!(info.meta, meta);
+        let json = serde_json::to_value(&info).unwrap();
+        assert_eq!(json["version"], 1);
+        assert_eq!(json["cipher"], "ChaCha12");
+        assert_eq!(json["meta"]["name"], "YS5wbmc=");
+        assert_eq!(json["meta"]["bin"], "AP8=");
+        let back: format::HeaderInfo = serde_json::from_value(json).unwrap();
+        assert_eq!(back, info);
+
+        let cipher = BoxDynCipher::factory().get_meta(ChaCha12::name()).unwrap();
+        let json = serde_json::to_string(&cipher).unwrap();
+        assert_eq!(json, r#"{"name":"ChaCha12","key_size":32,"iv_size":12}"#);
+        assert_eq!(serde_json::from_str::<CipherMeta>(&json).unwrap(), cipher);
+        let wrong = r#"{"name":"ChaCha12","key_size":16,"iv_size":12}"#;
+        assert!(serde_json::from_str::<CipherMeta>(wrong).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn positioned_file() {
+        use crate::stream::PositionedFile;
+        let path = std::env::temp_dir().join("enard_positioned_file.enard");
+        let data: Vec<u8> = (0..4 * KB).map(|i| (i % 251) as u8).collect();
+        let mut wr = EnardWriter::new(
+            fs::File::create(&path).unwrap(),
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            MetaMap::new(),
+        )
+        .unwrap();
+        wr.write_complete(data.as_slice()).unwrap();
+        drop(wr);
+
+        // Two readers sharing one file don't disturb each other
+        let file = fs::File::open(&path).unwrap();
+        let mut rd1 = EnardReader::new_boxed(PositionedFile::new(&file), KEY1).unwrap();
+        let mut rd2 = EnardReader::new_boxed(PositionedFile::new(&file), KEY1).unwrap();
+        let mut buf = [0u8; 100];
+        rd1.seek(SeekFrom::Start(1000)).unwrap();
+        rd2.read_exact(&mut buf).unwrap();
+        assert_eq!(buf[..], data[..100]);
+        rd1.read_exact(&mut buf).unwrap();
+        assert_eq!(buf[..], data[1000..1100]);
+        compare_bufs(&read_all(&mut rd2), &data[100..]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn sector_aligned_reads() {
+        use crate::stream::{AlignedRead, SECTOR_SIZE};
+        use std::io::{Seek, SeekFrom};
+
+        /// Fails any read an unbuffered file would reject
+        struct Unbuffered(Cursor<Vec<u8>>);
+        impl Read for Unbuffered {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                assert_eq!(self.0.position() % SECTOR_SIZE as u64, 0);
+                assert_eq!(buf.len() % SECTOR_SIZE, 0);
+                assert_eq!(buf.as_ptr() as usize % SECTOR_SIZE, 0);
+                self.0.read(buf)
+            }
+        }
+        impl Seek for Unbuffered {
+            fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+                self.0.seek(pos)
+            }
+        }
+
+        let data: Vec<u8> = (0..20 * KB).map(|i| (i % 251) as u8).collect();
+        let new_writer = || {
+            EnardWriter::new(
+                Cursor::new(Vec::new()),
+                BoxDynCipher::factory(),
+                ChaCha12::name(),
+                KEY1,
+                NONCE.into(),
+                MetaMap::new(),
+            )
+            .unwrap()
+        };
+        let mut wr = new_writer().with_data_alignment(SECTOR_SIZE);
+        wr.write_complete(data.as_slice()).unwrap();
+        let file = wr.into_inner().into_inner();
+        let header = format::parse_header(&file).unwrap();
+        assert_eq!(header.data_start % SECTOR_SIZE as u64, 0);
+
+        let inner = AlignedRead::new(Unbuffered(Cursor::new(file)));
+        let mut rd = EnardReader::new_boxed(inner, KEY1).unwrap();
+        rd.seek(SeekFrom::Start(10_000)).unwrap();
+        let mut buf = [0u8; 100];
+        rd.read_exact(&mut buf).unwrap();
+        assert_eq!(buf[..], data[10_000..10_100]);
+        rd.rewind().unwrap();
+        compare_bufs(&read_all(&mut rd), &data);
+
+        for alignment in [4, 24, 2 * SECTOR_SIZE] {
+            let mut wr = new_writer().with_data_alignment(alignment);
+            assert!(wr.write_header().is_err());
+            wr.abort().unwrap();
+        }
+    }
+
+    #[test]
+    fn data_alignment() {
+        let data = [0x5au8; 1000];
+        for alignment in [16, 64, 4096] {
+            let mut wr = EnardWriter::builder(Cursor::new(Vec::new()))
+                .key(KEY1)
+                .iv(NONCE)
+                .data_alignment(alignment)
+                .build()
+                .unwrap();
+            wr.write_complete(&data[..]).unwrap();
+            let file = wr.into_inner().into_inner();
+            let header = format::parse_header(&file).unwrap();
+            assert_eq!(header.data_start % alignment as u64, 0);
+
+            // Growing the header keeps the alignment
+            let mut editor =
+                EnardEditor::open(Cursor::new(file), BoxDynCipher::factory(), KEY1).unwrap();
+            editor
+                .meta_mut()
+                .insert(b"big".to_vec(), vec![1u8; alignment + 100]);
+            let file = editor.finish().unwrap().into_inner();
+            let header = format::parse_header(&file).unwrap();
+            assert_eq!(header.data_start % alignment as u64, 0);
+            let rd = EnardReader::new_boxed(Cursor::new(file), KEY1).unwrap();
+            compare_bufs(&read_all(rd), &data);
+        }
+    }
+
+    #[test]
+    fn split_file() {
+        use crate::split::{part_path, SplitFile};
+        let dir = std::env::temp_dir().join("enard_split_file");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let template = dir.join("pack.enard.{}").to_str().unwrap().to_string();
+        let write = |data: &[u8]| {
+            let mut wr = EnardWriter::new_split(
+                &template,
+                1000,
+                BoxDynCipher::factory(),
+                ChaCha12::name(),
+                KEY1,
+                NONCE.into(),
+                MetaMap::new(),
+            )
+            .unwrap();
+            wr.write_complete(data).unwrap();
+        };
+        let data: Vec<u8> = (0..4 * KB).map(|i| (i % 251) as u8).collect();
+        write(&data);
+        let file = SplitFile::open(&template).unwrap();
+        assert_eq!(file.part_count(), 5);
+        assert!(file.len() > data.len() as u64);
+        for i in 0..4 {
+            assert_eq!(fs::metadata(part_path(&template, i)).unwrap().len(), 1000);
+        }
+
+        let mut rd = EnardReader::new_boxed(file, KEY1).unwrap();
+        let mut buf = [0u8; 100];
+        rd.seek(SeekFrom::Start(1950)).unwrap();
+        rd.read_exact(&mut buf).unwrap();
+        assert_eq!(buf[..], data[1950..2050]);
+        rd.seek(SeekFrom::Start(0)).unwrap();
+        compare_bufs(&read_all(&mut rd), &data);
+
+        // Writing a smaller file removes the old parts
+        write(&data[..500]);
+        let file = SplitFile::open(&template).unwrap();
+        assert_eq!(file.part_count(), 1);
+        let mut rd = EnardReader::new_boxed(file, KEY1).unwrap();
+        compare_bufs(&read_all(&mut rd), &data[..500]);
+        assert!(SplitFile::create("no-template.enard", 1000).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn asset_index() {
+        use crate::index::{Index, IndexBuilder, IndexEntry};
+        use std::io::Write;
+        let assets: [(&str, &[u8]); 3] =
+            [("a.png", b"png data"), ("b.ogg", b""), ("c.txt", b"text")];
+        let mut builder = IndexBuilder::new();
+        for (name, data) in assets.iter() {
+            builder.push(name, data.len() as u64).unwrap();
+        }
+        assert!(builder.push("a.png", 1).is_err());
+        let index = builder.build();
+        let mut wr = EnardWriter::new(
+            Cursor::new(Vec::new()),
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            MetaMap::new(),
+        )
+        .unwrap()
+        .with_plaintext_hash()
+        .with_index(&index);
+        wr.write_header().unwrap();
+        for (_, data) in assets.iter() {
+            wr.write_all(data).unwrap();
+        }
+        wr.finish().unwrap();
+        let buf = wr.into_inner().into_inner();
+
+        let mut rd = EnardReader::new_boxed(Cursor::new(buf), KEY1).unwrap();
+        assert!(rd.plaintext_hash().unwrap().is_some());
+        let read_index = rd.open_index().unwrap().unwrap();
+        assert_eq!(read_index, index);
+        let entry = read_index.get("c.txt").unwrap();
+        assert_eq!(entry, IndexEntry { offset: 8, len: 4 });
+        let mut sub = rd.sub_reader(entry.offset, entry.len).unwrap();
+        assert_eq!(read_all(&mut sub), b"text");
+        let names: Vec<&str> = read_index.iter().map(|(name, _)| name).collect();
+        assert_eq!(names, ["a.png", "b.ogg", "c.txt"]);
+
+        // Entries past the end of the payload are rejected
+        let result = Index::decode(&index.encode(), 11);
+        assert!(matches!(
+            result,
+            Err(EnardError::BlockTooLarge {
+                size: 12,
+                limit: 11
+            })
+        ));
+    }
+
+    #[test]
+    fn reader_pool() {
+        let data: Vec<u8> = (0..64 * KB).map(|i| (i % 251) as u8).collect();
+        let mut wr = EnardWriter::new(
+            Cursor::new(Vec::new()),
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            MetaMap::new(),
+        )
+        .unwrap()
+        .with_plaintext_hash();
+        wr.write_complete(data.as_slice()).unwrap();
+        let buf = wr.into_inner().into_inner();
+
+        let pool = std::sync::Arc::new(EnardReaderPool::new_boxed(Cursor::new(buf), KEY1).unwrap());
+        assert_eq!(pool.data_size(), data.len() as u64);
+        let threads: Vec<_> = (0..4)
+            .map(|i| {
+                let pool = pool.clone();
+                std::thread::spawn(move || {
+                    let mut rd = pool.reader().unwrap();
+                    let start = i * 16 * KB;
+                    rd.seek(SeekFrom::Start(start as u64)).unwrap();
+                    let mut chunk = vec![0u8; 16 * KB];
+                    rd.read_exact(&mut chunk).unwrap();
+                    chunk
+                })
+            })
+            .collect();
+        for (i, thread) in threads.into_iter().enumerate() {
+            compare_bufs(
+                &thread.join().unwrap(),
+                &data[i * 16 * KB..(i + 1) * 16 * KB],
+            );
+        }
+        let mut rd = pool.reader().unwrap();
+        assert!(rd.plaintext_hash().unwrap().is_some());
+        compare_bufs(&read_all(&mut rd), &data);
+
+        let result = EnardReaderPool::new_boxed(Cursor::new(vec![0u8; 100]), KEY1);
+        assert!(matches!(
+            result.map(|_| ()).unwrap_err().root_cause(),
+            EnardError::InvalidMagic { .. }
+        ));
+    }
+
+    #[test]
+    fn save_restore_position() {
+        let data: Vec<u8> = (0..8 * KB).map(|i| (i % 251) as u8).collect();
+        let mut wr = EnardWriter::new(
+            Cursor::new(Vec::new()),
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            MetaMap::new(),
+        )
+        .unwrap()
+        .with_key_check();
+        wr.write_complete(data.as_slice()).unwrap();
+        let buf = wr.into_inner().into_inner();
+
+        let mut rd = EnardReader::new_boxed(Cursor::new(buf.clone()), KEY1)
+            .unwrap()
+            .with_readahead(KB);
+        let mut chunk = vec![0u8; 3000];
+        rd.read_exact(&mut chunk).unwrap();
+        let saved = rd.save_position();
+        assert_eq!(saved.position(), 3000);
+        drop(rd);
+
+        let mut rd =
+            EnardReader::restore_position_boxed(Cursor::new(buf.clone()), KEY1, &saved).unwrap();
+        compare_bufs(&read_all(&mut rd), &data[3000..]);
+        rd.seek(SeekFrom::Start(0)).unwrap();
+        compare_bufs(&read_all(&mut rd), &data);
+
+        let result =
+            EnardReader::restore_position_boxed(Cursor::new(buf.clone()), [1u8; 32], &saved);
+        assert!(matches!(result, Err(EnardError::WrongKey)));
+        let truncated = Cursor::new(buf[..buf.len() - 1].to_vec());
+        let result = EnardReader::restore_position_boxed(truncated, KEY1, &saved);
+        assert!(matches!(result, Err(EnardError::Truncated { .. })));
+    }
+
+    #[test]
+    fn obfuscated_header() {
+        use std::io::Write;
+        let write = |streaming: bool| {
+            let mut meta = MetaMap::new();
+            meta.insert(b"name".to_vec(), b"secret-level.dat".to_vec());
+            let mut wr = EnardWriter::new(
+                Cursor::new(Vec::new()),
+                BoxDynCipher::factory(),
+                ChaCha12::name(),
+                KEY1,
+                NONCE.into(),
+                meta,
+            )
+            .unwrap()
+            .with_obfuscated_header();
+            if !streaming {
+                wr = wr.with_data_alignment(64);
+            }
+            wr.write_header().unwrap();
+            wr.write_all(b"level data").unwrap();
+            if streaming {
+                wr.finish_streaming().unwrap();
+            } else {
+                wr.finish().unwrap();
+            }
+            wr.into_inner().into_inner()
+        };
+        let buf = write(false);
+        let contains = |needle: &[u8]| buf.windows(needle.len()).any(|w| w == needle);
+        assert!(!contains(b"ChaCha12") && !contains(b"secret-level.dat"));
+        assert!(format::parse_header(&buf).is_err());
+
+        let mut rd = EnardReader::new_boxed(Cursor::new(buf.clone()), KEY1).unwrap();
+        assert_eq!(rd.meta().get(&b"name"[..]).unwrap(), b"secret-level.dat");
+        assert_eq!(rd.data_start() % 64, 0);
+        assert_eq!(read_all(&mut rd), b"level data");
+        assert!(EnardReader::new_boxed(Cursor::new(buf.clone()), [1u8; 32]).is_err());
+
+        for file in [buf.clone(), write(true)] {
+            let mut dec = EnardDecryptWriter::new_boxed(Vec::new(), KEY1);
+            for chunk in file.chunks(7) {
+                dec.write_all(chunk).unwrap();
+            }
+            assert_eq!(dec.finish().unwrap(), b"level data");
+        }
+
+        // Rewriting the header would lose the obfuscation
+        let mut editor =
+            EnardEditor::open(Cursor::new(buf), BoxDynCipher::factory(), KEY1).unwrap();
+        editor.meta_mut().insert(b"patch".to_vec(), b"1".to_vec());
+        assert!(editor.finish().is_err());
+    }
+
+    #[test]
+    fn custom_magic() {
+        let data = b"stealthy data";
+        for magic in [&b"PAK1"[..], &b""[..]] {
+            let mut wr = EnardWriter::new(
+                Cursor::new(Vec::new()),
+                BoxDynCipher::factory(),
+                ChaCha12::name(),
+                KEY1,
+                NONCE.into(),
+                MetaMap::new(),
+            )
+            .unwrap()
+            .with_magic(magic)
+            .with_data_alignment(16);
+            wr.write_complete(&data[..]).unwrap();
+            let buf = wr.into_inner().into_inner();
+            assert!(buf.starts_with(magic));
+            assert!(!buf.windows(MAGIC.len()).any(|w| w == MAGIC));
+
+            let factory = BoxDynCipher::factory();
+            let mut rd =
+                EnardReader::new_with_magic(Cursor::new(buf.clone()), factory, KEY1, magic)
+                    .unwrap();
+            assert_eq!(rd.data_start() % 16, 0);
+            assert_eq!(read_all(&mut rd), data);
+            let result = EnardReader::new_boxed(Cursor::new(buf), KEY1);
+            assert!(matches!(
+                result.map(|_| ()).unwrap_err().root_cause(),
+                EnardError::InvalidMagic { .. }
+            ));
+        }
+    }
+
+    #[test]
+    fn aad() {
+        let data = b"level data";
+        let mut wr = EnardWriter::new(
+            Cursor::new(Vec::new()),
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            MetaMap::new(),
+        )
+        .unwrap()
+        .with_aad(b"build-1234/level1.pak");
+        wr.write_complete(&data[..]).unwrap();
+        let buf = wr.into_inner().into_inner();
+        assert!(!buf.windows(4).any(|w| w == b"1234"));
+
+        let open = |aad: &[u8]| {
+            let factory = BoxDynCipher::factory();
+            EnardReader::new_with_aad(Cursor::new(buf.clone()), factory, KEY1, aad)
+        };
+        let mut rd = open(b"build-1234/level1.pak").unwrap();
+        assert_eq!(read_all(&mut rd), data);
+        for aad in [&b"build-1235/level1.pak"[..], &b""[..]] {
+            let err = open(aad).map(|_| ()).unwrap_err();
+            assert!(matches!(err.root_cause(), EnardError::MacError(_)));
+        }
+        assert!(EnardReader::new_boxed(Cursor::new(buf.clone()), KEY1).is_err());
+        Verifier::new(Cursor::new(&buf), KEY1)
+            .unwrap()
+            .with_aad(b"build-1234/level1.pak")
+            .verify()
+            .unwrap();
+    }
+
+    #[test]
+    fn format_header_round_trip() {
+        use crate::format::{HeaderV1, HeaderV2};
+        let many: MetaMap = (0..255u32)
+            .map(|i| (i.to_le_bytes().to_vec(), vec![i as u8; i as usize]))
+            .collect();
+        let cases = [
+            (Vec::new(), Vec::new(), MetaMap::new()),
+            (b"ChaCha12".to_vec(), NONCE.to_vec(), MetaMap::new()),
+            (vec![b'x'; 255], vec![0xAB; 255], many),
+            (b"AES256CTR".to_vec(), vec![1; 16], {
+                let mut meta = MetaMap::new();
+                meta.insert(Vec::new(), vec![7; u16::MAX as usize]);
+                meta.insert(vec![0xFF; 255], Vec::new());
+                meta
+            }),
+        ];
+        for (cipher, iv, meta) in cases {
+            let v1 = HeaderV1 { cipher, iv, meta };
+            let mut buf = Vec::new();
+            v1.encode(&mut buf).unwrap();
+            let mut rd = &buf[..];
+            assert_eq!(HeaderV1::decode(&mut rd).unwrap(), v1);
+            assert!(rd.is_empty());
+
+            let v2 = HeaderV2 {
+                cipher: v1.cipher,
+                iv: v1.iv,
+                meta: v1.meta,
+            };
+            let mut buf = Vec::new();
+            v2.encode(&mut buf).unwrap();
+            let mut rd = &buf[..];
+            assert_eq!(HeaderV2::decode(&mut rd).unwrap(), v2);
+            assert!(rd.is_empty());
+        }
+
+        // Only v2 can hold larger fields
+        let mut large = HeaderV2 {
+            cipher: vec![b'x'; 256],
+            iv: Vec::new(),
+            meta: MetaMap::new(),
+        };
+        large
+            .meta
+            .insert(b"big".to_vec(), vec![0; u16::MAX as usize + 1]);
+        let mut buf = Vec::new();
+        large.encode(&mut buf).unwrap();
+        assert_eq!(HeaderV2::decode(&buf[..]).unwrap(), large);
+        let v1 = HeaderV1 {
+            cipher: large.cipher,
+            iv: Vec::new(),
+            meta: MetaMap::new(),
+        };
+        assert!(v1.encode(Vec::new()).is_err());
+        let v1 = HeaderV1 {
+            cipher: Vec::new(),
+            iv: Vec::new(),
+            meta: large.meta,
+        };
+        assert!(v1.encode(Vec::new()).is_err());
+        let too_many: MetaMap = (0..256u32)
+            .map(|i| (i.to_le_bytes().to_vec(), Vec::new()))
+            .collect();
+        let v1 = HeaderV1 {
+            cipher: Vec::new(),
+            iv: Vec::new(),
+            meta: too_many,
+        };
+        assert!(v1.encode(Vec::new()).is_err());
+
+        // The encoding is what the writer stores, followed by padding
+        for version in [FormatVersion::V1, FormatVersion::V2] {
+            let mut meta = MetaMap::new();
+            meta.insert(b"name".to_vec(), b"a.png".to_vec());
+            let mut wr = EnardWriter::new(
+                Cursor::new(Vec::new()),
+                BoxDynCipher::factory(),
+                ChaCha12::name(),
+                KEY1,
+                NONCE.into(),
+                meta.clone(),
+            )
+            .unwrap()
+            .with_version(version);
+            wr.write_complete(&b"data"[..]).unwrap();
+            let file = wr.into_inner().into_inner();
+            let header = &file[version.header_start()..];
+            let mut buf = Vec::new();
+            let cipher = ChaCha12::name().to_vec();
+            match version {
+                FormatVersion::V1 => {
+                    let h = HeaderV1 {
+                        cipher,
+                        iv: NONCE.to_vec(),
+                        meta,
+                    };
+                    h.encode(&mut buf).unwrap();
+                    assert_eq!(HeaderV1::decode(header).unwrap(), h);
+                }
+                FormatVersion::V2 => {
+                    let h = HeaderV2 {
+                        cipher,
+                        iv: NONCE.to_vec(),
+                        meta,
+                    };
+                    h.encode(&mut buf).unwrap();
+                    assert_eq!(HeaderV2::decode(header).unwrap(), h);
+                }
+            }
+            assert_eq!(&header[..buf.len()], &buf[..]);
+        }
+    }
+
+    #[test]
+    fn test_vectors() {
+        use crate::testvec::{generate, verify_test_vectors};
+        use sha2::{Digest, Sha256};
+        let mut vectors = generate().unwrap();
+        verify_test_vectors(&vectors).unwrap();
+        // Changing how files are written must be deliberate
+        let golden = [
+            (
+                "none-v1",
+                "72cffa82c81677a22b9416e2ddbbfbb51fd194347a4b481bac40e8d95502b607",
+            ),
+            (
+                "chacha12-v1",
+                "7d274a5153c82a02944213038397b3435054ff184f4814336cd4cb2a45176014",
+            ),
+            (
+                "chacha20-v2",
+                "ca0d36a71edc155c503b39081cd7a10497a34dd63ad7915f784d935999a8fe51",
+            ),
+        ];
+        for (name, expected) in golden {
+            // The ChaCha vectors need the chacha feature
+            if let Some(v) = vectors.iter().find(|v| v.name == name) {
+                let digest = Sha256::digest(&v.file);
+                let hex: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+                assert_eq!(hex, expected, "{}", name);
+            }
+        }
+
+        let last = vectors.last_mut().unwrap();
+        let n = last.file.len();
+        last.file[n - 1] ^= 1;
+        assert!(verify_test_vectors(&vectors).is_err());
+    }
+
+    /// Payload sizes around the cipher block size, and any size up to 40 KiB
+    fn payload() -> impl Strategy<Value = Vec<u8>> {
+        prop_oneof![
+            Just(0usize),
+            Just(1usize),
+            (1..4usize).prop_map(|n| 64 * n),
+            (1..4usize).prop_map(|n| 64 * n + 1),
+            0..40 * KB,
+            0..40 * KB,
+        ]
+        .prop_flat_map(|size| vec(any::<u8>(), size))
+    }
+
+    /// Metadata with short keys, and some keys of the largest size v1 allows
+    fn meta_entries() -> impl Strategy<Value = Vec<(Vec<u8>, Vec<u8>)>> {
+        let key = prop_oneof![3 => vec(any::<u8>(), 0..16), 1 => vec(any::<u8>(), 255)];
+        vec((key, vec(any::<u8>(), 0..300)), 0..8)
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(150))]
+
+        /// Random payloads, metadata and read patterns
+        #[test]
+        fn round_trip_properties(
+            cipher in select(vec![&b""[..], b"ChaCha8", b"ChaCha12", b"ChaCha20"]),
+            v2 in any::<bool>(),
+            data in payload(),
+            entries in meta_entries(),
+            alignment_shift in 0..10u32,
+            ext_len in option::of(0..100usize),
+            reads in vec((any::<Index>(), 0..4 * KB), 20),
+        ) {
+            let version = if v2 { FormatVersion::V2 } else { FormatVersion::V1 };
+            let size = data.len();
+            let meta: MetaMap = entries.into_iter().collect();
+            let alignment = 8 << alignment_shift;
+
+            let inner = Cursor::new(Vec::new());
+            let wr = if cipher.is_empty() {
+                EnardWriter::new_unencrypted(inner, meta.clone())
+            } else {
+                let factory = BoxDynCipher::factory();
+                EnardWriter::new(inner, factory, cipher, KEY1, NONCE.into(), meta.clone())
+            };
+            let mut wr = wr
+                .unwrap()
+                .with_version(version)
+                .with_data_alignment(alignment);
+            if let Some(ext_len) = ext_len {
+                let mut ext = MetaMap::new();
+                ext.insert(b"ext".to_vec(), vec![0xEE; ext_len]);
+                wr = wr.with_extended_meta(&ext);
+            }
+            wr.write_complete(&data[..]).unwrap();
+            let file = wr.into_inner().into_inner();
+
+            let inner = Cursor::new(&file[..]);
+            let mut rd = if cipher.is_empty() {
+                EnardReader::new_unencrypted(inner)
+            } else {
+                EnardReader::new_boxed(inner, KEY1)
+            }
+            .unwrap();
+            prop_assert_eq!(rd.data_start() % alignment as u64, 0);
+            for (k, v) in meta.iter() {
+                prop_assert_eq!(rd.meta().get(k), Some(v));
+            }
+            prop_assert_eq!(rd.data_size(), size as u64);
+
+            // Random seeks and chunked reads, which may cross the end of the data
+            for (pos, len) in reads {
+                let pos = pos.index(size + 1);
+                rd.seek(SeekFrom::Start(pos as u64)).unwrap();
+                let mut buf = vec![0u8; len];
+                let mut n = 0;
+                while n < buf.len() {
+                    match rd.read(&mut buf[n..]).unwrap() {
+                        0 => break,
+                        m => n += m,
+                    }
+                }
+                let end = (pos + buf.len()).min(size);
+                prop_assert_eq!(n, end - pos);
+                prop_assert_eq!(&buf[..n], &data[pos..end]);
+            }
+            rd.seek(SeekFrom::Start(0)).unwrap();
+            prop_assert_eq!(read_all(&mut rd), data);
+        }
+    }
+
+    #[test]
+    fn empty_payload() {
+        use std::io::Write;
+        let mut meta = MetaMap::new();
+        meta.insert(b"deleted".to_vec(), b"level2.pak".to_vec());
+        for version in [FormatVersion::V1, FormatVersion::V2] {
+            for meta in [MetaMap::new(), meta.clone()] {
+                for streaming in [false, true] {
+                    let mut wr = EnardWriter::new(
+                        Cursor::new(Vec::new()),
+                        BoxDynCipher::factory(),
+                        ChaCha12::name(),
+                        KEY1,
+                        NONCE.into(),
+                        meta.clone(),
+                    )
+                    .unwrap()
+                    .with_version(version);
+                    if streaming {
+                        wr.write_complete_streaming(std::io::empty()).unwrap();
+                    } else {
+                        wr.write_empty().unwrap();
+                    }
+                    let file = wr.into_inner().into_inner();
+
+                    let mut rd = EnardReader::new_boxed(Cursor::new(&file), KEY1).unwrap();
+                    assert!(rd.is_empty() && rd.is_eof());
+                    assert_eq!(rd.data_start() % DATA_ALIGNMENT as u64, 0);
+                    assert_eq!(rd.meta(), &meta);
+                    assert_eq!(rd.seek(SeekFrom::End(0)).unwrap(), 0);
+                    assert_eq!(rd.read(&mut [0u8; 16]).unwrap(), 0);
+                    assert!(read_all(rd.sub_reader(0, 0).unwrap()).is_empty());
+                    assert!(rd.sub_reader(0, 1).is_err());
+
+                    let (data, read_meta) = crate::decrypt_to_vec(&file, KEY1).unwrap();
+                    assert!(data.is_empty());
+                    assert_eq!(read_meta, meta);
+                    let mut dec = EnardDecryptWriter::new_boxed(Vec::new(), KEY1);
+                    dec.write_all(&file).unwrap();
+                    assert!(dec.finish().unwrap().is_empty());
+                    if !streaming {
+                        assert_eq!(verify(Cursor::new(&file), KEY1).unwrap().data_size, 0);
+                    }
+
+                    // The MAC over no data still covers the header
+                    let mut tampered = file.clone();
+                    let n = tampered.len();
+                    tampered[n - 1 - if streaming { 24 } else { 0 }] ^= 1;
+                    let result = EnardReader::new_boxed(Cursor::new(&tampered), KEY1);
+                    assert!(matches!(result.map(|_| ()), Err(EnardError::MacError(_))));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn open_with_hint() {
+        use crate::fs::AccessHint;
+        let dir = std::env::temp_dir().join("enard_open_with_hint");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("pack.enard");
+        let data: Vec<u8> = (0..100 * KB).map(|i| i as u8).collect();
+        let mut wr = EnardWriter::new(
+            fs::File::create(&path).unwrap(),
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            MetaMap::new(),
+        )
+        .unwrap();
+        wr.write_complete(&data[..]).unwrap();
+        drop(wr);
+
+        let mut rd = EnardReader::open(&path, KEY1).unwrap();
+        compare_bufs(&read_all(&mut rd), &data);
+        for hint in [AccessHint::Sequential, AccessHint::Random] {
+            let mut rd = EnardReader::open_with_hint(&path, KEY1, hint).unwrap();
+            rd.seek(SeekFrom::Start(50 * KB as u64)).unwrap();
+            compare_bufs(&read_all(&mut rd), &data[50 * KB..]);
+        }
+        assert!(EnardReader::open(dir.join("missing.enard"), KEY1).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn estimated_total_size() {
+        use std::io::Write;
+        let data = vec![7u8; 3 * KB + 5];
+        let mut ext = MetaMap::new();
+        ext.insert(b"manifest".to_vec(), vec![1; 100]);
+        for (version, alignment) in [(FormatVersion::V1, 8), (FormatVersion::V2, 64)] {
+            for options in 0..4 {
+                let mut meta = MetaMap::new();
+                meta.insert(b"name".to_vec(), b"a.bin".to_vec());
+                let mut wr = EnardWriter::new(
+                    Cursor::new(Vec::new()),
+                    BoxDynCipher::factory(),
+                    ChaCha12::name(),
+                    KEY1,
+                    NONCE.into(),
+                    meta,
+                )
+                .unwrap()
+                .with_version(version)
+                .with_data_alignment(alignment);
+                if options & 1 != 0 {
+                    wr = wr.with_extended_meta(&ext).with_obfuscated_header();
+                }
+                if options & 2 != 0 {
+                    wr = wr.with_plaintext_hash().with_magic(b"PAK");
+                }
+                let expected = wr.estimated_total_size(data.len() as u64).unwrap();
+                wr.write_header().unwrap();
+                assert_eq!(
+                    wr.estimated_total_size(data.len() as u64).unwrap(),
+                    expected
+                );
+                wr.write_all(&data).unwrap();
+                wr.finish().unwrap();
+                let file = wr.into_inner().into_inner();
+                assert_eq!(file.len() as u64, expected, "{:?} {}", version, options);
+            }
+        }
+        let wr = EnardWriter::new(
+            Cursor::new(Vec::new()),
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            MetaMap::new(),
+        )
+        .unwrap()
+        .with_meta_trailer();
+        assert!(wr.estimated_total_size(0).is_err());
+    }
+
+    #[test]
+    fn estimated_file_size() {
+        use std::io::Write;
+        let data = vec![7u8; 2 * KB + 3];
+        for name_len in [0, 5, 200] {
+            let mut meta = MetaMap::new();
+            meta.insert(b"name".to_vec(), vec![b'a'; name_len]);
+            let expected =
+                crate::estimated_file_size(data.len() as u64, &meta, ChaCha12::name()).unwrap();
+            let mut wr = EnardWriter::new(
+                Cursor::new(Vec::new()),
+                BoxDynCipher::factory(),
+                ChaCha12::name(),
+                KEY1,
+                NONCE.into(),
+                meta,
+            )
+            .unwrap();
+            wr.write_header().unwrap();
+            wr.write_all(&data).unwrap();
+            wr.finish().unwrap();
+            assert_eq!(wr.into_inner().into_inner().len() as u64, expected);
+        }
+        let err = crate::estimated_file_size(0, &MetaMap::new(), b"ROT13").unwrap_err();
+        assert!(matches!(err, EnardError::UnsupportedEncryption { .. }));
+    }
+
+    #[test]
+    fn box_dyn_cipher_reset_iv() {
+        use cipher::StreamCipher;
+        let iv2 = [0x25u8; 12];
+        let mut cipher = BoxDynCipher::new(b"ChaCha20", &KEY1, &NONCE).unwrap();
+        let mut first = [0u8; 100];
+        cipher.apply_keystream(&mut first);
+        cipher.reset_iv(&iv2).unwrap();
+        assert_eq!(cipher.current_pos(), 0);
+        let mut second = [0u8; 100];
+        cipher.apply_keystream(&mut second);
+
+        let mut expected = [0u8; 100];
+        BoxDynCipher::new(b"ChaCha20", &KEY1, &iv2)
+            .unwrap()
+            .apply_keystream(&mut expected);
+        assert_eq!(second, expected);
+        assert_ne!(first, second);
+        assert!(cipher.reset_iv(&[0u8; 8]).is_err());
+        assert!(BoxDynCipher::new(b"ChaCha21", &KEY1, &NONCE).is_err());
+
+        // Bare ciphers don't keep their key
+        let mut bare = ChaCha12::factory().create(b"", &KEY1, &NONCE).unwrap();
+        assert!(DynCipherCore::reset_iv(&mut bare, &iv2).is_err());
+    }
+
+    #[cfg(feature = "futures")]
+    #[test]
+    fn futures_async_reader() {
+        use crate::futures::AsyncEnardReader;
+        use futures_io::AsyncRead;
+        use std::io;
+        use std::pin::Pin;
+        use std::sync::Arc;
+        use std::task::{Context, Poll, Wake, Waker};
+
+        /// Returns a few bytes at a time, and `Pending` before each read
+        struct Trickle<'a>(&'a [u8], bool);
+        impl AsyncRead for Trickle<'_> {
+            fn poll_read(
+                mut self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: &mut [u8],
+            ) -> Poll<io::Result<usize>> {
+                self.1 = !self.1;
+                if self.1 {
+                    cx.waker().wake_by_ref();
+                    return Poll::Pending;
+                }
+                let n = self.0.len().min(buf.len()).min(1000);
+                buf[..n].copy_from_slice(&self.0[..n]);
+                self.0 = &self.0[n..];
+                Poll::Ready(Ok(n))
+            }
+        }
+        struct Noop;
+        impl Wake for Noop {
+            fn wake(self: Arc<Self>) {}
+        }
+        fn read_to_end<R: AsyncRead + Unpin>(rd: &mut R) -> io::Result<Vec<u8>> {
+            let waker: Waker = Arc::new(Noop).into();
+            let mut cx = Context::from_waker(&waker);
+            let mut out = Vec::new();
+            let mut buf = [0u8; 700];
+            loop {
+                match Pin::new(&mut *rd).poll_read(&mut cx, &mut buf) {
+                    Poll::Ready(Ok(0)) => return Ok(out),
+                    Poll::Ready(Ok(n)) => out.extend_from_slice(&buf[..n]),
+                    Poll::Ready(Err(e)) => return Err(e),
+                    Poll::Pending => {}
+                }
+            }
+        }
+
+        let data: Vec<u8> = (0..20 * KB).map(|i| (i % 251) as u8).collect();
+        let mut wr = EnardWriter::new(
+            Cursor::new(Vec::new()),
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            MetaMap::new(),
+        )
+        .unwrap();
+        wr.write_complete(&data[..]).unwrap();
+        let mut file = wr.into_inner().into_inner();
+
+        let mut rd = AsyncEnardReader::new_boxed(Trickle(&file, false), KEY1);
+        assert_eq!(read_to_end(&mut rd).unwrap(), data);
+        assert!(rd.is_verified());
+
+        // A modified file is only detected at the end
+        let last = file.len() - 1;
+        file[last] ^= 1;
+        let mut rd = AsyncEnardReader::new_boxed(Trickle(&file, false), KEY1);
+        let e = read_to_end(&mut rd).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert!(!rd.is_verified());
+    }
+
+    #[test]
+    fn write_summary() {
+        use std::io::Write;
+        let data = vec![7u8; 3 * KB];
+        let mut wr = EnardWriter::new(
+            Cursor::new(Vec::new()),
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            MetaMap::new(),
+        )
+        .unwrap();
+        wr.write_header().unwrap();
+        wr.write_all(&data).unwrap();
+        let summary = wr.finish().unwrap();
+        let file = wr.into_inner().into_inner();
+        assert_eq!(summary.total_bytes, file.len() as u64);
+        assert_eq!(summary.data_bytes, data.len() as u64);
+        assert_eq!(&summary.mac_tag[..], &file[file.len() - 32..]);
+        assert_eq!(summary.iv, NONCE);
+
+        // The footer comes after the tag
+        let mut wr = EnardWriter::new(
+            Cursor::new(Vec::new()),
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            MetaMap::new(),
+        )
+        .unwrap();
+        wr.write_header().unwrap();
+        wr.write_all(&data).unwrap();
+        let streamed = wr.finish_streaming().unwrap();
+        assert_eq!(
+            streamed.total_bytes,
+            wr.into_inner().into_inner().len() as u64
+        );
+        assert_eq!(streamed.mac_tag, summary.mac_tag);
+    }
+
+    #[test]
+    fn verify_tag_fast_path() {
+        let mut wr = EnardWriter::new(
+            Cursor::new(Vec::new()),
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            MetaMap::new(),
+        )
+        .unwrap()
+        .with_key_check();
+        wr.write_header().unwrap();
+        std::io::Write::write_all(&mut wr, &[5u8; 2000]).unwrap();
+        let tag = wr.finish().unwrap().mac_tag;
+        let file = wr.into_inner().into_inner();
+
+        let report = verify_tag(Cursor::new(&file), KEY1, &tag).unwrap();
+        assert_eq!(report.data_size, 2000);
+        let mut wrong_tag = tag;
+        wrong_tag[0] ^= 1;
+        let e = verify_tag(Cursor::new(&file), KEY1, &wrong_tag).unwrap_err();
+        assert_eq!(e.error_code(), ErrorCode::MacError);
+        assert!(verify_tag(Cursor::new(&file), [0x43; 32], &tag).is_err());
+        let e = verify_tag(Cursor::new(&file[..file.len() - 1]), KEY1, &tag).unwrap_err();
+        assert!(matches!(e, EnardError::Truncated { .. }));
+        let mut longer = file.clone();
+        longer.push(0);
+        assert!(verify_tag(Cursor::new(&longer), KEY1, &tag).is_err());
+    }
+
+    #[test]
+    fn clone_with_new_key() {
+        let key2 = [0x43u8; 32];
+        let data = vec![9u8; 3 * KB];
+        let mut wr = EnardWriter::new(
+            Cursor::new(Vec::new()),
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            MetaMap::new(),
+        )
+        .unwrap()
+        .with_key_check();
+        wr.write_complete(&data[..]).unwrap();
+        let file = wr.into_inner().into_inner();
+
+        let mut rd = EnardReader::new_boxed(Cursor::new(&file), KEY1).unwrap();
+        rd.seek(SeekFrom::Start(100)).unwrap();
+        let mut same = rd
+            .clone_with_new_key(Cursor::new(&file), BoxDynCipher::factory(), KEY1)
+            .unwrap();
+        assert_eq!(same.stream_position().unwrap(), 0);
+        assert_eq!(read_all(&mut same), data);
+
+        // The key check is for KEY1, so it's skipped
+        let mut other = rd
+            .clone_with_new_key(Cursor::new(&file), BoxDynCipher::factory(), key2)
+            .unwrap();
+        let layer = read_all(&mut other);
+        assert_eq!(layer.len(), data.len());
+        assert_ne!(layer, data);
+        assert!(EnardReader::new_boxed(Cursor::new(&file), key2).is_err());
+    }
+
+    #[test]
+    fn cipher_cascade() {
+        use crate::cascade::{decode_layers, encode_layers, CascadeCipher};
+        let layers: &[(&[u8], &[u8])] = &[(b"ChaCha20", &[0x24; 12]), (b"ChaCha8", &[0x25; 12])];
+        let iv = encode_layers(layers).unwrap();
+        let decoded = decode_layers(&iv).unwrap();
+        assert_eq!(decoded[1], (b"ChaCha8".to_vec(), vec![0x25; 12]));
+
+        let data = vec![3u8; 5 * KB];
+        let mut wr = EnardWriter::builder(Cursor::new(Vec::new()))
+            .cipher(CascadeCipher::NAME)
+            .key(KEY1)
+            .iv(iv.clone())
+            .build()
+            .unwrap();
+        wr.write_complete(&data[..]).unwrap();
+        let file = wr.into_inner().into_inner();
+        let mut rd = EnardReader::new_boxed(Cursor::new(&file), KEY1).unwrap();
+        assert_eq!(read_all(&mut rd), data);
+        rd.seek(SeekFrom::Start(4000)).unwrap();
+        assert_eq!(read_all(&mut rd), &data[4000..]);
+
+        // Each layer has its own key, so this isn't the same as either cipher alone
+        let mut cascade = BoxDynCipher::new(CascadeCipher::NAME, &KEY1, &iv).unwrap();
+        let mut buf = [0u8; 64];
+        cipher::StreamCipher::apply_keystream(&mut cascade, &mut buf);
+        let mut single = [0u8; 64];
+        let mut chacha = BoxDynCipher::new(b"ChaCha20", &KEY1, &NONCE).unwrap();
+        cipher::StreamCipher::apply_keystream(&mut chacha, &mut single);
+        assert_ne!(buf, single);
+
+        // No empty, nested or unknown layers
+        assert!(encode_layers(&[]).is_err());
+        assert!(encode_layers(&[(CascadeCipher::NAME, &iv)]).is_err());
+        let unknown = encode_layers(&[(b"Rot13", &[])]).unwrap();
+        assert!(BoxDynCipher::new(CascadeCipher::NAME, &KEY1, &unknown).is_err());
+        assert!(BoxDynCipher::new(CascadeCipher::NAME, &KEY1, &iv[..iv.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn forward_reader() {
+        let data: Vec<u8> = (0..20 * KB).map(|i| (i % 251) as u8).collect();
+        let mut wr = EnardWriter::new(
+            Cursor::new(Vec::new()),
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            MetaMap::new(),
+        )
+        .unwrap();
+        wr.write_complete(&data[..]).unwrap();
+        let mut file = wr.into_inner().into_inner();
+
+        // A slice is `Read` but not `Seek`
+        let mut rd = EnardForwardReader::new_boxed(&file[..], KEY1);
+        assert_eq!(read_all(&mut rd), data);
+        assert!(rd.is_verified());
+        rd.finish().unwrap();
+
+        // Stopping early still checks the rest of the file
+        let mut rd = EnardForwardReader::new_boxed(&file[..], KEY1);
+        let mut buf = [0u8; 100];
+        rd.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf[..], &data[..100]);
+        rd.finish().unwrap();
+
+        let truncated = &file[..file.len() - 10];
+        let rd = EnardForwardReader::new_boxed(truncated, KEY1);
+        let e = rd.finish().unwrap_err();
+        assert_eq!(e.error_code(), ErrorCode::Truncated);
+
+        let last = file.len() - 1;
+        file[last] ^= 1;
+        let mut rd = EnardForwardReader::new_boxed(&file[..], KEY1);
+        let mut out = Vec::new();
+        assert!(rd.read_to_end(&mut out).is_err());
+        assert!(!rd.is_verified());
+        assert_eq!(rd.finish().unwrap_err().error_code(), ErrorCode::MacError);
+    }
+
+    #[test]
+    fn new_buffered() {
+        let data: Vec<u8> = (0..20 * KB).map(|i| (i % 251) as u8).collect();
+        let mut wr = EnardWriter::new(
+            Cursor::new(Vec::new()),
+            BoxDynCipher::factory(),
+            ChaCha12::name(),
+            KEY1,
+            NONCE.into(),
+            MetaMap::new(),
+        )
+        .unwrap();
+        wr.write_complete(&data[..]).unwrap();
+        let file = wr.into_inner().into_inner();
+
+        for capacity in [0, 100, 64 * KB] {
+            let mut rd = EnardReader::new_buffered(Cursor::new(&file), KEY1, capacity).unwrap();
+            let mut buf = [0u8; 50];
+            rd.seek(SeekFrom::Start(5000)).unwrap();
+            rd.read_exact(&mut buf).unwrap();
+            assert_eq!(&buf[..], &data[5000..5050]);
+            rd.seek(SeekFrom::Start(10)).unwrap();
+            rd.read_exact(&mut buf).unwrap();
+            assert_eq!(&buf[..], &data[10..60]);
+            rd.seek(SeekFrom::Current(100)).unwrap();
+            rd.read_exact(&mut buf).unwrap();
+            assert_eq!(&buf[..], &data[160..210]);
+            rd.seek(SeekFrom::Start(0)).unwrap();
+            assert_eq!(read_all(&mut rd), data);
+        }
+    }
+
+    #[test]
+    fn write_complete_sized() {
+        let data: Vec<u8> = (0..20 * KB).map(|i| (i % 251) as u8).collect();
+        fn new_writer<W: std::io::Write>(inner: W) -> EnardWriter<W, BoxDynCipher> {
+            EnardWriter::new(
+                inner,
+                BoxDynCipher::factory(),
+                ChaCha12::name(),
+                KEY1,
+                NONCE.into(),
+                MetaMap::new(),
+            )
+            .unwrap()
+            .with_plaintext_hash()
+        }
+        let mut wr = new_writer(Cursor::new(Vec::new()));
+        wr.write_complete(&data[..]).unwrap();
+        let expected = wr.into_inner().into_inner();
+
+        // A `Vec` can't seek, and the file is the same as if the sizes were patched in
+        let mut wr = new_writer(Vec::new());
+        let len = data.len() as u64;
+        let total = wr.write_complete_sized(&data[..], len).unwrap();
+        let file = wr.into_inner();
+        assert_eq!(total, file.len() as u64);
+        assert_eq!(file, expected);
+
+        let mut wr = new_writer(Vec::new());
+        let e = wr.write_complete_sized(&data[..], len + 1).unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::UnexpectedEof);
+
+        let path = std::env::temp_dir().join("enard_write_complete_sized.enard");
+        let mut wr = new_writer(fs::File::create(&path).unwrap());
+        wr.preallocate(len).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), expected.len() as u64);
+        wr.write_complete_sized(&data[..], len).unwrap();
+        drop(wr);
+        assert_eq!(fs::read(&path).unwrap(), expected);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn header_checksum() {
+        use std::io::Write;
+        let data: Vec<u8> = (0..4 * KB).map(|i| (i % 251) as u8).collect();
+        let mut meta = MetaMap::new();
+        meta.insert(b"name".to_vec(), b"level1.dat".to_vec());
+        let new_writer = || {
+            EnardWriter::new(
+                Cursor::new(Vec::new()),
+                BoxDynCipher::factory(),
+                ChaCha12::name(),
+                KEY1,
+                NONCE.into(),
+                meta.clone(),
+            )
+            .unwrap()
+            .with_header_checksum()
+        };
+        let mut wr = new_writer();
+        wr.write_complete(&data[..]).unwrap();
+        let mut file = wr.into_inner().into_inner();
+        let header = crate::format::parse_header(&file).unwrap();
+        assert_eq!(header.data_start % DATA_ALIGNMENT as u64, 0);
+        let rd = EnardReader::new_boxed(Cursor::new(&file), KEY1).unwrap();
+        assert_eq!(read_all(rd), data);
+
+        // Streamed files guess the header size, which has to include the checksum
+        let mut wr = new_writer();
+        wr.write_complete_streaming(&data[..]).unwrap();
+        let streamed = wr.into_inner().into_inner();
+        let mut dec = EnardDecryptWriter::new_boxed(Vec::new(), KEY1);
+        dec.write_all(&streamed).unwrap();
+        assert_eq!(dec.finish().unwrap(), data);
+
+        // Rewriting the header keeps the checksum up to date
+        let mut editor =
+            EnardEditor::open(Cursor::new(file.clone()), BoxDynCipher::factory(), KEY1).unwrap();
+        editor.meta_mut().insert(b"long".to_vec(), vec![0x33; 300]);
+        let edited = editor.finish().unwrap().into_inner();
+        let rd = EnardReader::new_boxed(Cursor::new(&edited), KEY1).unwrap();
+        assert_eq!(read_all(rd), data);
+
+        let pos = file.windows(10).position(|w| w == b"level1.dat").unwrap();
+        file[pos] ^= 1;
+        let e = crate::format::parse_header(&file).unwrap_err();
+        assert_eq!(e.error_code(), ErrorCode::HeaderCorrupt);
+        let e = EnardReader::new_boxed(Cursor::new(&file), KEY1).unwrap_err();
+        assert_eq!(e.error_code(), ErrorCode::HeaderCorrupt);
+    }
+
+    #[test]
+    fn key_id() {
+        use crate::key::KeyRing;
+        let key2 = [0x43u8; 32];
+        let write = |key: &[u8; 32], id: Option<&[u8]>| {
+            let mut wr = EnardWriter::builder(Cursor::new(Vec::new()))
+                .key(*key)
+                .iv(NONCE)
+                .build()
+                .unwrap();
+            if let Some(id) = id {
+                wr = wr.with_key_id(id);
+            }
+            wr.write_complete(&b"hello"[..]).unwrap();
+            wr.into_inner().into_inner()
+        };
+        let fingerprint = crate::kdf::key_fingerprint(&key2);
+        assert_eq!(fingerprint, EnardKey::from(key2).fingerprint());
+        assert_ne!(fingerprint, crate::kdf::key_fingerprint(&KEY1));
+        let file = write(&key2, Some(&fingerprint));
+        let header = crate::format::parse_header(&file).unwrap();
+        assert_eq!(header.key_id(), Some(&fingerprint[..]));
+
+        let mut ring = KeyRing::new();
+        ring.insert(b"old", EnardKey::from(KEY1));
+        ring.insert_fingerprinted(EnardKey::from(key2));
+        let rd = EnardReader::new_boxed(Cursor::new(&file), &ring).unwrap();
+        assert_eq!(rd.key_id(), Some(&fingerprint[..]));
+        assert_eq!(read_all(rd), b"hello");
+        let file = write(&KEY1, Some(b"old"));
+        assert!(EnardReader::new_boxed(Cursor::new(&file), &ring).is_ok());
+
+        let file = write(&KEY1, Some(b"unknown"));
+        let e = EnardReader::new_boxed(Cursor::new(&file), &ring).unwrap_err();
+        assert_eq!(e.error_code(), ErrorCode::WrongKey);
+        let file = write(&KEY1, None);
+        let e = EnardReader::new_boxed(Cursor::new(&file), &ring).unwrap_err();
+        assert_eq!(e.error_code(), ErrorCode::MissingMeta);
+    }
+
+    #[test]
+    fn new_with_keys() {
+        let keys = [[0x41u8; 32], KEY1, [0x43u8; 32]];
+        let data: Vec<u8> = (0..4 * KB).map(|i| (i % 251) as u8).collect();
+        let write = |key: &[u8; 32], hint: u8| {
+            let mut wr = EnardWriter::builder(Cursor::new(Vec::new()))
+                .key(*key)
+                .iv(NONCE)
+                .build()
+                .unwrap();
+            wr = match hint {
+                1 => wr.with_key_check(),
+                2 => wr.with_key_id(&crate::kdf::key_fingerprint(key)),
+                _ => wr,
+            };
+            wr.write_complete(&data[..]).unwrap();
+            wr.into_inner().into_inner()
+        };
+        // Without a hint, with the key check value, and with the key ID
+        for hint in 0..3 {
+            for (i, key) in keys.iter().enumerate() {
+                let file = write(key, hint);
+                let (mut rd, index) =
+                    EnardReader::new_with_keys(Cursor::new(&file), &keys).unwrap();
+                assert_eq!(index, i);
+                rd.seek(SeekFrom::Start(100)).unwrap();
+                assert_eq!(read_all(rd), &data[100..]);
+            }
+            let file = write(&[0x44; 32], hint);
+            let e = EnardReader::new_with_keys(Cursor::new(&file), &keys).unwrap_err();
+            assert_eq!(e.error_code(), ErrorCode::WrongKey);
+        }
+
+        // Other errors are returned straight away
+        let file = write(&KEY1, 0);
+        let e = EnardReader::new_with_keys(Cursor::new(&file[..50]), &keys).unwrap_err();
+        assert_eq!(e.error_code(), ErrorCode::Truncated);
+        let no_keys: [&[u8]; 0] = [];
+        assert!(EnardReader::new_with_keys(Cursor::new(&file), &no_keys).is_err());
+    }
+
+    #[cfg(feature = "vfs-integration")]
+    #[test]
+    fn vfs_filesystem() {
+        use ::vfs::VfsPath;
+
+        let mut wr = EnardWriter::builder(Cursor::new(Vec::new()))
+            .key(KEY1)
+            .iv(NONCE)
+            .build()
+            .unwrap()
+            .with_meta_trailer();
+        wr.write_header().unwrap();
+        let mut index = crate::index::IndexBuilder::new();
+        index.write_asset(&mut wr, "a.txt", &b"first"[..]).unwrap();
+        let level = vec![7u8; 3 * KB];
+        index
+            .write_asset(&mut wr, "levels/one.bin", level.as_slice())
+            .unwrap();
+        wr.finish_with_meta_trailer(&index.build().to_meta())
+            .unwrap();
+        let buf = wr.into_inner().into_inner();
+
+        let fs = crate::vfs::EnardPackFs::from_reader(Cursor::new(buf), &KEY1).unwrap();
+        let root = VfsPath::from(fs);
+        assert_eq!(
+            root.join("a.txt").unwrap().read_to_string().unwrap(),
+            "first"
+        );
+        let levels = root.join("levels").unwrap();
+        assert!(levels.is_dir().unwrap());
+        let names: Vec<_> = levels.read_dir().unwrap().map(|p| p.filename()).collect();
+        assert_eq!(names, vec!["one.bin"]);
+        let one = levels.join("one.bin").unwrap();
+        assert_eq!(one.metadata().unwrap().len, level.len() as u64);
+        let mut file = one.open_file().unwrap();
+        file.seek(SeekFrom::Start(KB as u64)).unwrap();
+        assert_eq!(read_all(&mut file).len(), 2 * KB);
+        assert!(!root.join("missing").unwrap().exists().unwrap());
+        assert!(root.join("b.txt").unwrap().create_file().is_err());
+
+        // A file without an index
+        let mut wr = EnardWriter::builder(Cursor::new(Vec::new()))
+            .key(KEY1)
+            .iv(NONCE)
+            .build()
+            .unwrap();
+        wr.write_complete(&b"no index"[..]).unwrap();
+        let buf = wr.into_inner().into_inner();
+        let err = crate::vfs::EnardPackFs::from_reader(Cursor::new(buf), &KEY1).unwrap_err();
+        assert_eq!(err.error_code(), ErrorCode::MissingMeta);
+
+        #[cfg(feature = "zip-integration")]
+        {
+            let src_buf = fs::read("./arrow_up.zip").unwrap();
+            let mut wr = EnardWriter::builder(Cursor::new(Vec::new()))
+                .key(KEY1)
+                .iv(NONCE)
+                .build()
+                .unwrap();
+            wr.write_complete(src_buf.as_slice()).unwrap();
+            let buf = wr.into_inner().into_inner();
+            let archive = crate::zip::EnardZip::from_reader(Cursor::new(buf), &KEY1).unwrap();
+            let root = VfsPath::from(crate::vfs::EnardZipFs::new(archive));
+            let png = root.join("arrow_up.png").unwrap();
+            let meta = png.metadata().unwrap();
+            assert_eq!(meta.file_type, ::vfs::VfsFileType::File);
+            assert_eq!(
+                read_all(&mut png.open_file().unwrap()).len() as u64,
+                meta.len
+            );
+            assert!(root.is_dir().unwrap());
+        }
+    }
+
+    #[test]
+    fn copy_to_and_from() {
+        let data: Vec<u8> = (0..COPY_BUFFER_SIZE as u32 * 2 + 1000)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        let mut wr = EnardWriter::builder(Cursor::new(Vec::new()))
+            .key(KEY1)
+            .iv(NONCE)
+            .build()
+            .unwrap();
+        wr.write_header().unwrap();
+        assert_eq!(
+            wr.copy_from(&mut data.as_slice()).unwrap(),
+            data.len() as u64
+        );
+        wr.finish().unwrap();
+        let copied = wr.into_inner().into_inner();
+
+        let mut wr = EnardWriter::builder(Cursor::new(Vec::new()))
+            .key(KEY1)
+            .iv(NONCE)
+            .build()
+            .unwrap();
+        wr.write_complete(data.as_slice()).unwrap();
+        assert_eq!(copied, wr.into_inner().into_inner());
+
+        // Starting part way through the read-ahead buffer
+        let mut rd = EnardReader::new_boxed(Cursor::new(copied), KEY1)
+            .unwrap()
+            .with_readahead(4 * KB);
+        let mut head = [0u8; 10];
+        rd.read_exact(&mut head).unwrap();
+        let mut out = Vec::new();
+        assert_eq!(rd.copy_to(&mut out).unwrap(), (data.len() - 10) as u64);
+        compare_bufs(&out, &data[10..]);
+        assert_eq!(rd.copy_to(&mut out).unwrap(), 0);
+    }
+
+    #[test]
+    fn crypt_buffers_are_aligned() {
+        let mut buf = crate::core::CryptBuf::new(100);
+        assert_eq!(buf.len(), 100);
+        assert_eq!(buf.as_ptr() as usize % CRYPT_ALIGN, 0);
+        buf.resize(5000);
+        assert_eq!(buf.len(), 5000);
+        assert_eq!(buf.as_ptr() as usize % CRYPT_ALIGN, 0);
+
+        let mut wr = EnardWriter::builder(Cursor::new(Vec::new()))
+            .key(KEY1)
+            .iv(NONCE)
+            .build()
+            .unwrap()
+            .with_buffer_size(1000);
+        assert_eq!(wr.buffer_size(), 4 * CRYPT_BLOCK_SIZE);
+        wr.write_complete(&[7u8; 3000][..]).unwrap();
+        let rd = EnardReader::new_boxed(Cursor::new(wr.into_inner().into_inner()), KEY1)
+            .unwrap()
+            .with_readahead(1);
+        assert_eq!(rd.readahead_size(), CRYPT_BLOCK_SIZE);
+        assert_eq!(read_all(rd), vec![7u8; 3000]);
+    }
+
+    #[test]
+    fn reader_options() {
+        use crate::tuning::ReaderOptions;
+
+        let data: Vec<u8> = (0..10000u32).map(|i| (i % 253) as u8).collect();
+        let mut wr = EnardWriter::builder(Cursor::new(Vec::new()))
+            .key(KEY1)
+            .iv(NONCE)
+            .build()
+            .unwrap();
+        wr.write_complete(data.as_slice()).unwrap();
+        let file = wr.into_inner().into_inner();
+
+        let opts = ReaderOptions {
+            inner_buffer: 512,
+            crypt_chunk: 1000,
+            readahead: 100,
+        };
+        let mut rd = EnardReader::new_with_options(Cursor::new(file), KEY1, &opts).unwrap();
+        assert_eq!(rd.readahead_size(), CRYPT_BLOCK_SIZE);
+        let mut head = [0u8; 10];
+        rd.read_exact(&mut head).unwrap();
+        let mut out = Vec::new();
+        rd.copy_to(&mut out).unwrap();
+        compare_bufs(&out, &data[10..]);
+
+        // The options are kept by save_position
+        let saved = rd.save_position();
+        let inner = rd.into_inner();
+        assert_eq!(inner.capacity(), 512);
+        let rd = EnardReader::restore_position_boxed(inner, KEY1, &saved).unwrap();
+        assert_eq!(rd.readahead_size(), CRYPT_BLOCK_SIZE);
+    }
 }
@@ -0,0 +1,70 @@
+//! Limits on what the header parser accepts.
+//!
+//! Every size in an enard file is read before the MAC can be checked, so a hostile file
+//! can claim anything it wants. The parser never allocates more than the file actually
+//! contains, but [`ParseLimits`] bounds things further, e.g. for servers accepting
+//! uploads. Pass them to [`crate::EnardReader::new_with_limits`].
+//!
+//! ```rust
+//! use enard::limits::ParseLimits;
+//! let limits = ParseLimits {
+//!     max_header_size: 4096,
+//!     max_data_size: 1 << 20,
+//!     ..ParseLimits::default()
+//! };
+//! ```
+use crate::EnardError;
+
+/// Maximum sizes accepted when parsing an enard file. Anything larger fails with
+/// [`EnardError::BlockTooLarge`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ParseLimits {
+    /// Size in bytes of the header section, including padding
+    pub max_header_size: u64,
+    /// Number of metadata entries in the header
+    pub max_meta_entries: u64,
+    /// Size in bytes of a single metadata value
+    pub max_meta_value_size: u64,
+    /// Size in bytes of the data section
+    pub max_data_size: u64,
+}
+impl ParseLimits {
+    /// No limits besides the size of the file.
+    pub const UNLIMITED: Self = Self {
+        max_header_size: u64::MAX,
+        max_meta_entries: u64::MAX,
+        max_meta_value_size: u64::MAX,
+        max_data_size: u64::MAX,
+    };
+
+    /// Check the header and data sizes from the start of the file.
+    pub(crate) fn check_sizes(&self, header_size: u64, data_size: u64) -> Result<(), EnardError> {
+        check(header_size, self.max_header_size)?;
+        check(data_size, self.max_data_size)
+    }
+
+    /// Check the number of metadata entries.
+    pub(crate) fn check_meta_entries(&self, count: u64) -> Result<(), EnardError> {
+        check(count, self.max_meta_entries)
+    }
+}
+/// The defaults are far larger than any reasonable header, and don't limit the data.
+/// They're used by [`crate::EnardReader::new`] and [`crate::verify`].
+impl Default for ParseLimits {
+    fn default() -> Self {
+        Self {
+            max_header_size: 64 << 20,
+            max_meta_entries: 1 << 16,
+            max_meta_value_size: 16 << 20,
+            max_data_size: u64::MAX,
+        }
+    }
+}
+
+fn check(size: u64, limit: u64) -> Result<(), EnardError> {
+    if size > limit {
+        Err(EnardError::new_block_size(size, limit))
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,131 @@
+//! Find enard files embedded inside larger files.
+//!
+//! The intended use is shipping a single executable with the encrypted assets appended
+//! to the end of it. The simplest way is to append the enard file and then call
+//! [`write_trailer`] with the offset where the enard file starts. [`find`] also
+//! works without a trailer, as long as the enard file is the last thing in the file.
+//!
+//! ```no_run
+//! # fn main() -> Result<(), enard::EnardError> {
+//! use std::{fs::File, io::BufReader};
+//! let key = [0x42u8; 32];
+//! let exe = BufReader::new(File::open(std::env::current_exe()?)?);
+//! let reader = enard::EnardReader::from_embedded(exe, &key)?;
+//! # Ok(())
+//! # }
+//! ```
+use byteorder::{ReadBytesExt, WriteBytesExt, LE};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use crate::core::{FormatVersion, HEADER_START, MAGIC, TAG_SIZE};
+
+/// Magic bytes at the very end of a trailer.
+pub const TRAILER_MAGIC: &[u8; 8] = b"\x03ENARDPT";
+/// Size of the trailer, the `u64` offset followed by [`TRAILER_MAGIC`].
+pub const TRAILER_SIZE: usize = 8 + TRAILER_MAGIC.len();
+/// How many bytes to read at a time when scanning for the magic header.
+const SCAN_CHUNK: usize = 64 * 1024;
+
+/// Write a trailer pointing at an enard file which starts at `offset`.
+///
+/// This should be the last thing written to the file.
+pub fn write_trailer<W: Write>(mut writer: W, offset: u64) -> io::Result<()> {
+    writer.write_u64::<LE>(offset)?;
+    writer.write_all(TRAILER_MAGIC)
+}
+
+/// Returns the offset of the embedded enard file in `reader`, or `None` if there isn't one.
+///
+/// If the file ends with a trailer (see [`write_trailer`]) the offset is read from that,
+/// otherwise the file is scanned from the end for a magic header, and the last enard file
+/// which ends exactly at the end of `reader` is returned.
+pub fn find<R: Read + Seek>(mut reader: R) -> io::Result<Option<u64>> {
+    let len = reader.seek(SeekFrom::End(0))?;
+    if let Some(offset) = read_trailer(&mut reader, len)? {
+        // Don't trust the trailer blindly
+        if check_magic(&mut reader, offset)? {
+            return Ok(Some(offset));
+        }
+    }
+    scan(reader, len)
+}
+
+fn read_trailer<R: Read + Seek>(mut reader: R, len: u64) -> io::Result<Option<u64>> {
+    if len < TRAILER_SIZE as u64 {
+        return Ok(None);
+    }
+    reader.seek(SeekFrom::Start(len - TRAILER_SIZE as u64))?;
+    let offset = reader.read_u64::<LE>()?;
+    let mut magic_buf = [0u8; TRAILER_MAGIC.len()];
+    reader.read_exact(&mut magic_buf)?;
+    if &magic_buf == TRAILER_MAGIC && offset < len {
+        Ok(Some(offset))
+    } else {
+        Ok(None)
+    }
+}
+
+pub(crate) fn check_magic<R: Read + Seek>(mut reader: R, offset: u64) -> io::Result<bool> {
+    let mut magic_buf = [0u8; MAGIC.len()];
+    reader.seek(SeekFrom::Start(offset))?;
+    match reader.read_exact(&mut magic_buf) {
+        Ok(()) => Ok(&magic_buf == MAGIC),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Returns `true` if the sizes in the header at `offset` say the file ends at `len`.
+fn ends_at<R: Read + Seek>(mut reader: R, offset: u64, len: u64) -> io::Result<bool> {
+    if offset + (HEADER_START + TAG_SIZE) as u64 > len {
+        return Ok(false);
+    }
+    // Skip the magic
+    reader.seek(SeekFrom::Start(offset + MAGIC.len() as u64))?;
+    let header_size = match FormatVersion::from_field(reader.read_u16::<LE>()?) {
+        Ok((FormatVersion::V1, _)) => reader.read_u32::<LE>()? as u64,
+        Ok((FormatVersion::V2, _)) => reader.read_u64::<LE>()?,
+        Err(_) => return Ok(false),
+    };
+    let data_size = reader.read_u64::<LE>()?;
+    let end = ((reader.stream_position()? - offset) + TAG_SIZE as u64)
+        .checked_add(header_size)
+        .and_then(|v| v.checked_add(data_size))
+        .and_then(|v| v.checked_add(offset));
+    #[cfg(feature = "signature")]
+    {
+        use crate::signature::{SIGNATURE_BLOCK_SIZE, SIGNATURE_MAGIC};
+        // Signed files have a signature block after the MAC tag
+        if end.and_then(|v| v.checked_add(SIGNATURE_BLOCK_SIZE as u64)) == Some(len) {
+            let mut magic_buf = [0u8; SIGNATURE_MAGIC.len()];
+            reader.seek(SeekFrom::Start(len - SIGNATURE_MAGIC.len() as u64))?;
+            reader.read_exact(&mut magic_buf)?;
+            return Ok(&magic_buf == SIGNATURE_MAGIC);
+        }
+    }
+    Ok(end == Some(len))
+}
+
+fn scan<R: Read + Seek>(mut reader: R, len: u64) -> io::Result<Option<u64>> {
+    // Chunks overlap so the magic can't be split between two of them
+    let overlap = MAGIC.len() - 1;
+    let mut buf = vec![0u8; SCAN_CHUNK + overlap];
+    let mut end = len;
+    while end > 0 {
+        let start = end.saturating_sub(SCAN_CHUNK as u64);
+        let n = ((end - start) as usize + overlap).min((len - start) as usize);
+        reader.seek(SeekFrom::Start(start))?;
+        reader.read_exact(&mut buf[..n])?;
+        // Search backwards so we find the last match first
+        for i in (0..n.saturating_sub(overlap)).rev() {
+            if buf[i..].starts_with(MAGIC) {
+                let pos = start + i as u64;
+                if ends_at(&mut reader, pos, len)? {
+                    return Ok(Some(pos));
+                }
+            }
+        }
+        end = start;
+    }
+    Ok(None)
+}
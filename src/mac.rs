@@ -0,0 +1,143 @@
+//! Choose the MAC algorithm which authenticates an enard file.
+//!
+//! The algorithm is named in the metadata under [`MAC_META_KEY`], files without it use
+//! [`HMAC_SHA256`]. Like ciphers, MACs are created by name with a factory, so readers
+//! pick the right one for each file. [`DefaultMacFactory`] supports the HMACs built
+//! into enard, implement [`MacFactory`] to add others (e.g. KMAC or BLAKE3), and pass it
+//! to [`crate::EnardReader::new_with_mac_factory`] and
+//! [`crate::EnardWriter::with_mac_factory`].
+//!
+//! Tags are always [`TAG_SIZE`] bytes, MACs with longer outputs are truncated and MACs
+//! with shorter outputs are rejected.
+//!
+//! ```rust
+//! # fn main() -> Result<(), enard::EnardError> {
+//! use enard::mac::HMAC_SHA512;
+//! let mut wr = enard::EnardWriter::builder(std::io::Cursor::new(Vec::new()))
+//!     .key([0x42; 32])
+//!     .iv([0x24; 12])
+//!     .mac(HMAC_SHA512)
+//!     .build()?;
+//! wr.write_complete(&b"hello"[..])?;
+//! # Ok(())
+//! # }
+//! ```
+use std::io::{self, Write};
+
+use hmac::{Hmac, Mac};
+use sha2::{Sha256, Sha512};
+use subtle::ConstantTimeEq;
+
+use crate::{EnardError, MetaMap};
+
+/// Metadata key naming the MAC algorithm.
+pub const MAC_META_KEY: &[u8] = b"enard.mac";
+/// HMAC-SHA256, the default
+pub const HMAC_SHA256: &[u8] = b"HMAC-SHA256";
+/// HMAC-SHA512, truncated to [`TAG_SIZE`] bytes. Requires [`crate::FormatVersion::V2`].
+pub const HMAC_SHA512: &[u8] = b"HMAC-SHA512";
+/// Size in bytes of the MAC tag at the end of every enard file.
+pub const TAG_SIZE: usize = crate::core::TAG_SIZE;
+
+/// A MAC being computed, see [`MacFactory`].
+pub trait DynMac: Send {
+    /// Add `data` to the MAC.
+    fn update(&mut self, data: &[u8]);
+
+    /// Returns the tag, truncated to [`TAG_SIZE`] bytes.
+    fn finalize(self: Box<Self>) -> [u8; TAG_SIZE];
+
+    /// Size in bytes of the MAC's output before truncation. MACs with an output shorter
+    /// than [`TAG_SIZE`] are rejected when they're created.
+    fn output_size(&self) -> usize {
+        TAG_SIZE
+    }
+}
+
+/// Any [`Mac`], outputs longer than [`TAG_SIZE`] bytes are truncated. Outputs which are
+/// shorter are rejected when the MAC is created, see [`MacFactory::create`].
+impl<M: Mac + Send> DynMac for M {
+    fn update(&mut self, data: &[u8]) {
+        Mac::update(self, data)
+    }
+
+    fn output_size(&self) -> usize {
+        <M as digest::OutputSizeUser>::output_size()
+    }
+
+    fn finalize(self: Box<Self>) -> [u8; TAG_SIZE] {
+        let out = Mac::finalize(*self).into_bytes();
+        let mut tag = [0u8; TAG_SIZE];
+        tag.copy_from_slice(&out[..TAG_SIZE]);
+        tag
+    }
+}
+
+/// A MAC created by a [`MacFactory`].
+pub type BoxDynMac = Box<dyn DynMac>;
+
+/// Lets a MAC be used with [`std::io::copy`].
+impl Write for BoxDynMac {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Compare the tag of `mac` with `tag` in constant time.
+pub fn verify(mac: BoxDynMac, tag: &[u8]) -> Result<(), EnardError> {
+    if mac.finalize().ct_eq(tag).unwrap_u8() == 1 {
+        Ok(())
+    } else {
+        Err(digest::MacError.into())
+    }
+}
+
+/// Add additional authenticated data to `mac` after the data, see
+/// [`crate::EnardWriter::with_aad`]. Empty AAD adds nothing, so it's the same as none.
+pub(crate) fn update_aad(mac: &mut BoxDynMac, aad: &[u8]) {
+    if !aad.is_empty() {
+        mac.update(aad);
+        mac.update(&(aad.len() as u64).to_le_bytes());
+    }
+}
+
+/// Creates MACs by name, like [`crate::cipher_factory::CipherFactory`] for ciphers.
+pub trait MacFactory {
+    /// Create the MAC named `name` with `key`.
+    ///
+    /// Returns [`EnardError::UnsupportedEncryption`] if this factory doesn't support it.
+    /// Readers and writers also return it if the MAC's output is shorter than
+    /// [`TAG_SIZE`] bytes.
+    fn create(&self, name: &[u8], key: &[u8]) -> Result<BoxDynMac, EnardError>;
+}
+
+/// Reject MACs whose output is too short to fill a tag, see [`DynMac::output_size`].
+pub(crate) fn check_output_size(mac: BoxDynMac, name: &[u8]) -> Result<BoxDynMac, EnardError> {
+    if mac.output_size() < TAG_SIZE {
+        return Err(EnardError::new_unsupported_encryption(name));
+    }
+    Ok(mac)
+}
+
+/// Supports [`HMAC_SHA256`] and [`HMAC_SHA512`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultMacFactory;
+impl MacFactory for DefaultMacFactory {
+    fn create(&self, name: &[u8], key: &[u8]) -> Result<BoxDynMac, EnardError> {
+        match name {
+            HMAC_SHA256 => Ok(Box::new(Hmac::<Sha256>::new_from_slice(key)?)),
+            HMAC_SHA512 => Ok(Box::new(Hmac::<Sha512>::new_from_slice(key)?)),
+            _ => Err(EnardError::new_unsupported_encryption(name)),
+        }
+    }
+}
+
+/// Name of the MAC used by a file with the metadata `meta`.
+pub fn mac_name(meta: &MetaMap) -> &[u8] {
+    meta.get(MAC_META_KEY).map_or(HMAC_SHA256, |v| v.as_slice())
+}
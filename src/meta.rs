@@ -0,0 +1,99 @@
+//! Metadata keys reserved by enard.
+//!
+//! All keys starting with [`RESERVED_PREFIX`] belong to enard. The ones in [`KNOWN_KEYS`]
+//! have a defined meaning (see `format.md`) and are normally set through the typed
+//! methods, e.g. [`EnardWriter::with_content_type`] and [`EnardReader::content_type`].
+//! [`EnardWriter`] refuses to write any other key in the namespace, so typos are caught
+//! and new keys can be added later without clashing with existing files. Use
+//! [`EnardWriter::allow_reserved_meta`] to write them anyway.
+//!
+//! [`EnardWriter`]: crate::EnardWriter
+//! [`EnardWriter::with_content_type`]: crate::EnardWriter::with_content_type
+//! [`EnardWriter::allow_reserved_meta`]: crate::EnardWriter::allow_reserved_meta
+//! [`EnardReader::content_type`]: crate::EnardReader::content_type
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{EnardError, MetaMap};
+
+/// Prefix of all metadata keys reserved by enard.
+pub const RESERVED_PREFIX: &[u8] = b"enard.";
+/// MIME type of the plaintext, e.g. `image/png`.
+pub const CONTENT_TYPE: &[u8] = b"enard.content-type";
+/// Compression applied to the plaintext before encryption, e.g. `zstd`.
+pub const COMPRESSION: &[u8] = b"enard.compression";
+/// Creation time as seconds since the Unix epoch, stored as a `u64`.
+pub const CREATED: &[u8] = b"enard.created";
+/// Marks a file whose data isn't encrypted, see [`crate::EnardWriter::new_unencrypted`].
+/// The value is empty.
+pub const UNENCRYPTED: &[u8] = b"enard.unencrypted";
+/// SHA-256 of the plaintext, stored in the extended metadata, see
+/// [`crate::EnardWriter::with_plaintext_hash`].
+pub const PLAINTEXT_SHA256: &[u8] = b"enard.sha256";
+/// The file key wrapped for each recipient, see [`crate::EnardWriter::add_recipient`].
+pub const RECIPIENTS: &[u8] = b"enard.recipients";
+/// Marks a file whose extended metadata is a trailer, see
+/// [`crate::EnardWriter::with_meta_trailer`]. The value is empty.
+pub const META_TRAILER: &[u8] = b"enard.metatrailer";
+/// Names and positions of the assets in the payload, stored in the extended metadata, see
+/// [`crate::index`].
+pub const INDEX: &[u8] = b"enard.index";
+/// Identifies the key the file was written with, so readers with several keys can pick
+/// one without trying them all, see [`crate::EnardWriter::with_key_id`]. Any bytes, e.g.
+/// a name or [`crate::kdf::key_fingerprint`].
+pub const KEY_ID: &[u8] = b"enard.keyid";
+
+/// All reserved keys with a defined meaning.
+pub const KNOWN_KEYS: &[&[u8]] = &[
+    CONTENT_TYPE,
+    COMPRESSION,
+    CREATED,
+    UNENCRYPTED,
+    PLAINTEXT_SHA256,
+    RECIPIENTS,
+    META_TRAILER,
+    INDEX,
+    KEY_ID,
+    crate::checksum::CHECKSUM_META_KEY,
+    crate::EXTENDED_META_KEY,
+    crate::kdf::KEY_CHECK_META_KEY,
+    crate::kdf::SALT_META_KEY,
+    crate::kdf::SPLIT_KEYS_META_KEY,
+    crate::mac::MAC_META_KEY,
+];
+
+/// Returns `true` if `key` is in the reserved namespace.
+pub fn is_reserved(key: &[u8]) -> bool {
+    key.starts_with(RESERVED_PREFIX)
+}
+
+/// Returns [`EnardError::ReservedMeta`] for the first reserved key in `meta` which isn't
+/// one of the [`KNOWN_KEYS`].
+pub fn check_reserved(meta: &MetaMap) -> Result<(), EnardError> {
+    match meta
+        .keys()
+        .find(|k| is_reserved(k) && !KNOWN_KEYS.contains(&k.as_slice()))
+    {
+        Some(key) => Err(EnardError::new_reserved_meta(key)),
+        None => Ok(()),
+    }
+}
+
+/// Returns the value of `key` if it's valid UTF-8.
+pub(crate) fn get_str<'a>(meta: &'a MetaMap, key: &[u8]) -> Option<&'a str> {
+    meta.get(key).and_then(|v| std::str::from_utf8(v).ok())
+}
+
+/// Encodes a time for [`CREATED`], times before the epoch are stored as 0.
+pub(crate) fn encode_time(time: SystemTime) -> Vec<u8> {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    secs.to_le_bytes().to_vec()
+}
+
+/// Decodes a time stored by [`encode_time`].
+pub(crate) fn decode_time(value: &[u8]) -> Option<SystemTime> {
+    let secs = u64::from_le_bytes(<[u8; 8]>::try_from(value).ok()?);
+    UNIX_EPOCH.checked_add(Duration::from_secs(secs))
+}
@@ -0,0 +1,50 @@
+use std::io::{Cursor, Read};
+
+#[cfg(feature = "random")]
+use crate::EnardWriter;
+use crate::{EnardError, EnardReader, KeyProvider, MetaMap};
+
+/// Decrypt a whole enard file held in memory, returning the data and the metadata.
+///
+/// Meant for small files like configs and save games, larger files should use
+/// [`EnardReader`] so they don't have to be loaded all at once.
+///
+/// ```rust
+/// # fn main() -> Result<(), enard::EnardError> {
+/// # let mut wr = enard::EnardWriter::builder(std::io::Cursor::new(Vec::new()))
+/// #     .key([0x42; 32]).iv([0x24; 12]).meta("slot", "1").build()?;
+/// # wr.write_complete(&b"level 3"[..])?;
+/// # let file = wr.into_inner().into_inner();
+/// let (data, meta) = enard::decrypt_to_vec(&file, [0x42u8; 32])?;
+/// assert_eq!(data, b"level 3");
+/// assert_eq!(meta.get(b"slot".as_slice()).unwrap(), b"1");
+/// # Ok(())
+/// # }
+/// ```
+pub fn decrypt_to_vec<K: KeyProvider>(
+    file: &[u8],
+    key: K,
+) -> Result<(Vec<u8>, MetaMap), EnardError> {
+    let mut rd = EnardReader::new_boxed(Cursor::new(file), key)?;
+    let mut data = Vec::with_capacity(rd.data_size() as usize);
+    rd.read_to_end(&mut data)?;
+    Ok((data, rd.meta().clone()))
+}
+
+/// Encrypt `data` with the cipher named `cipher` and a random IV, returning the whole
+/// enard file. The counterpart of [`decrypt_to_vec`].
+#[cfg(feature = "random")]
+pub fn encrypt_to_vec<K: KeyProvider>(
+    data: &[u8],
+    key: K,
+    cipher: &[u8],
+    meta: MetaMap,
+) -> Result<Vec<u8>, EnardError> {
+    let mut wr = EnardWriter::builder(Cursor::new(Vec::new()))
+        .cipher(cipher)
+        .key(key)
+        .meta_map(meta)
+        .build()?;
+    wr.write_complete(data)?;
+    Ok(wr.into_inner().into_inner())
+}
@@ -0,0 +1,127 @@
+//! The operations behind the `enard` command line tool.
+//!
+//! GUI tools and build scripts can use these to behave exactly like the CLI, the same
+//! key lookup and parsing, the same cipher names, and a fresh random IV for every file.
+//!
+//! ```rust,no_run
+//! # fn main() -> Result<(), enard::EnardError> {
+//! use enard::ops;
+//! let key = ops::load_key(None, None)?;
+//! # #[cfg(feature = "random")]
+//! ops::encrypt_file("assets/a.png", "build/a.png", b"ChaCha12", &key, enard::MetaMap::new())?;
+//! ops::decrypt_file("build/a.png", "out/a.png", &key)?;
+//! # Ok(())
+//! # }
+//! ```
+use std::fs::File;
+use std::io::{self, BufRead, Read, Seek, Write};
+use std::path::Path;
+
+#[cfg(feature = "random")]
+use crate::cipher_factory::GetFactory;
+use crate::key::{self, EnardKey};
+#[cfg(feature = "random")]
+use crate::{BoxDynCipher, EnardWriter, MetaMap};
+use crate::{EnardError, EnardReader, KeyProvider};
+
+/// Environment variable the key is read from when no other key is given.
+pub const ENV_VAR_KEY: &str = "ENARD_KEY";
+
+/// Find the key the same way the CLI does, and parse it with [`key::parse`].
+///
+/// `keyfile` is checked first, then `key`, then the [`ENV_VAR_KEY`] environment
+/// variable. Returns an [`io::ErrorKind::NotFound`] error if none of them are set.
+pub fn load_key(keyfile: Option<&Path>, key: Option<&str>) -> Result<EnardKey, EnardError> {
+    if let Some(keyfile) = keyfile {
+        let mut buf = zeroize::Zeroizing::new(Vec::new());
+        File::open(keyfile)?.read_to_end(&mut buf)?;
+        key::parse(&buf)
+    } else if let Some(key) = key {
+        key::parse(key.as_bytes())
+    } else if let Some(env_key) = std::env::var_os(ENV_VAR_KEY) {
+        let env_key = env_key
+            .into_string()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "key is not valid unicode"))?;
+        key::parse(env_key.as_bytes())
+    } else {
+        let msg = format!("no key given and {} is not set", ENV_VAR_KEY);
+        Err(io::Error::new(io::ErrorKind::NotFound, msg).into())
+    }
+}
+
+/// Encrypt everything from `input` into `output` with the cipher named `cipher` and
+/// a random IV. Returns the number of bytes of data written.
+#[cfg(feature = "random")]
+pub fn encrypt<R, W, K>(
+    input: R,
+    output: W,
+    cipher: &[u8],
+    key: K,
+    meta: MetaMap,
+) -> Result<u64, EnardError>
+where
+    R: Read,
+    W: Write + Seek,
+    K: KeyProvider,
+{
+    let factory = BoxDynCipher::factory();
+    let (mut wr, _) = EnardWriter::new_with_random_iv(
+        output,
+        factory,
+        cipher,
+        key,
+        meta,
+        &mut rand::rngs::OsRng,
+    )?;
+    Ok(wr.write_complete(input)?)
+}
+
+/// Decrypt `input` into `output`, returning the number of bytes written.
+pub fn decrypt<R, W, K>(input: R, mut output: W, key: K) -> Result<u64, EnardError>
+where
+    R: BufRead + Seek,
+    W: Write,
+    K: KeyProvider,
+{
+    let mut rd = EnardReader::new_boxed(input, key)?;
+    let size = io::copy(&mut rd, &mut output)?;
+    output.flush()?;
+    Ok(size)
+}
+
+/// Encrypt the file at `input` to `output`, creating any missing parent directories.
+#[cfg(feature = "random")]
+pub fn encrypt_file<P: AsRef<Path>, Q: AsRef<Path>, K: KeyProvider>(
+    input: P,
+    output: Q,
+    cipher: &[u8],
+    key: K,
+    meta: MetaMap,
+) -> Result<u64, EnardError> {
+    let input = io::BufReader::new(File::open(input)?);
+    encrypt(
+        input,
+        create_with_parents(output.as_ref())?,
+        cipher,
+        key,
+        meta,
+    )
+}
+
+/// Decrypt the file at `input` to `output`, creating any missing parent directories.
+pub fn decrypt_file<P: AsRef<Path>, Q: AsRef<Path>, K: KeyProvider>(
+    input: P,
+    output: Q,
+    key: K,
+) -> Result<u64, EnardError> {
+    let input = io::BufReader::new(File::open(input)?);
+    let output = io::BufWriter::new(create_with_parents(output.as_ref())?);
+    decrypt(input, output, key)
+}
+
+fn create_with_parents(path: &Path) -> io::Result<File> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    File::create(path)
+}
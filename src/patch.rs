@@ -0,0 +1,284 @@
+//! Binary diffs between the plaintext of two enard files.
+//!
+//! [`diff`] compares the old and new plaintext and produces a [`Patch`], which is
+//! usually much smaller than the new file when only parts of it changed. The patch is
+//! encoded with [`Patch::encode`] and stored in its own enard file, so it's encrypted like
+//! everything else. [`apply`] turns the old plaintext and the patch back into the new
+//! plaintext.
+//!
+//! The diff is block based (like rsync): the old data is split into blocks of
+//! [`BLOCK_SIZE`] bytes, and any of those blocks found anywhere in the new data is copied
+//! instead of stored in the patch.
+use byteorder::{ReadBytesExt, WriteBytesExt, LE};
+use std::collections::HashMap;
+use std::io::{self, BufRead, ErrorKind, Read, Seek, SeekFrom, Write};
+
+use crate::checksum::checksum;
+use crate::{varint, EnardError};
+
+/// Magic bytes at the start of an encoded patch.
+pub const PATCH_MAGIC: &[u8; 8] = b"\x03ENPATCH";
+/// Size of the blocks the old data is split into.
+pub const BLOCK_SIZE: usize = 2048;
+
+const OP_COPY: u8 = 0;
+const OP_INSERT: u8 = 1;
+
+/// One step in rebuilding the new data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatchOp {
+    /// Copy `len` bytes starting at `offset` in the old data
+    Copy { offset: u64, len: u64 },
+    /// Insert new bytes
+    Insert(Vec<u8>),
+}
+
+/// Instructions for turning the old data into the new data, see [`diff`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Patch {
+    /// Size of the old data
+    pub old_size: u64,
+    /// CRC-32 of the old data
+    pub old_crc: u32,
+    /// Size of the new data
+    pub new_size: u64,
+    /// CRC-32 of the new data
+    pub new_crc: u32,
+    pub ops: Vec<PatchOp>,
+}
+impl Patch {
+    /// Write the encoded patch to `writer`.
+    pub fn encode<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(PATCH_MAGIC)?;
+        writer.write_u64::<LE>(self.old_size)?;
+        writer.write_u32::<LE>(self.old_crc)?;
+        writer.write_u64::<LE>(self.new_size)?;
+        writer.write_u32::<LE>(self.new_crc)?;
+        varint::write_u(&mut writer, self.ops.len() as u64)?;
+        for op in self.ops.iter() {
+            match op {
+                PatchOp::Copy { offset, len } => {
+                    writer.write_u8(OP_COPY)?;
+                    varint::write_u(&mut writer, *offset)?;
+                    varint::write_u(&mut writer, *len)?;
+                }
+                PatchOp::Insert(data) => {
+                    writer.write_u8(OP_INSERT)?;
+                    varint::write_u(&mut writer, data.len() as u64)?;
+                    writer.write_all(data)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Read an encoded patch from `reader`.
+    pub fn decode<R: BufRead>(mut reader: R) -> Result<Self, EnardError> {
+        let mut magic_buf = [0u8; PATCH_MAGIC.len()];
+        reader.read_exact(&mut magic_buf)?;
+        if &magic_buf != PATCH_MAGIC {
+            return Err(EnardError::new_invalid_magic(PATCH_MAGIC, &magic_buf));
+        }
+        let old_size = reader.read_u64::<LE>()?;
+        let old_crc = reader.read_u32::<LE>()?;
+        let new_size = reader.read_u64::<LE>()?;
+        let new_crc = reader.read_u32::<LE>()?;
+        let count = varint::read_u(&mut reader)?;
+        let mut ops = Vec::new();
+        for _ in 0..count {
+            let op = match reader.read_u8()? {
+                OP_COPY => PatchOp::Copy {
+                    offset: varint::read_u(&mut reader)?,
+                    len: varint::read_u(&mut reader)?,
+                },
+                OP_INSERT => {
+                    let len = varint::read_u(&mut reader)?;
+                    if len > new_size {
+                        return Err(EnardError::new_block_size(len, new_size));
+                    }
+                    let mut data = Vec::new();
+                    (&mut reader).take(len).read_to_end(&mut data)?;
+                    if data.len() as u64 != len {
+                        return Err(io::Error::from(ErrorKind::UnexpectedEof).into());
+                    }
+                    PatchOp::Insert(data)
+                }
+                kind => {
+                    let msg = format!("unknown patch operation {}", kind);
+                    return Err(io::Error::new(ErrorKind::InvalidData, msg).into());
+                }
+            };
+            ops.push(op);
+        }
+        Ok(Self {
+            old_size,
+            old_crc,
+            new_size,
+            new_crc,
+            ops,
+        })
+    }
+
+    fn push_copy(&mut self, offset: u64, len: u64) {
+        if let Some(PatchOp::Copy {
+            offset: prev_offset,
+            len: prev_len,
+        }) = self.ops.last_mut()
+        {
+            if *prev_offset + *prev_len == offset {
+                *prev_len += len;
+                return;
+            }
+        }
+        self.ops.push(PatchOp::Copy { offset, len });
+    }
+
+    fn push_insert(&mut self, data: &[u8]) {
+        if !data.is_empty() {
+            self.ops.push(PatchOp::Insert(data.to_vec()));
+        }
+    }
+}
+
+/// Rolling checksum of a block, the same idea as Adler-32.
+#[derive(Clone, Copy)]
+struct Rolling {
+    a: u32,
+    b: u32,
+}
+impl Rolling {
+    fn new(block: &[u8]) -> Self {
+        let mut a = 0u32;
+        let mut b = 0u32;
+        for (i, &x) in block.iter().enumerate() {
+            a = a.wrapping_add(x as u32);
+            b = b.wrapping_add((block.len() - i) as u32 * x as u32);
+        }
+        Self { a, b }
+    }
+
+    /// Slide the window forward one byte.
+    fn roll(&mut self, out: u8, inp: u8) {
+        self.a = self.a.wrapping_sub(out as u32).wrapping_add(inp as u32);
+        self.b = self
+            .b
+            .wrapping_sub(BLOCK_SIZE as u32 * out as u32)
+            .wrapping_add(self.a);
+    }
+
+    fn digest(self) -> u32 {
+        (self.a & 0xffff) | (self.b << 16)
+    }
+}
+
+/// Compute the patch which turns `old` into `new`.
+///
+/// `old` is read twice and then only the blocks which might match are read again,
+/// `new` is read into memory.
+pub fn diff<O: Read + Seek, N: Read>(mut old: O, mut new: N) -> io::Result<Patch> {
+    old.seek(SeekFrom::Start(0))?;
+    let old_crc = checksum(&mut old)?;
+    let old_size = old.stream_position()?;
+
+    // Index the full blocks of the old data by their rolling checksum
+    old.seek(SeekFrom::Start(0))?;
+    let mut blocks: HashMap<u32, Vec<u64>> = HashMap::new();
+    let mut block = vec![0u8; BLOCK_SIZE];
+    for index in 0..old_size / BLOCK_SIZE as u64 {
+        old.read_exact(&mut block)?;
+        blocks
+            .entry(Rolling::new(&block).digest())
+            .or_default()
+            .push(index);
+    }
+
+    let mut new_buf = Vec::new();
+    new.read_to_end(&mut new_buf)?;
+    let mut patch = Patch {
+        old_size,
+        old_crc,
+        new_size: new_buf.len() as u64,
+        new_crc: crc32fast::hash(&new_buf),
+        ops: Vec::new(),
+    };
+
+    let mut literal_start = 0;
+    let mut i = 0;
+    let mut rolling = None;
+    while i + BLOCK_SIZE <= new_buf.len() {
+        let window = &new_buf[i..i + BLOCK_SIZE];
+        let sum = *rolling.get_or_insert_with(|| Rolling::new(window));
+        let mut found = None;
+        for &index in blocks.get(&sum.digest()).into_iter().flatten() {
+            let offset = index * BLOCK_SIZE as u64;
+            old.seek(SeekFrom::Start(offset))?;
+            old.read_exact(&mut block)?;
+            if block == window {
+                found = Some(offset);
+                break;
+            }
+        }
+        if let Some(offset) = found {
+            patch.push_insert(&new_buf[literal_start..i]);
+            patch.push_copy(offset, BLOCK_SIZE as u64);
+            i += BLOCK_SIZE;
+            literal_start = i;
+            rolling = None;
+        } else {
+            if let (Some(r), Some(&inp)) = (rolling.as_mut(), new_buf.get(i + BLOCK_SIZE)) {
+                r.roll(new_buf[i], inp);
+            }
+            i += 1;
+        }
+    }
+    patch.push_insert(&new_buf[literal_start..]);
+    Ok(patch)
+}
+
+/// Apply `patch` to `old`, writing the new data to `new`.
+///
+/// Returns [`EnardError::ChecksumMismatch`] if `old` isn't the data the patch was made
+/// from, or the result doesn't match. In the second case the output is incomplete.
+pub fn apply<O: Read + Seek, W: Write>(
+    mut old: O,
+    patch: &Patch,
+    mut new: W,
+) -> Result<(), EnardError> {
+    old.seek(SeekFrom::Start(0))?;
+    let old_crc = checksum(&mut old)?;
+    if old_crc != patch.old_crc {
+        return Err(EnardError::ChecksumMismatch {
+            expected: patch.old_crc,
+            actual: old_crc,
+        });
+    }
+    let mut hasher = crc32fast::Hasher::new();
+    let mut buf = vec![0u8; BLOCK_SIZE];
+    for op in patch.ops.iter() {
+        match op {
+            PatchOp::Copy { offset, len } => {
+                old.seek(SeekFrom::Start(*offset))?;
+                let mut remaining = *len;
+                while remaining > 0 {
+                    let n = remaining.min(buf.len() as u64) as usize;
+                    old.read_exact(&mut buf[..n])?;
+                    hasher.update(&buf[..n]);
+                    new.write_all(&buf[..n])?;
+                    remaining -= n as u64;
+                }
+            }
+            PatchOp::Insert(data) => {
+                hasher.update(data);
+                new.write_all(data)?;
+            }
+        }
+    }
+    let new_crc = hasher.finalize();
+    if new_crc != patch.new_crc {
+        return Err(EnardError::ChecksumMismatch {
+            expected: patch.new_crc,
+            actual: new_crc,
+        });
+    }
+    Ok(())
+}
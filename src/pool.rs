@@ -0,0 +1,137 @@
+use std::io::{Read, Seek};
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+use zeroize::Zeroizing;
+
+use crate::cipher_factory::{CipherFactory, GetFactory};
+use crate::core::{extended_meta_size, EnardBuilder, FormatVersion, Header, Parsed, WithKey};
+use crate::dyn_cipher::BoxDynCipherFactory;
+use crate::limits::ParseLimits;
+use crate::stream::SharedReader;
+use crate::{BoxDynCipher, DynCipher, EnardError, EnardReader, KeyProvider, MetaMap};
+
+/// Opens an enard file once and hands out any number of [`EnardReader`]s for it.
+///
+/// Opening a file verifies the MAC, which means reading all of it. The pool does that
+/// once, then [`EnardReaderPool::reader`] only has to create a cipher, so e.g. each
+/// thread or each asset request in a frame can have its own reader, with its own
+/// position. The readers share the file through a [`SharedReader`], and the pool can be
+/// shared between threads.
+///
+/// ```rust
+/// # use std::io::{Cursor, Read, Seek, SeekFrom};
+/// # use enard::EnardReaderPool;
+/// # fn main() -> Result<(), enard::EnardError> {
+/// # let mut wr = enard::EnardWriter::builder(Cursor::new(Vec::new()))
+/// #     .key([0x42; 32])
+/// #     .iv([0x24; 12])
+/// #     .build()?;
+/// # wr.write_complete(&b"hello world"[..])?;
+/// # let file = Cursor::new(wr.into_inner().into_inner());
+/// let pool = EnardReaderPool::new_boxed(file, [0x42; 32])?;
+/// std::thread::spawn(move || -> Result<(), enard::EnardError> {
+///     let mut rd = pool.reader()?;
+///     rd.seek(SeekFrom::Start(6))?;
+///     let mut word = String::new();
+///     rd.read_to_string(&mut word)?;
+///     assert_eq!(word, "world");
+///     Ok(())
+/// })
+/// .join()
+/// .unwrap()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct EnardReaderPool<R, C, Cf = BoxDynCipherFactory> {
+    inner: Arc<Mutex<R>>,
+    factory: Cf,
+    cipher_key: Zeroizing<Vec<u8>>,
+    header: Header,
+    version: FormatVersion,
+    /// Offset of the header section in the reader
+    header_start: u64,
+    header_size: u64,
+    /// Size in bytes of the data, including the extended metadata
+    data_size: u64,
+    ext_meta_size: u64,
+    phantom: PhantomData<fn() -> C>,
+}
+impl<R, C, Cf> EnardReaderPool<R, C, Cf>
+where
+    R: Read + Seek,
+    C: DynCipher,
+    Cf: CipherFactory<C>,
+{
+    /// Open `reader`, verifying the MAC and parsing the header, like
+    /// [`EnardReader::new`].
+    pub fn new<K: KeyProvider>(reader: R, factory: Cf, key: K) -> Result<Self, EnardError> {
+        Self::new_with_limits(reader, factory, key, ParseLimits::default())
+    }
+
+    /// Same as [`EnardReaderPool::new`] but with custom [`ParseLimits`].
+    pub fn new_with_limits<K: KeyProvider>(
+        reader: R,
+        factory: Cf,
+        key: K,
+        limits: ParseLimits,
+    ) -> Result<Self, EnardError> {
+        let mut keys = WithKey::new(factory, key);
+        let (mut reader, mut parsed) = EnardBuilder::with_keys(reader, &mut keys)
+            .limits(limits)
+            .parse()?;
+        let ext_meta_size = extended_meta_size(&mut reader, &mut parsed)?;
+        let (factory, cipher_key) = keys.into_cipher_parts();
+        Ok(Self {
+            inner: Arc::new(Mutex::new(reader)),
+            factory,
+            cipher_key,
+            header: parsed.header,
+            version: parsed.version,
+            header_start: parsed.header_start,
+            header_size: parsed.header_size,
+            data_size: parsed.data_size,
+            ext_meta_size,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Create a new reader at the start of the data. Only the cipher is created, the MAC
+    /// was verified when the pool was created.
+    pub fn reader(&self) -> Result<EnardReader<SharedReader<R>, C>, EnardError> {
+        let cipher = self
+            .factory
+            .create(&self.header.cipher, &self.cipher_key, &self.header.iv)?;
+        let data_start = self.header_start + self.header_size;
+        let parsed = Parsed {
+            version: self.version,
+            header_start: self.header_start,
+            header_size: self.header_size,
+            data_size: self.data_size,
+            header: self.header.clone(),
+            mac_key: Zeroizing::default(),
+            cipher,
+        };
+        let inner = SharedReader::new(self.inner.clone(), data_start);
+        Ok(EnardReader::from_parsed(inner, parsed, self.ext_meta_size))
+    }
+
+    /// Metadata of the file
+    pub fn meta(&self) -> &MetaMap {
+        &self.header.meta
+    }
+
+    /// Size in bytes of the decrypted data
+    pub fn data_size(&self) -> u64 {
+        self.data_size - self.ext_meta_size
+    }
+}
+impl<R> EnardReaderPool<R, BoxDynCipher, BoxDynCipherFactory>
+where
+    R: Read + Seek,
+{
+    /// Same as [`EnardReaderPool::new`] using [`BoxDynCipher`], like
+    /// [`EnardReader::new_boxed`].
+    pub fn new_boxed<K: KeyProvider>(reader: R, key: K) -> Result<Self, EnardError> {
+        Self::new(reader, BoxDynCipher::factory(), key)
+    }
+}
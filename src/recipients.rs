@@ -0,0 +1,149 @@
+//! Wrap the file key for several recipients, so each can open the file with their own
+//! X25519 private key.
+//!
+//! The file is encrypted with a random key, as usual. [`EnardWriter::add_recipient`] then
+//! wraps that key for a recipient's public key and stores it in the metadata under
+//! [`crate::meta::RECIPIENTS`]. One file can be opened by e.g. a dev key, a QA key and the
+//! shipping key, without sharing any of them. Readers pass their private key to
+//! [`EnardReader::new_with_private_key`], or use a [`StaticSecret`] as a
+//! [`KeyProvider`].
+//!
+//! For each recipient an ephemeral X25519 key is generated, and the key-encryption key is
+//! derived from the shared secret with HKDF-SHA256. The file key is wrapped with AES-256
+//! key wrap with padding (RFC 5649), see `format.md` for the details.
+//!
+//! ```rust
+//! # use std::io::Cursor;
+//! # use enard::{BoxDynCipher, EnardReader, EnardWriter, MetaMap};
+//! # use enard::cipher_factory::GetFactory;
+//! use enard::recipients::{PublicKey, StaticSecret};
+//! # fn main() -> Result<(), enard::EnardError> {
+//! use rand::RngCore;
+//! let mut rng = rand::rngs::OsRng;
+//! let mut file_key = [0u8; 32];
+//! rng.fill_bytes(&mut file_key);
+//! let qa = StaticSecret::random_from_rng(&mut rng);
+//! let shipping = StaticSecret::random_from_rng(&mut rng);
+//! let mut wr = EnardWriter::new(
+//!     Cursor::new(Vec::new()),
+//!     BoxDynCipher::factory(),
+//!     b"ChaCha12",
+//!     file_key,
+//!     [0x24; 12].into(),
+//!     MetaMap::new(),
+//! )?
+//! .add_recipient(&PublicKey::from(&qa), &mut rng)?
+//! .add_recipient(&PublicKey::from(&shipping), &mut rng)?;
+//! wr.write_complete(&b"hello"[..])?;
+//! let file = wr.into_inner().into_inner();
+//! let rd = EnardReader::new_with_private_key(Cursor::new(file), &qa)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`EnardWriter::add_recipient`]: crate::EnardWriter::add_recipient
+//! [`EnardReader::new_with_private_key`]: crate::EnardReader::new_with_private_key
+use aes_kw::KekAes256;
+use hkdf::Hkdf;
+use rand::{CryptoRng, Rng};
+use sha2::Sha256;
+use std::io::{self, BufRead, ErrorKind, Read};
+use subtle::ConstantTimeEq;
+use zeroize::Zeroizing;
+
+pub use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::{varint, EnardError, KeyProvider, MetaMap};
+
+/// HKDF info used to derive the key-encryption key.
+const KEK_INFO: &[u8] = b"enard recipient key";
+
+/// Derive the key-encryption key for the shared secret of `ephemeral` and `recipient`.
+fn kek(shared: &[u8; 32], ephemeral: &PublicKey, recipient: &PublicKey) -> KekAes256 {
+    let mut salt = [0u8; 64];
+    salt[..32].copy_from_slice(ephemeral.as_bytes());
+    salt[32..].copy_from_slice(recipient.as_bytes());
+    let mut kek = Zeroizing::new([0u8; 32]);
+    Hkdf::<Sha256>::new(Some(&salt), shared)
+        .expand(KEK_INFO, kek.as_mut())
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    KekAes256::from(*kek)
+}
+
+/// Wrap `key` for `recipient`, returning the entry to append to the metadata.
+pub(crate) fn wrap_key<R: CryptoRng + Rng>(
+    key: &[u8],
+    recipient: &PublicKey,
+    rng: &mut R,
+) -> Result<Vec<u8>, EnardError> {
+    let secret = StaticSecret::random_from_rng(rng);
+    let ephemeral = PublicKey::from(&secret);
+    let shared = secret.diffie_hellman(recipient);
+    if !shared.was_contributory() {
+        let msg = "invalid recipient public key";
+        return Err(io::Error::new(ErrorKind::InvalidInput, msg).into());
+    }
+    let wrapped = kek(shared.as_bytes(), &ephemeral, recipient)
+        .wrap_with_padding_vec(key)
+        .map_err(|_| EnardError::InvalidLength)?;
+    let mut entry = Vec::with_capacity(64 + 2 + wrapped.len());
+    entry.extend_from_slice(recipient.as_bytes());
+    entry.extend_from_slice(ephemeral.as_bytes());
+    varint::write_u(&mut entry, wrapped.len() as u64)?;
+    entry.extend_from_slice(&wrapped);
+    Ok(entry)
+}
+
+/// One entry of [`crate::meta::RECIPIENTS`].
+struct Entry {
+    recipient: [u8; 32],
+    ephemeral: [u8; 32],
+    wrapped: Vec<u8>,
+}
+
+/// Read one entry of [`crate::meta::RECIPIENTS`], `None` at the end.
+fn read_entry<R: BufRead>(mut reader: R) -> io::Result<Option<Entry>> {
+    if reader.fill_buf()?.is_empty() {
+        return Ok(None);
+    }
+    let mut recipient = [0u8; 32];
+    let mut ephemeral = [0u8; 32];
+    reader.read_exact(&mut recipient)?;
+    reader.read_exact(&mut ephemeral)?;
+    let len = varint::read_u(&mut reader)?;
+    let mut wrapped = Vec::new();
+    reader.take(len).read_to_end(&mut wrapped)?;
+    if wrapped.len() as u64 != len {
+        return Err(ErrorKind::UnexpectedEof.into());
+    }
+    Ok(Some(Entry {
+        recipient,
+        ephemeral,
+        wrapped,
+    }))
+}
+
+/// Unwraps the file key with the private key.
+///
+/// Returns [`EnardError::WrongKey`] if the file wasn't written for this key.
+impl KeyProvider for StaticSecret {
+    fn key_for(&self, meta: &MetaMap) -> Result<Zeroizing<Vec<u8>>, EnardError> {
+        let mut entries = meta
+            .get(crate::meta::RECIPIENTS)
+            .ok_or_else(|| EnardError::new_missing_meta(crate::meta::RECIPIENTS))?
+            .as_slice();
+        let public = PublicKey::from(self);
+        while let Some(entry) = read_entry(&mut entries)? {
+            if entry.recipient.ct_eq(public.as_bytes()).unwrap_u8() == 0 {
+                continue;
+            }
+            let ephemeral = PublicKey::from(entry.ephemeral);
+            let shared = self.diffie_hellman(&ephemeral);
+            return kek(shared.as_bytes(), &ephemeral, &public)
+                .unwrap_with_padding_vec(&entry.wrapped)
+                .map(Zeroizing::new)
+                .map_err(|_| EnardError::WrongKey);
+        }
+        Err(EnardError::WrongKey)
+    }
+}
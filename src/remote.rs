@@ -0,0 +1,154 @@
+//! Read enard files directly from a remote server using HTTP Range requests.
+//!
+//! Enard doesn't depend on any particular HTTP client, instead implement [`RangeFetch`]
+//! for your client of choice and wrap it in a [`HttpRangeReader`]. For example with
+//! `ureq`:
+//!
+//! ```ignore
+//! struct UreqFetch(String);
+//! impl RangeFetch for UreqFetch {
+//!     fn content_length(&mut self) -> io::Result<u64> {
+//!         let resp = ureq::head(&self.0).call().map_err(io::Error::other)?;
+//!         resp.header("Content-Length")
+//!             .and_then(|v| v.parse().ok())
+//!             .ok_or_else(|| io::Error::other("missing Content-Length"))
+//!     }
+//!     fn fetch(&mut self, start: u64, buf: &mut [u8]) -> io::Result<usize> {
+//!         let range = format!("bytes={}-{}", start, start + buf.len() as u64 - 1);
+//!         let resp = ureq::get(&self.0).set("Range", &range).call().map_err(io::Error::other)?;
+//!         resp.into_reader().read(buf)
+//!     }
+//! }
+//! let reader = EnardReader::new_boxed(HttpRangeReader::new(UreqFetch(url))?, &key)?;
+//! ```
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom};
+
+/// Default number of bytes requested at once, see [`HttpRangeReader::with_block_size`].
+pub const DEFAULT_BLOCK_SIZE: usize = 256 * 1024;
+
+/// Fetches byte ranges of a remote resource.
+pub trait RangeFetch {
+    /// Total size in bytes of the resource (e.g. from a `HEAD` request).
+    fn content_length(&mut self) -> io::Result<u64>;
+
+    /// Fetch bytes starting at `start` into `buf` (e.g. using a `Range` header),
+    /// returning the number of bytes read. This should only return fewer bytes than
+    /// `buf.len()` at the end of the resource.
+    fn fetch(&mut self, start: u64, buf: &mut [u8]) -> io::Result<usize>;
+}
+
+/// Implements [`Read`] and [`Seek`] on top of a [`RangeFetch`].
+///
+/// Data is fetched in blocks and the most recent block is cached, so small
+/// sequential reads don't each turn into a request.
+pub struct HttpRangeReader<F> {
+    fetch: F,
+    len: u64,
+    pos: u64,
+    block: Vec<u8>,
+    /// Offset of `block` in the resource
+    block_start: u64,
+    block_size: usize,
+}
+impl<F: RangeFetch> HttpRangeReader<F> {
+    pub fn new(mut fetch: F) -> io::Result<Self> {
+        let len = fetch.content_length()?;
+        Ok(Self {
+            fetch,
+            len,
+            pos: 0,
+            block: Vec::new(),
+            block_start: 0,
+            block_size: DEFAULT_BLOCK_SIZE,
+        })
+    }
+
+    /// Set how many bytes are requested at once.
+    pub fn with_block_size(mut self, block_size: usize) -> Self {
+        self.block_size = block_size.max(1);
+        self
+    }
+
+    /// Total size of the remote resource
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns `true` if the remote resource is empty
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Unwraps this [`HttpRangeReader`], returning the underlying fetcher.
+    pub fn into_inner(self) -> F {
+        self.fetch
+    }
+
+    fn in_block(&self, pos: u64) -> bool {
+        pos >= self.block_start && pos < self.block_start + self.block.len() as u64
+    }
+
+    fn fill_block(&mut self) -> io::Result<()> {
+        let size = (self.len - self.pos).min(self.block_size as u64) as usize;
+        self.block.resize(size, 0);
+        let mut n = 0;
+        while n < size {
+            match self.fetch.fetch(self.pos + n as u64, &mut self.block[n..]) {
+                Ok(0) => break,
+                Ok(m) => n += m,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        self.block.truncate(n);
+        self.block_start = self.pos;
+        Ok(())
+    }
+}
+
+impl<F: RangeFetch> Read for HttpRangeReader<F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.len || buf.is_empty() {
+            return Ok(0);
+        }
+        if !self.in_block(self.pos) {
+            self.fill_block()?;
+            if self.block.is_empty() {
+                return Ok(0);
+            }
+        }
+        let offset = (self.pos - self.block_start) as usize;
+        let n = buf.len().min(self.block.len() - offset);
+        buf[..n].copy_from_slice(&self.block[offset..offset + n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<F: RangeFetch> Seek for HttpRangeReader<F> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::Current(rel) => checked_add_signed(self.pos, rel),
+            SeekFrom::End(rel) => checked_add_signed(self.len, rel),
+        };
+        match new_pos {
+            Some(new_pos) => {
+                self.pos = new_pos;
+                Ok(new_pos)
+            }
+            None => Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+fn checked_add_signed(base: u64, rel: i64) -> Option<u64> {
+    if rel >= 0 {
+        base.checked_add(rel as u64)
+    } else {
+        base.checked_sub(rel.unsigned_abs())
+    }
+}
@@ -0,0 +1,7 @@
+use crate::cipher_factory::impl_cipher_name;
+use salsa20::*;
+
+impl_cipher_name! { for Salsa8 }
+impl_cipher_name! { for Salsa12 }
+impl_cipher_name! { for Salsa20 }
+impl_cipher_name! { for XSalsa20 }
@@ -0,0 +1,64 @@
+//! Open files whose key is held somewhere enard can't see, such as a TPM or an OS keystore.
+//!
+//! A [`KeyProvider`](crate::KeyProvider) has to hand over the raw key bytes. A
+//! [`SealedKey`] instead creates the cipher and MAC itself, so the key only has to leave
+//! the keystore as a key schedule inside the cipher, or not at all if the keystore can
+//! compute the keystream and MAC. Pass it to [`EnardReader::new_sealed`].
+//!
+//! Each method is called once while opening the file, so implementations may block on
+//! the keystore. Async code should open the reader on a blocking task.
+//!
+//! ```rust
+//! use enard::cipher_factory::{CipherFactory, GetFactory};
+//! use enard::mac::{BoxDynMac, DefaultMacFactory, MacFactory};
+//! use enard::sealed::SealedKey;
+//! use enard::{BoxDynCipher, EnardError, EnardReader, MetaMap};
+//!
+//! /// Stand-in for a key stored in a keystore
+//! struct Keystore([u8; 32]);
+//! impl SealedKey<BoxDynCipher> for Keystore {
+//!     fn create_cipher(
+//!         &self,
+//!         name: &[u8],
+//!         iv: &[u8],
+//!         meta: &MetaMap,
+//!     ) -> Result<BoxDynCipher, EnardError> {
+//!         let key = enard::kdf::cipher_key(meta, &self.0)?;
+//!         BoxDynCipher::factory().create(name, &key, iv)
+//!     }
+//!
+//!     fn create_mac(&self, name: &[u8], meta: &MetaMap) -> Result<BoxDynMac, EnardError> {
+//!         let key = enard::kdf::mac_key(meta, &self.0)?;
+//!         DefaultMacFactory.create(name, &key)
+//!     }
+//! }
+//!
+//! # fn main() -> Result<(), EnardError> {
+//! # let mut wr = enard::EnardWriter::builder(std::io::Cursor::new(Vec::new()))
+//! #     .key([0x42; 32])
+//! #     .iv([0x24; 12])
+//! #     .build()?;
+//! # wr.write_complete(&b"hello"[..])?;
+//! # let file = std::io::Cursor::new(wr.into_inner().into_inner());
+//! let rd = EnardReader::new_sealed(file, &Keystore([0x42; 32]))?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`EnardReader::new_sealed`]: crate::EnardReader::new_sealed
+use crate::mac::BoxDynMac;
+use crate::{EnardError, MetaMap};
+
+/// A key which creates ciphers and MACs without revealing itself, see [`crate::sealed`].
+///
+/// Both methods are given the metadata, e.g. to check
+/// [`crate::kdf::SPLIT_KEYS_META_KEY`].
+pub trait SealedKey<C> {
+    /// Create the cipher `name` with the IV `iv`. This is only called once the MAC has
+    /// been verified.
+    fn create_cipher(&self, name: &[u8], iv: &[u8], meta: &MetaMap) -> Result<C, EnardError>;
+
+    /// Create the MAC `name`, see [`crate::mac`]. Note that `meta` has **not** been
+    /// verified yet, because the MAC is needed to do that.
+    fn create_mac(&self, name: &[u8], meta: &MetaMap) -> Result<BoxDynMac, EnardError>;
+}
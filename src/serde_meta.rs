@@ -0,0 +1,89 @@
+//! Serialize a [`MetaMap`] with string keys and base64 values, for JSON or TOML manifests.
+//!
+//! Metadata keys and values are bytes, which most formats can't represent nicely. Use
+//! this module with `#[serde(with = "enard::serde_meta")]` to write keys as strings and
+//! values as standard base64. Keys which aren't UTF-8 can't be serialized. Entries keep
+//! their order, like [`MetaMap`] itself.
+//!
+//! ```rust
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! #[derive(serde::Serialize, serde::Deserialize)]
+//! struct Asset {
+//!     path: String,
+//!     #[serde(with = "enard::serde_meta")]
+//!     meta: enard::MetaMap,
+//! }
+//!
+//! let mut meta = enard::MetaMap::new();
+//! meta.insert(b"name".to_vec(), b"a.png".to_vec());
+//! let asset = Asset { path: "a.enard".into(), meta };
+//! let json = serde_json::to_string(&asset)?;
+//! assert_eq!(json, r#"{"path":"a.enard","meta":{"name":"YS5wbmc="}}"#);
+//! # Ok(())
+//! # }
+//! ```
+use std::fmt;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::de::{self, Deserializer, MapAccess, Visitor};
+use serde::ser::{self, SerializeMap, Serializer};
+
+use crate::{EnardError, MetaMap};
+
+/// Serialize `meta` as a map of strings to base64 strings.
+pub fn serialize<S: Serializer>(meta: &MetaMap, serializer: S) -> Result<S::Ok, S::Error> {
+    let mut map = serializer.serialize_map(Some(meta.len()))?;
+    for (key, value) in meta.iter() {
+        let key = std::str::from_utf8(key).map_err(|_| {
+            ser::Error::custom(format!("metadata key '{}' isn't UTF-8", key.escape_ascii()))
+        })?;
+        map.serialize_entry(key, &STANDARD.encode(value))?;
+    }
+    map.end()
+}
+
+/// Deserialize a map written by [`serialize`].
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<MetaMap, D::Error> {
+    deserializer.deserialize_map(MetaVisitor)
+}
+
+struct MetaVisitor;
+impl<'de> Visitor<'de> for MetaVisitor {
+    type Value = MetaMap;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a map of metadata keys to base64 values")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<MetaMap, A::Error> {
+        let mut meta = MetaMap::with_capacity(access.size_hint().unwrap_or(0));
+        while let Some((key, value)) = access.next_entry::<String, String>()? {
+            let value = STANDARD
+                .decode(value)
+                .map_err(|_| de::Error::custom(EnardError::InvalidBase64))?;
+            meta.insert(key.into_bytes(), value);
+        }
+        Ok(meta)
+    }
+}
+
+/// Serialize a byte string as base64, e.g. `#[serde(with = "enard::serde_meta::bytes")]`.
+pub mod bytes {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    use crate::EnardError;
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        STANDARD
+            .decode(text)
+            .map_err(|_| de::Error::custom(EnardError::InvalidBase64))
+    }
+}
@@ -0,0 +1,131 @@
+//! Ed25519 signatures, so enard files can be authenticated with only a public key.
+//!
+//! The MAC can only be checked by someone who has the symmetric key, which is often
+//! shared widely (e.g. shipped inside a game). A signature lets clients check that a file
+//! came from whoever holds the signing key. Sign files with [`EnardWriter::sign_with`]
+//! and check them with [`EnardReader::verify_signature`] or [`verify_signature`], which
+//! doesn't need the symmetric key at all.
+//!
+//! The signature is stored in a block directly after the MAC tag, see `format.md`.
+//!
+//! [`EnardWriter::sign_with`]: crate::EnardWriter::sign_with
+//! [`EnardReader::verify_signature`]: crate::EnardReader::verify_signature
+use byteorder::{ReadBytesExt, LE};
+use ed25519_dalek::{Signature, Signer};
+use sha2::{Digest, Sha256};
+use std::io::{self, Read, Seek, SeekFrom};
+
+pub use ed25519_dalek::{SigningKey, VerifyingKey};
+
+use crate::core::{FormatVersion, MAGIC, TAG_SIZE};
+use crate::EnardError;
+
+/// Magic bytes at the end of a signature block.
+pub const SIGNATURE_MAGIC: &[u8; 8] = b"\x03ENARDSG";
+/// Size of a signature block, the signature followed by [`SIGNATURE_MAGIC`].
+pub const SIGNATURE_BLOCK_SIZE: usize = Signature::BYTE_SIZE + SIGNATURE_MAGIC.len();
+
+/// Builds the message which is signed, given the hash of the header and data sections.
+fn message(version: FormatVersion, header_size: u64, data_size: u64, digest: &[u8]) -> Vec<u8> {
+    let mut msg = Vec::with_capacity(MAGIC.len() + 2 + 8 + 8 + digest.len());
+    msg.extend_from_slice(MAGIC);
+    msg.extend_from_slice(&(version as u16).to_le_bytes());
+    msg.extend_from_slice(&header_size.to_le_bytes());
+    msg.extend_from_slice(&data_size.to_le_bytes());
+    msg.extend_from_slice(digest);
+    msg
+}
+
+/// Signing state for [`crate::EnardWriter`], hashes the header and data as they're written.
+pub(crate) struct WriteSigner {
+    key: SigningKey,
+    hasher: Sha256,
+}
+impl WriteSigner {
+    pub fn new(key: SigningKey) -> Self {
+        Self {
+            key,
+            hasher: Sha256::new(),
+        }
+    }
+
+    pub fn update(&mut self, b: &[u8]) {
+        self.hasher.update(b);
+    }
+
+    /// Returns the signature block to write after the MAC tag.
+    pub fn finish(self, version: FormatVersion, header_size: u64, data_size: u64) -> Vec<u8> {
+        let digest = self.hasher.finalize();
+        let msg = message(version, header_size, data_size, &digest);
+        let mut block = self.key.sign(&msg).to_bytes().to_vec();
+        block.extend_from_slice(SIGNATURE_MAGIC);
+        block
+    }
+}
+
+/// Verify the signature of the enard file starting at the current position of `reader`.
+///
+/// This only needs the public key, the data isn't decrypted. Returns
+/// [`EnardError::InvalidSignature`] if the file isn't signed or the signature is wrong.
+pub fn verify_signature<R: Read + Seek>(
+    mut reader: R,
+    public_key: &VerifyingKey,
+) -> Result<(), EnardError> {
+    let start = reader.stream_position()?;
+    let mut magic_buf = [0u8; MAGIC.len()];
+    reader.read_exact(&mut magic_buf)?;
+    if &magic_buf != MAGIC {
+        return Err(EnardError::new_invalid_magic(MAGIC, &magic_buf));
+    }
+    let (version, _) = FormatVersion::from_field(reader.read_u16::<LE>()?)?;
+    let header_size = match version {
+        FormatVersion::V1 => reader.read_u32::<LE>()? as u64,
+        FormatVersion::V2 => reader.read_u64::<LE>()?,
+    };
+    let data_size = reader.read_u64::<LE>()?;
+    let header_start = start + version.header_start() as u64;
+    verify_at(
+        reader,
+        header_start,
+        version,
+        header_size,
+        data_size,
+        public_key,
+    )
+}
+
+/// Verify the signature of an enard file whose prefix has already been parsed.
+pub(crate) fn verify_at<R: Read + Seek>(
+    mut reader: R,
+    header_start: u64,
+    version: FormatVersion,
+    header_size: u64,
+    data_size: u64,
+    public_key: &VerifyingKey,
+) -> Result<(), EnardError> {
+    reader.seek(SeekFrom::Start(header_start))?;
+    let mut hasher = Sha256::new();
+    let len = header_size + data_size;
+    let n = io::copy(&mut (&mut reader).take(len), &mut hasher)?;
+    if n != len {
+        return Err(EnardError::InvalidSignature);
+    }
+    reader.seek(SeekFrom::Current(TAG_SIZE as i64))?;
+    let mut block = [0u8; SIGNATURE_BLOCK_SIZE];
+    match reader.read_exact(&mut block) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+            return Err(EnardError::InvalidSignature)
+        }
+        Err(e) => return Err(e.into()),
+    }
+    let (sig, magic) = block.split_at(Signature::BYTE_SIZE);
+    if magic != SIGNATURE_MAGIC {
+        return Err(EnardError::InvalidSignature);
+    }
+    let sig = Signature::from_slice(sig).map_err(|_| EnardError::InvalidSignature)?;
+    let msg = message(version, header_size, data_size, &hasher.finalize());
+    public_key
+        .verify_strict(&msg, &sig)
+        .map_err(|_| EnardError::InvalidSignature)
+}
@@ -0,0 +1,227 @@
+//! Split one enard file across several files, e.g. to stay under the 4 GB limit of FAT32
+//! or a platform's maximum pack size.
+//!
+//! A [`SplitFile`] joins the parts into one stream which can be read, written and seeked
+//! as if it were a single file. Parts are named by replacing `{}` in a path template with
+//! the part number, starting at 0, so `"assets.enard.{}"` gives `assets.enard.0`,
+//! `assets.enard.1`, and so on. Write split files with [`crate::EnardWriter::new_split`],
+//! and read them by passing [`SplitFile::open`] to any [`crate::EnardReader`]
+//! constructor.
+//!
+//! ```rust,no_run
+//! # use enard::cipher_factory::GetFactory;
+//! # use enard::split::SplitFile;
+//! # use enard::{BoxDynCipher, EnardReader, EnardWriter, MetaMap};
+//! # fn main() -> Result<(), enard::EnardError> {
+//! # let (key, data) = ([0x42u8; 32], &b"..."[..]);
+//! let mut wr = EnardWriter::new_split(
+//!     "assets.enard.{}",
+//!     4 << 30,
+//!     BoxDynCipher::factory(),
+//!     b"ChaCha12",
+//!     key,
+//!     [0x24; 12].into(),
+//!     MetaMap::new(),
+//! )?;
+//! wr.write_complete(data)?;
+//!
+//! let rd = EnardReader::new_boxed(SplitFile::open("assets.enard.{}")?, key)?;
+//! # Ok(())
+//! # }
+//! ```
+use std::fs::{File, OpenOptions};
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+use crate::stream::checked_add_signed;
+
+/// Several files joined into one stream, see [`crate::split`].
+#[derive(Debug)]
+pub struct SplitFile {
+    template: String,
+    /// Maximum size of each part when writing, `None` if opened for reading
+    max_part_size: Option<u64>,
+    parts: Vec<File>,
+    /// Offset of each part in the stream
+    starts: Vec<u64>,
+    len: u64,
+    pos: u64,
+}
+impl SplitFile {
+    /// Create a new split file for writing, with parts of at most `max_part_size` bytes.
+    ///
+    /// Parts are created as they're needed. Existing parts with the same template are
+    /// removed first, so none are left over from a larger file.
+    pub fn create(template: &str, max_part_size: u64) -> io::Result<Self> {
+        check_template(template)?;
+        if max_part_size == 0 {
+            let msg = "the maximum part size must not be 0";
+            return Err(io::Error::new(ErrorKind::InvalidInput, msg));
+        }
+        for index in 0.. {
+            match std::fs::remove_file(part_path(template, index)) {
+                Ok(()) => {}
+                Err(e) if e.kind() == ErrorKind::NotFound => break,
+                Err(e) => return Err(e),
+            }
+        }
+        let mut file = Self {
+            template: template.to_string(),
+            max_part_size: Some(max_part_size),
+            parts: Vec::new(),
+            starts: Vec::new(),
+            len: 0,
+            pos: 0,
+        };
+        file.add_part()?;
+        Ok(file)
+    }
+
+    /// Open the parts of an existing split file for reading, in order until a part is
+    /// missing. Parts may have any size.
+    pub fn open(template: &str) -> io::Result<Self> {
+        check_template(template)?;
+        let mut file = Self {
+            template: template.to_string(),
+            max_part_size: None,
+            parts: Vec::new(),
+            starts: Vec::new(),
+            len: 0,
+            pos: 0,
+        };
+        loop {
+            let part = match File::open(part_path(template, file.parts.len())) {
+                Ok(part) => part,
+                Err(e) if e.kind() == ErrorKind::NotFound && !file.parts.is_empty() => break,
+                Err(e) => return Err(e),
+            };
+            file.starts.push(file.len);
+            file.len += part.metadata()?.len();
+            file.parts.push(part);
+        }
+        Ok(file)
+    }
+
+    /// Number of parts
+    pub fn part_count(&self) -> usize {
+        self.parts.len()
+    }
+
+    /// Total size of all parts
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns `true` if all parts are empty
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Flush all parts to disk, see [`File::sync_all`].
+    pub fn sync_all(&self) -> io::Result<()> {
+        self.parts.iter().try_for_each(File::sync_all)
+    }
+
+    /// Create the next part when writing.
+    fn add_part(&mut self) -> io::Result<()> {
+        let path = part_path(&self.template, self.parts.len());
+        let part = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        let start = self.max_part_size.unwrap_or(0) * self.parts.len() as u64;
+        self.starts.push(start);
+        self.parts.push(part);
+        Ok(())
+    }
+
+    /// Returns the part containing `pos`, and how many bytes of it are left after `pos`.
+    fn part_at(&self, pos: u64) -> (usize, u64) {
+        let index = match self.starts.binary_search(&pos) {
+            Ok(i) => i,
+            Err(i) => i - 1,
+        };
+        let end = match self.max_part_size {
+            Some(max) => self.starts[index] + max,
+            None => self.starts.get(index + 1).copied().unwrap_or(self.len),
+        };
+        (index, end.saturating_sub(pos))
+    }
+}
+impl Read for SplitFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.len || buf.is_empty() {
+            return Ok(0);
+        }
+        let (index, left) = self.part_at(self.pos);
+        let left = left.min(self.len - self.pos);
+        let limit = (buf.len() as u64).min(left) as usize;
+        let part = &mut self.parts[index];
+        part.seek(SeekFrom::Start(self.pos - self.starts[index]))?;
+        let n = part.read(&mut buf[..limit])?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+impl Write for SplitFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let max = match self.max_part_size {
+            Some(max) => max,
+            None => {
+                let msg = "split file was opened for reading";
+                return Err(io::Error::new(ErrorKind::PermissionDenied, msg));
+            }
+        };
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        while self.parts.len() as u64 <= self.pos / max {
+            self.add_part()?;
+        }
+        let (index, left) = self.part_at(self.pos);
+        let limit = (buf.len() as u64).min(left) as usize;
+        let part = &mut self.parts[index];
+        part.seek(SeekFrom::Start(self.pos - self.starts[index]))?;
+        let n = part.write(&buf[..limit])?;
+        self.pos += n as u64;
+        self.len = self.len.max(self.pos);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.parts.iter_mut().try_for_each(File::flush)
+    }
+}
+impl Seek for SplitFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(p) => Some(p),
+            SeekFrom::Current(rel) => checked_add_signed(self.pos, rel),
+            SeekFrom::End(rel) => checked_add_signed(self.len, rel),
+        };
+        self.pos = new_pos.ok_or_else(|| {
+            let msg = format!("invalid seek to {:?}", pos);
+            io::Error::new(ErrorKind::InvalidInput, msg)
+        })?;
+        Ok(self.pos)
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        Ok(self.pos)
+    }
+}
+
+/// Path of part `index` of `template`.
+pub fn part_path(template: &str, index: usize) -> PathBuf {
+    PathBuf::from(template.replacen("{}", &index.to_string(), 1))
+}
+
+fn check_template(template: &str) -> io::Result<()> {
+    if !template.contains("{}") {
+        let msg = format!("path template '{}' must contain '{{}}'", template);
+        return Err(io::Error::new(ErrorKind::InvalidInput, msg));
+    }
+    Ok(())
+}
@@ -0,0 +1,520 @@
+//! Stream adapters for building other container formats.
+//!
+//! [`CipherRead`] and [`CipherWriteStream`] apply a cipher's keystream to a plain
+//! stream, without the enard header or MAC. [`SubSeek`] is a bounded view of part of a
+//! stream, see also [`crate::EnardReader::sub_reader`]. [`ReadSeek`] and [`WriteSeek`]
+//! make it possible to box streams, e.g. for [`crate::EnardReader::new_dyn`].
+//! On Unix, [`PositionedFile`] reads a file with positioned reads, so seeking is free.
+//! [`AlignedRead`] only makes sector-aligned reads, for unbuffered or direct I/O.
+//! [`SharedReader`] gives several readers their own position in one stream.
+//!
+//! ```rust
+//! use chacha20::ChaCha12;
+//! use enard::cipher_factory::{CipherFactory, GetFactory};
+//! use enard::stream::{CipherRead, CipherWriteStream};
+//! use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+//! # fn main() -> Result<(), enard::EnardError> {
+//! let (key, iv) = ([0x42; 32], [0x24; 12]);
+//! let cipher = ChaCha12::factory().create(b"", &key, &iv)?;
+//! let mut wr = CipherWriteStream::new(Vec::new(), cipher);
+//! wr.write_all(b"hello world")?;
+//! let encrypted = wr.into_inner();
+//!
+//! let cipher = ChaCha12::factory().create(b"", &key, &iv)?;
+//! let mut rd = CipherRead::new(Cursor::new(encrypted), cipher);
+//! rd.seek(SeekFrom::Start(6))?;
+//! let mut word = String::new();
+//! rd.read_to_string(&mut word)?;
+//! assert_eq!(word, "world");
+//! # Ok(())
+//! # }
+//! ```
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex};
+
+use crate::core::{round_to_crypt_block, CryptBuf};
+use crate::{DynCipher, DEFAULT_BUFFER_SIZE};
+
+/// A stream which is [`Read`] and [`Seek`], so it can be used as `Box<dyn ReadSeek>`.
+///
+/// Implemented for every type which is both.
+pub trait ReadSeek: Read + Seek {}
+impl<T: Read + Seek + ?Sized> ReadSeek for T {}
+
+/// A stream which is [`Write`] and [`Seek`], so it can be used as `Box<dyn WriteSeek>`,
+/// e.g. for [`crate::EnardWriter::builder`].
+///
+/// Implemented for every type which is both.
+pub trait WriteSeek: Write + Seek {}
+impl<T: Write + Seek + ?Sized> WriteSeek for T {}
+
+/// Decrypts (or encrypts) everything read from `inner` with the keystream of a cipher.
+///
+/// Position 0 of `inner` is position 0 of the keystream, so seeking `inner` moves the
+/// cipher to the same position.
+pub struct CipherRead<R, C> {
+    inner: R,
+    cipher: C,
+}
+impl<R, C: DynCipher> CipherRead<R, C> {
+    /// Wrap `inner`, which must be at the position matching the cipher's position.
+    pub fn new(inner: R, cipher: C) -> Self {
+        Self { inner, cipher }
+    }
+
+    /// Unwraps this [`CipherRead`], returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+impl<R: Read, C: DynCipher> Read for CipherRead<R, C> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.cipher
+            .try_apply_keystream(&mut buf[..n])
+            .map_err(|e| io::Error::new(ErrorKind::Other, format!("{:?}", e)))?;
+        Ok(n)
+    }
+}
+impl<R: Seek, C: DynCipher> Seek for CipherRead<R, C> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_pos = self.inner.seek(pos)?;
+        self.cipher
+            .try_seek(new_pos)
+            .map_err(|e| io::Error::new(ErrorKind::Other, format!("{:?}", e)))?;
+        Ok(new_pos)
+    }
+}
+
+/// Encrypts everything written with the keystream of a cipher before passing it to `inner`.
+///
+/// The plaintext is copied into an internal buffer to be encrypted, which is zeroed when
+/// the stream is dropped.
+pub struct CipherWriteStream<W, C> {
+    inner: W,
+    cipher: C,
+    buf: CryptBuf,
+}
+impl<W, C: DynCipher> CipherWriteStream<W, C> {
+    /// Wrap `inner`, using a buffer of [`DEFAULT_BUFFER_SIZE`] bytes.
+    pub fn new(inner: W, cipher: C) -> Self {
+        Self::with_buffer_size(inner, cipher, DEFAULT_BUFFER_SIZE)
+    }
+
+    /// Wrap `inner`, encrypting at most `size` bytes at a time. `size` is rounded up to a
+    /// multiple of [`crate::CRYPT_BLOCK_SIZE`].
+    pub fn with_buffer_size(inner: W, cipher: C, size: usize) -> Self {
+        Self {
+            inner,
+            cipher,
+            buf: CryptBuf::new(round_to_crypt_block(size.max(1))),
+        }
+    }
+
+    /// Unwraps this [`CipherWriteStream`], returning the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+impl<W: Write, C: DynCipher> Write for CipherWriteStream<W, C> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = buf.len().min(self.buf.len());
+        let chunk = &mut self.buf[..n];
+        chunk.copy_from_slice(&buf[..n]);
+        self.cipher
+            .try_apply_keystream(chunk)
+            .map_err(|e| io::Error::new(ErrorKind::Other, format!("{:?}", e)))?;
+        // The keystream has been used, so the whole chunk has to be written
+        self.inner.write_all(chunk)?;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A bounded view of `len` bytes of `inner` starting at `start`.
+///
+/// Positions are relative to `start`, and reads stop at the end of the view. Seeking
+/// outside of the view is an error.
+///
+/// ```rust
+/// # use std::io::{Cursor, Read};
+/// # use enard::stream::SubSeek;
+/// let mut sub = SubSeek::new(Cursor::new(b"hello world"), 6, 5).unwrap();
+/// let mut word = String::new();
+/// sub.read_to_string(&mut word).unwrap();
+/// assert_eq!(word, "world");
+/// ```
+pub struct SubSeek<R> {
+    inner: R,
+    start: u64,
+    len: u64,
+    /// Position relative to `start`, the inner stream is always at `start + pos`
+    pos: u64,
+}
+impl<R: Seek> SubSeek<R> {
+    /// Create a view of `inner`, seeking it to `start`. The current position of `inner`
+    /// doesn't matter.
+    pub fn new(mut inner: R, start: u64, len: u64) -> io::Result<Self> {
+        inner.seek(SeekFrom::Start(start))?;
+        Ok(Self {
+            inner,
+            start,
+            len,
+            pos: 0,
+        })
+    }
+
+    /// Size of the view in bytes
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns `true` if the view is empty
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Unwraps this [`SubSeek`], returning the underlying stream.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+impl<R: Read> Read for SubSeek<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let limit = (buf.len() as u64).min(self.len - self.pos) as usize;
+        let n = self.inner.read(&mut buf[..limit])?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+impl<R: Seek> Seek for SubSeek<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(p) => Some(p),
+            SeekFrom::Current(rel) => checked_add_signed(self.pos, rel),
+            SeekFrom::End(rel) => checked_add_signed(self.len, rel),
+        };
+        let new_pos = match new_pos {
+            Some(p) if p <= self.len => p,
+            _ => {
+                let msg = format!("invalid seek to {:?}, the view is {} bytes", pos, self.len);
+                return Err(io::Error::new(ErrorKind::InvalidInput, msg));
+            }
+        };
+        self.inner.seek(SeekFrom::Start(self.start + new_pos))?;
+        self.pos = new_pos;
+        Ok(new_pos)
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        Ok(self.pos)
+    }
+}
+
+/// Sector size assumed by [`AlignedRead`]. This is also the largest alignment
+/// [`crate::EnardWriter::with_data_alignment`] accepts.
+pub const SECTOR_SIZE: usize = 4096;
+
+/// Only reads whole [`SECTOR_SIZE`] blocks at sector-aligned offsets from `inner`, into a
+/// sector-aligned buffer, and copies the requested bytes out of it.
+///
+/// Files opened for unbuffered I/O (`FILE_FLAG_NO_BUFFERING` on Windows, `O_DIRECT` on
+/// Linux) reject any other reads. Wrap them in an [`AlignedRead`] before passing them to
+/// [`crate::EnardReader`], which decrypts into the caller's buffer as usual. Write files
+/// with `EnardWriter::with_data_alignment(SECTOR_SIZE)` so the data starts on a sector.
+///
+/// Reads smaller than the buffer are served from it, like a [`std::io::BufReader`].
+///
+/// ```rust
+/// # use std::io::{Cursor, Read};
+/// use enard::stream::{AlignedRead, SECTOR_SIZE};
+/// # fn main() -> Result<(), enard::EnardError> {
+/// let mut wr = enard::EnardWriter::builder(Cursor::new(Vec::new()))
+///     .key([0x42; 32])
+///     .iv([0x24; 12])
+///     .build()?
+///     .with_data_alignment(SECTOR_SIZE);
+/// wr.write_complete(&b"hello"[..])?;
+/// let file = Cursor::new(wr.into_inner().into_inner());
+/// let mut rd = enard::EnardReader::new_boxed(AlignedRead::new(file), [0x42; 32])?;
+/// let mut text = String::new();
+/// rd.read_to_string(&mut text)?;
+/// assert_eq!(text, "hello");
+/// # Ok(())
+/// # }
+/// ```
+pub struct AlignedRead<R> {
+    inner: R,
+    /// Has an extra sector, so there's always an aligned `capacity` bytes inside it
+    buf: Vec<u8>,
+    capacity: usize,
+    /// Offset in `inner` of the start of the buffer
+    buf_start: u64,
+    /// Number of valid bytes in the buffer
+    buf_len: usize,
+    pos: u64,
+    /// Position of `inner`, if known
+    inner_pos: Option<u64>,
+}
+impl<R> AlignedRead<R> {
+    /// Wrap `inner` with a buffer of [`DEFAULT_BUFFER_SIZE`], reading from position 0.
+    pub fn new(inner: R) -> Self {
+        Self::with_capacity(DEFAULT_BUFFER_SIZE, inner)
+    }
+
+    /// Wrap `inner` with a buffer of `capacity` bytes, rounded up to whole sectors.
+    pub fn with_capacity(capacity: usize, inner: R) -> Self {
+        let capacity = (capacity.max(1) + SECTOR_SIZE - 1) & !(SECTOR_SIZE - 1);
+        Self {
+            inner,
+            buf: vec![0u8; capacity + SECTOR_SIZE],
+            capacity,
+            buf_start: 0,
+            buf_len: 0,
+            pos: 0,
+            inner_pos: None,
+        }
+    }
+
+    /// Unwraps this [`AlignedRead`], returning the underlying stream.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// The sector-aligned part of `buf`
+    fn aligned_buf(buf: &mut [u8], capacity: usize) -> &mut [u8] {
+        let offset = buf.as_ptr().align_offset(SECTOR_SIZE);
+        &mut buf[offset..offset + capacity]
+    }
+}
+impl<R: Read + Seek> AlignedRead<R> {
+    /// Fill the buffer starting at the sector containing `pos`.
+    fn fill(&mut self, pos: u64) -> io::Result<()> {
+        let start = pos - pos % SECTOR_SIZE as u64;
+        self.buf_len = 0;
+        if self.inner_pos != Some(start) {
+            self.inner_pos = None;
+            self.inner.seek(SeekFrom::Start(start))?;
+        }
+        let buf = Self::aligned_buf(&mut self.buf, self.capacity);
+        let mut n = 0;
+        // Only the last read before the end of the file may be short
+        while n < buf.len() {
+            match self.inner.read(&mut buf[n..]) {
+                Ok(0) => break,
+                Ok(m) => {
+                    n += m;
+                    if m % SECTOR_SIZE != 0 {
+                        break;
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => {
+                    self.inner_pos = None;
+                    return Err(e);
+                }
+            }
+        }
+        self.buf_start = start;
+        self.buf_len = n;
+        self.inner_pos = Some(start + n as u64);
+        Ok(())
+    }
+}
+impl<R: Read + Seek> Read for AlignedRead<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let buf_end = self.buf_start + self.buf_len as u64;
+        if self.pos < self.buf_start || self.pos >= buf_end {
+            self.fill(self.pos)?;
+        }
+        let offset = (self.pos - self.buf_start) as usize;
+        if offset >= self.buf_len {
+            return Ok(0);
+        }
+        let n = out.len().min(self.buf_len - offset);
+        let buf = Self::aligned_buf(&mut self.buf, self.capacity);
+        out[..n].copy_from_slice(&buf[offset..offset + n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+impl<R: Seek> Seek for AlignedRead<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(p) => Some(p),
+            SeekFrom::Current(rel) => checked_add_signed(self.pos, rel),
+            SeekFrom::End(rel) => {
+                self.inner_pos = None;
+                let end = self.inner.seek(SeekFrom::End(0))?;
+                self.inner_pos = Some(end);
+                checked_add_signed(end, rel)
+            }
+        };
+        self.pos = new_pos.ok_or_else(|| {
+            let msg = format!("invalid seek to {:?}", pos);
+            io::Error::new(ErrorKind::InvalidInput, msg)
+        })?;
+        Ok(self.pos)
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        Ok(self.pos)
+    }
+}
+
+/// Reads a [`File`](std::fs::File) with positioned reads (`pread`), only keeping track
+/// of the position itself.
+///
+/// [`crate::EnardReader`] seeks its inner reader whenever the caller seeks, so random
+/// access to a plain `File` costs a seek and a read each time. With a `PositionedFile`
+/// seeking doesn't need a syscall, halving that. The file's own position is never used,
+/// so several readers can share one file, e.g. through an `Arc<File>` or `&File`.
+///
+/// Seeking relative to the end asks the OS for the file size. Only available on Unix.
+///
+/// ```rust,no_run
+/// # fn main() -> Result<(), enard::EnardError> {
+/// use enard::stream::PositionedFile;
+/// let file = std::sync::Arc::new(std::fs::File::open("assets.enard")?);
+/// let rd = enard::EnardReader::new_boxed(PositionedFile::new(file.clone()), [0x42; 32])?;
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(unix)]
+pub struct PositionedFile<F = std::fs::File> {
+    file: F,
+    pos: u64,
+}
+#[cfg(unix)]
+impl<F: std::borrow::Borrow<std::fs::File>> PositionedFile<F> {
+    /// Read `file` starting at position 0, whatever the file's own position is.
+    pub fn new(file: F) -> Self {
+        Self { file, pos: 0 }
+    }
+
+    /// Returns a reference to the file
+    pub fn get_ref(&self) -> &F {
+        &self.file
+    }
+
+    /// Unwraps this [`PositionedFile`], returning the file. Its position is unchanged.
+    pub fn into_inner(self) -> F {
+        self.file
+    }
+}
+#[cfg(unix)]
+impl<F: std::borrow::Borrow<std::fs::File>> Read for PositionedFile<F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        use std::os::unix::fs::FileExt;
+        let n = self.file.borrow().read_at(buf, self.pos)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+#[cfg(unix)]
+impl<F: std::borrow::Borrow<std::fs::File>> Seek for PositionedFile<F> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(p) => Some(p),
+            SeekFrom::Current(rel) => checked_add_signed(self.pos, rel),
+            SeekFrom::End(rel) => checked_add_signed(self.file.borrow().metadata()?.len(), rel),
+        };
+        self.pos = new_pos.ok_or_else(|| {
+            let msg = format!("invalid seek to {:?}", pos);
+            io::Error::new(ErrorKind::InvalidInput, msg)
+        })?;
+        Ok(self.pos)
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        Ok(self.pos)
+    }
+}
+
+/// A handle to a stream shared with other handles, each with its own position.
+///
+/// Every read locks the stream and seeks it to this handle's position first, so handles
+/// can be used from different threads. Cloning a handle keeps its position. This works
+/// with any stream, but on Unix [`PositionedFile`] avoids the lock for files.
+///
+/// ```rust
+/// # use std::io::{Cursor, Read};
+/// # use std::sync::{Arc, Mutex};
+/// use enard::stream::SharedReader;
+/// let inner = Arc::new(Mutex::new(Cursor::new(b"hello world")));
+/// let mut a = SharedReader::new(inner.clone(), 0);
+/// let mut b = SharedReader::new(inner, 6);
+/// let mut buf = [0u8; 5];
+/// b.read_exact(&mut buf).unwrap();
+/// assert_eq!(&buf, b"world");
+/// a.read_exact(&mut buf).unwrap();
+/// assert_eq!(&buf, b"hello");
+/// ```
+#[derive(Debug)]
+pub struct SharedReader<R> {
+    inner: Arc<Mutex<R>>,
+    pos: u64,
+}
+impl<R> SharedReader<R> {
+    /// Create a handle to `inner` at position `pos`.
+    pub fn new(inner: Arc<Mutex<R>>, pos: u64) -> Self {
+        Self { inner, pos }
+    }
+
+    /// Returns the shared stream
+    pub fn get_ref(&self) -> &Arc<Mutex<R>> {
+        &self.inner
+    }
+
+    fn lock(&self) -> io::Result<std::sync::MutexGuard<'_, R>> {
+        self.inner.lock().map_err(|_| {
+            let msg = "a thread panicked while using the shared stream";
+            io::Error::new(ErrorKind::Other, msg)
+        })
+    }
+}
+impl<R> Clone for SharedReader<R> {
+    fn clone(&self) -> Self {
+        Self::new(self.inner.clone(), self.pos)
+    }
+}
+impl<R: Read + Seek> Read for SharedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = {
+            let mut inner = self.lock()?;
+            inner.seek(SeekFrom::Start(self.pos))?;
+            inner.read(buf)?
+        };
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+impl<R: Seek> Seek for SharedReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(p) => Some(p),
+            SeekFrom::Current(rel) => checked_add_signed(self.pos, rel),
+            SeekFrom::End(rel) => checked_add_signed(self.lock()?.seek(SeekFrom::End(0))?, rel),
+        };
+        self.pos = new_pos.ok_or_else(|| {
+            let msg = format!("invalid seek to {:?}", pos);
+            io::Error::new(ErrorKind::InvalidInput, msg)
+        })?;
+        Ok(self.pos)
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        Ok(self.pos)
+    }
+}
+
+pub(crate) fn checked_add_signed(base: u64, rel: i64) -> Option<u64> {
+    if rel >= 0 {
+        base.checked_add(rel as u64)
+    } else {
+        base.checked_sub(rel.unsigned_abs())
+    }
+}
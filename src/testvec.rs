@@ -0,0 +1,137 @@
+//! Canonical enard files for checking other implementations, and this one across versions.
+//!
+//! [`generate`] writes the same small file with each supported cipher and format version,
+//! using the fixed [`KEY`], [`IV`], [`PLAINTEXT`] and [`meta`]. An implementation in
+//! another language should produce exactly the same bytes, and be able to read them
+//! back. [`verify_test_vectors`] checks files against the ones written by this version
+//! of enard. `enard test-vectors` writes them to a directory, or verifies a directory
+//! written by another implementation.
+//!
+//! ```rust
+//! # fn main() -> Result<(), enard::EnardError> {
+//! let vectors = enard::testvec::generate()?;
+//! enard::testvec::verify_test_vectors(&vectors)?;
+//! # Ok(())
+//! # }
+//! ```
+use std::io::{self, Cursor, ErrorKind, Read};
+
+use crate::{EnardError, EnardReader, EnardWriter, FormatVersion, MetaMap};
+
+/// Key of every encrypted test vector
+pub const KEY: [u8; 32] = [0x42; 32];
+/// IV of every encrypted test vector
+pub const IV: [u8; 12] = [0x24; 12];
+/// Data of every test vector, long enough to span several cipher blocks
+pub const PLAINTEXT: &[u8] = b"enard test vector: the quick brown fox jumps over the lazy dog, \
+    and then does it again to cross a block boundary.";
+
+/// Ciphers with test vectors, the empty name is an unencrypted file
+const CIPHERS: &[&[u8]] = &[
+    b"",
+    #[cfg(feature = "chacha")]
+    b"ChaCha8",
+    #[cfg(feature = "chacha")]
+    b"ChaCha12",
+    #[cfg(feature = "chacha")]
+    b"ChaCha20",
+];
+
+/// A canonical enard file, see [`crate::testvec`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestVector {
+    /// Unique name such as `chacha12-v2`, which can be used as a file name
+    pub name: String,
+    /// Ascii name of the cipher, empty if the file isn't encrypted
+    pub cipher: Vec<u8>,
+    pub version: FormatVersion,
+    /// The complete enard file
+    pub file: Vec<u8>,
+}
+
+/// Metadata of every test vector. Unencrypted files also have
+/// [`crate::meta::UNENCRYPTED`].
+pub fn meta() -> MetaMap {
+    let mut meta = MetaMap::new();
+    meta.insert(b"name".to_vec(), b"test-vector.txt".to_vec());
+    meta.insert(b"empty".to_vec(), Vec::new());
+    meta
+}
+
+/// Write the test vectors for every cipher this build supports, in both format versions.
+pub fn generate() -> Result<Vec<TestVector>, EnardError> {
+    let mut vectors = Vec::new();
+    for cipher in CIPHERS {
+        for version in [FormatVersion::V1, FormatVersion::V2] {
+            vectors.push(generate_one(cipher, version)?);
+        }
+    }
+    Ok(vectors)
+}
+
+fn generate_one(cipher: &[u8], version: FormatVersion) -> Result<TestVector, EnardError> {
+    let inner = Cursor::new(Vec::new());
+    let mut wr = if cipher.is_empty() {
+        EnardWriter::new_unencrypted(inner, meta())?
+    } else {
+        EnardWriter::builder(inner)
+            .cipher(cipher)
+            .key(KEY)
+            .iv(IV)
+            .meta_map(meta())
+            .build()?
+    }
+    .with_version(version);
+    wr.write_complete(PLAINTEXT)?;
+    let cipher_name = if cipher.is_empty() {
+        "none".to_string()
+    } else {
+        String::from_utf8_lossy(cipher).to_lowercase()
+    };
+    Ok(TestVector {
+        name: format!("{}-v{}", cipher_name, u16::from(version)),
+        cipher: cipher.to_vec(),
+        version,
+        file: wr.into_inner().into_inner(),
+    })
+}
+
+/// Check that each of `vectors` can be read back, and is exactly the file this version
+/// of enard writes for its cipher and version.
+///
+/// Returns an [`ErrorKind::InvalidData`] error naming the first vector which doesn't
+/// match.
+pub fn verify_test_vectors(vectors: &[TestVector]) -> Result<(), EnardError> {
+    for vector in vectors {
+        let mismatch = |what: &str| {
+            let msg = format!("test vector {}: {} doesn't match", vector.name, what);
+            EnardError::from(io::Error::new(ErrorKind::InvalidData, msg))
+        };
+        let file = Cursor::new(&vector.file[..]);
+        let mut rd = if vector.cipher.is_empty() {
+            EnardReader::new_unencrypted(file)?
+        } else {
+            EnardReader::new_boxed(file, KEY)?
+        };
+        if rd.version() != vector.version {
+            return Err(mismatch("version"));
+        }
+        let mut expected_meta = meta();
+        if vector.cipher.is_empty() {
+            expected_meta.insert(crate::meta::UNENCRYPTED.to_vec(), Vec::new());
+        }
+        if rd.meta() != &expected_meta {
+            return Err(mismatch("metadata"));
+        }
+        let mut data = Vec::new();
+        rd.read_to_end(&mut data)?;
+        if data != PLAINTEXT {
+            return Err(mismatch("plaintext"));
+        }
+        let expected = generate_one(&vector.cipher, vector.version)?;
+        if vector.file != expected.file {
+            return Err(mismatch("file"));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,66 @@
+//! Buffer sizes used by enard, and [`ReaderOptions`] to choose them when opening a file.
+//!
+//! There are three buffers on the read path:
+//!
+//! - The inner buffer, a [`std::io::BufReader`] around the file, which sets how much is
+//!   read from the disk at a time.
+//! - The read-ahead buffer, see [`EnardReader::with_readahead`], which decrypts ahead of
+//!   small reads so each one doesn't cost a cipher call.
+//! - The crypt chunk, see [`EnardReader::with_crypt_chunk`], which
+//!   [`EnardReader::copy_to`] reads and decrypts at a time.
+//!
+//! The defaults suit loading assets from an SSD. Run `cargo bench -- tuning` to compare
+//! sizes on the target hardware, and keep the inner buffer at least as large as the
+//! usual read.
+//!
+//! ```rust,no_run
+//! use enard::tuning::ReaderOptions;
+//! use enard::EnardReader;
+//! # fn main() -> Result<(), enard::EnardError> {
+//! // Streaming large files: read more at a time, and skip the read-ahead buffer
+//! let opts = ReaderOptions {
+//!     inner_buffer: 1024 * 1024,
+//!     readahead: 0,
+//!     ..ReaderOptions::default()
+//! };
+//! let mut rd = EnardReader::open_with_options("video.enard", [0x42u8; 32], &opts)?;
+//! rd.copy_to(&mut std::io::sink())?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`EnardReader::with_readahead`]: crate::EnardReader::with_readahead
+//! [`EnardReader::with_crypt_chunk`]: crate::EnardReader::with_crypt_chunk
+//! [`EnardReader::copy_to`]: crate::EnardReader::copy_to
+pub use crate::core::{COPY_BUFFER_SIZE, CRYPT_ALIGN, CRYPT_BLOCK_SIZE, DEFAULT_BUFFER_SIZE};
+
+/// Default [`ReaderOptions::inner_buffer`]. Larger reads rarely make SSDs faster, and it's
+/// small enough to not be wasted on small files.
+pub const INNER_BUFFER_SIZE: usize = 64 * 1024;
+/// Default [`ReaderOptions::readahead`], enough for parsers which read a few bytes at a
+/// time.
+pub const READAHEAD_SIZE: usize = 16 * 1024;
+
+/// Buffer sizes for [`EnardReader::new_with_options`] and
+/// [`EnardReader::open_with_options`], see [`crate::tuning`].
+///
+/// [`EnardReader::new_with_options`]: crate::EnardReader::new_with_options
+/// [`EnardReader::open_with_options`]: crate::EnardReader::open_with_options
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ReaderOptions {
+    /// Capacity of the [`std::io::BufReader`] around the inner reader, 0 for none
+    pub inner_buffer: usize,
+    /// Bytes [`crate::EnardReader::copy_to`] reads and decrypts at a time
+    pub crypt_chunk: usize,
+    /// Bytes to read ahead, 0 to disable, see [`crate::EnardReader::with_readahead`]
+    pub readahead: usize,
+}
+impl Default for ReaderOptions {
+    fn default() -> Self {
+        Self {
+            inner_buffer: INNER_BUFFER_SIZE,
+            crypt_chunk: COPY_BUFFER_SIZE,
+            readahead: READAHEAD_SIZE,
+        }
+    }
+}
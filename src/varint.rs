@@ -1,85 +1,101 @@
-/// A 64-bit version of LEB-encoding, see `<https://en.wikipedia.org/wiki/LEB128>`.
+//! A 63-bit version of LEB128 encoding, see `<https://en.wikipedia.org/wiki/LEB128>`.
+//!
+//! Format v2 uses unsigned varints for all of its length prefixes. Values take at most
+//! 9 bytes, so unsigned values must fit in 63 bits and signed values in
+//! `-(1 << 62)..(1 << 62)`, anything larger is an error.
+//!
+//! ```rust
+//! use enard::varint;
+//! let mut buf = Vec::new();
+//! varint::write_u(&mut buf, 300).unwrap();
+//! assert_eq!(buf, [0xac, 0x02]);
+//! assert_eq!(varint::read_u(buf.as_slice()).unwrap(), 300);
+//! ```
 pub use varint_soft::*;
 
 /// This is the default software implementation of varint encoding.
 /// It's not fast, but it'll work on all platforms and that's good enough for now.
 mod varint_soft {
-    use std::io::{BufRead, Error as IoError, Write};
+    use std::io::{BufRead, Error as IoError, ErrorKind, Write};
 
-    use super::eof_check;
     const MASK_7: u8 = 0x7f;
-    const BIT_7: u8 = 0b0100000;
+    /// Sign bit of a 7-bit block
+    const BIT_7: u8 = 0x40;
     const MASK_MORE_BYTES: u8 = 0x80;
-    const U63_MASK: u64 = 0x7fffffffffffffff;
+    const U63_MAX: u64 = 0x7fffffffffffffff;
     const BLOCK_SIZE: u32 = 7;
-
-    /// Extend the final 1-bit to the end of the number.
-    ///
-    /// See https://www.reddit.com/r/rust/comments/6175al/arbitrary_width_sign_extension_in_rust/
-    fn _fill_ones(v: u64) -> u64 {
-        let lz = v.leading_zeros();
-        v.wrapping_shl(lz).wrapping_shr(lz)
-    }
+    /// Maximum number of bytes in a varint
+    const MAX_BYTES: usize = 9;
 
     fn read_impl<R: BufRead>(mut r: R, signed: bool) -> Result<u64, IoError> {
-        let buf = r.fill_buf()?;
-        let buf_len = buf.len().min(9);
-        eof_check(buf_len)?;
         let mut result = 0u64;
         let mut shift = 0;
-        let mut i = 0;
-        while i < buf_len {
-            let b = buf[i];
+        for _ in 0..MAX_BYTES {
+            let mut b = [0u8; 1];
+            r.read_exact(&mut b)?;
+            let b = b[0];
             result |= ((b & MASK_7) as u64) << shift;
             shift += BLOCK_SIZE;
-            i += 1;
             if (b & MASK_MORE_BYTES) == 0 {
                 // Check for and perform sign extension
-                if signed && (b & BIT_7) > 0 {
+                if signed && (b & BIT_7) > 0 && shift < 64 {
                     // Fill the remaining data with 1s
                     result |= (!0) << shift;
                 }
-                break;
+                return Ok(result);
             }
         }
-        r.consume(i);
-        Ok(result)
+        Err(IoError::new(ErrorKind::InvalidData, "varint is too long"))
     }
 
+    /// Read an unsigned varint.
     pub fn read_u<R: BufRead>(r: R) -> Result<u64, IoError> {
         read_impl(r, false)
     }
 
+    /// Read a signed varint.
     pub fn read_i<R: BufRead>(r: R) -> Result<i64, IoError> {
         Ok(read_impl(r, true)? as i64)
     }
 
+    /// Write an unsigned varint, `value` must fit in 63 bits.
     pub fn write_u<W: Write>(mut w: W, value: u64) -> Result<(), IoError> {
-        // Chop off last bit since we only read 63-bit integers
-        let mut value = value & U63_MASK;
-        let mut buf = [0u8; 9];
+        if value > U63_MAX {
+            let msg = format!("{} is too large for a varint", value);
+            return Err(IoError::new(ErrorKind::InvalidInput, msg));
+        }
+        let mut value = value;
+        let mut buf = [0u8; MAX_BYTES];
         let mut i = 0;
-        while value > 0 {
-            buf[i] = (value as u8 & MASK_7) | MASK_MORE_BYTES;
-            value <<= BLOCK_SIZE;
+        loop {
+            buf[i] = value as u8 & MASK_7;
+            value >>= BLOCK_SIZE;
+            if value == 0 {
+                break;
+            }
+            buf[i] |= MASK_MORE_BYTES;
             i += 1;
         }
-        // Clear MASK_MORE_BYTES
-        buf[i] &= !MASK_MORE_BYTES;
         w.write_all(&buf[0..i + 1])
     }
 
-    pub fn write_i<W: Write>(w: W, value: i64) -> Result<(), IoError> {
-        // If the number is positive, it's the same as an unsigned write
-        if value >= 0 {
-            write_u(w, value as u64)
-        } else {
-            // We have remove the extra sign bits before we can write, so determine
-            // how many 7-bit blocks are needed to encode `value`.
-            let used_blocks = ((64 - value.leading_ones()) / BLOCK_SIZE).min(9);
-            // Now mask out remaining bits
-            let mask = !(u64::MAX << (used_blocks * BLOCK_SIZE));
-            write_u(w, value as u64 & mask)
+    /// Write a signed varint, `value` must be in `-(1 << 62)..(1 << 62)`.
+    pub fn write_i<W: Write>(mut w: W, value: i64) -> Result<(), IoError> {
+        let mut value = value;
+        let mut buf = [0u8; MAX_BYTES];
+        for i in 0..MAX_BYTES {
+            let b = value as u8 & MASK_7;
+            // Arithmetic shift, so negative numbers end at -1
+            value >>= BLOCK_SIZE;
+            // Done once the rest is just sign bits, and the sign bit of this block is right
+            let sign_set = (b & BIT_7) != 0;
+            if (value == 0 && !sign_set) || (value == -1 && sign_set) {
+                buf[i] = b;
+                return w.write_all(&buf[0..i + 1]);
+            }
+            buf[i] = b | MASK_MORE_BYTES;
         }
+        let msg = "value is too large for a signed varint";
+        Err(IoError::new(ErrorKind::InvalidInput, msg))
     }
 }
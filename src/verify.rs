@@ -0,0 +1,213 @@
+use byteorder::{ReadBytesExt, LE};
+use std::io::{self, Cursor, ErrorKind, Read, Seek, SeekFrom};
+use subtle::ConstantTimeEq;
+use zeroize::Zeroizing;
+
+use crate::core::*;
+use crate::decrypt_writer::SIGNATURE_BLOCK_SIZE;
+use crate::error::EnardError;
+use crate::limits::ParseLimits;
+use crate::mac::{BoxDynMac, DefaultMacFactory, MacFactory};
+use crate::KeyProvider;
+
+/// Summary of a successfully verified enard file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyReport {
+    /// Format version of the file
+    pub version: FormatVersion,
+    /// Size in bytes of the header, including padding
+    pub header_size: u64,
+    /// Size in bytes of the (encrypted) data section
+    pub data_size: u64,
+    /// Ascii name of the cipher used to encrypt the data
+    pub cipher: Vec<u8>,
+    /// Metadata stored in the header
+    pub meta: MetaMap,
+}
+
+/// Verifies the MAC of an enard file without decrypting it.
+///
+/// Unlike [`crate::EnardReader`] this only requires [`Read`], the file is processed
+/// front-to-back in a single pass. The header is parsed and fed into the MAC when the
+/// verifier is created, so it's possible to inspect the header before streaming the
+/// rest of the file through [`Verifier::verify`]. The header isn't authenticated until
+/// [`Verifier::verify`] succeeds.
+///
+/// ```rust
+/// # use std::io::Cursor;
+/// # fn check(pack: &[u8], key: &[u8]) -> Result<(), enard::EnardError> {
+/// let report = enard::verify(Cursor::new(pack), key)?;
+/// println!("{} bytes of data", report.data_size);
+/// # Ok(())
+/// # }
+/// ```
+pub struct Verifier<R> {
+    inner: R,
+    mac: BoxDynMac,
+    aad: Zeroizing<Vec<u8>>,
+    report: VerifyReport,
+}
+impl<R: Read> Verifier<R> {
+    /// Reads the header of the enard file and feeds it into the MAC. It isn't
+    /// authenticated until [`Verifier::verify`] succeeds.
+    pub fn new<K: KeyProvider>(reader: R, key: K) -> Result<Self, EnardError> {
+        Self::new_impl(reader, |meta| {
+            let key = key.key_for(meta)?;
+            crate::kdf::check_key(meta, &key)?;
+            crate::kdf::mac_key(meta, &key)
+        })
+    }
+
+    /// Same as [`Verifier::new`] but only using the MAC key, see [`crate::kdf::mac_key`].
+    ///
+    /// For files written with [`crate::kdf::insert_split_keys`] the MAC key can't be
+    /// used to decrypt the data, so it can be given to a party which only checks files.
+    pub fn with_mac_key(reader: R, mac_key: &[u8]) -> Result<Self, EnardError> {
+        Self::new_impl(reader, |_| Ok(Zeroizing::new(mac_key.to_vec())))
+    }
+
+    fn new_impl<F>(mut reader: R, mac_key: F) -> Result<Self, EnardError>
+    where
+        F: FnOnce(&MetaMap) -> Result<Zeroizing<Vec<u8>>, EnardError>,
+    {
+        let mut magic_buf = [0u8; MAGIC.len()];
+        reader.read_exact(&mut magic_buf)?;
+        if &magic_buf != MAGIC {
+            return Err(EnardError::new_invalid_magic(MAGIC, &magic_buf));
+        }
+        let (version, obfuscated) = FormatVersion::from_field(reader.read_u16::<LE>()?)?;
+        if obfuscated {
+            return Err(obfuscated_header().into());
+        }
+        // See `EnardBuilder::build` for format details
+        let header_size = match version {
+            FormatVersion::V1 => reader.read_u32::<LE>()? as u64,
+            FormatVersion::V2 => reader.read_u64::<LE>()?,
+        };
+        let data_size = reader.read_u64::<LE>()?;
+        if header_size == 0 && data_size == 0 {
+            return Err(sizes_in_footer().into());
+        }
+        let limits = ParseLimits::default();
+        limits.check_sizes(header_size, data_size)?;
+
+        // The whole header is covered by the MAC, so read it all at once
+        let mut header = Vec::new();
+        (&mut reader).take(header_size).read_to_end(&mut header)?;
+        let header_start = version.header_start() as u64;
+        let expected = header_start
+            .saturating_add(header_size)
+            .saturating_add(data_size)
+            .saturating_add(TAG_SIZE as u64);
+        if (header.len() as u64) < header_size {
+            let actual = header_start + header.len() as u64;
+            return Err(EnardError::Truncated { expected, actual });
+        }
+        let parsed = Header::read(Cursor::new(&header), version, header_size, &limits)?;
+        let mac_name = crate::mac::mac_name(&parsed.meta);
+        let mut mac = DefaultMacFactory.create(mac_name, &mac_key(&parsed.meta)?)?;
+        mac.update(&header);
+
+        Ok(Self {
+            inner: reader,
+            mac,
+            aad: Zeroizing::default(),
+            report: VerifyReport {
+                version,
+                header_size,
+                data_size,
+                cipher: parsed.cipher,
+                meta: parsed.meta,
+            },
+        })
+    }
+
+    /// Verify the MAC with the additional authenticated data `aad`, see
+    /// [`crate::EnardWriter::with_aad`].
+    pub fn with_aad(mut self, aad: &[u8]) -> Self {
+        self.aad = Zeroizing::new(aad.to_vec());
+        self
+    }
+
+    /// Header information, available before the data is verified.
+    ///
+    /// Note that although the header has been read, it has **not** been verified yet.
+    pub fn report(&self) -> &VerifyReport {
+        &self.report
+    }
+
+    /// Stream the data section through the MAC and check the tag at the end of the file.
+    pub fn verify(mut self) -> Result<VerifyReport, EnardError> {
+        let data_size = self.report.data_size;
+        let mut n = io::copy(&mut (&mut self.inner).take(data_size), &mut self.mac)?;
+        let mut tag_buf = [0u8; TAG_SIZE];
+        if n == data_size {
+            n += read_up_to(&mut self.inner, &mut tag_buf)? as u64;
+        }
+        if n != data_size + TAG_SIZE as u64 {
+            let before_data = self.report.version.header_start() as u64 + self.report.header_size;
+            return Err(EnardError::Truncated {
+                expected: before_data + data_size + TAG_SIZE as u64,
+                actual: before_data + n,
+            });
+        }
+        crate::mac::update_aad(&mut self.mac, &self.aad);
+        crate::mac::verify(self.mac, &tag_buf)?;
+        Ok(self.report)
+    }
+
+    /// Unwraps this [`Verifier`], returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+/// Verify the enard file contained in `reader` using `key`, without decrypting it.
+///
+/// This is shorthand for `Verifier::new(reader, key)?.verify()`.
+pub fn verify<R: Read, K: KeyProvider>(reader: R, key: K) -> Result<VerifyReport, EnardError> {
+    Verifier::new(reader, key)?.verify()
+}
+
+/// Check that the enard file in `reader` has the MAC tag `expected_tag`, without
+/// computing the MAC, e.g. against a tag recorded in a trusted manifest from
+/// [`crate::WriteSummary`].
+///
+/// Only the header and the tag are read, and the file must end right after the tag (or
+/// its signature block), so this quickly catches a stale, replaced, or truncated file.
+/// **The data isn't read, so damage in the middle of the data isn't detected**, use
+/// [`verify`] for that. The key is only checked if the file has a key check (see
+/// [`crate::EnardWriter::with_key_check`]).
+///
+/// Returns [`EnardError::MacError`] if the tag doesn't match.
+pub fn verify_tag<R, K>(reader: R, key: K, expected_tag: &[u8]) -> Result<VerifyReport, EnardError>
+where
+    R: Read + Seek,
+    K: KeyProvider,
+{
+    let verifier = Verifier::new(reader, key)?;
+    let report = verifier.report.clone();
+    let mut reader = verifier.into_inner();
+    let data_start = reader.stream_position()?;
+    let tag_end = data_start + report.data_size + TAG_SIZE as u64;
+    let end = reader.seek(SeekFrom::End(0))?;
+    if end < tag_end {
+        let start = data_start - report.header_size - report.version.header_start() as u64;
+        return Err(EnardError::Truncated {
+            expected: tag_end - start,
+            actual: end - start,
+        });
+    }
+    let extra = end - tag_end;
+    if extra != 0 && extra != SIGNATURE_BLOCK_SIZE as u64 {
+        let msg = format!("{} unexpected bytes after the MAC tag", extra);
+        return Err(io::Error::new(ErrorKind::InvalidData, msg).into());
+    }
+    reader.seek(SeekFrom::Start(tag_end - TAG_SIZE as u64))?;
+    let mut tag = [0u8; TAG_SIZE];
+    reader.read_exact(&mut tag)?;
+    if tag.ct_eq(expected_tag).unwrap_u8() != 1 {
+        return Err(digest::MacError.into());
+    }
+    Ok(report)
+}
@@ -0,0 +1,287 @@
+//! Read-only [`vfs`](::vfs) filesystems backed by an enard file, so code which already
+//! does its IO through a [`VfsPath`] can read from an encrypted pack instead of a
+//! directory.
+//!
+//! [`EnardPackFs`] serves the assets of an [`Index`], see [`crate::index`], and
+//! [`EnardZipFs`] (with the `zip-integration` feature) serves a zip archive inside an
+//! enard file. Asset names are split into directories on `/`.
+//!
+//! ```no_run
+//! use enard::vfs::EnardPackFs;
+//! use vfs::VfsPath;
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let key = [0x42u8; 32];
+//! let root: VfsPath = EnardPackFs::open("assets.enard", &key)?.into();
+//! let level = root.join("levels/level1.json")?.read_to_string()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`VfsPath`]: ::vfs::VfsPath
+use ::vfs::error::VfsErrorKind;
+use ::vfs::{FileSystem, SeekAndRead, VfsError, VfsFileType, VfsMetadata, VfsResult};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, Write};
+use std::path::Path;
+
+use crate::index::Index;
+use crate::stream::SubSeek;
+use crate::{BoxDynCipher, EnardError, EnardReaderPool};
+
+/// Implements the [`FileSystem`] methods which modify it, returning
+/// [`VfsErrorKind::NotSupported`].
+macro_rules! read_only {
+    () => {
+        fn create_dir(&self, _path: &str) -> VfsResult<()> {
+            Err(VfsErrorKind::NotSupported.into())
+        }
+
+        fn create_file(&self, _path: &str) -> VfsResult<Box<dyn Write + Send>> {
+            Err(VfsErrorKind::NotSupported.into())
+        }
+
+        fn append_file(&self, _path: &str) -> VfsResult<Box<dyn Write + Send>> {
+            Err(VfsErrorKind::NotSupported.into())
+        }
+
+        fn remove_file(&self, _path: &str) -> VfsResult<()> {
+            Err(VfsErrorKind::NotSupported.into())
+        }
+
+        fn remove_dir(&self, _path: &str) -> VfsResult<()> {
+            Err(VfsErrorKind::NotSupported.into())
+        }
+    };
+}
+
+/// A [`FileSystem`] which serves the assets listed in the [`Index`] of an enard file.
+///
+/// The MAC is verified once when the filesystem is created. Each opened file has its
+/// own reader, so files can be read at the same time and aren't loaded into memory.
+pub struct EnardPackFs<R = BufReader<File>> {
+    pool: EnardReaderPool<R, BoxDynCipher>,
+    index: Index,
+}
+
+impl EnardPackFs {
+    /// Open the enard file at `path` and read its index. The file is buffered with
+    /// [`crate::tuning::INNER_BUFFER_SIZE`] bytes.
+    pub fn open<P: AsRef<Path>>(path: P, key: &[u8]) -> Result<Self, EnardError> {
+        let capacity = crate::tuning::INNER_BUFFER_SIZE;
+        let file = BufReader::with_capacity(capacity, File::open(path)?);
+        Self::from_reader(file, key)
+    }
+}
+
+impl<R: Read + Seek> EnardPackFs<R> {
+    /// Read the index of the enard file in `reader`.
+    ///
+    /// Returns [`EnardError::MissingMeta`] if the file doesn't have an index.
+    pub fn from_reader(reader: R, key: &[u8]) -> Result<Self, EnardError> {
+        let pool = EnardReaderPool::new_boxed(reader, key)?;
+        let index = pool
+            .reader()?
+            .open_index()?
+            .ok_or_else(|| EnardError::new_missing_meta(crate::meta::INDEX))?;
+        Ok(Self { pool, index })
+    }
+
+    /// The index of the assets
+    pub fn index(&self) -> &Index {
+        &self.index
+    }
+}
+
+impl<R> fmt::Debug for EnardPackFs<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EnardPackFs")
+            .field("index", &self.index)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<R: Read + Seek + Send + 'static> FileSystem for EnardPackFs<R> {
+    fn read_dir(&self, path: &str) -> VfsResult<Box<dyn Iterator<Item = String> + Send>> {
+        let names = self.index.iter().map(|(name, _)| name);
+        let children = children(names, entry_name(path)).ok_or(VfsErrorKind::FileNotFound)?;
+        Ok(Box::new(children.into_iter()))
+    }
+
+    fn open_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndRead + Send>> {
+        let entry = self
+            .index
+            .get(entry_name(path))
+            .ok_or(VfsErrorKind::FileNotFound)?;
+        let reader = self.pool.reader().map_err(vfs_error)?;
+        Ok(Box::new(SubSeek::new(reader, entry.offset, entry.len)?))
+    }
+
+    fn metadata(&self, path: &str) -> VfsResult<VfsMetadata> {
+        let name = entry_name(path);
+        if let Some(entry) = self.index.get(name) {
+            return Ok(file_metadata(entry.len));
+        }
+        let names = self.index.iter().map(|(name, _)| name);
+        match children(names, name) {
+            Some(_) => Ok(dir_metadata()),
+            None => Err(VfsErrorKind::FileNotFound.into()),
+        }
+    }
+
+    fn exists(&self, path: &str) -> VfsResult<bool> {
+        Ok(self.metadata(path).is_ok())
+    }
+
+    read_only!();
+}
+
+#[cfg(feature = "zip-integration")]
+pub use self::zip_fs::EnardZipFs;
+
+#[cfg(feature = "zip-integration")]
+mod zip_fs {
+    use ::vfs::error::VfsErrorKind;
+    use ::vfs::{FileSystem, SeekAndRead, VfsMetadata, VfsResult};
+    use std::fmt;
+    use std::fs::File;
+    use std::io::{self, BufReader, Cursor, Read, Seek, Write};
+    use std::path::Path;
+    use std::sync::Mutex;
+
+    use super::{children, dir_metadata, entry_name, file_metadata};
+    use crate::zip::{EnardZip, EnardZipArchive};
+    use crate::EnardError;
+
+    /// A [`FileSystem`] which serves the files of a zip archive inside an enard file.
+    ///
+    /// Files are decompressed into memory when they're opened, and the archive is
+    /// behind a lock, so only one file is read from it at a time.
+    pub struct EnardZipFs<R: Read + Seek = BufReader<File>> {
+        archive: Mutex<EnardZipArchive<R>>,
+    }
+
+    impl EnardZipFs {
+        /// Open the enard file at `path` and read the zip archive inside it.
+        pub fn open<P: AsRef<Path>>(path: P, key: &[u8]) -> Result<Self, EnardError> {
+            Ok(Self::new(EnardZip::open(path, key)?))
+        }
+    }
+
+    impl<R: Read + Seek> EnardZipFs<R> {
+        /// Serve the files of an already opened archive.
+        pub fn new(archive: EnardZipArchive<R>) -> Self {
+            Self {
+                archive: Mutex::new(archive),
+            }
+        }
+
+        fn children(&self, name: &str) -> Option<std::collections::BTreeSet<String>> {
+            let archive = self.archive.lock().unwrap();
+            children(archive.file_names(), name)
+        }
+    }
+
+    impl<R: Read + Seek> fmt::Debug for EnardZipFs<R> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("EnardZipFs").finish_non_exhaustive()
+        }
+    }
+
+    impl<R: Read + Seek + Send + 'static> FileSystem for EnardZipFs<R> {
+        fn read_dir(&self, path: &str) -> VfsResult<Box<dyn Iterator<Item = String> + Send>> {
+            let children = self
+                .children(entry_name(path))
+                .ok_or(VfsErrorKind::FileNotFound)?;
+            Ok(Box::new(children.into_iter()))
+        }
+
+        fn open_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndRead + Send>> {
+            let mut archive = self.archive.lock().unwrap();
+            let mut file = match archive.by_name(entry_name(path)) {
+                Ok(file) if file.is_file() => file,
+                Ok(_) | Err(::zip::result::ZipError::FileNotFound) => {
+                    return Err(VfsErrorKind::FileNotFound.into())
+                }
+                Err(e) => return Err(io::Error::from(e).into()),
+            };
+            let mut buf = Vec::with_capacity(file.size() as usize);
+            file.read_to_end(&mut buf)?;
+            Ok(Box::new(Cursor::new(buf)))
+        }
+
+        fn metadata(&self, path: &str) -> VfsResult<VfsMetadata> {
+            let name = entry_name(path);
+            if let Ok(file) = self.archive.lock().unwrap().by_name(name) {
+                if file.is_file() {
+                    return Ok(file_metadata(file.size()));
+                }
+            }
+            match self.children(name) {
+                Some(_) => Ok(dir_metadata()),
+                None => Err(VfsErrorKind::FileNotFound.into()),
+            }
+        }
+
+        fn exists(&self, path: &str) -> VfsResult<bool> {
+            Ok(self.metadata(path).is_ok())
+        }
+
+        read_only!();
+    }
+}
+
+/// Convert a vfs path, which is empty or starts with `/`, to an asset name.
+fn entry_name(path: &str) -> &str {
+    path.trim_start_matches('/')
+}
+
+/// Returns the names of the direct children of the directory `name`, or `None` if
+/// there is no such directory.
+fn children<'a, I>(names: I, name: &str) -> Option<BTreeSet<String>>
+where
+    I: Iterator<Item = &'a str>,
+{
+    let prefix = if name.is_empty() {
+        String::new()
+    } else {
+        format!("{}/", name)
+    };
+    let mut found = name.is_empty();
+    let mut children = BTreeSet::new();
+    for entry in names {
+        if let Some(rest) = entry.strip_prefix(&prefix) {
+            found = true;
+            if let Some(child) = rest.split('/').next().filter(|c| !c.is_empty()) {
+                children.insert(child.to_owned());
+            }
+        }
+    }
+    if found {
+        Some(children)
+    } else {
+        None
+    }
+}
+
+fn file_metadata(len: u64) -> VfsMetadata {
+    VfsMetadata {
+        file_type: VfsFileType::File,
+        len,
+    }
+}
+
+fn dir_metadata() -> VfsMetadata {
+    VfsMetadata {
+        file_type: VfsFileType::Directory,
+        len: 0,
+    }
+}
+
+fn vfs_error(e: EnardError) -> VfsError {
+    match e {
+        EnardError::IO(e) => e.into(),
+        e => VfsErrorKind::Other(e.to_string()).into(),
+    }
+}
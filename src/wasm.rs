@@ -0,0 +1,69 @@
+//! JavaScript bindings for use in the browser via `wasm-bindgen`.
+//!
+//! The whole enard file is held in memory, which matches how assets are usually
+//! fetched over HTTP. From JavaScript:
+//!
+//! ```js
+//! const resp = await fetch("assets.zip.enard");
+//! const data = new Uint8Array(await resp.arrayBuffer());
+//! const reader = new EnardReader(data, key);
+//! const plain = reader.readAll();
+//! ```
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use wasm_bindgen::prelude::*;
+
+use crate::{BoxDynCipher, EnardError};
+
+fn to_js(e: impl Into<EnardError>) -> JsError {
+    JsError::new(&e.into().to_string())
+}
+
+/// Decrypts an in-memory enard file.
+#[wasm_bindgen(js_name = EnardReader)]
+pub struct WasmEnardReader {
+    inner: crate::EnardReader<Cursor<Vec<u8>>, BoxDynCipher>,
+}
+
+#[wasm_bindgen(js_class = EnardReader)]
+impl WasmEnardReader {
+    /// Parse and verify the enard file in `data`. Throws if the file is invalid
+    /// or the key is wrong.
+    #[wasm_bindgen(constructor)]
+    pub fn new(data: Vec<u8>, key: &[u8]) -> Result<WasmEnardReader, JsError> {
+        let inner = crate::EnardReader::new_boxed(Cursor::new(data), key).map_err(to_js)?;
+        Ok(Self { inner })
+    }
+
+    /// Decrypt up to `buf.length` bytes into `buf`, returning the number of bytes read.
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize, JsError> {
+        self.inner.read(buf).map_err(to_js)
+    }
+
+    /// Decrypt everything from the current position to the end of the data.
+    #[wasm_bindgen(js_name = readAll)]
+    pub fn read_all(&mut self) -> Result<Vec<u8>, JsError> {
+        let mut buf = Vec::new();
+        self.inner.read_to_end(&mut buf).map_err(to_js)?;
+        Ok(buf)
+    }
+
+    /// Seek to an absolute position in the decrypted data.
+    pub fn seek(&mut self, pos: f64) -> Result<f64, JsError> {
+        let pos = self
+            .inner
+            .seek(SeekFrom::Start(pos as u64))
+            .map_err(to_js)?;
+        Ok(pos as f64)
+    }
+
+    /// Size in bytes of the decrypted data.
+    #[wasm_bindgen(getter, js_name = dataSize)]
+    pub fn data_size(&self) -> f64 {
+        self.inner.data_size() as f64
+    }
+
+    /// Returns the metadata value for `key`, or `undefined` if there isn't one.
+    pub fn meta(&self, key: &str) -> Option<Vec<u8>> {
+        self.inner.meta().get(key.as_bytes()).cloned()
+    }
+}
@@ -0,0 +1,48 @@
+//! Open encrypted zip archives, the most common use case for enard.
+//!
+//! ```no_run
+//! # fn main() -> Result<(), enard::EnardError> {
+//! let key = [0x42u8; 32];
+//! let mut archive = enard::zip::EnardZip::open("assets.zip.enard", &key)?;
+//! for name in archive.file_names() {
+//!     println!("{}", name);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use ::zip::ZipArchive;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek};
+use std::path::Path;
+
+use crate::tuning::{INNER_BUFFER_SIZE, READAHEAD_SIZE};
+use crate::{BoxDynCipher, EnardError, EnardReader};
+
+/// A zip archive inside an enard file.
+pub type EnardZipArchive<R> = ZipArchive<EnardReader<R, BoxDynCipher>>;
+
+/// Helpers for opening zip archives stored in enard files.
+pub struct EnardZip;
+impl EnardZip {
+    /// Open the enard file at `path` and read the zip archive inside it. The file is
+    /// buffered with [`INNER_BUFFER_SIZE`] bytes.
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        key: &[u8],
+    ) -> Result<EnardZipArchive<BufReader<File>>, EnardError> {
+        let file = BufReader::with_capacity(INNER_BUFFER_SIZE, File::open(path)?);
+        Self::from_reader(file, key)
+    }
+
+    /// Read the zip archive inside the enard file in `reader`.
+    ///
+    /// The reader should already be buffered. Zip parsing does many small reads, so the
+    /// [`EnardReader`] reads ahead [`READAHEAD_SIZE`] bytes.
+    pub fn from_reader<R: Read + Seek>(
+        reader: R,
+        key: &[u8],
+    ) -> Result<EnardZipArchive<R>, EnardError> {
+        let reader = EnardReader::new_boxed(reader, key)?.with_readahead(READAHEAD_SIZE);
+        Ok(ZipArchive::new(reader).map_err(io::Error::from)?)
+    }
+}